@@ -0,0 +1,204 @@
+// Build and evaluation times of each backend, for networks from a few
+// dozen to 100k connections, both dense and sparse.  Run with
+//
+//   cargo bench --bench networks -- [filter] [options]
+//
+// which takes Criterion's options, such as `--save-baseline NAME` to
+// keep the times of a run and `--baseline NAME` to compare against
+// them.
+
+use std::collections::BTreeSet;
+use std::hint::black_box;
+
+use criterion::measurement::WallTime;
+use criterion::{
+    criterion_group, criterion_main, BenchmarkGroup, BenchmarkId, Criterion,
+};
+use entendre::*;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+const BATCH_SIZE: usize = 256;
+
+// Fully connected layers of `width` hidden nodes between the inputs
+// and outputs.
+fn dense(
+    inputs: u32,
+    layers: u32,
+    width: u32,
+    outputs: u32,
+) -> NeuralNetBuilder {
+    let mut rng = ChaCha8Rng::seed_from_u64(0);
+    let mut builder = NeuralNetBuilder::new();
+    builder.set_default_activation(ActivationFunction::Sigmoid);
+    builder.add_nodes(NodeType::Input, inputs);
+    let mut previous = 0..inputs;
+    let sizes = (0..layers).map(|_| width).chain(Some(outputs));
+    sizes.enumerate().for_each(|(i, size)| {
+        let node_type = if i as u32 == layers {
+            NodeType::Output
+        } else {
+            NodeType::Hidden
+        };
+        let start = previous.end;
+        builder.add_nodes(node_type, size);
+        (start..start + size).for_each(|dest| {
+            previous.clone().for_each(|origin| {
+                let weight = rng.gen_range(-1.0..1.0);
+                builder.add_normal_connection_raw(origin, dest, weight);
+            });
+        });
+        previous = start..start + size;
+    });
+    builder
+}
+
+// `connections` random connections between `hidden` hidden nodes,
+// each from an input or an earlier hidden node, with every output fed
+// by a random hidden node.
+fn sparse(
+    inputs: u32,
+    hidden: u32,
+    outputs: u32,
+    connections: usize,
+) -> NeuralNetBuilder {
+    let mut rng = ChaCha8Rng::seed_from_u64(0);
+    let mut builder = NeuralNetBuilder::new();
+    builder.set_default_activation(ActivationFunction::Sigmoid);
+    builder.add_nodes(NodeType::Input, inputs);
+    builder.add_nodes(NodeType::Hidden, hidden);
+    builder.add_nodes(NodeType::Output, outputs);
+
+    let mut pairs = BTreeSet::new();
+    (inputs + hidden..inputs + hidden + outputs).for_each(|dest| {
+        pairs.insert((rng.gen_range(inputs..inputs + hidden), dest));
+    });
+    while pairs.len() < connections {
+        let dest = rng.gen_range(inputs..inputs + hidden);
+        pairs.insert((rng.gen_range(0..dest), dest));
+    }
+    pairs.into_iter().for_each(|(origin, dest)| {
+        let weight = rng.gen_range(-1.0..1.0);
+        builder.add_normal_connection_raw(origin, dest, weight);
+    });
+    builder
+}
+
+fn topologies() -> Vec<(&'static str, NeuralNetBuilder)> {
+    vec![
+        ("small_dense", dense(8, 1, 8, 4)),
+        ("small_sparse", sparse(8, 16, 4, 40)),
+        ("medium_dense", dense(16, 2, 28, 8)),
+        ("medium_sparse", sparse(16, 200, 8, 1_000)),
+        ("large_dense", dense(64, 3, 175, 16)),
+        ("large_sparse", sparse(64, 10_000, 16, 100_000)),
+    ]
+}
+
+#[derive(Clone, Copy)]
+enum Case {
+    Build,
+    Evaluate,
+    EvaluateBatch,
+}
+
+impl Case {
+    fn name(self) -> &'static str {
+        match self {
+            Case::Build => "build",
+            Case::Evaluate => "evaluate",
+            Case::EvaluateBatch => "evaluate_batch",
+        }
+    }
+}
+
+// Times one case for a backend.  Every topology is feed-forward with
+// sigmoid nodes, which every backend builds, so failing to build is a
+// bug rather than something to skip.
+fn backend<N: NeuralNet>(
+    group: &mut BenchmarkGroup<'_, WallTime>,
+    case: Case,
+    backend: &str,
+    topology: &str,
+    builder: &NeuralNetBuilder,
+) {
+    let mut net = N::build_from(builder).unwrap_or_else(|err| {
+        panic!("{}/{}/{}: {}", case.name(), backend, topology, err)
+    });
+    let id = BenchmarkId::new(backend, topology);
+
+    let mut rng = ChaCha8Rng::seed_from_u64(1);
+    let mut random_inputs = || {
+        (0..net.num_inputs())
+            .map(|_| rng.gen_range(-1.0..1.0))
+            .collect::<Vec<f32>>()
+    };
+    match case {
+        Case::Build => group.bench_function(id, |b| {
+            b.iter(|| N::build_from(black_box(builder)))
+        }),
+        Case::Evaluate => {
+            let inputs = random_inputs();
+            let mut outputs = vec![0.0; net.num_outputs()];
+            group.bench_function(id, |b| {
+                b.iter(|| net.evaluate_into(black_box(&inputs), &mut outputs))
+            })
+        }
+        Case::EvaluateBatch => {
+            let batch =
+                (0..BATCH_SIZE).map(|_| random_inputs()).collect::<Vec<_>>();
+            group.bench_function(id, |b| {
+                b.iter(|| net.evaluate_batch(black_box(&batch)))
+            })
+        }
+    };
+}
+
+fn run(c: &mut Criterion, case: Case) {
+    let mut group = c.benchmark_group(case.name());
+    topologies().iter().for_each(|(topology, builder)| {
+        // The large networks take Criterion's fewest samples, since
+        // some backends take a while on them.
+        let sample_size = match topology.starts_with("large") {
+            true => 10,
+            false => 100,
+        };
+        group.sample_size(sample_size);
+        backend::<ConsecutiveNeuralNet>(
+            &mut group,
+            case,
+            "consecutive",
+            topology,
+            builder,
+        );
+        backend::<LayeredNeuralNet>(
+            &mut group, case, "layered", topology, builder,
+        );
+        backend::<SparseNeuralNet>(
+            &mut group, case, "sparse", topology, builder,
+        );
+        backend::<QuantizedNeuralNet>(
+            &mut group,
+            case,
+            "quantized",
+            topology,
+            builder,
+        );
+    });
+    group.finish();
+}
+
+fn build(c: &mut Criterion) {
+    run(c, Case::Build);
+}
+
+fn evaluate(c: &mut Criterion) {
+    run(c, Case::Evaluate);
+}
+
+fn evaluate_batch(c: &mut Criterion) {
+    run(c, Case::EvaluateBatch);
+}
+
+criterion_group!(benches, build, evaluate, evaluate_batch);
+criterion_main!(benches);
@@ -0,0 +1,38 @@
+#ifndef ENTENDRE_H
+#define ENTENDRE_H
+
+/* Generated by cbindgen from ffi/src/lib.rs, so don't edit it by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+typedef struct EntendreNet EntendreNet;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+struct EntendreNet *entendre_net_load(const uint8_t *data, size_t len);
+
+size_t entendre_net_num_inputs(const struct EntendreNet *net);
+
+size_t entendre_net_num_outputs(const struct EntendreNet *net);
+
+int entendre_net_evaluate(struct EntendreNet *net,
+                          const float *inputs,
+                          size_t num_inputs,
+                          float *outputs,
+                          size_t num_outputs);
+
+void entendre_net_reset(struct EntendreNet *net);
+
+void entendre_net_free(struct EntendreNet *net);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* ENTENDRE_H */
@@ -0,0 +1,215 @@
+// C API for evaluating networks evolved with entendre, from the bytes
+// of a genome file such as one written by `Genome::save`, or of a
+// genome or network in the JSON format.  The header in
+// include/entendre.h is generated from this file, after changing it,
+// by running
+//
+//   cbindgen --config cbindgen.toml --output include/entendre.h
+//
+// in ffi/, which the tests check was done.  Networks may be used from
+// any thread, but only from one at a time.
+//
+// Requirements on pointers are given with each function, rather than
+// in doc comments.
+#![allow(clippy::missing_safety_doc)]
+
+use std::os::raw::c_int;
+use std::ptr;
+use std::slice;
+
+use entendre::*;
+
+// Opaque to C, which only ever holds pointers to it.
+pub struct EntendreNet {
+    net: ConsecutiveNeuralNet,
+}
+
+// Builds a network from the `len` bytes at `data`, returning null if
+// they aren't a genome file, genome JSON or network JSON, or don't
+// form a valid network.  Only network JSON carries the settings that
+// genomes lack, such as input scaling and output mappings.  The
+// network must be released with `entendre_net_free`.
+#[no_mangle]
+pub unsafe extern "C" fn entendre_net_load(
+    data: *const u8,
+    len: usize,
+) -> *mut EntendreNet {
+    if data.is_null() {
+        return ptr::null_mut();
+    }
+    let bytes = slice::from_raw_parts(data, len);
+    match load(bytes) {
+        Some(net) => Box::into_raw(Box::new(EntendreNet { net })),
+        None => ptr::null_mut(),
+    }
+}
+
+fn load(bytes: &[u8]) -> Option<ConsecutiveNeuralNet> {
+    let builder = match std::str::from_utf8(bytes) {
+        Ok(json) if json.trim_start().starts_with('{') => {
+            NeuralNetBuilder::from_json(json)
+                .or_else(|_| Genome::from_json(json).map(|g| g.to_builder()))
+                .ok()?
+        }
+        _ => Genome::read_from(bytes).ok()?.to_builder(),
+    };
+    builder.build().ok()
+}
+
+// Number of values expected by `entendre_net_evaluate`, or zero for a
+// null network.
+#[no_mangle]
+pub unsafe extern "C" fn entendre_net_num_inputs(
+    net: *const EntendreNet,
+) -> usize {
+    net.as_ref().map_or(0, |net| net.net.num_inputs())
+}
+
+// Number of values written by `entendre_net_evaluate`, or zero for a
+// null network.
+#[no_mangle]
+pub unsafe extern "C" fn entendre_net_num_outputs(
+    net: *const EntendreNet,
+) -> usize {
+    net.as_ref().map_or(0, |net| net.net.num_outputs())
+}
+
+// Evaluates the network on the `num_inputs` values at `inputs`,
+// writing its outputs to the `num_outputs` values at `outputs`.
+// Returns 0 on success, or -1 without evaluating if any pointer is
+// null or either count doesn't match the network.
+#[no_mangle]
+pub unsafe extern "C" fn entendre_net_evaluate(
+    net: *mut EntendreNet,
+    inputs: *const f32,
+    num_inputs: usize,
+    outputs: *mut f32,
+    num_outputs: usize,
+) -> c_int {
+    let net = match net.as_mut() {
+        Some(net) => &mut net.net,
+        None => return -1,
+    };
+    if inputs.is_null()
+        || outputs.is_null()
+        || num_inputs != net.num_inputs()
+        || num_outputs != net.num_outputs()
+    {
+        return -1;
+    }
+    let inputs = slice::from_raw_parts(inputs, num_inputs);
+    let outputs = slice::from_raw_parts_mut(outputs, num_outputs);
+    net.evaluate_into(inputs, outputs);
+    0
+}
+
+// Clears the values carried between evaluations by recurrent
+// connections, as at the start of an episode.
+#[no_mangle]
+pub unsafe extern "C" fn entendre_net_reset(net: *mut EntendreNet) {
+    if let Some(net) = net.as_mut() {
+        net.net.reset_state();
+    }
+}
+
+// Releases a network from `entendre_net_load`.  Null is ignored.
+#[no_mangle]
+pub unsafe extern "C" fn entendre_net_free(net: *mut EntendreNet) {
+    if !net.is_null() {
+        drop(Box::from_raw(net));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_c_api() -> std::io::Result<()> {
+        let mut tracker = InnovationTracker::new();
+        let mut genome =
+            Genome::with_io(2, 1, ActivationFunction::Identity, &mut tracker);
+        genome.add_connection(0, 2, 2.0, ConnectionType::Normal, &mut tracker);
+        genome.add_connection(1, 2, -1.0, ConnectionType::Normal, &mut tracker);
+        let mut bytes = Vec::new();
+        genome.write_to(&mut bytes)?;
+
+        unsafe {
+            let net = entendre_net_load(bytes.as_ptr(), bytes.len());
+            assert!(!net.is_null());
+            assert_eq!(entendre_net_num_inputs(net), 2);
+            assert_eq!(entendre_net_num_outputs(net), 1);
+
+            let inputs = [3.0, 1.0];
+            let mut outputs = [0.0];
+            let res = entendre_net_evaluate(
+                net,
+                inputs.as_ptr(),
+                2,
+                outputs.as_mut_ptr(),
+                1,
+            );
+            assert_eq!((res, outputs), (0, [5.0]));
+            let res = entendre_net_evaluate(
+                net,
+                inputs.as_ptr(),
+                1,
+                outputs.as_mut_ptr(),
+                1,
+            );
+            assert_eq!(res, -1);
+            entendre_net_free(net);
+
+            assert!(entendre_net_load(bytes.as_ptr(), 4).is_null());
+            assert!(entendre_net_load(ptr::null(), 0).is_null());
+            entendre_net_free(ptr::null_mut());
+        }
+
+        // Network JSON keeps the input scaling that genomes lack.
+        let mut builder = genome.to_builder();
+        builder.set_input_scaling(vec![InputScaling::MinMax {
+            min: 0.0,
+            max: 6.0,
+        }]);
+        let genome_json = genome.to_json().unwrap();
+        let network_json = builder.to_json().unwrap();
+        unsafe {
+            let inputs = [3.0, 1.0];
+            let mut outputs = [0.0];
+            [(genome_json, 5.0), (network_json, 0.0)].iter().for_each(
+                |(json, expected)| {
+                    let net = entendre_net_load(json.as_ptr(), json.len());
+                    assert!(!net.is_null());
+                    entendre_net_evaluate(
+                        net,
+                        inputs.as_ptr(),
+                        2,
+                        outputs.as_mut_ptr(),
+                        1,
+                    );
+                    assert_eq!(outputs, [*expected]);
+                    entendre_net_free(net);
+                },
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_header_up_to_date() {
+        let crate_dir = env!("CARGO_MANIFEST_DIR");
+        let config =
+            cbindgen::Config::from_file(format!("{}/cbindgen.toml", crate_dir))
+                .unwrap();
+        let mut generated = Vec::new();
+        cbindgen::generate_with_config(crate_dir, config)
+            .unwrap()
+            .write(&mut generated);
+        let header =
+            std::fs::read(format!("{}/include/entendre.h", crate_dir)).unwrap();
+        assert!(
+            generated == header,
+            "include/entendre.h is out of date, so regenerate it with cbindgen"
+        );
+    }
+}
@@ -0,0 +1,204 @@
+// Builds networks from arbitrary sequences of builder calls, and
+// evaluates them with every CPU backend that accepts them.  Building
+// may fail, but nothing may panic.  Run with
+//
+//   cargo +nightly fuzz run build_and_evaluate
+
+#![no_main]
+
+use entendre::*;
+use libfuzzer_sys::fuzz_target;
+
+struct Bytes<'a>(&'a [u8]);
+
+impl Bytes<'_> {
+    fn u8(&mut self) -> Option<u8> {
+        let (x, rest) = self.0.split_first()?;
+        self.0 = rest;
+        Some(*x)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        let mut bytes = [0; 4];
+        for byte in &mut bytes {
+            *byte = self.u8()?;
+        }
+        Some(u32::from_le_bytes(bytes))
+    }
+
+    fn f32(&mut self) -> Option<f32> {
+        self.u32().map(f32::from_bits)
+    }
+
+    fn bool(&mut self) -> Option<bool> {
+        Some(self.u8()? & 1 == 1)
+    }
+}
+
+fn func(bytes: &mut Bytes) -> Option<ActivationFunction> {
+    use ActivationFunction::*;
+    Some(match bytes.u8()? % 18 {
+        0 => Sigmoid,
+        1 => Identity,
+        2 => Tanh,
+        3 => Relu,
+        4 => Gaussian,
+        5 => Sin,
+        6 => Cos,
+        7 => Abs,
+        8 => Square,
+        9 => LeakyRelu(bytes.f32()?),
+        10 => Elu(bytes.f32()?),
+        11 => SteepenedSigmoid(bytes.f32()?),
+        12 => Step,
+        13 => Softplus,
+        14 => Sinc,
+        15 => Clamped,
+        16 => Inverse,
+        _ => Log,
+    })
+}
+
+fn aggregation(bytes: &mut Bytes) -> Option<AggregationFunction> {
+    use AggregationFunction::*;
+    Some(match bytes.u8()? % 6 {
+        0 => Sum,
+        1 => Product,
+        2 => Min,
+        3 => Max,
+        4 => Mean,
+        _ => AbsSum,
+    })
+}
+
+fn node_type(bytes: &mut Bytes) -> Option<NodeType> {
+    Some(match bytes.u8()? % 4 {
+        0 => NodeType::Bias,
+        1 => NodeType::Input,
+        2 => NodeType::Output,
+        _ => NodeType::Hidden,
+    })
+}
+
+// Applies one builder call, or returns `None` once out of bytes.
+fn step(builder: &mut NeuralNetBuilder, bytes: &mut Bytes) -> Option<()> {
+    match bytes.u8()? % 10 {
+        0 => {
+            builder.add_node(node_type(bytes)?, func(bytes)?);
+        }
+        1 => {
+            let connection_type = if bytes.bool()? {
+                ConnectionType::Recurrent
+            } else {
+                ConnectionType::Normal
+            };
+            let (origin, dest) = (bytes.u8()? as u32, bytes.u8()? as u32);
+            builder.add_connection_raw(
+                origin,
+                dest,
+                bytes.f32()?,
+                connection_type,
+            );
+        }
+        2 => {
+            let index = bytes.u8()? as usize;
+            let (bias, response) = (bytes.f32()?, bytes.f32()?);
+            let aggregation = aggregation(bytes)?;
+            if let Some(node) = builder.nodes.get_mut(index) {
+                node.bias = bias;
+                node.response = response;
+                node.aggregation = aggregation;
+            }
+        }
+        3 => {
+            let memory = MemoryCell {
+                memory_weight: bytes.f32()?,
+                ..MemoryCell::default()
+            };
+            builder.set_memory(NodeId(bytes.u8()? as u32), Some(memory));
+        }
+        4 => {
+            let rule = HebbianRule {
+                learning_rate: bytes.f32()?,
+                a: bytes.f32()?,
+                ..HebbianRule::default()
+            };
+            builder.set_plasticity(bytes.u8()? as usize, Some(rule));
+        }
+        5 => {
+            let enabled = bytes.bool()?;
+            builder.set_enabled(bytes.u8()? as usize, enabled);
+        }
+        6 => {
+            builder.set_output_transform(match bytes.u8()? % 3 {
+                0 => OutputTransform::Identity,
+                1 => OutputTransform::Softmax,
+                _ => OutputTransform::ArgMax,
+            });
+        }
+        7 => {
+            builder.set_propagation(if bytes.bool()? {
+                Propagation::Passes(bytes.u8()? as u32)
+            } else {
+                Propagation::UntilStable {
+                    epsilon: bytes.f32()?,
+                    max_passes: bytes.u8()? as u32,
+                }
+            });
+        }
+        8 => {
+            builder.set_duplicate_policy(match bytes.u8()? % 3 {
+                0 => DuplicatePolicy::Allow,
+                1 => DuplicatePolicy::Reject,
+                _ => DuplicatePolicy::Merge,
+            });
+            builder.set_bias_value(bytes.f32()?);
+        }
+        _ => {
+            let bounds = WeightBounds {
+                min: bytes.f32()?,
+                max: bytes.f32()?,
+                policy: match bytes.u8()? % 3 {
+                    0 => BoundsPolicy::Clamp,
+                    1 => BoundsPolicy::Reject,
+                    _ => BoundsPolicy::Wrap,
+                },
+            };
+            builder.set_weight_bounds(Some(bounds));
+        }
+    }
+    Some(())
+}
+
+fn evaluate<N: NeuralNet>(builder: &NeuralNetBuilder, inputs: &[f32]) {
+    if let Ok(mut net) = builder.build::<N>() {
+        let _ = net.evaluate(inputs);
+        let _ = net.try_evaluate(inputs);
+        let _ = net.evaluate_batch(&[inputs.to_vec(), inputs.to_vec()]);
+        let _ = net.set_weight(0, 1.0);
+        net.reset_state();
+    }
+}
+
+fuzz_target!(|data: &[u8]| {
+    // The first byte is the number of builder calls, and whatever is
+    // left after them is the inputs.
+    let mut bytes = Bytes(data);
+    let num_steps = bytes.u8().unwrap_or(0);
+    let mut builder = NeuralNetBuilder::new();
+    for _ in 0..num_steps {
+        if step(&mut builder, &mut bytes).is_none() {
+            break;
+        }
+    }
+    let inputs = bytes
+        .0
+        .chunks_exact(4)
+        .map(|x| f32::from_le_bytes([x[0], x[1], x[2], x[3]]))
+        .collect::<Vec<_>>();
+
+    evaluate::<ConsecutiveNeuralNet>(&builder, &inputs);
+    evaluate::<LayeredNeuralNet>(&builder, &inputs);
+    evaluate::<SparseNeuralNet>(&builder, &inputs);
+    evaluate::<QuantizedNeuralNet>(&builder, &inputs);
+});
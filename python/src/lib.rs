@@ -0,0 +1,296 @@
+// Python bindings for building, evaluating and evolving networks, as
+// the `entendre` module, built with maturin.  Networks use the
+// consecutive backend.  Batches of inputs can be given as anything
+// numpy can convert to a 2D array of floats, one row per input.
+//
+// Errors raise ValueError, or IndexError for genome indices out of
+// range.
+
+use numpy::ndarray::Array2;
+use numpy::{
+    AllowTypeChange, IntoPyArray, PyArray2, PyArrayLike1, PyArrayLike2,
+};
+use pyo3::exceptions::{PyIndexError, PyValueError};
+use pyo3::prelude::*;
+
+use entendre::{
+    ActivationFunction, ConsecutiveNeuralNet, Error, NeuralNet as _, NodeType,
+    PopulationConfig,
+};
+
+fn value_error(err: Error) -> PyErr {
+    PyValueError::new_err(err.to_string())
+}
+
+fn activation(name: &str) -> PyResult<ActivationFunction> {
+    ActivationFunction::from_name(name).ok_or_else(|| {
+        PyValueError::new_err(format!("Unknown activation function {}", name))
+    })
+}
+
+fn node_type(name: &str) -> PyResult<NodeType> {
+    use NodeType::*;
+    match name {
+        "bias" => Ok(Bias),
+        "input" => Ok(Input),
+        "output" => Ok(Output),
+        "hidden" => Ok(Hidden),
+        _ => Err(PyValueError::new_err(format!("Unknown node type {}", name))),
+    }
+}
+
+#[pyclass(name = "NeuralNet", skip_from_py_object)]
+#[derive(Debug, Clone)]
+pub struct PyNeuralNet {
+    net: ConsecutiveNeuralNet,
+}
+
+#[pymethods]
+impl PyNeuralNet {
+    #[getter]
+    fn num_inputs(&self) -> usize {
+        self.net.num_inputs()
+    }
+
+    #[getter]
+    fn num_outputs(&self) -> usize {
+        self.net.num_outputs()
+    }
+
+    fn evaluate(
+        &mut self,
+        inputs: PyArrayLike1<f32, AllowTypeChange>,
+    ) -> PyResult<Vec<f32>> {
+        let inputs = inputs.as_array().to_vec();
+        self.net.try_evaluate(&inputs).map_err(value_error)
+    }
+
+    // Evaluates each row in turn, without holding the GIL, and returns
+    // the outputs as the rows of an array.
+    fn evaluate_batch<'py>(
+        &mut self,
+        py: Python<'py>,
+        inputs: PyArrayLike2<'py, f32, AllowTypeChange>,
+    ) -> PyResult<Bound<'py, PyArray2<f32>>> {
+        let inputs = inputs.as_array();
+        let (num_rows, num_columns) = inputs.dim();
+        if num_columns != self.net.num_inputs() {
+            return Err(value_error(Error::InputSizeMismatch {
+                expected: self.net.num_inputs(),
+                got: num_columns,
+            }));
+        }
+        let rows = inputs
+            .outer_iter()
+            .map(|row| row.to_vec())
+            .collect::<Vec<_>>();
+        let net = &mut self.net;
+        let outputs = py.detach(|| net.evaluate_batch(&rows));
+        let num_outputs = self.net.num_outputs();
+        let outputs = Array2::from_shape_vec(
+            (num_rows, num_outputs),
+            outputs.into_iter().flatten().collect(),
+        )
+        .map_err(|err| PyValueError::new_err(err.to_string()))?;
+        Ok(outputs.into_pyarray(py))
+    }
+
+    fn reset_state(&mut self) {
+        self.net.reset_state();
+    }
+}
+
+#[pyclass(name = "NeuralNetBuilder", skip_from_py_object)]
+#[derive(Debug, Clone, Default)]
+pub struct PyNeuralNetBuilder {
+    builder: entendre::NeuralNetBuilder,
+}
+
+#[pymethods]
+impl PyNeuralNetBuilder {
+    #[new]
+    fn new() -> Self {
+        Self::default()
+    }
+
+    // Applies to nodes added afterwards.
+    fn set_default_activation(&mut self, name: &str) -> PyResult<()> {
+        self.builder.set_default_activation(activation(name)?);
+        Ok(())
+    }
+
+    fn add_nodes(&mut self, node_type_name: &str, n: u32) -> PyResult<()> {
+        self.builder.add_nodes(node_type(node_type_name)?, n);
+        Ok(())
+    }
+
+    #[pyo3(signature = (origin, dest, weight, recurrent = false))]
+    fn add_connection(
+        &mut self,
+        origin: u32,
+        dest: u32,
+        weight: f32,
+        recurrent: bool,
+    ) {
+        match recurrent {
+            true => self
+                .builder
+                .add_recurrent_connection_raw(origin, dest, weight),
+            false => {
+                self.builder.add_normal_connection_raw(origin, dest, weight)
+            }
+        };
+    }
+
+    fn build(&mut self) -> PyResult<PyNeuralNet> {
+        let net = self.builder.build().map_err(value_error)?;
+        Ok(PyNeuralNet { net })
+    }
+}
+
+// Reporters aren't `Sync`, so populations stay on the thread that
+// made them.
+#[pyclass(name = "Population", unsendable)]
+#[derive(Debug)]
+pub struct PyPopulation {
+    population: entendre::Population,
+}
+
+impl PyPopulation {
+    fn build(genome: &entendre::Genome) -> PyResult<PyNeuralNet> {
+        let net = genome.to_builder().build().map_err(value_error)?;
+        Ok(PyNeuralNet { net })
+    }
+}
+
+#[pymethods]
+impl PyPopulation {
+    // Uses the default configuration apart from the given sizes, with
+    // a random seed unless one is given.
+    #[new]
+    #[pyo3(signature = (num_inputs, num_outputs, population_size = 150, seed = None))]
+    fn new(
+        num_inputs: u32,
+        num_outputs: u32,
+        population_size: usize,
+        seed: Option<u64>,
+    ) -> Self {
+        let population = entendre::Population::new(PopulationConfig {
+            num_inputs,
+            num_outputs,
+            population_size,
+            seed,
+            ..PopulationConfig::default()
+        });
+        Self { population }
+    }
+
+    // Reads the configuration from a NEAT-Python config file.
+    #[staticmethod]
+    #[pyo3(signature = (text, seed = None))]
+    fn from_neat_python(text: &str, seed: Option<u64>) -> PyResult<Self> {
+        let config = PopulationConfig {
+            seed,
+            ..PopulationConfig::from_neat_python(text).map_err(value_error)?
+        };
+        Ok(Self {
+            population: entendre::Population::new(config),
+        })
+    }
+
+    #[getter]
+    fn generation(&self) -> usize {
+        self.population.generation()
+    }
+
+    fn __len__(&self) -> usize {
+        self.population.genomes().len()
+    }
+
+    // Network of the genome at `index` of the current generation.
+    fn network(&self, index: usize) -> PyResult<PyNeuralNet> {
+        let genome = self.population.genomes().get(index).ok_or_else(|| {
+            PyIndexError::new_err("Genome index out of range")
+        })?;
+        Self::build(genome)
+    }
+
+    fn networks(&self) -> PyResult<Vec<PyNeuralNet>> {
+        self.population.genomes().iter().map(Self::build).collect()
+    }
+
+    // Best network so far and its fitness, if any generation has been
+    // evaluated.
+    fn champion(&self) -> PyResult<Option<(PyNeuralNet, f32)>> {
+        self.population
+            .champion()
+            .map(|(genome, fitness)| Ok((Self::build(genome)?, fitness)))
+            .transpose()
+    }
+
+    // Moves to the next generation given the fitness of each network
+    // of the current one, in the order of `networks`.
+    fn advance_generation(
+        &mut self,
+        fitnesses: PyArrayLike1<f32, AllowTypeChange>,
+    ) -> PyResult<()> {
+        let fitnesses = fitnesses.as_array().to_vec();
+        if fitnesses.len() != self.population.genomes().len() {
+            return Err(PyValueError::new_err(
+                "Expected one fitness per genome",
+            ));
+        }
+        self.population.advance_generation(&fitnesses);
+        Ok(())
+    }
+
+    // Scores each network of the current generation with `fitness`, a
+    // callable taking a network and returning a number, then moves to
+    // the next generation.  Returns the fitnesses.
+    fn evolve(&mut self, fitness: &Bound<'_, PyAny>) -> PyResult<Vec<f32>> {
+        let fitnesses = self
+            .networks()?
+            .into_iter()
+            .map(|net| fitness.call1((net,))?.extract::<f32>())
+            .collect::<PyResult<Vec<_>>>()?;
+        self.population.advance_generation(&fitnesses);
+        Ok(fitnesses)
+    }
+}
+
+#[pymodule]
+#[pyo3(name = "entendre")]
+fn entendre_python(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyNeuralNet>()?;
+    m.add_class::<PyNeuralNetBuilder>()?;
+    m.add_class::<PyPopulation>()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pyo3::ffi::c_str;
+
+    #[test]
+    fn test_bindings() -> PyResult<()> {
+        Python::attach(|py| {
+            let mut builder = PyNeuralNetBuilder::new();
+            builder.add_nodes("input", 2)?;
+            builder.add_nodes("output", 1)?;
+            builder.add_connection(0, 2, 1.0, false);
+            let net = builder.build()?;
+            assert_eq!((net.num_inputs(), net.num_outputs()), (2, 1));
+            assert!(builder.add_nodes("sensor", 1).is_err());
+
+            let mut population = PyPopulation::new(2, 1, 10, Some(0));
+            let fitness =
+                py.eval(c_str!("lambda net: net.num_inputs / 2"), None, None)?;
+            assert_eq!(population.evolve(&fitness)?, vec![1.0; 10]);
+            assert_eq!(population.generation(), 1);
+            assert!(population.champion()?.is_some());
+            assert!(population.network(10).is_err());
+            Ok(())
+        })
+    }
+}
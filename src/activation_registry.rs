@@ -0,0 +1,138 @@
+use std::collections::BTreeMap;
+use std::sync::{RwLock, RwLockReadGuard};
+
+use crate::neural_net::*;
+
+type Registry = BTreeMap<String, fn(f32) -> f32>;
+
+// Custom activation functions by name, shared by the whole program so
+// that anything reading a saved genome or config can find them.
+static CUSTOM_ACTIVATIONS: RwLock<Registry> = RwLock::new(BTreeMap::new());
+
+fn registry() -> RwLockReadGuard<'static, Registry> {
+    // The map is never left half-updated, so a panic elsewhere while
+    // holding the lock doesn't invalidate it.
+    CUSTOM_ACTIVATIONS
+        .read()
+        .unwrap_or_else(|err| err.into_inner())
+}
+
+// Names a custom activation function, so that `ActivationFunction`'s
+// `name` and `from_name` can convert it, and so that it can be saved
+// in checkpoints and serialized.  Registering a name again replaces
+// its function.  Every program that loads the saved function must
+// register it under the same name first.
+//
+// Panics if `name` is the name of a built-in function or has
+// parentheses, which would make it ambiguous.
+pub fn register_activation(name: &str, func: fn(f32) -> f32) {
+    assert!(
+        !name.contains(['(', ')']),
+        "Activation function name {:?} has parentheses",
+        name
+    );
+    let builtin = ActivationFunction::from_name(name).is_some_and(|existing| {
+        !matches!(existing, ActivationFunction::Custom(_))
+    });
+    assert!(!builtin, "{:?} is a built-in activation function", name);
+    CUSTOM_ACTIVATIONS
+        .write()
+        .unwrap_or_else(|err| err.into_inner())
+        .insert(name.to_owned(), func);
+}
+
+// Names of every registered custom activation function, in order.
+pub fn registered_activations() -> Vec<String> {
+    registry().keys().cloned().collect()
+}
+
+pub(crate) fn custom(name: &str) -> Option<fn(f32) -> f32> {
+    registry().get(name).cloned()
+}
+
+// Function pointers compare by address, the same as for
+// `ActivationFunction`'s `PartialEq`.
+#[allow(unpredictable_function_pointer_comparisons)]
+pub(crate) fn custom_name(func: fn(f32) -> f32) -> Option<String> {
+    registry()
+        .iter()
+        .find(|(_, f)| **f == func)
+        .map(|(name, _)| name.clone())
+}
+
+#[cfg(feature = "serde")]
+pub(crate) fn serialize_custom<S: serde::Serializer>(
+    func: &fn(f32) -> f32,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    use serde::ser::Error;
+    let name = custom_name(*func).ok_or_else(|| {
+        S::Error::custom("Custom activation function isn't registered")
+    })?;
+    serializer.serialize_str(&name)
+}
+
+#[cfg(feature = "serde")]
+pub(crate) fn deserialize_custom<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<fn(f32) -> f32, D::Error> {
+    use serde::de::Error;
+    use serde::Deserialize;
+    let name = String::deserialize(deserializer)?;
+    custom(&name).ok_or_else(|| {
+        D::Error::custom(format!(
+            "Unknown custom activation function {:?}",
+            name
+        ))
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn cube(x: f32) -> f32 {
+        x * x * x
+    }
+
+    #[test]
+    fn test_activation_names() {
+        use ActivationFunction::*;
+        let funcs = [
+            Sigmoid,
+            Identity,
+            Tanh,
+            Relu,
+            Gaussian,
+            Sin,
+            Cos,
+            Abs,
+            Square,
+            LeakyRelu(0.01),
+            Elu(0.5),
+            SteepenedSigmoid(4.924273),
+            Step,
+            Softplus,
+            Sinc,
+            Clamped,
+            Inverse,
+            Log,
+        ];
+        funcs.iter().for_each(|func| {
+            let name = func.name().unwrap();
+            assert_eq!(ActivationFunction::from_name(&name), Some(*func));
+        });
+        assert_eq!(LeakyRelu(0.25).name().unwrap(), "leaky_relu(0.25)");
+        assert_eq!(ActivationFunction::from_name("elu"), Some(Elu(1.0)));
+        assert_eq!(ActivationFunction::from_name("tanh(2)"), None);
+        assert_eq!(ActivationFunction::from_name("elu(x)"), None);
+
+        assert_eq!(Custom(cube).name(), None);
+        assert_eq!(ActivationFunction::from_name("test_cube"), None);
+        register_activation("test_cube", cube);
+        assert_eq!(Custom(cube).name().unwrap(), "test_cube");
+        let func = ActivationFunction::from_name("test_cube").unwrap();
+        assert_eq!(func.apply(2.0), 8.0);
+        assert!(registered_activations().contains(&"test_cube".to_owned()));
+    }
+}
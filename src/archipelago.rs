@@ -0,0 +1,317 @@
+use rand::seq::index::sample;
+
+use crate::environment::*;
+use crate::genome::*;
+use crate::neural_net::*;
+use crate::population::*;
+use crate::selection::*;
+
+// Which islands send migrants to which.
+#[derive(Debug, PartialEq, Clone)]
+pub enum MigrationTopology {
+    // Each island to the next, and the last to the first.
+    Ring,
+    // Each island to every other island.
+    Complete,
+    // Pairs of source and destination islands.
+    Custom(Vec<(usize, usize)>),
+}
+
+impl MigrationTopology {
+    // Pairs of source and destination among `num_islands` islands,
+    // without any from an island to itself.
+    pub fn routes(&self, num_islands: usize) -> Vec<(usize, usize)> {
+        use MigrationTopology::*;
+        let routes = match self {
+            Ring => (0..num_islands)
+                .map(|from| (from, (from + 1) % num_islands))
+                .collect(),
+            Complete => (0..num_islands)
+                .flat_map(|from| (0..num_islands).map(move |to| (from, to)))
+                .collect(),
+            Custom(routes) => routes
+                .iter()
+                .cloned()
+                .filter(|(from, to)| *from < num_islands && *to < num_islands)
+                .collect::<Vec<_>>(),
+        };
+        routes.into_iter().filter(|(from, to)| from != to).collect()
+    }
+}
+
+// How the genomes sent from an island are chosen.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum MigrantSelection {
+    // The fittest genomes.
+    Best,
+    // Genomes picked as parents would be, so possibly more than once.
+    Select(SelectionStrategy),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct ArchipelagoConfig {
+    pub num_islands: usize,
+    // Configuration of every island.  A seed is offset by the index
+    // of the island, so that islands don't evolve identically.
+    pub island: PopulationConfig,
+    pub topology: MigrationTopology,
+    // Number of generations between migrations, with none at zero.
+    pub migration_interval: usize,
+    // Number of genomes sent along each route.
+    pub num_migrants: usize,
+    pub migrant_selection: MigrantSelection,
+}
+
+impl Default for ArchipelagoConfig {
+    fn default() -> Self {
+        Self {
+            num_islands: 4,
+            island: PopulationConfig::default(),
+            topology: MigrationTopology::Ring,
+            migration_interval: 10,
+            num_migrants: 2,
+            migrant_selection: MigrantSelection::Best,
+        }
+    }
+}
+
+// Several populations evolving apart, with the fittest genomes of
+// each occasionally copied to others, which keeps more diversity
+// than a single population of the same total size.  The islands share
+// their innovation numbers, so that migrants line up with the genes
+// of their new island in crossover.
+#[derive(Debug)]
+pub struct Archipelago {
+    pub config: ArchipelagoConfig,
+    islands: Vec<Population>,
+    tracker: InnovationTracker,
+}
+
+impl Archipelago {
+    pub fn new(config: ArchipelagoConfig) -> Self {
+        let islands = (0..config.num_islands)
+            .map(|i| {
+                Population::new(PopulationConfig {
+                    seed: config.island.seed.map(|s| s.wrapping_add(i as u64)),
+                    ..config.island.clone()
+                })
+            })
+            .collect::<Vec<_>>();
+        // Every island starts from the same genes, numbered alike.
+        let tracker = islands
+            .first()
+            .map(|island| island.tracker.clone())
+            .unwrap_or_default();
+        Self {
+            config,
+            islands,
+            tracker,
+        }
+    }
+
+    pub fn islands(&self) -> &[Population] {
+        &self.islands
+    }
+
+    // For adding reporters and the like.  Replacing genomes of an
+    // island with genomes from elsewhere breaks the shared innovation
+    // numbers.
+    pub fn islands_mut(&mut self) -> &mut [Population] {
+        &mut self.islands
+    }
+
+    pub fn generation(&self) -> usize {
+        self.islands.first().map_or(0, |island| island.generation())
+    }
+
+    // Best genome seen so far on any island, along with its fitness.
+    pub fn champion(&self) -> Option<(&Genome, f32)> {
+        self.islands
+            .iter()
+            .filter_map(|island| island.champion())
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+    }
+
+    // Fitness of each genome of each island.
+    pub fn evaluate<N, F>(&self, fitness: &F) -> Result<Vec<Vec<f32>>, Error>
+    where
+        N: NeuralNet + Send,
+        F: FitnessFunction<N> + Sync,
+    {
+        self.islands
+            .iter()
+            .map(|island| island.evaluate(fitness))
+            .collect()
+    }
+
+    // Advances every island, given the fitnesses of `evaluate`, then
+    // sends migrants if it is time to.  Migrants are chosen from the
+    // evaluated generation and replace randomly chosen genomes of the
+    // next.
+    pub fn advance_generation(&mut self, fitnesses: &[Vec<f32>]) {
+        assert_eq!(
+            fitnesses.len(),
+            self.islands.len(),
+            "Expected the fitnesses of every island"
+        );
+        let interval = self.config.migration_interval;
+        let (num_migrants, selection) =
+            (self.config.num_migrants, self.config.migrant_selection);
+        let migrating = interval > 0
+            && num_migrants > 0
+            && (self.generation() + 1).is_multiple_of(interval);
+        let emigrants = match migrating {
+            true => self
+                .islands
+                .iter_mut()
+                .zip(fitnesses)
+                .map(|(island, fitnesses)| {
+                    island.select_migrants(fitnesses, num_migrants, selection)
+                })
+                .collect(),
+            false => Vec::new(),
+        };
+
+        let tracker = &mut self.tracker;
+        self.islands.iter_mut().zip(fitnesses).for_each(
+            |(island, fitnesses)| {
+                std::mem::swap(&mut island.tracker, tracker);
+                island.advance_generation(fitnesses);
+                std::mem::swap(&mut island.tracker, tracker);
+            },
+        );
+
+        if migrating {
+            let routes = self.config.topology.routes(self.islands.len());
+            self.islands.iter_mut().enumerate().for_each(|(i, island)| {
+                let immigrants = routes
+                    .iter()
+                    .filter(|(_, to)| *to == i)
+                    .flat_map(|(from, _)| emigrants[*from].iter().cloned())
+                    .collect::<Vec<_>>();
+                island.immigrate(immigrants);
+            });
+        }
+    }
+
+    // Evaluates every island and advances, returning the fitnesses of
+    // the evaluated generation.
+    pub fn run_generation<N, F>(
+        &mut self,
+        fitness: &F,
+    ) -> Result<Vec<Vec<f32>>, Error>
+    where
+        N: NeuralNet + Send,
+        F: FitnessFunction<N> + Sync,
+    {
+        let fitnesses = self.evaluate(fitness)?;
+        self.advance_generation(&fitnesses);
+        Ok(fitnesses)
+    }
+}
+
+impl Population {
+    fn select_migrants(
+        &mut self,
+        fitnesses: &[f32],
+        num_migrants: usize,
+        selection: MigrantSelection,
+    ) -> Vec<Genome> {
+        let mut ranked = (0..self.genomes.len()).collect::<Vec<_>>();
+        ranked.sort_by(|a, b| fitnesses[*b].total_cmp(&fitnesses[*a]));
+        let chosen = match selection {
+            MigrantSelection::Best => {
+                ranked.iter().take(num_migrants).cloned().collect()
+            }
+            MigrantSelection::Select(_) if ranked.is_empty() => Vec::new(),
+            MigrantSelection::Select(strategy) => (0..num_migrants)
+                .map(|_| strategy.select(&ranked, fitnesses, &mut self.rng))
+                .collect::<Vec<_>>(),
+        };
+        chosen.iter().map(|i| self.genomes[*i].clone()).collect()
+    }
+
+    // Replaces randomly chosen genomes of the current generation with
+    // `migrants`, and sorts them into species again.  Migrants start a
+    // new line in the lineage, since their parents live elsewhere.
+    fn immigrate(&mut self, migrants: Vec<Genome>) {
+        if migrants.is_empty() {
+            return;
+        }
+        let amount = migrants.len().min(self.genomes.len());
+        let positions = sample(&mut self.rng, self.genomes.len(), amount);
+        positions.iter().zip(migrants).for_each(|(i, migrant)| {
+            self.genomes[i] = migrant;
+            if self.config.track_lineage {
+                self.genome_ids[i] = self.lineage.record(
+                    self.generation,
+                    Vec::new(),
+                    Vec::new(),
+                );
+            }
+        });
+        self.speciator.speciate(&self.genomes, &mut self.rng);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::*;
+
+    #[test]
+    fn test_routes() {
+        assert_eq!(
+            MigrationTopology::Ring.routes(3),
+            vec![(0, 1), (1, 2), (2, 0)]
+        );
+        assert_eq!(MigrationTopology::Complete.routes(2), vec![(0, 1), (1, 0)]);
+        assert_eq!(MigrationTopology::Ring.routes(1), vec![]);
+        let custom = MigrationTopology::Custom(vec![(0, 2), (1, 1), (0, 5)]);
+        assert_eq!(custom.routes(3), vec![(0, 2)]);
+    }
+
+    #[test]
+    fn test_migration() -> Result<(), Error> {
+        let config = ArchipelagoConfig {
+            num_islands: 3,
+            island: PopulationConfig {
+                population_size: 12,
+                num_inputs: 2,
+                num_outputs: 1,
+                seed: Some(11),
+                ..PopulationConfig::default()
+            },
+            migration_interval: 2,
+            num_migrants: 1,
+            ..ArchipelagoConfig::default()
+        };
+        let mut archipelago = Archipelago::new(config);
+        let fitness =
+            |net: &mut ConsecutiveNeuralNet| net.evaluate(&[1.0, 0.5])[0];
+
+        archipelago.run_generation(&fitness)?;
+        let fitnesses = archipelago.evaluate(&fitness)?;
+        // The fittest genome of each island moves to the next.
+        let best = archipelago
+            .islands()
+            .iter()
+            .zip(&fitnesses)
+            .map(|(island, fitnesses)| {
+                let i = (0..fitnesses.len())
+                    .max_by(|a, b| fitnesses[*a].total_cmp(&fitnesses[*b]))
+                    .unwrap();
+                island.genomes()[i].clone()
+            })
+            .collect::<Vec<_>>();
+        archipelago.advance_generation(&fitnesses);
+
+        assert_eq!(archipelago.generation(), 2);
+        (0..3).for_each(|i| {
+            let island = &archipelago.islands()[(i + 1) % 3];
+            assert!(island.genomes().contains(&best[i]));
+        });
+        assert!(archipelago.champion().is_some());
+        Ok(())
+    }
+}
@@ -0,0 +1,120 @@
+use std::future::Future;
+
+use tokio::task::JoinSet;
+
+use crate::neural_net::*;
+use crate::population::*;
+
+// A fitness function for IO-bound evaluations, such as ones that wait
+// on a remote simulator, which are run as tokio tasks rather than
+// each holding a thread.  The network is moved into the future, which
+// must not borrow from its surroundings.
+pub trait AsyncFitnessFunction<N> {
+    type Future: Future<Output = f32> + Send + 'static;
+
+    fn evaluate(&self, net: N) -> Self::Future;
+}
+
+impl<N, F, Fut> AsyncFitnessFunction<N> for F
+where
+    F: Fn(N) -> Fut,
+    Fut: Future<Output = f32> + Send + 'static,
+{
+    type Future = Fut;
+
+    fn evaluate(&self, net: N) -> Fut {
+        self(net)
+    }
+}
+
+impl Population {
+    // Same as `evaluate`, but with at most `max_concurrent` of the
+    // evaluations running at once, spawned on the current tokio
+    // runtime.  Must be awaited from within a runtime.  A panic in an
+    // evaluation is resumed here.
+    pub async fn evaluate_async<N, F>(
+        &self,
+        fitness: &F,
+        max_concurrent: usize,
+    ) -> Result<Vec<f32>, Error>
+    where
+        N: NeuralNet + Send + 'static,
+        F: AsyncFitnessFunction<N>,
+    {
+        let nets = self
+            .genomes()
+            .iter()
+            .map(|genome| genome.to_builder().build::<N>())
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut fitnesses = vec![0.0; nets.len()];
+        let mut pending = nets.into_iter().enumerate();
+        let mut tasks = JoinSet::new();
+        loop {
+            while tasks.len() < max_concurrent.max(1) {
+                match pending.next() {
+                    Some((i, net)) => {
+                        let future = fitness.evaluate(net);
+                        tasks.spawn(async move { (i, future.await) });
+                    }
+                    None => break,
+                }
+            }
+            match tasks.join_next().await {
+                Some(Ok((i, value))) => fitnesses[i] = value,
+                Some(Err(err)) => std::panic::resume_unwind(err.into_panic()),
+                None => return Ok(fitnesses),
+            }
+        }
+    }
+
+    // Same as `run_generation`, but evaluating with `evaluate_async`.
+    pub async fn run_generation_async<N, F>(
+        &mut self,
+        fitness: &F,
+        max_concurrent: usize,
+    ) -> Result<Vec<f32>, Error>
+    where
+        N: NeuralNet + Send + 'static,
+        F: AsyncFitnessFunction<N>,
+    {
+        let fitnesses = self.evaluate_async(fitness, max_concurrent).await?;
+        self.advance_generation(&fitnesses);
+        Ok(fitnesses)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::*;
+
+    #[test]
+    fn test_async_evaluation() -> Result<(), Error> {
+        let config = PopulationConfig {
+            population_size: 10,
+            num_inputs: 2,
+            num_outputs: 1,
+            seed: Some(5),
+            ..PopulationConfig::default()
+        };
+        let mut population = Population::new(config);
+        let fitness =
+            |net: &mut ConsecutiveNeuralNet| net.evaluate(&[1.0, -1.0])[0];
+        let expected = population.evaluate(&fitness)?;
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let fitnesses = runtime.block_on(population.run_generation_async(
+            &|mut net: ConsecutiveNeuralNet| async move {
+                tokio::task::yield_now().await;
+                fitness(&mut net)
+            },
+            3,
+        ))?;
+        assert_eq!(fitnesses, expected);
+        assert_eq!(population.generation(), 1);
+        Ok(())
+    }
+}
@@ -0,0 +1,484 @@
+use crate::neural_net::*;
+use crate::neural_net_layered::{layer_order, node_depths};
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Optimizer {
+    // Stochastic gradient descent, where each step moves along a
+    // velocity that keeps `momentum` of the previous step.
+    Sgd {
+        learning_rate: f32,
+        momentum: f32,
+    },
+    // Adam, after Kingma and Ba, with bias-corrected moment estimates.
+    Adam {
+        learning_rate: f32,
+        beta1: f32,
+        beta2: f32,
+        epsilon: f32,
+    },
+}
+
+impl Optimizer {
+    pub fn sgd(learning_rate: f32) -> Self {
+        Optimizer::Sgd {
+            learning_rate,
+            momentum: 0.0,
+        }
+    }
+
+    pub fn adam(learning_rate: f32) -> Self {
+        Optimizer::Adam {
+            learning_rate,
+            beta1: 0.9,
+            beta2: 0.999,
+            epsilon: 1e-8,
+        }
+    }
+}
+
+impl Default for Optimizer {
+    fn default() -> Self {
+        Self::adam(0.01)
+    }
+}
+
+// What an optimizer keeps between steps, for weights that are
+// always given in the same order.
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) struct OptimizerState {
+    // Per weight, the velocity for SGD, or both moment estimates for
+    // Adam.
+    first_moments: Vec<f32>,
+    second_moments: Vec<f32>,
+    steps: i32,
+}
+
+impl OptimizerState {
+    pub(crate) fn new(num_weights: usize) -> Self {
+        Self {
+            first_moments: vec![0.0; num_weights],
+            second_moments: vec![0.0; num_weights],
+            steps: 0,
+        }
+    }
+
+    // Moves the weights against their gradient, so as to minimize.
+    pub(crate) fn step<'a>(
+        &mut self,
+        optimizer: &Optimizer,
+        weights: impl Iterator<Item = &'a mut f32>,
+        grads: &[f32],
+    ) {
+        self.steps += 1;
+        let moments =
+            self.first_moments.iter_mut().zip(&mut self.second_moments);
+        match *optimizer {
+            Optimizer::Sgd {
+                learning_rate,
+                momentum,
+            } => weights.zip(moments).zip(grads).for_each(
+                |((weight, (velocity, _)), grad)| {
+                    *velocity = momentum * *velocity - learning_rate * grad;
+                    *weight += *velocity;
+                },
+            ),
+            Optimizer::Adam {
+                learning_rate,
+                beta1,
+                beta2,
+                epsilon,
+            } => {
+                let correction1 = 1.0 - beta1.powi(self.steps);
+                let correction2 = 1.0 - beta2.powi(self.steps);
+                weights.zip(moments).zip(grads).for_each(
+                    |((weight, (m, v)), grad)| {
+                        *m = beta1 * *m + (1.0 - beta1) * grad;
+                        *v = beta2 * *v + (1.0 - beta2) * grad * grad;
+                        let m_hat = *m / correction1;
+                        let v_hat = *v / correction2;
+                        *weight -=
+                            learning_rate * m_hat / (v_hat.sqrt() + epsilon);
+                    },
+                );
+            }
+        }
+    }
+}
+
+// Trains the connection weights of a feed-forward network by
+// backpropagation, minimizing half the squared error between its
+// outputs and the targets.  Only networks whose nodes all sum their
+// inputs can be trained, through activation functions with a known
+// derivative.  The output transform isn't trained through, so
+// outputs are compared before it is applied.
+//
+// The weights are those of the builder, indexed like its
+// connections, which can be copied back into a genome with
+// `Genome::set_weights_from` for Lamarckian evolution.
+#[derive(Debug, Clone)]
+pub struct Trainer {
+    builder: NeuralNetBuilder,
+    // Enabled connections, with duplicates merged and weights bounded
+    // as the backends do, which are what is trained.
+    connections: Vec<ConnectionTemplate>,
+    slots: ConnectionSlots,
+    pub optimizer: Optimizer,
+    // Nodes such that every connection comes from an earlier node.
+    order: Vec<usize>,
+    // Indices of the connections into each node.
+    incoming: Vec<Vec<usize>>,
+    input_indices: Vec<usize>,
+    output_indices: Vec<usize>,
+    state: OptimizerState,
+}
+
+// Value of each node before and after its activation function.
+struct Forward {
+    sums: Vec<f32>,
+    activations: Vec<f32>,
+}
+
+impl Trainer {
+    pub fn new(
+        builder: NeuralNetBuilder,
+        optimizer: Optimizer,
+    ) -> Result<Self, Error> {
+        let resolved = builder.resolve_connections()?;
+        let (order, incoming) = Self::topology(&resolved)
+            .map_err(|error| resolved.original_error(error))?;
+        let connections = resolved.connections.clone();
+        let slots = resolved.slots.clone();
+        drop(resolved);
+
+        let indices_of = |node_type: NodeType| {
+            (0..builder.nodes.len())
+                .filter(|i| builder.nodes[*i].node_type == node_type)
+                .collect::<Vec<_>>()
+        };
+        Ok(Self {
+            order,
+            incoming,
+            input_indices: indices_of(NodeType::Input),
+            output_indices: indices_of(NodeType::Output),
+            state: OptimizerState::new(connections.len()),
+            builder,
+            connections,
+            slots,
+            optimizer,
+        })
+    }
+
+    // Order of the nodes, and the connections into each of them.
+    #[allow(clippy::type_complexity)]
+    fn topology(
+        builder: &NeuralNetBuilder,
+    ) -> Result<(Vec<usize>, Vec<Vec<usize>>), Error> {
+        builder.check_node_indices()?;
+        builder.check_feed_forward()?;
+        builder.check_sum_aggregation()?;
+        if let Some(node) = builder
+            .nodes
+            .iter()
+            .find(|node| node.func.derivative(0.0).is_none())
+        {
+            return Err(Error::UnsupportedActivationFunction {
+                func: node.func,
+            });
+        }
+
+        let (order, _) = layer_order(&node_depths(builder)?);
+        let mut incoming = vec![Vec::new(); builder.nodes.len()];
+        builder
+            .connections
+            .iter()
+            .enumerate()
+            .for_each(|(i, conn)| incoming[conn.dest as usize].push(i));
+        Ok((order, incoming))
+    }
+
+    pub fn builder(&self) -> &NeuralNetBuilder {
+        &self.builder
+    }
+
+    pub fn into_builder(self) -> NeuralNetBuilder {
+        self.builder
+    }
+
+    fn forward(&self, inputs: &[f32]) -> Forward {
+        let num_nodes = self.builder.nodes.len();
+        let mut sums = vec![0.0; num_nodes];
+        let mut activations = vec![0.0; num_nodes];
+        let scaling = self.builder.input_scaling();
+        self.input_indices.iter().enumerate().for_each(|(j, i)| {
+            let x = inputs.get(j).cloned().unwrap_or(0.0);
+            activations[*i] = scale_input(scaling, j, x);
+        });
+
+        self.order.iter().for_each(|i| {
+            let node = &self.builder.nodes[*i];
+            match node.node_type {
+                NodeType::Input => {}
+                NodeType::Bias => activations[*i] = self.builder.bias_value(),
+                NodeType::Hidden | NodeType::Output => {
+                    let aggregate = self.incoming[*i]
+                        .iter()
+                        .map(|c| {
+                            let conn = &self.connections[*c];
+                            conn.weight * activations[conn.origin as usize]
+                        })
+                        .sum::<f32>();
+                    sums[*i] = node.response * (aggregate + node.bias);
+                    activations[*i] = node.func.apply(sums[*i]);
+                }
+            }
+        });
+
+        Forward { sums, activations }
+    }
+
+    // Outputs of the network before its output transform.  Missing
+    // inputs are treated as zero and inputs are scaled, as by
+    // `NeuralNet::evaluate`.
+    pub fn evaluate(&self, inputs: &[f32]) -> Vec<f32> {
+        let forward = self.forward(inputs);
+        self.output_indices
+            .iter()
+            .map(|i| forward.activations[*i])
+            .collect()
+    }
+
+    // Loss on a single example, and its gradient with respect to
+    // each connection weight.  Outputs without a target don't count
+    // towards the loss.
+    pub fn gradients(
+        &self,
+        inputs: &[f32],
+        targets: &[f32],
+    ) -> (f32, Vec<f32>) {
+        let forward = self.forward(inputs);
+        let mut activation_grads = vec![0.0; self.builder.nodes.len()];
+        let loss = self
+            .output_indices
+            .iter()
+            .zip(targets)
+            .map(|(i, target)| {
+                let error = forward.activations[*i] - target;
+                activation_grads[*i] = error;
+                0.5 * error * error
+            })
+            .sum::<f32>();
+
+        // Every node is visited after all the nodes it feeds, so its
+        // gradient is complete by then.
+        let mut grads = vec![0.0; self.connections.len()];
+        self.order.iter().rev().for_each(|i| {
+            let node = &self.builder.nodes[*i];
+            let slope = node
+                .func
+                .derivative_from_output(forward.activations[*i])
+                .or_else(|| node.func.derivative(forward.sums[*i]))
+                .unwrap_or(0.0);
+            let delta = activation_grads[*i] * slope * node.response;
+            self.incoming[*i].iter().for_each(|c| {
+                let conn = &self.connections[*c];
+                let origin = conn.origin as usize;
+                grads[*c] += delta * forward.activations[origin];
+                activation_grads[origin] += delta * conn.weight;
+            });
+        });
+
+        (loss, grads)
+    }
+
+    // Takes one optimizer step along the mean gradient of a batch of
+    // `(inputs, targets)` examples, returning their mean loss before
+    // the step.
+    pub fn train_batch(&mut self, batch: &[(Vec<f32>, Vec<f32>)]) -> f32 {
+        if batch.is_empty() {
+            return 0.0;
+        }
+        let scale = 1.0 / batch.len() as f32;
+        let (loss, grads) = batch.iter().fold(
+            (0.0, vec![0.0; self.connections.len()]),
+            |(loss, mut grads), (inputs, targets)| {
+                let (l, g) = self.gradients(inputs, targets);
+                grads.iter_mut().zip(g).for_each(|(a, b)| *a += b * scale);
+                (loss + l * scale, grads)
+            },
+        );
+        self.step(&grads);
+        loss
+    }
+
+    // Runs `epochs` passes over `examples` in batches of `batch_size`,
+    // returning the mean loss of the last pass.
+    pub fn train(
+        &mut self,
+        examples: &[(Vec<f32>, Vec<f32>)],
+        epochs: usize,
+        batch_size: usize,
+    ) -> f32 {
+        let batch_size = batch_size.max(1);
+        (0..epochs).fold(0.0, |_, _| {
+            let total = examples
+                .chunks(batch_size)
+                .map(|batch| self.train_batch(batch) * batch.len() as f32)
+                .sum::<f32>();
+            total / examples.len().max(1) as f32
+        })
+    }
+
+    fn step(&mut self, grads: &[f32]) {
+        let weights = self.connections.iter_mut().map(|c| &mut c.weight);
+        self.state.step(&self.optimizer, weights, grads);
+
+        // Back into the builder, where the first of any merged
+        // duplicates takes the change in their sum.
+        let mut sums = vec![0.0; self.connections.len()];
+        let mut firsts = vec![None; self.connections.len()];
+        let slots = &self.slots;
+        self.builder
+            .connections
+            .iter()
+            .enumerate()
+            .for_each(|(i, conn)| {
+                if let Ok(slot) = slots.get(i) {
+                    sums[slot] += conn.weight;
+                    firsts[slot].get_or_insert(i);
+                }
+            });
+        firsts.iter().enumerate().for_each(|(slot, first)| {
+            if let Some(i) = first {
+                let trained = self.connections[slot].weight;
+                self.builder.connections[*i].weight += trained - sums[slot];
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::*;
+
+    #[test]
+    fn test_gradients_match_finite_differences() -> Result<(), Error> {
+        let mut builder = NeuralNetBuilder::new();
+        builder
+            .set_default_activation(ActivationFunction::Tanh)
+            .add_nodes(NodeType::Input, 2)
+            .add_nodes(NodeType::Bias, 1)
+            .add_nodes(NodeType::Hidden, 2)
+            .add_nodes(NodeType::Output, 1)
+            .add_normal_connection_raw(0, 3, 0.5)
+            .add_normal_connection_raw(1, 3, -0.3)
+            .add_normal_connection_raw(2, 4, 0.8)
+            .add_normal_connection_raw(1, 4, 0.2)
+            .add_normal_connection_raw(3, 5, 1.1)
+            .add_normal_connection_raw(4, 5, -0.7)
+            .add_normal_connection_raw(0, 5, 0.4);
+        builder.nodes[4].func = ActivationFunction::SteepenedSigmoid(4.9);
+
+        let (inputs, targets) = ([0.6, -1.2], [0.25]);
+        let trainer = Trainer::new(builder.clone(), Optimizer::default())?;
+        let mut net = builder.build::<ConsecutiveNeuralNet>()?;
+        assert!(
+            (trainer.evaluate(&inputs)[0] - net.evaluate(&inputs)[0]).abs()
+                < 1e-6
+        );
+
+        let (_, grads) = trainer.gradients(&inputs, &targets);
+        let h = 1e-3;
+        grads.iter().enumerate().for_each(|(c, grad)| {
+            let loss_at = |delta: f32| {
+                let mut b = builder.clone();
+                b.connections[c].weight += delta;
+                let t = Trainer::new(b, Optimizer::default()).unwrap();
+                t.gradients(&inputs, &targets).0
+            };
+            let estimate = (loss_at(h) - loss_at(-h)) / (2.0 * h);
+            assert!((grad - estimate).abs() < 1e-3, "{} {}", grad, estimate);
+        });
+        Ok(())
+    }
+
+    #[test]
+    fn test_train_xor() -> Result<(), Error> {
+        let mut builder = NeuralNetBuilder::new();
+        builder
+            .set_default_activation(ActivationFunction::Tanh)
+            .add_nodes(NodeType::Input, 3)
+            .add_nodes(NodeType::Hidden, 2)
+            .add_nodes(NodeType::Output, 1);
+        builder.nodes[5].func = ActivationFunction::Sigmoid;
+        let weights = [0.5, -0.4, 0.3, 0.6, -0.2, -0.5];
+        (0..3).for_each(|i| {
+            builder.add_normal_connection_raw(i, 3, weights[i as usize]);
+            builder.add_normal_connection_raw(i, 4, weights[i as usize + 3]);
+        });
+        builder
+            .add_normal_connection_raw(3, 5, 0.7)
+            .add_normal_connection_raw(4, 5, -0.8)
+            .add_normal_connection_raw(2, 5, 0.1);
+
+        let examples = Xor::CASES
+            .iter()
+            .map(|(inputs, target)| (inputs.to_vec(), vec![*target]))
+            .collect::<Vec<_>>();
+        let mut trainer = Trainer::new(builder, Optimizer::adam(0.05))?;
+        let loss = trainer.train(&examples, 1000, 4);
+        assert!(loss < 0.01, "{}", loss);
+
+        let mut net = trainer.into_builder().build::<ConsecutiveNeuralNet>()?;
+        assert!(Xor.is_solved(&mut net));
+
+        // Trained weights can be written back into a genome.
+        let mut tracker = InnovationTracker::new();
+        let mut genome =
+            Genome::with_io(2, 1, ActivationFunction::Identity, &mut tracker);
+        genome.add_connection(0, 2, 0.5, ConnectionType::Normal, &mut tracker);
+        genome.add_connection(1, 2, 0.1, ConnectionType::Normal, &mut tracker);
+        genome.connections[0].enabled = false;
+        let mut trainer =
+            Trainer::new(genome.to_builder(), Optimizer::sgd(0.1))?;
+        trainer.train(&[(vec![1.0, 2.0], vec![1.0])], 100, 1);
+        genome.set_weights_from(trainer.builder());
+        assert_eq!(genome.connections[0].weight, 0.5);
+        assert!((genome.connections[1].weight - 0.5).abs() < 1e-6);
+        assert_eq!(&genome.to_builder(), trainer.builder());
+
+        let mut recurrent = NeuralNetBuilder::new();
+        recurrent
+            .add_nodes(NodeType::Input, 1)
+            .add_nodes(NodeType::Output, 1)
+            .add_recurrent_connection_raw(1, 1, 1.0);
+        assert_eq!(
+            Trainer::new(recurrent, Optimizer::sgd(0.1)).err(),
+            Some(Error::UnsupportedRecurrentConnection { connection: 0 })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_input_scaling() -> Result<(), Error> {
+        let mut builder = NeuralNetBuilder::new();
+        builder
+            .set_default_activation(ActivationFunction::Identity)
+            .add_nodes(NodeType::Input, 1)
+            .add_nodes(NodeType::Output, 1)
+            .add_normal_connection_raw(0, 1, 1.0)
+            .set_input_scaling(vec![InputScaling::MinMax {
+                min: 0.0,
+                max: 10.0,
+            }]);
+        let mut net = builder.build::<ConsecutiveNeuralNet>()?;
+        let trainer = Trainer::new(builder, Optimizer::sgd(0.1))?;
+        assert_eq!(trainer.evaluate(&[5.0]), vec![0.5]);
+        assert_eq!(trainer.evaluate(&[5.0]), net.evaluate(&[5.0]));
+
+        // Gradients are taken with respect to the scaled input.
+        let (_, gradients) = trainer.gradients(&[5.0], &[1.0]);
+        assert_eq!(gradients, vec![-0.25]);
+        Ok(())
+    }
+}
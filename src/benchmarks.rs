@@ -0,0 +1,254 @@
+use crate::environment::*;
+use crate::neural_net::*;
+
+// The XOR problem.  Networks get the two operands followed by a
+// constant one, which acts as a bias, and should output the XOR of
+// the operands from their first output.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct Xor;
+
+impl Xor {
+    pub const CASES: [([f32; 3], f32); 4] = [
+        ([0.0, 0.0, 1.0], 0.0),
+        ([0.0, 1.0, 1.0], 1.0),
+        ([1.0, 0.0, 1.0], 1.0),
+        ([1.0, 1.0, 1.0], 0.0),
+    ];
+
+    // Whether every output is on the right side of one half.
+    pub fn is_solved<N: NeuralNet>(&self, net: &mut N) -> bool {
+        Self::CASES.iter().all(|(inputs, expected)| {
+            (net.evaluate(inputs)[0] > 0.5) == (*expected > 0.5)
+        })
+    }
+}
+
+impl<N: NeuralNet> FitnessFunction<N> for Xor {
+    // Four minus the total error, squared, so at most 16.
+    fn evaluate(&self, net: &mut N) -> f32 {
+        let error = Self::CASES
+            .iter()
+            .map(|(inputs, expected)| {
+                (net.evaluate(inputs)[0] - expected).abs()
+            })
+            .sum::<f32>();
+        (4.0 - error).powi(2)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+struct Pole {
+    mass: f32,
+    half_length: f32,
+    angle: f32,
+    velocity: f32,
+}
+
+const GRAVITY: f32 = 9.8;
+const CART_MASS: f32 = 1.0;
+const MAX_FORCE: f32 = 10.0;
+const TRACK_LIMIT: f32 = 2.4;
+const TIME_STEP: f32 = 0.01;
+
+// Cart and pole balancing, with one or two poles hinged on a cart
+// running along a track.  The first output of the network pushes the
+// cart left below one half and right above it.  Each step the poles
+// stay up and the cart stays on the track is worth a reward of one.
+//
+// Observations are scaled to roughly [-1, 1]: the cart position,
+// then each pole angle, followed by the matching velocities if
+// `velocities` is set.
+#[derive(Debug, PartialEq, Clone)]
+pub struct PoleBalancing {
+    pub velocities: bool,
+    // Episodes end once a pole leans further than this, in radians.
+    pub failure_angle: f32,
+    position: f32,
+    speed: f32,
+    poles: Vec<Pole>,
+    initial_angle: f32,
+}
+
+impl PoleBalancing {
+    // The classic task, with a single pole that fails beyond 12
+    // degrees.
+    pub fn single(velocities: bool) -> Self {
+        Self::new(velocities, 12f32.to_radians(), 1f32.to_radians(), 1)
+    }
+
+    // A long and a short pole on the same cart, which fails when
+    // either leans beyond 36 degrees.
+    pub fn double(velocities: bool) -> Self {
+        Self::new(velocities, 36f32.to_radians(), 4.5f32.to_radians(), 2)
+    }
+
+    fn new(
+        velocities: bool,
+        failure_angle: f32,
+        initial_angle: f32,
+        num_poles: usize,
+    ) -> Self {
+        let pole = |i: usize| Pole {
+            mass: 0.1 / 10f32.powi(i as i32),
+            half_length: 0.5 / 10f32.powi(i as i32),
+            angle: 0.0,
+            velocity: 0.0,
+        };
+        let mut env = Self {
+            velocities,
+            failure_angle,
+            position: 0.0,
+            speed: 0.0,
+            poles: (0..num_poles).map(pole).collect(),
+            initial_angle,
+        };
+        env.reset();
+        env
+    }
+
+    pub fn num_observations(&self) -> usize {
+        match self.velocities {
+            true => 2 * (1 + self.poles.len()),
+            false => 1 + self.poles.len(),
+        }
+    }
+
+    fn observation(&self) -> Vec<f32> {
+        let mut observation = vec![self.position / TRACK_LIMIT];
+        observation
+            .extend(self.poles.iter().map(|p| p.angle / self.failure_angle));
+        if self.velocities {
+            observation.push(self.speed / 2.0);
+            observation.extend(self.poles.iter().map(|p| p.velocity / 2.0));
+        }
+        observation
+    }
+
+    fn failed(&self) -> bool {
+        self.position.abs() > TRACK_LIMIT
+            || self
+                .poles
+                .iter()
+                .any(|p| p.angle.abs() > self.failure_angle)
+    }
+}
+
+impl Environment for PoleBalancing {
+    fn reset(&mut self) -> Vec<f32> {
+        self.position = 0.0;
+        self.speed = 0.0;
+        let initial_angle = self.initial_angle;
+        self.poles.iter_mut().enumerate().for_each(|(i, pole)| {
+            pole.angle = if i == 0 { initial_angle } else { 0.0 };
+            pole.velocity = 0.0;
+        });
+        self.observation()
+    }
+
+    // Frictionless dynamics after Wieland, integrated by Euler's
+    // method.
+    fn step(&mut self, action: &[f32]) -> Step {
+        let a = action.first().cloned().unwrap_or(0.5).clamp(0.0, 1.0);
+        let force = (2.0 * a - 1.0) * MAX_FORCE;
+
+        let (effective_force, effective_mass) =
+            self.poles.iter().fold((0.0, 0.0), |(f, m), pole| {
+                let (sin, cos) = pole.angle.sin_cos();
+                let f_i =
+                    pole.mass * pole.half_length * pole.velocity.powi(2) * sin
+                        - 0.75 * pole.mass * cos * GRAVITY * sin;
+                let m_i = pole.mass * (1.0 - 0.75 * cos * cos);
+                (f + f_i, m + m_i)
+            });
+        let acceleration =
+            (force + effective_force) / (CART_MASS + effective_mass);
+
+        self.position += TIME_STEP * self.speed;
+        self.speed += TIME_STEP * acceleration;
+        self.poles.iter_mut().for_each(|pole| {
+            let (sin, cos) = pole.angle.sin_cos();
+            let angular_acceleration =
+                -0.75 / pole.half_length * (acceleration * cos - GRAVITY * sin);
+            pole.angle += TIME_STEP * pole.velocity;
+            pole.velocity += TIME_STEP * angular_acceleration;
+        });
+
+        let done = self.failed();
+        Step {
+            observation: self.observation(),
+            reward: if done { 0.0 } else { 1.0 },
+            done,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::*;
+
+    #[test]
+    fn test_neat_solves_xor() -> Result<(), Error> {
+        let config = PopulationConfig {
+            num_inputs: 3,
+            num_outputs: 1,
+            seed: Some(0),
+            output_activation: ActivationFunction::SteepenedSigmoid(4.9),
+            mutation: MutationConfig {
+                add_connection_prob: 0.3,
+                add_node_prob: 0.1,
+                weight_perturb_power: 1.0,
+                activation_functions: vec![
+                    ActivationFunction::SteepenedSigmoid(4.9),
+                ],
+                ..MutationConfig::default()
+            },
+            speciation: SpeciationConfig {
+                target_species: Some(10),
+                ..SpeciationConfig::default()
+            },
+            ..PopulationConfig::default()
+        };
+        let mut population = Population::new(config);
+        for _ in 0..200 {
+            population.run_generation::<ConsecutiveNeuralNet, _>(&Xor)?;
+            let (champion, _) = population.champion().unwrap();
+            let mut net =
+                champion.to_builder().build::<ConsecutiveNeuralNet>()?;
+            if Xor.is_solved(&mut net) {
+                return Ok(());
+            }
+        }
+        panic!("XOR was not solved");
+    }
+
+    #[test]
+    fn test_pole_balancing() -> Result<(), Error> {
+        let env = PoleBalancing::single(true);
+        assert_eq!(env.num_observations(), 4);
+        assert_eq!(env.observation().len(), 4);
+        assert_eq!(PoleBalancing::double(false).num_observations(), 3);
+
+        // Without any force, the pole falls over.
+        let mut fitness = EpisodicFitness::new(|_| PoleBalancing::single(true));
+        let mut idle = NeuralNetBuilder::new()
+            .add_nodes(NodeType::Input, 4)
+            .add_nodes(NodeType::Output, 1)
+            .build::<ConsecutiveNeuralNet>()?;
+        let steps = fitness.evaluate(&mut idle);
+        assert!(steps > 0.0 && steps < 1000.0);
+
+        // Pushing the cart the way the pole leans keeps it up for
+        // longer.
+        let mut push = NeuralNetBuilder::new()
+            .set_default_activation(ActivationFunction::Sigmoid)
+            .add_nodes(NodeType::Input, 4)
+            .add_nodes(NodeType::Output, 1)
+            .add_normal_connection_raw(1, 4, 10.0)
+            .add_normal_connection_raw(3, 4, 10.0)
+            .build::<ConsecutiveNeuralNet>()?;
+        fitness.max_steps = 10_000;
+        assert!(fitness.evaluate(&mut push) > steps);
+        Ok(())
+    }
+}
@@ -0,0 +1,1171 @@
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+
+use crate::genome::*;
+use crate::hall_of_fame::*;
+use crate::lineage::*;
+use crate::mutation::*;
+use crate::neural_net::*;
+use crate::phased_search::*;
+use crate::population::*;
+use crate::species::*;
+
+// Checkpoints start with these bytes, followed by the format version.
+// All numbers are little-endian, and every length is written as a
+// u64 ahead of its elements.
+const MAGIC: &[u8; 8] = b"ENTENDRE";
+const VERSION: u32 = 1;
+
+// Mutation kinds are written as their index here.
+const MUTATION_KINDS: [MutationKind; 11] = [
+    MutationKind::Weights,
+    MutationKind::AddConnection,
+    MutationKind::AddNode,
+    MutationKind::ToggleEnable,
+    MutationKind::Activation,
+    MutationKind::DeleteConnection,
+    MutationKind::DeleteNode,
+    MutationKind::TogglePlasticity,
+    MutationKind::Plasticity,
+    MutationKind::ToggleMemory,
+    MutationKind::Memory,
+];
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+struct Writer<W: Write> {
+    inner: W,
+}
+
+impl<W: Write> Writer<W> {
+    fn bytes(&mut self, x: &[u8]) -> io::Result<()> {
+        self.inner.write_all(x)
+    }
+
+    fn u8(&mut self, x: u8) -> io::Result<()> {
+        self.bytes(&[x])
+    }
+
+    fn u32(&mut self, x: u32) -> io::Result<()> {
+        self.bytes(&x.to_le_bytes())
+    }
+
+    fn u64(&mut self, x: u64) -> io::Result<()> {
+        self.bytes(&x.to_le_bytes())
+    }
+
+    fn u128(&mut self, x: u128) -> io::Result<()> {
+        self.bytes(&x.to_le_bytes())
+    }
+
+    fn usize(&mut self, x: usize) -> io::Result<()> {
+        self.u64(x as u64)
+    }
+
+    fn f32(&mut self, x: f32) -> io::Result<()> {
+        self.bytes(&x.to_le_bytes())
+    }
+
+    fn bool(&mut self, x: bool) -> io::Result<()> {
+        self.u8(x as u8)
+    }
+
+    fn string(&mut self, x: &str) -> io::Result<()> {
+        self.usize(x.len())?;
+        self.bytes(x.as_bytes())
+    }
+
+    // LEB128: seven bits per byte, lowest first, with the top bit set
+    // on every byte but the last.
+    fn varint(&mut self, mut x: u64) -> io::Result<()> {
+        while x >= 0x80 {
+            self.u8(x as u8 | 0x80)?;
+            x >>= 7;
+        }
+        self.u8(x as u8)
+    }
+
+    fn node_type(&mut self, x: NodeType) -> io::Result<()> {
+        use NodeType::*;
+        self.u8(match x {
+            Bias => 0,
+            Input => 1,
+            Output => 2,
+            Hidden => 3,
+        })
+    }
+
+    fn connection_type(&mut self, x: ConnectionType) -> io::Result<()> {
+        use ConnectionType::*;
+        self.u8(match x {
+            Normal => 0,
+            Recurrent => 1,
+        })
+    }
+
+    fn func(&mut self, x: ActivationFunction) -> io::Result<()> {
+        use ActivationFunction::*;
+        let (code, param) = match x {
+            Sigmoid => (0, None),
+            Identity => (1, None),
+            Tanh => (2, None),
+            Relu => (3, None),
+            Gaussian => (4, None),
+            Sin => (5, None),
+            Cos => (6, None),
+            Abs => (7, None),
+            Square => (8, None),
+            LeakyRelu(alpha) => (9, Some(alpha)),
+            Elu(alpha) => (10, Some(alpha)),
+            SteepenedSigmoid(slope) => (11, Some(slope)),
+            Step => (13, None),
+            Softplus => (14, None),
+            Sinc => (15, None),
+            Clamped => (16, None),
+            Inverse => (17, None),
+            Log => (18, None),
+            Custom(_) => {
+                let name = x.name().ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "Custom activation functions must be registered to \
+                         be saved",
+                    )
+                })?;
+                self.u8(12)?;
+                return self.string(&name);
+            }
+        };
+        self.u8(code)?;
+        param.map_or(Ok(()), |param| self.f32(param))
+    }
+
+    fn genome(&mut self, genome: &Genome) -> io::Result<()> {
+        self.usize(genome.nodes.len())?;
+        genome.nodes.iter().try_for_each(|node| {
+            self.u32(node.id)?;
+            self.node_type(node.node_type)?;
+            self.func(node.func)?;
+            self.memory(node.memory)?;
+            self.f32(node.bias)
+        })?;
+
+        self.usize(genome.connections.len())?;
+        genome.connections.iter().try_for_each(|conn| {
+            self.u32(conn.innovation)?;
+            self.u32(conn.origin)?;
+            self.u32(conn.dest)?;
+            self.f32(conn.weight)?;
+            self.connection_type(conn.connection_type)?;
+            self.bool(conn.enabled)?;
+            self.plasticity(conn.plasticity)
+        })?;
+        self.mutation_rates(genome.mutation_rates)
+    }
+
+    // Same genome as `genome`, with lengths, ids and innovation
+    // numbers as varints, and flags packed into single bytes.
+    // Innovation numbers are written as the zigzag-encoded difference
+    // from the previous connection's, since they're mostly ascending.
+    fn compact_genome(&mut self, genome: &Genome) -> io::Result<()> {
+        self.varint(genome.nodes.len() as u64)?;
+        genome.nodes.iter().try_for_each(|node| {
+            self.varint(node.id as u64)?;
+            self.node_type(node.node_type)?;
+            self.func(node.func)?;
+            self.memory(node.memory)?;
+            self.f32(node.bias)
+        })?;
+
+        self.varint(genome.connections.len() as u64)?;
+        let mut previous = 0i64;
+        genome.connections.iter().try_for_each(|conn| {
+            let delta = conn.innovation as i64 - previous;
+            previous = conn.innovation as i64;
+            self.varint(((delta << 1) ^ (delta >> 63)) as u64)?;
+            self.varint(conn.origin as u64)?;
+            self.varint(conn.dest as u64)?;
+            self.f32(conn.weight)?;
+            let recurrent = conn.connection_type == ConnectionType::Recurrent;
+            self.u8(recurrent as u8
+                | (conn.enabled as u8) << 1
+                | (conn.plasticity.is_some() as u8) << 2)?;
+            conn.plasticity
+                .map_or(Ok(()), |rule| self.hebbian_rule(rule))
+        })?;
+        self.mutation_rates(genome.mutation_rates)
+    }
+
+    fn memory(&mut self, x: Option<MemoryCell>) -> io::Result<()> {
+        self.bool(x.is_some())?;
+        x.map_or(Ok(()), |cell| {
+            let (z, r) = (cell.update_gate, cell.reset_gate);
+            [
+                z.input_weight,
+                z.memory_weight,
+                z.bias,
+                r.input_weight,
+                r.memory_weight,
+                r.bias,
+                cell.memory_weight,
+            ]
+            .iter()
+            .try_for_each(|x| self.f32(*x))
+        })
+    }
+
+    fn plasticity(&mut self, x: Option<HebbianRule>) -> io::Result<()> {
+        self.bool(x.is_some())?;
+        x.map_or(Ok(()), |rule| self.hebbian_rule(rule))
+    }
+
+    fn hebbian_rule(&mut self, rule: HebbianRule) -> io::Result<()> {
+        [rule.learning_rate, rule.a, rule.b, rule.c, rule.d]
+            .iter()
+            .try_for_each(|x| self.f32(*x))
+    }
+
+    fn mutation_rates(&mut self, x: Option<MutationRates>) -> io::Result<()> {
+        self.bool(x.is_some())?;
+        x.map_or(Ok(()), |rates| {
+            [
+                rates.weight_mutation_prob,
+                rates.weight_perturb_power,
+                rates.add_connection_prob,
+                rates.add_node_prob,
+            ]
+            .iter()
+            .try_for_each(|x| self.f32(*x))
+        })
+    }
+
+    fn tracker(&mut self, tracker: &InnovationTracker) -> io::Result<()> {
+        self.u32(tracker.next_node_id)?;
+        self.u32(tracker.next_innovation)?;
+
+        // Sorted, so that the same state always gives the same bytes.
+        let mut connections = tracker.connections.iter().collect::<Vec<_>>();
+        connections.sort_by_key(|(_key, innovation)| **innovation);
+        self.usize(connections.len())?;
+        connections.iter().try_for_each(
+            |((origin, dest, connection_type), innovation)| {
+                self.u32(*origin)?;
+                self.u32(*dest)?;
+                self.connection_type(*connection_type)?;
+                self.u32(**innovation)
+            },
+        )?;
+
+        let mut splits = tracker.splits.iter().collect::<Vec<_>>();
+        splits.sort();
+        self.usize(splits.len())?;
+        splits.iter().try_for_each(|(innovation, node)| {
+            self.u32(**innovation)?;
+            self.u32(**node)
+        })
+    }
+
+    fn speciator(&mut self, speciator: &Speciator) -> io::Result<()> {
+        self.f32(speciator.threshold)?;
+        self.u32(speciator.next_species_id)?;
+        self.usize(speciator.species.len())?;
+        speciator.species.iter().try_for_each(|species| {
+            self.u32(species.id)?;
+            self.genome(&species.representative)?;
+            self.usize(species.members.len())?;
+            species.members.iter().try_for_each(|i| self.usize(*i))?;
+            self.f32(species.best_fitness)?;
+            self.usize(species.stagnant_generations)
+        })
+    }
+
+    fn rng(&mut self, rng: &ChaCha8Rng) -> io::Result<()> {
+        self.bytes(&rng.get_seed())?;
+        self.u64(rng.get_stream())?;
+        self.u128(rng.get_word_pos())
+    }
+
+    fn phase(&mut self, state: &PhaseState) -> io::Result<()> {
+        self.bool(state.phase == SearchPhase::Simplifying)?;
+        match state.ceiling {
+            Some(ceiling) => {
+                self.bool(true)?;
+                self.f32(ceiling)?;
+            }
+            None => self.bool(false)?,
+        }
+        self.f32(state.lowest)?;
+        self.usize(state.stalled)
+    }
+
+    fn hall_of_fame_entry(
+        &mut self,
+        entry: &HallOfFameEntry,
+    ) -> io::Result<()> {
+        self.usize(entry.generation)?;
+        match entry.species {
+            Some(species) => {
+                self.bool(true)?;
+                self.u32(species)?;
+            }
+            None => self.bool(false)?,
+        }
+        self.genome(&entry.genome)?;
+        self.f32(entry.fitness)
+    }
+
+    fn lineage(&mut self, lineage: &Lineage) -> io::Result<()> {
+        self.u64(lineage.next_id)?;
+        self.usize(lineage.records.len())?;
+        lineage.records.values().try_for_each(|record| {
+            self.u64(record.id)?;
+            self.usize(record.generation)?;
+            self.usize(record.parents.len())?;
+            record.parents.iter().try_for_each(|id| self.u64(*id))?;
+            self.usize(record.mutations.len())?;
+            record.mutations.iter().try_for_each(|kind| {
+                let index = MUTATION_KINDS.iter().position(|k| k == kind);
+                self.u8(index.unwrap_or_default() as u8)
+            })?;
+            match record.fitness {
+                Some(fitness) => {
+                    self.bool(true)?;
+                    self.f32(fitness)
+                }
+                None => self.bool(false),
+            }
+        })
+    }
+}
+
+struct Reader<R: Read> {
+    inner: R,
+}
+
+impl<R: Read> Reader<R> {
+    fn array<const N: usize>(&mut self) -> io::Result<[u8; N]> {
+        let mut buf = [0; N];
+        self.inner.read_exact(&mut buf)?;
+        Ok(buf)
+    }
+
+    fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.array::<1>()?[0])
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    fn u64(&mut self) -> io::Result<u64> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+
+    fn u128(&mut self) -> io::Result<u128> {
+        Ok(u128::from_le_bytes(self.array()?))
+    }
+
+    fn usize(&mut self) -> io::Result<usize> {
+        let x = self.u64()?;
+        usize::try_from(x).map_err(|_| invalid_data("Length out of range"))
+    }
+
+    fn f32(&mut self) -> io::Result<f32> {
+        Ok(f32::from_le_bytes(self.array()?))
+    }
+
+    fn bool(&mut self) -> io::Result<bool> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(invalid_data("Invalid boolean")),
+        }
+    }
+
+    fn vec<T>(
+        &mut self,
+        mut element: impl FnMut(&mut Self) -> io::Result<T>,
+    ) -> io::Result<Vec<T>> {
+        let len = self.usize()?;
+        // Not pre-allocated, since a corrupt length could be huge.
+        (0..len).map(|_| element(self)).collect()
+    }
+
+    fn string(&mut self) -> io::Result<String> {
+        String::from_utf8(self.vec(|r| r.u8())?)
+            .map_err(|_| invalid_data("Invalid string"))
+    }
+
+    fn varint(&mut self) -> io::Result<u64> {
+        let mut x = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.u8()?;
+            x |= ((byte & 0x7f) as u64) << shift;
+            if byte < 0x80 {
+                return Ok(x);
+            }
+        }
+        Err(invalid_data("Varint too long"))
+    }
+
+    fn varint_u32(&mut self) -> io::Result<u32> {
+        u32::try_from(self.varint()?)
+            .map_err(|_| invalid_data("Number out of range"))
+    }
+
+    // Same as `vec`, with the length as a varint.
+    fn compact_vec<T>(
+        &mut self,
+        mut element: impl FnMut(&mut Self) -> io::Result<T>,
+    ) -> io::Result<Vec<T>> {
+        let len = self.varint()?;
+        (0..len).map(|_| element(self)).collect()
+    }
+
+    fn node_type(&mut self) -> io::Result<NodeType> {
+        use NodeType::*;
+        match self.u8()? {
+            0 => Ok(Bias),
+            1 => Ok(Input),
+            2 => Ok(Output),
+            3 => Ok(Hidden),
+            _ => Err(invalid_data("Invalid node type")),
+        }
+    }
+
+    fn connection_type(&mut self) -> io::Result<ConnectionType> {
+        use ConnectionType::*;
+        match self.u8()? {
+            0 => Ok(Normal),
+            1 => Ok(Recurrent),
+            _ => Err(invalid_data("Invalid connection type")),
+        }
+    }
+
+    fn func(&mut self) -> io::Result<ActivationFunction> {
+        use ActivationFunction::*;
+        match self.u8()? {
+            0 => Ok(Sigmoid),
+            1 => Ok(Identity),
+            2 => Ok(Tanh),
+            3 => Ok(Relu),
+            4 => Ok(Gaussian),
+            5 => Ok(Sin),
+            6 => Ok(Cos),
+            7 => Ok(Abs),
+            8 => Ok(Square),
+            9 => Ok(LeakyRelu(self.f32()?)),
+            10 => Ok(Elu(self.f32()?)),
+            11 => Ok(SteepenedSigmoid(self.f32()?)),
+            12 => {
+                let name = self.string()?;
+                match ActivationFunction::from_name(&name) {
+                    Some(func @ Custom(_)) => Ok(func),
+                    _ => {
+                        Err(invalid_data("Unknown custom activation function"))
+                    }
+                }
+            }
+            13 => Ok(Step),
+            14 => Ok(Softplus),
+            15 => Ok(Sinc),
+            16 => Ok(Clamped),
+            17 => Ok(Inverse),
+            18 => Ok(Log),
+            _ => Err(invalid_data("Invalid activation function")),
+        }
+    }
+
+    fn genome(&mut self) -> io::Result<Genome> {
+        let nodes = self.vec(|r| {
+            Ok(NodeGene {
+                id: r.u32()?,
+                node_type: r.node_type()?,
+                func: r.func()?,
+                memory: r.memory()?,
+                bias: r.f32()?,
+            })
+        })?;
+        let connections = self.vec(|r| {
+            Ok(ConnectionGene {
+                innovation: r.u32()?,
+                origin: r.u32()?,
+                dest: r.u32()?,
+                weight: r.f32()?,
+                connection_type: r.connection_type()?,
+                enabled: r.bool()?,
+                plasticity: r.plasticity()?,
+            })
+        })?;
+        Ok(Genome {
+            nodes,
+            connections,
+            mutation_rates: self.mutation_rates()?,
+        })
+    }
+
+    fn compact_genome(&mut self) -> io::Result<Genome> {
+        let nodes = self.compact_vec(|r| {
+            Ok(NodeGene {
+                id: r.varint_u32()?,
+                node_type: r.node_type()?,
+                func: r.func()?,
+                memory: r.memory()?,
+                bias: r.f32()?,
+            })
+        })?;
+        let mut previous = 0i64;
+        let connections = self.compact_vec(|r| {
+            let zigzag = r.varint()?;
+            let delta = (zigzag >> 1) as i64 ^ -((zigzag & 1) as i64);
+            let innovation = u32::try_from(previous + delta)
+                .map_err(|_| invalid_data("Innovation number out of range"))?;
+            previous = innovation as i64;
+            let origin = r.varint_u32()?;
+            let dest = r.varint_u32()?;
+            let weight = r.f32()?;
+            let flags = r.u8()?;
+            if flags > 0b111 {
+                return Err(invalid_data("Invalid connection flags"));
+            }
+            Ok(ConnectionGene {
+                innovation,
+                origin,
+                dest,
+                weight,
+                connection_type: if flags & 1 != 0 {
+                    ConnectionType::Recurrent
+                } else {
+                    ConnectionType::Normal
+                },
+                enabled: flags & 2 != 0,
+                plasticity: if flags & 4 != 0 {
+                    Some(r.hebbian_rule()?)
+                } else {
+                    None
+                },
+            })
+        })?;
+        Ok(Genome {
+            nodes,
+            connections,
+            mutation_rates: self.mutation_rates()?,
+        })
+    }
+
+    fn gate(&mut self) -> io::Result<Gate> {
+        Ok(Gate {
+            input_weight: self.f32()?,
+            memory_weight: self.f32()?,
+            bias: self.f32()?,
+        })
+    }
+
+    fn memory(&mut self) -> io::Result<Option<MemoryCell>> {
+        if !self.bool()? {
+            return Ok(None);
+        }
+        Ok(Some(MemoryCell {
+            update_gate: self.gate()?,
+            reset_gate: self.gate()?,
+            memory_weight: self.f32()?,
+        }))
+    }
+
+    fn plasticity(&mut self) -> io::Result<Option<HebbianRule>> {
+        if !self.bool()? {
+            return Ok(None);
+        }
+        self.hebbian_rule().map(Some)
+    }
+
+    fn hebbian_rule(&mut self) -> io::Result<HebbianRule> {
+        Ok(HebbianRule {
+            learning_rate: self.f32()?,
+            a: self.f32()?,
+            b: self.f32()?,
+            c: self.f32()?,
+            d: self.f32()?,
+        })
+    }
+
+    fn mutation_rates(&mut self) -> io::Result<Option<MutationRates>> {
+        if !self.bool()? {
+            return Ok(None);
+        }
+        Ok(Some(MutationRates {
+            weight_mutation_prob: self.f32()?,
+            weight_perturb_power: self.f32()?,
+            add_connection_prob: self.f32()?,
+            add_node_prob: self.f32()?,
+        }))
+    }
+
+    fn tracker(&mut self) -> io::Result<InnovationTracker> {
+        let next_node_id = self.u32()?;
+        let next_innovation = self.u32()?;
+        let connections = self.vec(|r| {
+            let key = (r.u32()?, r.u32()?, r.connection_type()?);
+            Ok((key, r.u32()?))
+        })?;
+        let splits = self.vec(|r| Ok((r.u32()?, r.u32()?)))?;
+
+        Ok(InnovationTracker {
+            next_node_id,
+            next_innovation,
+            connections: connections.into_iter().collect(),
+            splits: splits.into_iter().collect(),
+        })
+    }
+
+    fn speciator(&mut self, config: SpeciationConfig) -> io::Result<Speciator> {
+        let threshold = self.f32()?;
+        let next_species_id = self.u32()?;
+        let species = self.vec(|r| {
+            Ok(Species {
+                id: r.u32()?,
+                representative: r.genome()?,
+                members: r.vec(|r| r.usize())?,
+                best_fitness: r.f32()?,
+                stagnant_generations: r.usize()?,
+            })
+        })?;
+
+        Ok(Speciator {
+            config,
+            species,
+            threshold,
+            next_species_id,
+        })
+    }
+
+    fn rng(&mut self) -> io::Result<ChaCha8Rng> {
+        let mut rng = ChaCha8Rng::from_seed(self.array()?);
+        rng.set_stream(self.u64()?);
+        rng.set_word_pos(self.u128()?);
+        Ok(rng)
+    }
+
+    fn phase(&mut self) -> io::Result<PhaseState> {
+        let phase = match self.bool()? {
+            true => SearchPhase::Simplifying,
+            false => SearchPhase::Complexifying,
+        };
+        let ceiling = if self.bool()? {
+            Some(self.f32()?)
+        } else {
+            None
+        };
+        Ok(PhaseState {
+            phase,
+            ceiling,
+            lowest: self.f32()?,
+            stalled: self.usize()?,
+        })
+    }
+
+    fn hall_of_fame_entry(&mut self) -> io::Result<HallOfFameEntry> {
+        let generation = self.usize()?;
+        let species = if self.bool()? {
+            Some(self.u32()?)
+        } else {
+            None
+        };
+        Ok(HallOfFameEntry {
+            generation,
+            species,
+            genome: self.genome()?,
+            fitness: self.f32()?,
+        })
+    }
+
+    fn lineage(&mut self) -> io::Result<Lineage> {
+        let next_id = self.u64()?;
+        let records = self.vec(|r| {
+            let id = r.u64()?;
+            let generation = r.usize()?;
+            let parents = r.vec(|r| r.u64())?;
+            let mutations = r.vec(|r| {
+                MUTATION_KINDS
+                    .get(r.u8()? as usize)
+                    .cloned()
+                    .ok_or_else(|| invalid_data("Invalid mutation kind"))
+            })?;
+            let fitness = if r.bool()? { Some(r.f32()?) } else { None };
+            Ok(LineageRecord {
+                id,
+                generation,
+                parents,
+                mutations,
+                fitness,
+            })
+        })?;
+        Ok(Lineage {
+            records: records.into_iter().map(|r| (r.id, r)).collect(),
+            next_id,
+        })
+    }
+}
+
+impl Population {
+    // Saves everything needed to resume evolution exactly where it
+    // stopped, including the fitnesses given to `tell` so far this
+    // generation, apart from the configuration, which must be given
+    // again to `load_checkpoint`, and the reporters.
+    pub fn save_checkpoint<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        self.write_checkpoint(BufWriter::new(File::create(path)?))
+    }
+
+    pub fn load_checkpoint<P: AsRef<Path>>(
+        path: P,
+        config: PopulationConfig,
+    ) -> io::Result<Self> {
+        Self::read_checkpoint(BufReader::new(File::open(path)?), config)
+    }
+
+    // Same as `save_checkpoint`, but to any writer, for targets
+    // without a filesystem.
+    pub fn write_checkpoint<W: Write>(&self, writer: W) -> io::Result<()> {
+        let mut w = Writer { inner: writer };
+
+        w.bytes(MAGIC)?;
+        w.u32(VERSION)?;
+
+        w.usize(self.generation)?;
+        w.usize(self.genomes.len())?;
+        self.genomes
+            .iter()
+            .try_for_each(|genome| w.genome(genome))?;
+        w.tracker(&self.tracker)?;
+        w.speciator(&self.speciator)?;
+        match &self.champion {
+            Some((genome, fitness)) => {
+                w.bool(true)?;
+                w.genome(genome)?;
+                w.f32(*fitness)?;
+            }
+            None => w.bool(false)?,
+        }
+        w.rng(&self.rng)?;
+        w.phase(&self.phase)?;
+        w.usize(self.hall_of_fame.len())?;
+        self.hall_of_fame
+            .entries()
+            .try_for_each(|entry| w.hall_of_fame_entry(entry))?;
+        w.lineage(&self.lineage)?;
+        w.usize(self.genome_ids.len())?;
+        self.genome_ids.iter().try_for_each(|id| w.u64(*id))?;
+        w.usize(self.told.len())?;
+        self.told.iter().try_for_each(|fitness| match fitness {
+            Some(fitness) => {
+                w.bool(true)?;
+                w.f32(*fitness)
+            }
+            None => w.bool(false),
+        })?;
+
+        w.inner.flush()
+    }
+
+    pub fn read_checkpoint<R: Read>(
+        reader: R,
+        config: PopulationConfig,
+    ) -> io::Result<Self> {
+        let mut r = Reader { inner: reader };
+
+        if &r.array::<8>()? != MAGIC {
+            return Err(invalid_data("Not a checkpoint file"));
+        }
+        if r.u32()? != VERSION {
+            return Err(invalid_data("Unsupported checkpoint version"));
+        }
+
+        let generation = r.usize()?;
+        let genomes = r.vec(|r| r.genome())?;
+        let tracker = r.tracker()?;
+        let speciator = r.speciator(config.speciation.clone())?;
+        let champion = if r.bool()? {
+            Some((r.genome()?, r.f32()?))
+        } else {
+            None
+        };
+        let rng = r.rng()?;
+        let phase = r.phase()?;
+        // The capacity comes from the new configuration, which may
+        // drop the oldest entries.
+        let mut hall_of_fame = HallOfFame::new(config.hall_of_fame_capacity);
+        r.vec(|r| r.hall_of_fame_entry())?
+            .into_iter()
+            .for_each(|entry| hall_of_fame.push(entry));
+        let lineage = r.lineage()?;
+        let genome_ids = r.vec(|r| r.u64())?;
+        let told =
+            r.vec(|r| Ok(if r.bool()? { Some(r.f32()?) } else { None }))?;
+
+        let num_genomes = genomes.len();
+        if speciator
+            .species
+            .iter()
+            .flat_map(|s| s.members.iter())
+            .any(|i| *i >= num_genomes)
+        {
+            return Err(invalid_data("Species member out of range"));
+        }
+        if !genome_ids.is_empty() && genome_ids.len() != num_genomes {
+            return Err(invalid_data("Wrong number of genome ids"));
+        }
+        if !told.is_empty() && told.len() != num_genomes {
+            return Err(invalid_data("Wrong number of told fitnesses"));
+        }
+
+        Ok(Self {
+            config,
+            genomes,
+            tracker,
+            speciator,
+            generation,
+            champion,
+            rng,
+            reporters: Vec::new(),
+            phase,
+            hall_of_fame,
+            lineage,
+            genome_ids,
+            told,
+        })
+    }
+}
+
+// Genome files hold a single genome, such as an evolved champion,
+// in the same encoding as checkpoints.
+const GENOME_MAGIC: &[u8; 8] = b"ENTGENOM";
+const GENOME_VERSION: u32 = 1;
+
+impl Genome {
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        self.write_to(BufWriter::new(File::create(path)?))
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::read_from(BufReader::new(File::open(path)?))
+    }
+
+    pub fn write_to<W: Write>(&self, writer: W) -> io::Result<()> {
+        let mut w = Writer { inner: writer };
+        w.bytes(GENOME_MAGIC)?;
+        w.u32(GENOME_VERSION)?;
+        w.genome(self)?;
+        w.inner.flush()
+    }
+
+    pub fn read_from<R: Read>(reader: R) -> io::Result<Self> {
+        let mut r = Reader { inner: reader };
+        if &r.array::<8>()? != GENOME_MAGIC {
+            return Err(invalid_data("Not a genome file"));
+        }
+        if r.u32()? != GENOME_VERSION {
+            return Err(invalid_data("Unsupported genome file version"));
+        }
+        r.genome()
+    }
+}
+
+// Compact files hold any number of genomes, such as a population or
+// an archive, in about half the space of checkpoints, using the
+// encoding of `Writer::compact_genome`.  The magic bytes and version
+// are followed by the number of genomes as a varint, the genomes, and
+// a CRC-32 of everything before it, to catch corrupted files.
+const COMPACT_MAGIC: &[u8; 8] = b"ENTCMPCT";
+const COMPACT_VERSION: u32 = 1;
+
+// CRC-32 as used by zlib and PNG.
+const CRC_TABLE: [u32; 256] = crc_table();
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                0xedb88320 ^ (crc >> 1)
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+// Keeps a CRC-32 of the bytes passing through.
+struct Checksummed<T> {
+    inner: T,
+    state: u32,
+}
+
+impl<T> Checksummed<T> {
+    fn new(inner: T) -> Self {
+        Self { inner, state: !0 }
+    }
+
+    fn update(&mut self, bytes: &[u8]) {
+        bytes.iter().for_each(|byte| {
+            let index = (self.state ^ *byte as u32) & 0xff;
+            self.state = CRC_TABLE[index as usize] ^ (self.state >> 8);
+        });
+    }
+
+    fn checksum(&self) -> u32 {
+        !self.state
+    }
+}
+
+impl<W: Write> Write for Checksummed<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.update(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<R: Read> Read for Checksummed<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.update(&buf[..n]);
+        Ok(n)
+    }
+}
+
+impl Genome {
+    pub fn write_compact_list<W: Write>(
+        genomes: &[Genome],
+        writer: W,
+    ) -> io::Result<()> {
+        let mut w = Writer {
+            inner: Checksummed::new(writer),
+        };
+        w.bytes(COMPACT_MAGIC)?;
+        w.u32(COMPACT_VERSION)?;
+        w.varint(genomes.len() as u64)?;
+        genomes
+            .iter()
+            .try_for_each(|genome| w.compact_genome(genome))?;
+
+        let checksum = w.inner.checksum();
+        let mut writer = w.inner.inner;
+        writer.write_all(&checksum.to_le_bytes())?;
+        writer.flush()
+    }
+
+    pub fn read_compact_list<R: Read>(reader: R) -> io::Result<Vec<Genome>> {
+        let mut r = Reader {
+            inner: Checksummed::new(reader),
+        };
+        if &r.array::<8>()? != COMPACT_MAGIC {
+            return Err(invalid_data("Not a compact genome file"));
+        }
+        if r.u32()? != COMPACT_VERSION {
+            return Err(invalid_data(
+                "Unsupported compact genome file version",
+            ));
+        }
+        let genomes = r.compact_vec(|r| r.compact_genome())?;
+
+        let checksum = r.inner.checksum();
+        let mut stored = [0; 4];
+        r.inner.inner.read_exact(&mut stored)?;
+        if u32::from_le_bytes(stored) != checksum {
+            return Err(invalid_data("Checksum mismatch"));
+        }
+        Ok(genomes)
+    }
+}
+
+impl Population {
+    // The genomes of the current generation as a compact file, which
+    // `Genome::read_compact_list` reads back.  Unlike a checkpoint, this
+    // doesn't hold what is needed to resume evolution.
+    pub fn write_compact_genomes<W: Write>(&self, writer: W) -> io::Result<()> {
+        Genome::write_compact_list(&self.genomes, writer)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_resume_from_checkpoint() -> io::Result<()> {
+        let config = PopulationConfig {
+            population_size: 30,
+            num_inputs: 2,
+            num_outputs: 2,
+            output_activation: ActivationFunction::SteepenedSigmoid(4.9),
+            phased_search: Some(PhasedSearchConfig {
+                complexity_threshold: 1.0,
+                ..PhasedSearchConfig::default()
+            }),
+            hall_of_fame_capacity: 20,
+            track_lineage: true,
+            ..PopulationConfig::default()
+        };
+        let fitness = |population: &Population| {
+            population
+                .genomes()
+                .iter()
+                .map(|g| g.connections.iter().map(|c| c.weight).sum::<f32>())
+                .collect::<Vec<_>>()
+        };
+
+        let mut original = Population::new(config.clone());
+        (0..3).for_each(|_| original.advance_generation(&fitness(&original)));
+
+        let path = std::env::temp_dir()
+            .join(format!("entendre-checkpoint-{}.bin", std::process::id()));
+        original.save_checkpoint(&path)?;
+        let resumed = Population::load_checkpoint(&path, config.clone());
+        std::fs::remove_file(&path)?;
+        let mut resumed = resumed?;
+
+        assert_eq!(resumed.generation(), original.generation());
+        assert_eq!(resumed.genomes(), original.genomes());
+
+        // Resumed runs continue with the same random choices.
+        (0..3).for_each(|_| {
+            original.advance_generation(&fitness(&original));
+            resumed.advance_generation(&fitness(&resumed));
+        });
+        assert_eq!(resumed.genomes(), original.genomes());
+        assert_eq!(resumed.champion(), original.champion());
+        assert_eq!(resumed.hall_of_fame(), original.hall_of_fame());
+        assert!(!resumed.hall_of_fame().is_empty());
+        assert_eq!(resumed.lineage(), original.lineage());
+        assert_eq!(resumed.genome_ids(), original.genome_ids());
+
+        let mut bytes = Vec::new();
+        original.write_checkpoint(&mut bytes)?;
+        let copy =
+            Population::read_checkpoint(bytes.as_slice(), config.clone())?;
+        assert_eq!(copy.genomes(), original.genomes());
+
+        // Fitnesses already told are kept, so a resumed ask/tell loop
+        // only asks about the rest of the generation.
+        let requests = original.ask();
+        original.tell(&requests[0], 1.0);
+        let mut bytes = Vec::new();
+        original.write_checkpoint(&mut bytes)?;
+        let mut copy = Population::read_checkpoint(bytes.as_slice(), config)?;
+        assert_eq!(copy.ask(), original.ask());
+        assert_eq!(copy.ask().len(), requests.len() - 1);
+        requests[1..].iter().for_each(|request| {
+            original.tell(request, 1.0);
+            copy.tell(request, 1.0);
+        });
+        assert_eq!(copy.genomes(), original.genomes());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_genome_file() -> io::Result<()> {
+        let mut tracker = InnovationTracker::new();
+        let mut genome = Genome::with_io(
+            2,
+            1,
+            ActivationFunction::LeakyRelu(0.1),
+            &mut tracker,
+        );
+        genome.add_connection(0, 2, 0.5, ConnectionType::Normal, &mut tracker);
+        genome.add_connection(
+            2,
+            2,
+            -1.5,
+            ConnectionType::Recurrent,
+            &mut tracker,
+        );
+        genome.connections[1].enabled = false;
+        genome.nodes[2].memory = Some(MemoryCell::default());
+        genome.connections[1].plasticity = Some(HebbianRule {
+            b: -0.25,
+            ..HebbianRule::default()
+        });
+        let mut bytes = Vec::new();
+        genome.write_to(&mut bytes)?;
+        assert_eq!(Genome::read_from(bytes.as_slice())?, genome);
+
+        bytes[0] = b'X';
+        assert!(Genome::read_from(bytes.as_slice()).is_err());
+
+        // Custom functions are saved by their registered name.
+        fn halve(x: f32) -> f32 {
+            x / 2.0
+        }
+        genome.nodes[0].func = ActivationFunction::Custom(halve);
+        assert!(genome.write_to(&mut Vec::new()).is_err());
+        crate::register_activation("checkpoint_halve", halve);
+        let mut bytes = Vec::new();
+        genome.write_to(&mut bytes)?;
+        let copy = Genome::read_from(bytes.as_slice())?;
+        assert_eq!(copy.nodes[0].func.apply(3.0), 1.5);
+        Ok(())
+    }
+
+    #[test]
+    fn test_compact_genomes() -> io::Result<()> {
+        let config = PopulationConfig {
+            population_size: 50,
+            num_inputs: 4,
+            num_outputs: 2,
+            seed: Some(2),
+            ..PopulationConfig::default()
+        };
+        let mut population = Population::new(config);
+        let fitnesses = vec![1.0; 50];
+        (0..5).for_each(|_| population.advance_generation(&fitnesses));
+
+        // Out of order, with large innovation numbers and every
+        // optional field.
+        let mut genomes = population.genomes().to_vec();
+        genomes[0].connections.reverse();
+        genomes[0].connections[0].innovation = u32::MAX;
+        genomes[0].connections[1].connection_type = ConnectionType::Recurrent;
+        genomes[0].connections[1].plasticity = Some(HebbianRule::default());
+        genomes[0].nodes[0].memory = Some(MemoryCell::default());
+        genomes[0].mutation_rates =
+            Some(MutationRates::from_config(&MutationConfig::default()));
+
+        let mut bytes = Vec::new();
+        Genome::write_compact_list(&genomes, &mut bytes)?;
+        assert_eq!(Genome::read_compact_list(bytes.as_slice())?, genomes);
+
+        let mut population_bytes = Vec::new();
+        population.write_compact_genomes(&mut population_bytes)?;
+        let mut checkpoint_bytes = Vec::new();
+        population.write_checkpoint(&mut checkpoint_bytes)?;
+        assert!(2 * population_bytes.len() < checkpoint_bytes.len());
+
+        // Any corruption is caught by the checksum, if not before.
+        let middle = bytes.len() / 2;
+        bytes[middle] ^= 0x10;
+        assert!(Genome::read_compact_list(bytes.as_slice()).is_err());
+        bytes[middle] ^= 0x10;
+        bytes.pop();
+        assert!(Genome::read_compact_list(bytes.as_slice()).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,298 @@
+use rand::seq::index::sample;
+use rand::Rng;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use crate::genome::*;
+use crate::hall_of_fame::*;
+use crate::neural_net::*;
+use crate::population::*;
+
+// A match between a network of each population, returning the score
+// of each.  Competitive games reward one at the expense of the other,
+// while cooperative games reward both alike.  Any
+// `Fn(&mut A, &mut B) -> (f32, f32)` is a game.
+pub trait Game<A, B> {
+    fn play(&self, first: &mut A, second: &mut B) -> (f32, f32);
+}
+
+impl<A, B, F: Fn(&mut A, &mut B) -> (f32, f32)> Game<A, B> for F {
+    fn play(&self, first: &mut A, second: &mut B) -> (f32, f32) {
+        self(first, second)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct CoevolutionConfig {
+    pub first: PopulationConfig,
+    pub second: PopulationConfig,
+    // Number of genomes of the other population's current generation
+    // that each genome plays against.
+    pub num_opponents: usize,
+    // Number of past champions of the other population that each
+    // genome also plays against, so that neither population can win
+    // by forgetting how to beat strategies of the past.
+    pub num_champion_opponents: usize,
+    // Number of past champions kept for each population.
+    pub hall_of_fame_capacity: usize,
+}
+
+impl Default for CoevolutionConfig {
+    fn default() -> Self {
+        Self {
+            first: PopulationConfig::default(),
+            second: PopulationConfig::default(),
+            num_opponents: 5,
+            num_champion_opponents: 3,
+            hall_of_fame_capacity: 50,
+        }
+    }
+}
+
+// Two populations whose fitness comes from games against each other.
+// All genomes of a population play the same opponents in a
+// generation, so that their fitnesses are comparable.
+#[derive(Debug)]
+pub struct Coevolution {
+    pub config: CoevolutionConfig,
+    first: Population,
+    second: Population,
+    // Best genome of each past generation of each population.
+    first_champions: HallOfFame,
+    second_champions: HallOfFame,
+}
+
+impl Coevolution {
+    pub fn new(config: CoevolutionConfig) -> Self {
+        let capacity = config.hall_of_fame_capacity;
+        Self {
+            first: Population::new(config.first.clone()),
+            second: Population::new(config.second.clone()),
+            first_champions: HallOfFame::new(capacity),
+            second_champions: HallOfFame::new(capacity),
+            config,
+        }
+    }
+
+    pub fn first(&self) -> &Population {
+        &self.first
+    }
+
+    pub fn second(&self) -> &Population {
+        &self.second
+    }
+
+    // For adding reporters and the like.
+    pub fn first_mut(&mut self) -> &mut Population {
+        &mut self.first
+    }
+
+    pub fn second_mut(&mut self) -> &mut Population {
+        &mut self.second
+    }
+
+    pub fn first_champions(&self) -> &HallOfFame {
+        &self.first_champions
+    }
+
+    pub fn second_champions(&self) -> &HallOfFame {
+        &self.second_champions
+    }
+
+    pub fn generation(&self) -> usize {
+        self.first.generation()
+    }
+
+    // Fitness of each genome of both populations, as its mean score
+    // over its games.  Opponents are drawn afresh each time, by the
+    // random number generator of the population that plays them.
+    pub fn evaluate<A, B, G>(
+        &mut self,
+        game: &G,
+    ) -> Result<(Vec<f32>, Vec<f32>), Error>
+    where
+        A: NeuralNet + Clone + Send + Sync,
+        B: NeuralNet + Clone + Send + Sync,
+        G: Game<A, B> + Sync,
+    {
+        let (num_opponents, num_champions) = (
+            self.config.num_opponents,
+            self.config.num_champion_opponents,
+        );
+        let first_opponents = choose_opponents(
+            &self.second,
+            &self.second_champions,
+            num_opponents,
+            num_champions,
+            &mut self.first.rng,
+        );
+        let second_opponents = choose_opponents(
+            &self.first,
+            &self.first_champions,
+            num_opponents,
+            num_champions,
+            &mut self.second.rng,
+        );
+
+        let first_nets = build_all::<A>(self.first.genomes())?;
+        let second_nets = build_all::<B>(self.second.genomes())?;
+        let first_opponents = build_all::<B>(&first_opponents)?;
+        let second_opponents = build_all::<A>(&second_opponents)?;
+
+        let first_fitnesses =
+            mean_scores(&first_nets, &first_opponents, |net, opponent| {
+                game.play(net, opponent).0
+            });
+        let second_fitnesses =
+            mean_scores(&second_nets, &second_opponents, |net, opponent| {
+                game.play(opponent, net).1
+            });
+        Ok((first_fitnesses, second_fitnesses))
+    }
+
+    // Archives the champion of each population, given the fitnesses
+    // of `evaluate`, then advances both.
+    pub fn advance_generation(
+        &mut self,
+        first_fitnesses: &[f32],
+        second_fitnesses: &[f32],
+    ) {
+        let generation = self.generation();
+        self.first_champions.record(
+            generation,
+            self.first.genomes(),
+            first_fitnesses,
+            &[],
+        );
+        self.second_champions.record(
+            generation,
+            self.second.genomes(),
+            second_fitnesses,
+            &[],
+        );
+        self.first.advance_generation(first_fitnesses);
+        self.second.advance_generation(second_fitnesses);
+    }
+
+    // Evaluates both populations and advances, returning the
+    // fitnesses of the evaluated generation.
+    pub fn run_generation<A, B, G>(
+        &mut self,
+        game: &G,
+    ) -> Result<(Vec<f32>, Vec<f32>), Error>
+    where
+        A: NeuralNet + Clone + Send + Sync,
+        B: NeuralNet + Clone + Send + Sync,
+        G: Game<A, B> + Sync,
+    {
+        let (first, second) = self.evaluate(game)?;
+        self.advance_generation(&first, &second);
+        Ok((first, second))
+    }
+}
+
+// Up to `num_opponents` distinct genomes of the current generation of
+// `population`, followed by up to `num_champions` distinct past
+// champions.
+fn choose_opponents<R: Rng>(
+    population: &Population,
+    champions: &HallOfFame,
+    num_opponents: usize,
+    num_champions: usize,
+    rng: &mut R,
+) -> Vec<Genome> {
+    let genomes = population.genomes();
+    let champions = champions.champions().collect::<Vec<_>>();
+    let current = sample(rng, genomes.len(), num_opponents.min(genomes.len()))
+        .into_iter()
+        .map(|i| genomes[i].clone())
+        .collect::<Vec<_>>();
+    let past = sample(rng, champions.len(), num_champions.min(champions.len()))
+        .into_iter()
+        .map(|i| champions[i].genome.clone())
+        .collect::<Vec<_>>();
+    current.into_iter().chain(past).collect()
+}
+
+fn build_all<N: NeuralNet>(genomes: &[Genome]) -> Result<Vec<N>, Error> {
+    genomes
+        .iter()
+        .map(|genome| genome.to_builder().build::<N>())
+        .collect()
+}
+
+// Mean score of each network over a game against every opponent.
+// Each game is played by fresh copies of both networks, so that no
+// state carries over between games.
+fn mean_scores<N, M, P>(nets: &[N], opponents: &[M], play: P) -> Vec<f32>
+where
+    N: Clone + Sync,
+    M: Clone + Sync,
+    P: Fn(&mut N, &mut M) -> f32 + Sync,
+{
+    let score = |net: &N| {
+        let total = opponents
+            .iter()
+            .map(|opponent| play(&mut net.clone(), &mut opponent.clone()))
+            .sum::<f32>();
+        total / opponents.len().max(1) as f32
+    };
+
+    #[cfg(feature = "parallel")]
+    {
+        nets.par_iter().map(score).collect()
+    }
+
+    #[cfg(not(feature = "parallel"))]
+    {
+        nets.iter().map(score).collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::*;
+
+    #[test]
+    fn test_coevolution() -> Result<(), Error> {
+        let population = PopulationConfig {
+            population_size: 10,
+            num_inputs: 1,
+            num_outputs: 1,
+            output_activation: ActivationFunction::Identity,
+            seed: Some(2),
+            ..PopulationConfig::default()
+        };
+        let config = CoevolutionConfig {
+            first: population.clone(),
+            second: PopulationConfig {
+                seed: Some(3),
+                ..population
+            },
+            num_opponents: 3,
+            num_champion_opponents: 2,
+            hall_of_fame_capacity: 4,
+        };
+        let mut coevolution = Coevolution::new(config);
+
+        // The larger output wins.
+        let game = |a: &mut ConsecutiveNeuralNet,
+                    b: &mut ConsecutiveNeuralNet| {
+            let (a, b) = (a.evaluate(&[1.0])[0], b.evaluate(&[1.0])[0]);
+            (a - b, b - a)
+        };
+        let (first, second) = coevolution.evaluate(&game)?;
+        assert_eq!(first.len(), 10);
+        assert_eq!(second.len(), 10);
+
+        (0..6)
+            .try_for_each(|_| coevolution.run_generation(&game).map(|_| ()))?;
+        assert_eq!(coevolution.generation(), 6);
+        assert_eq!(coevolution.first_champions().len(), 4);
+        assert_eq!(coevolution.second_champions().len(), 4);
+        let oldest = coevolution.first_champions().entries().next().unwrap();
+        assert_eq!(oldest.generation, 2);
+        Ok(())
+    }
+}
@@ -0,0 +1,187 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::neural_net::*;
+
+// A point in the substrate that a CPPN is queried over, usually
+// within [-1, 1] on each axis.
+pub type Point = (f32, f32);
+
+// What each input node of a CPPN receives, for a query between two
+// points `a` and `b`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum CppnInput {
+    X1,
+    Y1,
+    X2,
+    Y2,
+    // Euclidean distance between the two points.
+    Distance,
+    // Always one.
+    Bias,
+}
+
+impl CppnInput {
+    // The inputs used by HyperNEAT, in the usual order.
+    pub const STANDARD: [CppnInput; 6] = [
+        CppnInput::X1,
+        CppnInput::Y1,
+        CppnInput::X2,
+        CppnInput::Y2,
+        CppnInput::Distance,
+        CppnInput::Bias,
+    ];
+
+    pub fn value(&self, a: Point, b: Point) -> f32 {
+        use CppnInput::*;
+        match self {
+            X1 => a.0,
+            Y1 => a.1,
+            X2 => b.0,
+            Y2 => b.1,
+            Distance => (b.0 - a.0).hypot(b.1 - a.1),
+            Bias => 1.0,
+        }
+    }
+}
+
+// Evenly spaced points covering [-1, 1] on both axes, `resolution`
+// points to a side, in row-major order.  A single point per side is
+// placed at the center.
+pub fn grid_points(resolution: usize) -> Vec<Point> {
+    let coord = |i: usize| match resolution {
+        1 => 0.0,
+        _ => -1.0 + 2.0 * i as f32 / (resolution - 1) as f32,
+    };
+    (0..resolution)
+        .flat_map(|row| {
+            (0..resolution).map(move |col| (coord(col), coord(row)))
+        })
+        .collect()
+}
+
+// A network treated as a Compositional Pattern Producing Network,
+// queried with pairs of points rather than raw input vectors.
+#[derive(Debug)]
+pub struct Cppn<N> {
+    net: N,
+    inputs: Vec<CppnInput>,
+}
+
+impl<N: NeuralNet> Cppn<N> {
+    // `inputs` gives the meaning of each input node of `net`, in
+    // order.
+    pub fn new(net: N, inputs: &[CppnInput]) -> Result<Self, Error> {
+        if net.num_inputs() == inputs.len() {
+            Ok(Self {
+                net,
+                inputs: inputs.to_vec(),
+            })
+        } else {
+            Err(Error::InputSizeMismatch {
+                expected: net.num_inputs(),
+                got: inputs.len(),
+            })
+        }
+    }
+
+    // Builds a CPPN with the standard inputs.
+    pub fn build_from(builder: &NeuralNetBuilder) -> Result<Self, Error> {
+        Self::new(N::build_from(builder)?, &CppnInput::STANDARD)
+    }
+
+    pub fn net(&self) -> &N {
+        &self.net
+    }
+
+    pub fn net_mut(&mut self) -> &mut N {
+        &mut self.net
+    }
+
+    pub fn into_inner(self) -> N {
+        self.net
+    }
+
+    pub fn inputs(&self) -> &[CppnInput] {
+        &self.inputs
+    }
+
+    fn input_values(&self, a: Point, b: Point) -> Vec<f32> {
+        self.inputs.iter().map(|input| input.value(a, b)).collect()
+    }
+
+    pub fn query(&mut self, a: Point, b: Point) -> Vec<f32> {
+        let inputs = self.input_values(a, b);
+        self.net.evaluate(&inputs)
+    }
+
+    // Same as calling `query` for each pair, but evaluated as a
+    // single batch.
+    pub fn query_batch(&mut self, pairs: &[(Point, Point)]) -> Vec<Vec<f32>> {
+        let inputs = pairs
+            .iter()
+            .map(|(a, b)| self.input_values(*a, *b))
+            .collect::<Vec<_>>();
+        self.net.evaluate_batch(&inputs)
+    }
+
+    // Queries every pair of a point in `sources` with a point in
+    // `targets`, with the results in row-major order by source.
+    pub fn query_grid(
+        &mut self,
+        sources: &[Point],
+        targets: &[Point],
+    ) -> Vec<Vec<f32>> {
+        let pairs = sources
+            .iter()
+            .flat_map(|a| targets.iter().map(move |b| (*a, *b)))
+            .collect::<Vec<_>>();
+        self.query_batch(&pairs)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ConsecutiveNeuralNet;
+
+    #[test]
+    fn test_query() -> Result<(), Error> {
+        let mut builder = NeuralNetBuilder::new();
+        builder
+            .set_default_activation(ActivationFunction::Identity)
+            .add_inputs(6)
+            .add_nodes(NodeType::Output, 2)
+            // The first output is x2 - x1, the second the distance
+            // plus bias.
+            .add_normal_connection_raw(2, 6, 1.0)
+            .add_normal_connection_raw(0, 6, -1.0)
+            .add_normal_connection_raw(4, 7, 1.0)
+            .add_normal_connection_raw(5, 7, 1.0);
+        let mut cppn = Cppn::<ConsecutiveNeuralNet>::build_from(&builder)?;
+
+        assert_eq!(cppn.query((0.0, 0.0), (3.0, 4.0)), vec![3.0, 6.0]);
+
+        let points = grid_points(3);
+        assert_eq!(points.len(), 9);
+        assert_eq!(points[0], (-1.0, -1.0));
+        assert_eq!(points[5], (1.0, 0.0));
+
+        let res = cppn.query_grid(&points[..2], &points);
+        assert_eq!(res.len(), 18);
+        assert_eq!(res[1], cppn.query(points[0], points[1]));
+        assert_eq!(res[9 + 8], cppn.query(points[1], points[8]));
+
+        let net = cppn.into_inner();
+        let res = Cppn::new(net, &[CppnInput::X1]);
+        assert!(matches!(
+            res,
+            Err(Error::InputSizeMismatch {
+                expected: 6,
+                got: 1
+            })
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,203 @@
+use std::collections::HashMap;
+
+use rand::Rng;
+
+use crate::genome::*;
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct CrossoverConfig {
+    // If true, genes present in both parents get the average of both
+    // weights.  Otherwise, each matching gene is copied from a
+    // randomly chosen parent.
+    pub average_matching_weights: bool,
+    // Probability that a gene is disabled in the child if it is
+    // disabled in either parent.
+    pub inherit_disabled_prob: f32,
+}
+
+impl Default for CrossoverConfig {
+    fn default() -> Self {
+        Self {
+            average_matching_weights: false,
+            inherit_disabled_prob: 0.75,
+        }
+    }
+}
+
+impl Genome {
+    // Produces a child by aligning the genes of both parents by
+    // innovation number.  Matching genes are inherited from either
+    // parent, while disjoint and excess genes are only inherited
+    // from the fitter parent, as are self-adapting mutation rates.  If
+    // both parents are equally fit, the smaller genome is treated as
+    // the fitter one.
+    pub fn crossover<R: Rng + ?Sized>(
+        &self,
+        self_fitness: f32,
+        other: &Genome,
+        other_fitness: f32,
+        config: &CrossoverConfig,
+        rng: &mut R,
+    ) -> Genome {
+        let self_is_fitter = if self_fitness == other_fitness {
+            self.connections.len() <= other.connections.len()
+        } else {
+            self_fitness > other_fitness
+        };
+        let (fitter, weaker) = if self_is_fitter {
+            (self, other)
+        } else {
+            (other, self)
+        };
+
+        let weaker_connections = weaker
+            .connections
+            .iter()
+            .map(|conn| (conn.innovation, conn))
+            .collect::<HashMap<_, _>>();
+
+        let connections = fitter
+            .connections
+            .iter()
+            .map(|conn| match weaker_connections.get(&conn.innovation) {
+                None => *conn,
+                Some(matching) => {
+                    let mut child =
+                        if rng.gen::<bool>() { *conn } else { **matching };
+                    if config.average_matching_weights {
+                        child.weight = (conn.weight + matching.weight) / 2.0;
+                    }
+                    child.enabled = if !conn.enabled || !matching.enabled {
+                        rng.gen::<f32>() >= config.inherit_disabled_prob
+                    } else {
+                        true
+                    };
+                    child
+                }
+            })
+            .collect();
+
+        let weaker_nodes = weaker
+            .nodes
+            .iter()
+            .map(|node| (node.id, node))
+            .collect::<HashMap<_, _>>();
+
+        let nodes = fitter
+            .nodes
+            .iter()
+            .map(|node| match weaker_nodes.get(&node.id) {
+                Some(matching) if rng.gen::<bool>() => **matching,
+                _ => *node,
+            })
+            .collect();
+
+        // Genes disabled in the fitter parent may be enabled in the
+        // child, which can close a loop.
+        let mut child = Genome {
+            nodes,
+            connections,
+            mutation_rates: fitter.mutation_rates,
+        };
+        child.break_loops();
+        child
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::neural_net::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_crossover_alignment() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut tracker = InnovationTracker::new();
+        let func = ActivationFunction::Sigmoid;
+
+        let mut fitter = Genome::with_io(2, 1, func, &mut tracker);
+        fitter.add_connection(0, 2, 1.0, ConnectionType::Normal, &mut tracker);
+        fitter.add_connection(1, 2, 1.0, ConnectionType::Normal, &mut tracker);
+        let mut weaker = fitter.clone();
+        weaker.connections.iter_mut().for_each(|c| c.weight = 3.0);
+
+        // Only in the fitter parent
+        let hidden = fitter.add_node(NodeType::Hidden, func, &mut tracker);
+        fitter.add_connection(
+            0,
+            hidden,
+            1.0,
+            ConnectionType::Normal,
+            &mut tracker,
+        );
+        // Only in the weaker parent
+        weaker.add_connection(
+            2,
+            2,
+            1.0,
+            ConnectionType::Recurrent,
+            &mut tracker,
+        );
+
+        let config = CrossoverConfig::default();
+        for _ in 0..20 {
+            let child = weaker.crossover(0.0, &fitter, 1.0, &config, &mut rng);
+            let innovations = child
+                .connections
+                .iter()
+                .map(|c| c.innovation)
+                .collect::<Vec<_>>();
+            let expected = fitter
+                .connections
+                .iter()
+                .map(|c| c.innovation)
+                .collect::<Vec<_>>();
+            assert_eq!(innovations, expected);
+            assert_eq!(child.nodes.len(), fitter.nodes.len());
+            assert!(child.connections[..2]
+                .iter()
+                .all(|c| c.weight == 1.0 || c.weight == 3.0));
+        }
+
+        let config = CrossoverConfig {
+            average_matching_weights: true,
+            ..CrossoverConfig::default()
+        };
+        let child = fitter.crossover(1.0, &weaker, 0.0, &config, &mut rng);
+        assert!(child.connections[..2].iter().all(|c| c.weight == 2.0));
+        assert_eq!(child.connections[2].weight, 1.0);
+    }
+
+    #[test]
+    fn test_crossover_breaks_loops() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut tracker = InnovationTracker::new();
+        let func = ActivationFunction::Sigmoid;
+
+        let mut genome = Genome::with_io(1, 1, func, &mut tracker);
+        let a = genome.add_node(NodeType::Hidden, func, &mut tracker);
+        let b = genome.add_node(NodeType::Hidden, func, &mut tracker);
+        genome.add_connection(0, a, 1.0, ConnectionType::Normal, &mut tracker);
+        genome.add_connection(a, b, 1.0, ConnectionType::Normal, &mut tracker);
+        genome.add_connection(b, a, 1.0, ConnectionType::Normal, &mut tracker);
+        genome.add_connection(b, 1, 1.0, ConnectionType::Normal, &mut tracker);
+
+        let mut fitter = genome.clone();
+        fitter.connections[1].enabled = false;
+        let mut weaker = genome;
+        weaker.connections[2].enabled = false;
+
+        let config = CrossoverConfig {
+            inherit_disabled_prob: 0.0,
+            ..CrossoverConfig::default()
+        };
+        let child = fitter.crossover(1.0, &weaker, 0.0, &config, &mut rng);
+        assert!(child.connections.iter().any(|c| !c.enabled));
+        assert!(child
+            .to_builder()
+            .build::<crate::ConsecutiveNeuralNet>()
+            .is_ok());
+    }
+}
@@ -0,0 +1,387 @@
+use std::collections::VecDeque;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Condvar, Mutex};
+use std::time::Duration;
+
+use crate::genome::*;
+use crate::population::*;
+
+// Fitness evaluation on other processes or machines, built with the
+// `distributed` feature.  A coordinator sends each genome, in the
+// encoding of genome files, over a connection to a worker, which
+// answers with its fitness as a little-endian f32.  Connections stay
+// open for any number of genomes.
+
+// Opens connections to workers, such as over TCP.
+pub trait Transport: Sync {
+    type Stream: Read + Write;
+
+    fn num_workers(&self) -> usize;
+
+    // Connection to worker `worker`, on which reads and writes fail
+    // after `timeout`.
+    fn connect(
+        &self,
+        worker: usize,
+        timeout: Duration,
+    ) -> io::Result<Self::Stream>;
+}
+
+#[derive(Debug, Clone)]
+pub struct TcpTransport {
+    pub workers: Vec<SocketAddr>,
+}
+
+impl Transport for TcpTransport {
+    type Stream = TcpStream;
+
+    fn num_workers(&self) -> usize {
+        self.workers.len()
+    }
+
+    fn connect(
+        &self,
+        worker: usize,
+        timeout: Duration,
+    ) -> io::Result<TcpStream> {
+        let stream =
+            TcpStream::connect_timeout(&self.workers[worker], timeout)?;
+        stream.set_read_timeout(Some(timeout))?;
+        stream.set_write_timeout(Some(timeout))?;
+        stream.set_nodelay(true)?;
+        Ok(stream)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct DistributedConfig {
+    // Longest wait for a worker to connect, accept a genome or answer
+    // with its fitness.
+    pub timeout: Duration,
+    // Number of times a genome is sent again, to any worker, after a
+    // failed attempt.
+    pub retries: usize,
+}
+
+impl Default for DistributedConfig {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(60),
+            retries: 2,
+        }
+    }
+}
+
+// Hands genomes out to the workers of a transport, one at a time per
+// worker, until every genome has a fitness.
+#[derive(Debug, Clone)]
+pub struct Coordinator<T> {
+    pub transport: T,
+    pub config: DistributedConfig,
+}
+
+// Sends one genome and waits for its fitness.
+fn request<S: Read + Write>(
+    stream: &mut S,
+    genome: &Genome,
+) -> io::Result<f32> {
+    genome.write_to(&mut *stream)?;
+    let mut fitness = [0; 4];
+    stream.read_exact(&mut fitness)?;
+    Ok(f32::from_le_bytes(fitness))
+}
+
+impl<T: Transport> Coordinator<T> {
+    pub fn new(transport: T, config: DistributedConfig) -> Self {
+        Self { transport, config }
+    }
+
+    // Fitness of each genome, in the same order.  Fails with the last
+    // error of a genome whose every attempt failed, or of the last
+    // worker that stopped accepting connections while genomes were
+    // left.
+    pub fn evaluate(&self, genomes: &[Genome]) -> io::Result<Vec<f32>> {
+        let num_workers = self.transport.num_workers();
+        let work = Work {
+            state: Mutex::new(WorkState {
+                queue: (0..genomes.len()).map(|i| (i, 0)).collect(),
+                in_flight: 0,
+                num_workers,
+                failure: None,
+            }),
+            changed: Condvar::new(),
+        };
+        let fitnesses = Mutex::new(vec![None; genomes.len()]);
+
+        std::thread::scope(|scope| {
+            (0..num_workers).for_each(|worker| {
+                let (work, fitnesses) = (&work, &fitnesses);
+                scope.spawn(move || {
+                    self.serve_queue(worker, genomes, work, fitnesses)
+                });
+            });
+        });
+
+        let failure = work.state.into_inner().unwrap().failure;
+        fitnesses
+            .into_inner()
+            .unwrap()
+            .into_iter()
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| {
+                failure.unwrap_or_else(|| {
+                    io::Error::new(io::ErrorKind::NotConnected, "No workers")
+                })
+            })
+    }
+
+    // Sends genomes from the queue to one worker until every genome
+    // is done, reconnecting after every failure.  While the queue is
+    // empty but other workers still have genomes in flight, the
+    // worker waits, since those genomes may fail and come back.  A
+    // worker that can't be connected to more than `retries` times in a
+    // row is given up on, without counting against the genomes, which
+    // are left to the other workers.
+    fn serve_queue(
+        &self,
+        worker: usize,
+        genomes: &[Genome],
+        work: &Work,
+        fitnesses: &Mutex<Vec<Option<f32>>>,
+    ) {
+        let mut stream = None;
+        let mut refused = 0;
+        while let Some((index, attempts)) = work.next() {
+            let connected = match stream {
+                Some(ref mut stream) => stream,
+                None => {
+                    match self.transport.connect(worker, self.config.timeout) {
+                        Ok(new) => {
+                            refused = 0;
+                            stream.insert(new)
+                        }
+                        Err(err) => {
+                            refused += 1;
+                            let give_up = refused > self.config.retries;
+                            work.finish(|state| {
+                                state.queue.push_front((index, attempts));
+                                if give_up {
+                                    state.num_workers -= 1;
+                                    // Only the last worker's error is
+                                    // kept, as the others may still
+                                    // manage without this one.
+                                    if state.num_workers == 0 {
+                                        state.failure = Some(err);
+                                    }
+                                }
+                            });
+                            if give_up {
+                                return;
+                            }
+                            continue;
+                        }
+                    }
+                }
+            };
+            match request(connected, &genomes[index]) {
+                Ok(fitness) => {
+                    fitnesses.lock().unwrap()[index] = Some(fitness);
+                    work.finish(|_| {});
+                }
+                Err(err) => {
+                    stream = None;
+                    work.finish(|state| {
+                        if attempts < self.config.retries {
+                            state.queue.push_back((index, attempts + 1));
+                        } else {
+                            state.failure = Some(err);
+                            state.queue.clear();
+                        }
+                    });
+                }
+            }
+        }
+    }
+}
+
+// Genomes shared between the workers of one evaluation.
+struct Work {
+    state: Mutex<WorkState>,
+    // Signalled whenever a genome is done or queued again.
+    changed: Condvar,
+}
+
+struct WorkState {
+    // Indices of the genomes to send, with the number of failed
+    // attempts of each.
+    queue: VecDeque<(usize, usize)>,
+    // Genomes sent to a worker, but not yet done or queued again.
+    in_flight: usize,
+    // Workers that haven't been given up on.
+    num_workers: usize,
+    failure: Option<io::Error>,
+}
+
+impl Work {
+    // The next genome to send, waiting while other workers may still
+    // queue one again, or `None` once there are none left.
+    fn next(&self) -> Option<(usize, usize)> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if let Some(next) = state.queue.pop_front() {
+                state.in_flight += 1;
+                return Some(next);
+            }
+            if state.in_flight == 0 {
+                return None;
+            }
+            state = self.changed.wait(state).unwrap();
+        }
+    }
+
+    // Ends the attempt at a genome from `next`, with `f` queueing it
+    // again if it should be.
+    fn finish(&self, f: impl FnOnce(&mut WorkState)) {
+        let mut state = self.state.lock().unwrap();
+        state.in_flight -= 1;
+        f(&mut state);
+        self.changed.notify_all();
+    }
+}
+
+impl Population {
+    // Same as `evaluate`, but with fitnesses computed by the workers
+    // of `coordinator`.
+    pub fn evaluate_distributed<T: Transport>(
+        &self,
+        coordinator: &Coordinator<T>,
+    ) -> io::Result<Vec<f32>> {
+        coordinator.evaluate(self.genomes())
+    }
+}
+
+// Answers the requests of a coordinator on one connection until it
+// is closed.
+pub fn serve_connection<S, F>(stream: S, fitness: &F) -> io::Result<()>
+where
+    S: Read + Write,
+    F: Fn(&Genome) -> f32,
+{
+    let mut stream = BufReader::new(stream);
+    loop {
+        // A closed connection is the normal end, so only ends that
+        // aren't between two genomes are errors.
+        if stream.fill_buf()?.is_empty() {
+            return Ok(());
+        }
+        let genome = Genome::read_from(&mut stream)?;
+        let result = fitness(&genome).to_le_bytes();
+        stream.get_mut().write_all(&result)?;
+        stream.get_mut().flush()?;
+    }
+}
+
+// Runs a worker that evaluates genomes for coordinators connecting to
+// `listener`, one connection at a time, until accepting fails.
+// Failed connections are dropped, since the coordinator retries their
+// genomes elsewhere.
+pub fn serve_worker<F>(listener: &TcpListener, fitness: F) -> io::Result<()>
+where
+    F: Fn(&Genome) -> f32,
+{
+    loop {
+        let (stream, _) = listener.accept()?;
+        stream.set_nodelay(true)?;
+        let _ = serve_connection(stream, &fitness);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::*;
+
+    #[test]
+    fn test_distributed_evaluation() -> io::Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let worker = listener.local_addr()?;
+        // Nothing listens here, so every attempt on it fails.
+        let dead = TcpListener::bind("127.0.0.1:0")?.local_addr()?;
+        std::thread::spawn(move || {
+            serve_worker(&listener, |genome| {
+                genome.connections.iter().map(|c| c.weight).sum()
+            })
+        });
+
+        let population = Population::new(PopulationConfig {
+            population_size: 12,
+            num_inputs: 2,
+            num_outputs: 1,
+            seed: Some(3),
+            ..PopulationConfig::default()
+        });
+        let expected = population
+            .genomes()
+            .iter()
+            .map(|g| g.connections.iter().map(|c| c.weight).sum::<f32>())
+            .collect::<Vec<_>>();
+
+        let config = DistributedConfig {
+            timeout: Duration::from_secs(5),
+            retries: 12,
+        };
+        let transport = TcpTransport {
+            workers: vec![dead, worker],
+        };
+        let coordinator = Coordinator::new(transport, config.clone());
+        assert_eq!(population.evaluate_distributed(&coordinator)?, expected);
+
+        let transport = TcpTransport {
+            workers: vec![dead],
+        };
+        let coordinator = Coordinator::new(transport, config);
+        assert!(population.evaluate_distributed(&coordinator).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_retry_on_other_worker() -> io::Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let worker = listener.local_addr()?;
+        std::thread::spawn(move || serve_worker(&listener, |_| 1.0));
+        // Reads one genome, then closes the connection without an
+        // answer, slowly enough that the other worker has emptied the
+        // queue by then, and stops listening.
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let flaky = listener.local_addr()?;
+        let failed = std::thread::spawn(move || -> io::Result<()> {
+            let (mut stream, _) = listener.accept()?;
+            Genome::read_from(&mut stream)?;
+            std::thread::sleep(Duration::from_millis(100));
+            Ok(())
+        });
+
+        let population = Population::new(PopulationConfig {
+            population_size: 30,
+            num_inputs: 2,
+            num_outputs: 1,
+            seed: Some(3),
+            ..PopulationConfig::default()
+        });
+        // The failed genome can't go back to the flaky worker, which
+        // is given up on, so only the other worker can do it.
+        let config = DistributedConfig {
+            timeout: Duration::from_secs(5),
+            retries: 1,
+        };
+        let transport = TcpTransport {
+            workers: vec![flaky, worker],
+        };
+        let coordinator = Coordinator::new(transport, config);
+        let fitnesses = population.evaluate_distributed(&coordinator)?;
+        assert_eq!(fitnesses, vec![1.0; 30]);
+        assert!(failed.join().unwrap().is_ok());
+        Ok(())
+    }
+}
@@ -0,0 +1,112 @@
+use std::fmt::Write;
+
+use crate::neural_net::*;
+
+impl NeuralNetBuilder {
+    // Graphviz DOT description of the network.  Inputs and bias nodes
+    // are drawn as boxes on the left, outputs as double circles on the
+    // right, recurrent connections as dashed edges, and disabled
+    // connections in gray.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::new();
+        self.write_dot(&mut dot)
+            .expect("Writing to a String cannot fail");
+        dot
+    }
+
+    fn write_dot(&self, dot: &mut String) -> std::fmt::Result {
+        writeln!(dot, "digraph {{")?;
+        writeln!(dot, "    rankdir=LR;")?;
+
+        self.nodes.iter().enumerate().try_for_each(|(i, node)| {
+            use NodeType::*;
+            match node.node_type {
+                // Sensors pass their value through unchanged, so the
+                // activation function is irrelevant.
+                Input | Bias => writeln!(
+                    dot,
+                    "    n{} [label=\"{}: {:?}\", shape=box];",
+                    i, i, node.node_type
+                ),
+                Output => writeln!(
+                    dot,
+                    "    n{} [label=\"{}: {:?}\\n{:?}\", shape=doublecircle];",
+                    i, i, node.node_type, node.func
+                ),
+                Hidden => writeln!(
+                    dot,
+                    "    n{} [label=\"{}: {:?}\\n{:?}\"];",
+                    i, i, node.node_type, node.func
+                ),
+            }
+        })?;
+
+        let rank = |dot: &mut String,
+                    rank: &str,
+                    types: &[NodeType]|
+         -> std::fmt::Result {
+            let nodes = self
+                .nodes
+                .iter()
+                .enumerate()
+                .filter(|(_i, n)| types.contains(&n.node_type))
+                .map(|(i, _n)| format!(" n{};", i))
+                .collect::<String>();
+            if nodes.is_empty() {
+                Ok(())
+            } else {
+                writeln!(dot, "    {{ rank={};{} }}", rank, nodes)
+            }
+        };
+        rank(dot, "source", &[NodeType::Input, NodeType::Bias])?;
+        rank(dot, "sink", &[NodeType::Output])?;
+
+        self.connections.iter().try_for_each(|conn| {
+            let style = match conn.connection_type {
+                ConnectionType::Normal => "",
+                ConnectionType::Recurrent => ", style=dashed",
+            };
+            let color = if conn.enabled { "" } else { ", color=gray" };
+            writeln!(
+                dot,
+                "    n{} -> n{} [label=\"{}\"{}{}];",
+                conn.origin, conn.dest, conn.weight, style, color
+            )
+        })?;
+
+        writeln!(dot, "}}")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_to_dot() {
+        let mut builder = NeuralNetBuilder::new();
+        let input = builder.add_input();
+        builder.add_nodes(NodeType::Output, 1);
+        let hidden =
+            builder.add_node(NodeType::Hidden, ActivationFunction::Tanh);
+        let dot = builder
+            .add_normal_connection(input, hidden, 1.5)
+            .add_normal_connection_raw(2, 1, -0.5)
+            .add_recurrent_connection_raw(1, 2, 0.25)
+            .to_dot();
+
+        let expected = "digraph {
+    rankdir=LR;
+    n0 [label=\"0: Input\", shape=box];
+    n1 [label=\"1: Output\\nSigmoid\", shape=doublecircle];
+    n2 [label=\"2: Hidden\\nTanh\"];
+    { rank=source; n0; }
+    { rank=sink; n1; }
+    n0 -> n2 [label=\"1.5\"];
+    n2 -> n1 [label=\"-0.5\"];
+    n1 -> n2 [label=\"0.25\", style=dashed];
+}
+";
+        assert_eq!(dot, expected);
+    }
+}
@@ -0,0 +1,135 @@
+use crate::neural_net::*;
+
+// A task that networks are scored on, with higher fitness being
+// better.  Any `Fn(&mut N) -> f32` is a fitness function.
+pub trait FitnessFunction<N> {
+    fn evaluate(&self, net: &mut N) -> f32;
+}
+
+impl<N, F: Fn(&mut N) -> f32> FitnessFunction<N> for F {
+    fn evaluate(&self, net: &mut N) -> f32 {
+        self(net)
+    }
+}
+
+// What an environment returns after each action.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Step {
+    pub observation: Vec<f32>,
+    pub reward: f32,
+    // Whether the episode has ended.
+    pub done: bool,
+}
+
+// An episodic task in the style of reinforcement learning, where the
+// network acts on each observation in turn.
+pub trait Environment {
+    // Starts a new episode, returning the first observation.
+    fn reset(&mut self) -> Vec<f32>;
+    fn step(&mut self, action: &[f32]) -> Step;
+}
+
+// Fitness of a network as its total reward per episode, averaged
+// over `num_episodes` episodes of at most `max_steps` steps.  The
+// environment of each episode is made by `make_environment`, from the
+// episode's index, so that runs don't share state.
+#[derive(Debug, Clone)]
+pub struct EpisodicFitness<F> {
+    pub make_environment: F,
+    pub num_episodes: usize,
+    pub max_steps: usize,
+}
+
+impl<F> EpisodicFitness<F> {
+    pub fn new(make_environment: F) -> Self {
+        Self {
+            make_environment,
+            num_episodes: 1,
+            max_steps: 1000,
+        }
+    }
+}
+
+impl<N, E, F> FitnessFunction<N> for EpisodicFitness<F>
+where
+    N: NeuralNet,
+    E: Environment,
+    F: Fn(usize) -> E,
+{
+    fn evaluate(&self, net: &mut N) -> f32 {
+        let total = (0..self.num_episodes)
+            .map(|episode| {
+                let mut environment = (self.make_environment)(episode);
+                let mut observation = environment.reset();
+                net.reset_state();
+                let mut reward = 0.0;
+                for _ in 0..self.max_steps {
+                    let step = environment.step(&net.evaluate(&observation));
+                    reward += step.reward;
+                    if step.done {
+                        break;
+                    }
+                    observation = step.observation;
+                }
+                reward
+            })
+            .sum::<f32>();
+        total / self.num_episodes.max(1) as f32
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ConsecutiveNeuralNet;
+
+    // Rewards outputs close to a target, which moves by one each
+    // step, and ends once the target reaches `end`.
+    struct Tracking {
+        target: f32,
+        end: f32,
+    }
+
+    impl Environment for Tracking {
+        fn reset(&mut self) -> Vec<f32> {
+            self.target = 0.0;
+            vec![self.target]
+        }
+
+        fn step(&mut self, action: &[f32]) -> Step {
+            let reward = -(action[0] - self.target).abs();
+            self.target += 1.0;
+            Step {
+                observation: vec![self.target],
+                reward,
+                done: self.target >= self.end,
+            }
+        }
+    }
+
+    #[test]
+    fn test_episodic_fitness() -> Result<(), Error> {
+        let mut net = NeuralNetBuilder::new()
+            .set_default_activation(ActivationFunction::Identity)
+            .add_nodes(NodeType::Input, 1)
+            .add_nodes(NodeType::Output, 1)
+            .add_normal_connection_raw(0, 1, 2.0)
+            .build::<ConsecutiveNeuralNet>()?;
+
+        // The network outputs twice the target, so loses the target
+        // as reward at each step.
+        let mut fitness = EpisodicFitness::new(|episode| Tracking {
+            target: 0.0,
+            end: 3.0 + episode as f32,
+        });
+        assert_eq!(fitness.evaluate(&mut net), -3.0);
+        fitness.num_episodes = 2;
+        assert_eq!(fitness.evaluate(&mut net), -4.5);
+        fitness.max_steps = 2;
+        assert_eq!(fitness.evaluate(&mut net), -1.0);
+
+        let closure = |net: &mut ConsecutiveNeuralNet| net.evaluate(&[1.0])[0];
+        assert_eq!(closure.evaluate(&mut net), 2.0);
+        Ok(())
+    }
+}
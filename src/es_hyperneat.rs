@@ -0,0 +1,362 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::cppn::*;
+use crate::neural_net::*;
+
+// Substrate for ES-HyperNEAT, where only the inputs and outputs are
+// placed by hand, and hidden nodes are placed wherever the CPPN's
+// pattern of weights has the most information.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct EvolvableSubstrate {
+    pub inputs: Vec<Point>,
+    pub outputs: Vec<Point>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct EsHyperNeatConfig {
+    // CPPN output read as the connection weight.
+    pub weight_output: usize,
+    // CPPN weights are scaled by this.
+    pub max_weight: f32,
+    // The quadtree is always divided down to `initial_depth`, and
+    // further down to `max_depth` where the variance of the weights
+    // below a square exceeds `division_threshold`.
+    pub initial_depth: u32,
+    pub max_depth: u32,
+    pub division_threshold: f32,
+    // Squares whose weights vary less than this aren't explored
+    // further.
+    pub variance_threshold: f32,
+    // A connection is only expressed if its weight differs from
+    // those of neighboring points by more than this, on both sides
+    // along at least one axis.
+    pub band_threshold: f32,
+    // Number of times connections are explored out of the hidden
+    // nodes found so far.
+    pub iteration_level: u32,
+    pub hidden_activation: ActivationFunction,
+    pub output_activation: ActivationFunction,
+}
+
+impl Default for EsHyperNeatConfig {
+    fn default() -> Self {
+        Self {
+            weight_output: 0,
+            max_weight: 5.0,
+            initial_depth: 3,
+            max_depth: 5,
+            division_threshold: 0.5,
+            variance_threshold: 0.03,
+            band_threshold: 0.3,
+            iteration_level: 1,
+            hidden_activation: ActivationFunction::Sigmoid,
+            output_activation: ActivationFunction::Sigmoid,
+        }
+    }
+}
+
+// Square of the quadtree, centered at `point` and extending `width`
+// to each side, with the CPPN weight at its center.
+struct QuadPoint {
+    point: Point,
+    width: f32,
+    weight: f32,
+    children: Vec<QuadPoint>,
+}
+
+impl QuadPoint {
+    fn leaf_weights(&self, weights: &mut Vec<f32>) {
+        if self.children.is_empty() {
+            weights.push(self.weight);
+        }
+        self.children.iter().for_each(|c| c.leaf_weights(weights));
+    }
+
+    fn variance(&self) -> f32 {
+        let mut weights = Vec::new();
+        self.leaf_weights(&mut weights);
+        let n = weights.len() as f32;
+        let mean = weights.iter().sum::<f32>() / n;
+        weights.iter().map(|w| (w - mean) * (w - mean)).sum::<f32>() / n
+    }
+}
+
+// Queries a CPPN for connections to or from a fixed point.
+struct Explorer<'a, N> {
+    cppn: &'a mut Cppn<N>,
+    config: &'a EsHyperNeatConfig,
+    fixed: Point,
+    outgoing: bool,
+}
+
+impl<'a, N: NeuralNet> Explorer<'a, N> {
+    fn weight(&mut self, point: Point) -> f32 {
+        let outputs = if self.outgoing {
+            self.cppn.query(self.fixed, point)
+        } else {
+            self.cppn.query(point, self.fixed)
+        };
+        outputs
+            .get(self.config.weight_output)
+            .cloned()
+            .unwrap_or(0.0)
+    }
+
+    fn divide(&mut self, square: &mut QuadPoint, depth: u32) {
+        let (x, y) = square.point;
+        let width = square.width / 2.0;
+        square.children = [(-1.0, -1.0), (1.0, -1.0), (-1.0, 1.0), (1.0, 1.0)]
+            .iter()
+            .map(|(dx, dy)| {
+                let point = (x + dx * width, y + dy * width);
+                QuadPoint {
+                    point,
+                    width,
+                    weight: self.weight(point),
+                    children: Vec::new(),
+                }
+            })
+            .collect();
+
+        let config = self.config;
+        let divide = depth < config.initial_depth
+            || (depth < config.max_depth
+                && square.variance() > config.division_threshold);
+        if divide {
+            square
+                .children
+                .iter_mut()
+                .for_each(|child| self.divide(child, depth + 1));
+        }
+    }
+
+    // Points of the quadtree whose connections are expressed, along
+    // with their weights.
+    fn extract(&mut self, square: &QuadPoint, found: &mut Vec<(Point, f32)>) {
+        square.children.iter().for_each(|child| {
+            if child.variance() >= self.config.variance_threshold {
+                self.extract(child, found);
+                return;
+            }
+
+            let (x, y) = child.point;
+            let w = child.width;
+            let mut diff =
+                |point: Point| (child.weight - self.weight(point)).abs();
+            let (left, right) = (diff((x - w, y)), diff((x + w, y)));
+            let (down, up) = (diff((x, y - w)), diff((x, y + w)));
+            let band = left.min(right).max(down.min(up));
+            if band > self.config.band_threshold {
+                found.push((child.point, child.weight));
+            }
+        });
+    }
+
+    fn explore(&mut self) -> Vec<(Point, f32)> {
+        let mut root = QuadPoint {
+            point: (0.0, 0.0),
+            width: 1.0,
+            weight: 0.0,
+            children: Vec::new(),
+        };
+        self.divide(&mut root, 0);
+        let mut found = Vec::new();
+        self.extract(&root, &mut found);
+        found
+    }
+}
+
+fn point_key(point: Point) -> (u32, u32) {
+    (point.0.to_bits(), point.1.to_bits())
+}
+
+impl EvolvableSubstrate {
+    // Network with the inputs, then the outputs, then each hidden node
+    // that lies on a path from an input to an output.  Connections
+    // that would close a loop are recurrent.
+    pub fn decode<N: NeuralNet>(
+        &self,
+        cppn: &mut Cppn<N>,
+        config: &EsHyperNeatConfig,
+    ) -> NeuralNetBuilder {
+        let num_sensors = self.inputs.len() + self.outputs.len();
+        let mut hidden = Vec::new();
+        let mut hidden_index = HashMap::new();
+        let mut connections = Vec::new();
+
+        let explore = |cppn: &mut Cppn<N>, fixed: Point, outgoing: bool| {
+            Explorer {
+                cppn,
+                config,
+                fixed,
+                outgoing,
+            }
+            .explore()
+        };
+
+        // Hidden nodes are found by exploring out of the inputs, and
+        // then out of the newly found hidden nodes.
+        let mut unexplored = self
+            .inputs
+            .iter()
+            .enumerate()
+            .map(|(i, point)| (i, *point))
+            .collect::<Vec<_>>();
+        (0..=config.iteration_level).for_each(|_| {
+            let sources = std::mem::take(&mut unexplored);
+            sources.iter().for_each(|(origin, point)| {
+                explore(cppn, *point, true).iter().for_each(
+                    |(dest, weight)| {
+                        let index = *hidden_index
+                            .entry(point_key(*dest))
+                            .or_insert_with(|| {
+                                hidden.push(*dest);
+                                let index = num_sensors + hidden.len() - 1;
+                                unexplored.push((index, *dest));
+                                index
+                            });
+                        connections.push((*origin, index, *weight));
+                    },
+                );
+            });
+        });
+
+        // Outputs only connect to hidden nodes that were already
+        // found.
+        self.outputs.iter().enumerate().for_each(|(i, point)| {
+            explore(cppn, *point, false)
+                .iter()
+                .for_each(|(origin, weight)| {
+                    if let Some(index) = hidden_index.get(&point_key(*origin)) {
+                        connections.push((
+                            *index,
+                            self.inputs.len() + i,
+                            *weight,
+                        ));
+                    }
+                });
+        });
+
+        // Hidden nodes that don't lie on a path from an input to an
+        // output have no effect.
+        let num_nodes = num_sensors + hidden.len();
+        let reachable = |starts: &mut dyn Iterator<Item = usize>,
+                         forward: bool| {
+            let mut reached = starts.collect::<HashSet<_>>();
+            let mut queue = reached.iter().cloned().collect::<VecDeque<_>>();
+            while let Some(node) = queue.pop_front() {
+                connections.iter().for_each(|(origin, dest, _)| {
+                    let (from, to) = match forward {
+                        true => (*origin, *dest),
+                        false => (*dest, *origin),
+                    };
+                    if from == node && reached.insert(to) {
+                        queue.push_back(to);
+                    }
+                });
+            }
+            reached
+        };
+        let from_inputs = reachable(&mut (0..self.inputs.len()), true);
+        let to_outputs =
+            reachable(&mut (self.inputs.len()..num_sensors), false);
+        let mut new_index = (0..num_sensors).map(Some).collect::<Vec<_>>();
+        let mut num_kept = num_sensors;
+        (num_sensors..num_nodes).for_each(|i| {
+            if from_inputs.contains(&i) && to_outputs.contains(&i) {
+                new_index.push(Some(num_kept));
+                num_kept += 1;
+            } else {
+                new_index.push(None);
+            }
+        });
+
+        let mut builder = NeuralNetBuilder::new();
+        builder
+            .add_inputs(self.inputs.len() as u32)
+            .set_default_activation(config.output_activation)
+            .add_nodes(NodeType::Output, self.outputs.len() as u32)
+            .set_default_activation(config.hidden_activation)
+            .add_nodes(NodeType::Hidden, (num_kept - num_sensors) as u32);
+        connections.iter().for_each(|(origin, dest, weight)| {
+            if let (Some(origin), Some(dest)) =
+                (new_index[*origin], new_index[*dest])
+            {
+                let (origin, dest) = (origin as u32, dest as u32);
+                let closes_loop = origin == dest
+                    || builder.upstream_nodes(origin).contains(&dest);
+                let connection_type = match closes_loop {
+                    true => ConnectionType::Recurrent,
+                    false => ConnectionType::Normal,
+                };
+                builder.add_connection_raw(
+                    origin,
+                    dest,
+                    weight * config.max_weight,
+                    connection_type,
+                );
+            }
+        });
+
+        builder
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ConsecutiveNeuralNet;
+
+    #[test]
+    fn test_decode() -> Result<(), Error> {
+        let substrate = EvolvableSubstrate {
+            inputs: vec![(-0.5, -1.0), (0.5, -1.0)],
+            outputs: vec![(0.0, 1.0)],
+        };
+        let config = EsHyperNeatConfig::default();
+
+        // A constant pattern has no information, so nothing is
+        // expressed.
+        let mut builder = NeuralNetBuilder::new();
+        builder.add_inputs(6).add_nodes(NodeType::Output, 1);
+        let mut cppn = Cppn::<ConsecutiveNeuralNet>::build_from(&builder)?;
+        let net = substrate.decode(&mut cppn, &config);
+        assert_eq!(net.nodes.len(), 3);
+        assert!(net.connections.is_empty());
+
+        // Weights peak along y2 = 1/16 and along y1 = 1/16, so hidden
+        // nodes are placed at y = 1/16, where the quadtree has a row of
+        // points.  Those are connected to the inputs, to the output and
+        // to each other.
+        let mut builder = NeuralNetBuilder::new();
+        builder.add_inputs(6);
+        builder.add_node(NodeType::Output, ActivationFunction::Identity);
+        builder
+            .set_default_activation(ActivationFunction::Gaussian)
+            .add_nodes(NodeType::Hidden, 2)
+            .add_normal_connection_raw(3, 7, 20.0)
+            .add_normal_connection_raw(5, 7, -1.25)
+            .add_normal_connection_raw(1, 8, 20.0)
+            .add_normal_connection_raw(5, 8, -1.25)
+            .add_normal_connection_raw(7, 6, 1.0)
+            .add_normal_connection_raw(8, 6, 1.0);
+        let mut cppn = Cppn::<ConsecutiveNeuralNet>::build_from(&builder)?;
+        let net = substrate.decode(&mut cppn, &config);
+
+        let num_hidden = 16;
+        assert_eq!(net.nodes.len(), 3 + num_hidden);
+        assert!(net.nodes[3..]
+            .iter()
+            .all(|node| node.node_type == NodeType::Hidden));
+        assert_eq!(
+            net.connections.len(),
+            2 * num_hidden + num_hidden * num_hidden + num_hidden
+        );
+        assert!(net
+            .connections
+            .iter()
+            .any(|conn| conn.connection_type == ConnectionType::Recurrent));
+        net.build::<ConsecutiveNeuralNet>()?;
+        Ok(())
+    }
+}
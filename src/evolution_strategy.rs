@@ -0,0 +1,226 @@
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+use crate::backprop::*;
+use crate::environment::*;
+use crate::neural_net::*;
+use crate::population::*;
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct EvolutionStrategyConfig {
+    // Number of perturbed networks per step, rounded up to an even
+    // number, since perturbations come in mirrored pairs.
+    pub population_size: usize,
+    // Standard deviation of the perturbations.
+    pub sigma: f32,
+    pub optimizer: Optimizer,
+    // Pulls every weight towards zero by this fraction of it each
+    // step.
+    pub weight_decay: f32,
+    pub seed: Option<u64>,
+}
+
+impl Default for EvolutionStrategyConfig {
+    fn default() -> Self {
+        Self {
+            population_size: 50,
+            sigma: 0.1,
+            optimizer: Optimizer::adam(0.01),
+            weight_decay: 0.0,
+            seed: None,
+        }
+    }
+}
+
+// Optimizes the connection weights of a fixed topology from fitness
+// alone, as in the evolution strategies of Salimans et al.  Each step
+// evaluates mirrored Gaussian perturbations of the current weights,
+// and moves along the estimated gradient of fitness, with fitnesses
+// replaced by their centered ranks so that the scale of fitness
+// doesn't matter.
+//
+// `ask` and `tell` split a step for callers that evaluate the
+// networks themselves, while `step` does both.
+#[derive(Debug, Clone)]
+pub struct EvolutionStrategy {
+    pub config: EvolutionStrategyConfig,
+    builder: NeuralNetBuilder,
+    rng: ChaCha8Rng,
+    // Unscaled perturbations of the last `ask`, one per pair.
+    noise: Vec<Vec<f32>>,
+    state: OptimizerState,
+}
+
+// Standard normal sample, by the Box-Muller transform.
+pub(crate) fn gaussian<R: Rng + ?Sized>(rng: &mut R) -> f32 {
+    let u = 1.0 - rng.gen::<f32>();
+    let v = rng.gen::<f32>();
+    (-2.0 * u.ln()).sqrt() * (2.0 * std::f32::consts::PI * v).cos()
+}
+
+impl EvolutionStrategy {
+    pub fn new(
+        builder: NeuralNetBuilder,
+        config: EvolutionStrategyConfig,
+    ) -> Self {
+        let rng = match config.seed {
+            Some(seed) => ChaCha8Rng::seed_from_u64(seed),
+            None => ChaCha8Rng::from_entropy(),
+        };
+        let state = OptimizerState::new(builder.connections.len());
+        Self {
+            config,
+            builder,
+            rng,
+            noise: Vec::new(),
+            state,
+        }
+    }
+
+    // The current weights, which aren't among those asked about.
+    pub fn builder(&self) -> &NeuralNetBuilder {
+        &self.builder
+    }
+
+    pub fn into_builder(self) -> NeuralNetBuilder {
+        self.builder
+    }
+
+    // Perturbed copies of the current network to evaluate, in
+    // mirrored pairs.
+    pub fn ask(&mut self) -> Vec<NeuralNetBuilder> {
+        let num_pairs = self.config.population_size.div_ceil(2);
+        let num_weights = self.builder.connections.len();
+        let rng = &mut self.rng;
+        self.noise = (0..num_pairs)
+            .map(|_| (0..num_weights).map(|_| gaussian(rng)).collect())
+            .collect();
+
+        let sigma = self.config.sigma;
+        let builder = &self.builder;
+        self.noise
+            .iter()
+            .flat_map(|noise| {
+                vec![sigma, -sigma].into_iter().map(move |scale| {
+                    let mut perturbed = builder.clone();
+                    perturbed
+                        .connections
+                        .iter_mut()
+                        .zip(noise)
+                        .for_each(|(conn, e)| conn.weight += scale * e);
+                    perturbed
+                })
+            })
+            .collect()
+    }
+
+    // Updates the weights from the fitness of each network of the
+    // last `ask`, in the same order.
+    pub fn tell(&mut self, fitnesses: &[f32]) {
+        let n = fitnesses.len().min(2 * self.noise.len());
+        if n < 2 {
+            return;
+        }
+        let mut order = (0..n).collect::<Vec<_>>();
+        order.sort_by(|a, b| fitnesses[*a].total_cmp(&fitnesses[*b]));
+        let mut ranks = vec![0.0; n];
+        order.iter().enumerate().for_each(|(rank, i)| {
+            ranks[*i] = rank as f32 / (n - 1) as f32 - 0.5;
+        });
+
+        // The optimizers minimize, so they are given the gradient of
+        // minus the fitness.
+        let scale = 1.0 / (n as f32 * self.config.sigma);
+        let decay = self.config.weight_decay;
+        let grads = self
+            .builder
+            .connections
+            .iter()
+            .enumerate()
+            .map(|(j, conn)| {
+                let ascent = self
+                    .noise
+                    .iter()
+                    .zip(ranks.chunks(2))
+                    .map(|(noise, pair)| {
+                        noise[j] * (pair[0] - pair.get(1).unwrap_or(&0.0))
+                    })
+                    .sum::<f32>();
+                decay * conn.weight - scale * ascent
+            })
+            .collect::<Vec<_>>();
+
+        let weights =
+            self.builder.connections.iter_mut().map(|c| &mut c.weight);
+        self.state.step(&self.config.optimizer, weights, &grads);
+    }
+
+    // Evaluates the perturbed networks with backend `N`, then updates
+    // the weights, returning the fitnesses.
+    pub fn step<N, F>(&mut self, fitness: &F) -> Result<Vec<f32>, Error>
+    where
+        N: NeuralNet + Send,
+        F: FitnessFunction<N> + Sync,
+    {
+        let mut nets = self
+            .ask()
+            .iter_mut()
+            .map(|builder| builder.build::<N>())
+            .collect::<Result<Vec<_>, _>>()?;
+        let fitnesses =
+            evaluate_parallel(&mut nets, |net| fitness.evaluate(net));
+        self.tell(&fitnesses);
+        Ok(fitnesses)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::*;
+
+    #[test]
+    fn test_evolution_strategy() -> Result<(), Error> {
+        let mut builder = NeuralNetBuilder::new();
+        builder
+            .set_default_activation(ActivationFunction::Identity)
+            .add_nodes(NodeType::Input, 2)
+            .add_nodes(NodeType::Output, 1)
+            .add_normal_connection_raw(0, 2, 0.0)
+            .add_normal_connection_raw(1, 2, 0.0);
+        // Best when the output is 2 for the first input and -1 for
+        // the second.
+        let fitness = |net: &mut ConsecutiveNeuralNet| {
+            -(net.evaluate(&[1.0, 0.0])[0] - 2.0).powi(2)
+                - (net.evaluate(&[0.0, 1.0])[0] + 1.0).powi(2)
+        };
+
+        let mut es = EvolutionStrategy::new(
+            builder,
+            EvolutionStrategyConfig {
+                population_size: 19,
+                optimizer: Optimizer::adam(0.05),
+                seed: Some(0),
+                ..EvolutionStrategyConfig::default()
+            },
+        );
+        let asked = es.ask();
+        assert_eq!(asked.len(), 20);
+        let mirrored =
+            asked[0].connections[0].weight + asked[1].connections[0].weight;
+        assert!(mirrored.abs() < 1e-6);
+
+        (0..300).try_for_each(|_| {
+            es.step::<ConsecutiveNeuralNet, _>(&fitness).map(|_| ())
+        })?;
+        let weights = es
+            .builder()
+            .connections
+            .iter()
+            .map(|conn| conn.weight)
+            .collect::<Vec<_>>();
+        assert!((weights[0] - 2.0).abs() < 0.1, "{:?}", weights);
+        assert!((weights[1] + 1.0).abs() < 0.1, "{:?}", weights);
+        Ok(())
+    }
+}
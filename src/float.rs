@@ -0,0 +1,205 @@
+use core::fmt::Debug;
+use core::ops::{Add, AddAssign, Div, Mul, Neg, Sub};
+
+// Scalar that networks can be evaluated in.  Builders and genomes
+// always hold `f32`s, which are converted when a network is built,
+// so only the evaluation itself runs at the precision of the scalar.
+// Implemented for `f32` and `f64`, and small enough to implement for
+// a fixed-point type.
+pub trait Float:
+    Copy
+    + Debug
+    + Default
+    + PartialOrd
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Mul<Output = Self>
+    + Div<Output = Self>
+    + Neg<Output = Self>
+    + AddAssign
+{
+    const ZERO: Self;
+    const ONE: Self;
+
+    fn from_f32(x: f32) -> Self;
+    fn to_f32(self) -> f32;
+
+    fn exp(self) -> Self;
+    fn exp_m1(self) -> Self;
+    fn tanh(self) -> Self;
+    fn sin(self) -> Self;
+    fn cos(self) -> Self;
+    fn ln(self) -> Self;
+    fn ln_1p(self) -> Self;
+
+    fn is_nan(self) -> bool {
+        self.partial_cmp(&self).is_none()
+    }
+
+    fn abs(self) -> Self {
+        if self < Self::ZERO {
+            -self
+        } else {
+            self
+        }
+    }
+
+    // Unlike the `std` methods, these return `self` if either value
+    // is NaN.
+    fn max(self, other: Self) -> Self {
+        if other > self {
+            other
+        } else {
+            self
+        }
+    }
+
+    fn min(self, other: Self) -> Self {
+        if other < self {
+            other
+        } else {
+            self
+        }
+    }
+}
+
+// The functions that `core` lacks come from libm without the `std`
+// feature.
+macro_rules! impl_float {
+    (
+        $t:ty,
+        $exp:ident,
+        $exp_m1:ident,
+        $tanh:ident,
+        $sin:ident,
+        $cos:ident,
+        $ln:ident,
+        $ln_1p:ident
+    ) => {
+        impl Float for $t {
+            const ZERO: Self = 0.0;
+            const ONE: Self = 1.0;
+
+            fn from_f32(x: f32) -> Self {
+                x as $t
+            }
+
+            fn to_f32(self) -> f32 {
+                self as f32
+            }
+
+            #[cfg(feature = "std")]
+            fn exp(self) -> Self {
+                <$t>::exp(self)
+            }
+
+            #[cfg(feature = "std")]
+            fn exp_m1(self) -> Self {
+                <$t>::exp_m1(self)
+            }
+
+            #[cfg(feature = "std")]
+            fn tanh(self) -> Self {
+                <$t>::tanh(self)
+            }
+
+            #[cfg(feature = "std")]
+            fn sin(self) -> Self {
+                <$t>::sin(self)
+            }
+
+            #[cfg(feature = "std")]
+            fn cos(self) -> Self {
+                <$t>::cos(self)
+            }
+
+            #[cfg(feature = "std")]
+            fn ln(self) -> Self {
+                <$t>::ln(self)
+            }
+
+            #[cfg(feature = "std")]
+            fn ln_1p(self) -> Self {
+                <$t>::ln_1p(self)
+            }
+
+            #[cfg(not(feature = "std"))]
+            fn exp(self) -> Self {
+                libm::$exp(self)
+            }
+
+            #[cfg(not(feature = "std"))]
+            fn exp_m1(self) -> Self {
+                libm::$exp_m1(self)
+            }
+
+            #[cfg(not(feature = "std"))]
+            fn tanh(self) -> Self {
+                libm::$tanh(self)
+            }
+
+            #[cfg(not(feature = "std"))]
+            fn sin(self) -> Self {
+                libm::$sin(self)
+            }
+
+            #[cfg(not(feature = "std"))]
+            fn cos(self) -> Self {
+                libm::$cos(self)
+            }
+
+            #[cfg(not(feature = "std"))]
+            fn ln(self) -> Self {
+                libm::$ln(self)
+            }
+
+            #[cfg(not(feature = "std"))]
+            fn ln_1p(self) -> Self {
+                libm::$ln_1p(self)
+            }
+
+            fn is_nan(self) -> bool {
+                <$t>::is_nan(self)
+            }
+
+            fn abs(self) -> Self {
+                <$t>::abs(self)
+            }
+
+            fn max(self, other: Self) -> Self {
+                <$t>::max(self, other)
+            }
+
+            fn min(self, other: Self) -> Self {
+                <$t>::min(self, other)
+            }
+        }
+    };
+}
+
+impl_float!(f32, expf, expm1f, tanhf, sinf, cosf, logf, log1pf);
+impl_float!(f64, exp, expm1, tanh, sin, cos, log, log1p);
+
+// Approximation of `exp`, with a relative error of about 2e-6, from a
+// polynomial for the fractional power of two and the exponent bits
+// for the integer one.  Inputs beyond about ±87 are clamped, to the
+// smallest or largest power of two that is a normal `f32`.
+pub fn fast_exp(x: f32) -> f32 {
+    let t = (x * core::f32::consts::LOG2_E).clamp(-126.0, 126.0);
+    // Rounded rather than truncated, so that the fraction is within
+    // ±0.5, where the polynomial is most accurate.
+    let shifted = t + 0.5;
+    let i = shifted as i32 - (shifted < 0.0) as i32;
+    let f = (t - i as f32) * core::f32::consts::LN_2;
+    let p = 1.0
+        + f * (1.0
+            + f * (1.0 / 2.0
+                + f * (1.0 / 6.0 + f * (1.0 / 24.0 + f * (1.0 / 120.0)))));
+    f32::from_bits(((i + 127) as u32) << 23) * p
+}
+
+// Approximation of `tanh` through `fast_exp`, with an absolute error
+// of about 1e-6.
+pub fn fast_tanh(x: f32) -> f32 {
+    1.0 - 2.0 / (fast_exp(2.0 * x) + 1.0)
+}
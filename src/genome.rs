@@ -0,0 +1,590 @@
+use std::collections::{HashMap, HashSet};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::mutation::MutationRates;
+use crate::network_metrics::NetworkMetrics;
+use crate::neural_net::*;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct NodeGene {
+    pub id: u32,
+    pub node_type: NodeType,
+    pub func: ActivationFunction,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub memory: Option<MemoryCell>,
+    // Bias of the node in the network, zero for nodes made by
+    // mutation.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub bias: f32,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ConnectionGene {
+    pub innovation: u32,
+    pub origin: u32,
+    pub dest: u32,
+    pub weight: f32,
+    pub connection_type: ConnectionType,
+    pub enabled: bool,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub plasticity: Option<HebbianRule>,
+}
+
+// Hands out node ids and innovation numbers.  The same structural
+// change always receives the same number, no matter which genome it
+// occurs in, so that genes can later be aligned between genomes.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct InnovationTracker {
+    pub(crate) next_node_id: u32,
+    pub(crate) next_innovation: u32,
+    pub(crate) connections: HashMap<(u32, u32, ConnectionType), u32>,
+    // Node created by splitting the connection with the given
+    // innovation number.
+    pub(crate) splits: HashMap<u32, u32>,
+}
+
+impl InnovationTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn new_node_id(&mut self) -> u32 {
+        let id = self.next_node_id;
+        self.next_node_id += 1;
+        id
+    }
+
+    // Marks all node ids below `num_nodes` as used, without handing
+    // out new ones.  Genomes that share a fixed set of input and
+    // output nodes use this to agree on their ids.
+    pub fn reserve_node_ids(&mut self, num_nodes: u32) {
+        self.next_node_id = self.next_node_id.max(num_nodes);
+    }
+
+    // Records the node ids and connection innovations of a genome
+    // that was made with another tracker, such as one loaded from a
+    // file, so that the numbers handed out from now on agree with it.
+    pub fn observe(&mut self, genome: &Genome) {
+        genome.nodes.iter().for_each(|node| {
+            self.reserve_node_ids(node.id.saturating_add(1));
+        });
+        genome.connections.iter().for_each(|conn| {
+            self.connections
+                .entry((conn.origin, conn.dest, conn.connection_type))
+                .or_insert(conn.innovation);
+            self.next_innovation =
+                self.next_innovation.max(conn.innovation.saturating_add(1));
+        });
+    }
+
+    pub fn connection_innovation(
+        &mut self,
+        origin: u32,
+        dest: u32,
+        connection_type: ConnectionType,
+    ) -> u32 {
+        let next_innovation = &mut self.next_innovation;
+        *self
+            .connections
+            .entry((origin, dest, connection_type))
+            .or_insert_with(|| {
+                let innovation = *next_innovation;
+                *next_innovation += 1;
+                innovation
+            })
+    }
+
+    // Id of the node that is inserted when splitting the connection
+    // `innovation`, shared by every genome that makes the same split.
+    pub fn split_node_id(&mut self, innovation: u32) -> u32 {
+        let next_node_id = &mut self.next_node_id;
+        *self.splits.entry(innovation).or_insert_with(|| {
+            let id = *next_node_id;
+            *next_node_id += 1;
+            id
+        })
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Genome {
+    pub nodes: Vec<NodeGene>,
+    pub connections: Vec<ConnectionGene>,
+    // Rates this genome is mutated with, if they are self-adapting.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub mutation_rates: Option<MutationRates>,
+}
+
+impl Genome {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Whether both genomes have the same genes in the same order,
+    // with every weight within `tolerance` of the other's.
+    pub fn approx_eq(&self, other: &Self, tolerance: f32) -> bool {
+        self.nodes == other.nodes
+            && self.mutation_rates == other.mutation_rates
+            && self.connections.len() == other.connections.len()
+            && self.connections.iter().zip(other.connections.iter()).all(
+                |(a, b)| {
+                    (a.weight - b.weight).abs() <= tolerance
+                        && *a
+                            == ConnectionGene {
+                                weight: a.weight,
+                                ..*b
+                            }
+                },
+            )
+    }
+
+    // A genome with only input and output nodes, and no connections.
+    // Inputs receive ids `0..num_inputs`, followed by the outputs, so
+    // every genome created this way shares the same sensor and output
+    // nodes.
+    pub fn with_io(
+        num_inputs: u32,
+        num_outputs: u32,
+        func: ActivationFunction,
+        tracker: &mut InnovationTracker,
+    ) -> Self {
+        tracker.reserve_node_ids(num_inputs + num_outputs);
+
+        let inputs = (0..num_inputs).map(|id| NodeGene {
+            id,
+            node_type: NodeType::Input,
+            func: ActivationFunction::Identity,
+            memory: None,
+            bias: 0.0,
+        });
+        let outputs =
+            (num_inputs..num_inputs + num_outputs).map(|id| NodeGene {
+                id,
+                node_type: NodeType::Output,
+                func,
+                memory: None,
+                bias: 0.0,
+            });
+
+        Self {
+            nodes: inputs.chain(outputs).collect(),
+            connections: Vec::new(),
+            mutation_rates: None,
+        }
+    }
+
+    pub fn add_node(
+        &mut self,
+        node_type: NodeType,
+        func: ActivationFunction,
+        tracker: &mut InnovationTracker,
+    ) -> u32 {
+        let id = tracker.new_node_id();
+        self.nodes.push(NodeGene {
+            id,
+            node_type,
+            func,
+            memory: None,
+            bias: 0.0,
+        });
+        id
+    }
+
+    pub fn add_connection(
+        &mut self,
+        origin: u32,
+        dest: u32,
+        weight: f32,
+        connection_type: ConnectionType,
+        tracker: &mut InnovationTracker,
+    ) -> u32 {
+        let innovation =
+            tracker.connection_innovation(origin, dest, connection_type);
+        self.connections.push(ConnectionGene {
+            innovation,
+            origin,
+            dest,
+            weight,
+            connection_type,
+            enabled: true,
+            plasticity: None,
+        });
+        innovation
+    }
+
+    pub fn node(&self, id: u32) -> Option<&NodeGene> {
+        self.nodes.iter().find(|n| n.id == id)
+    }
+
+    pub fn has_connection(
+        &self,
+        origin: u32,
+        dest: u32,
+        connection_type: ConnectionType,
+    ) -> bool {
+        self.connections.iter().any(|conn| {
+            conn.origin == origin
+                && conn.dest == dest
+                && conn.connection_type == connection_type
+        })
+    }
+
+    // Whether an enabled normal connection from `origin` to `dest`
+    // would close a loop of normal connections, which can't be
+    // evaluated.
+    pub fn creates_loop(&self, origin: u32, dest: u32) -> bool {
+        let mut visited = HashSet::new();
+        let mut stack = vec![dest];
+
+        while let Some(node) = stack.pop() {
+            if node == origin {
+                return true;
+            }
+            if visited.insert(node) {
+                stack.extend(
+                    self.connections
+                        .iter()
+                        .filter(|conn| {
+                            conn.enabled
+                                && conn.connection_type
+                                    == ConnectionType::Normal
+                                && conn.origin == node
+                        })
+                        .map(|conn| conn.dest),
+                );
+            }
+        }
+
+        false
+    }
+
+    // Removes disabled connections, connections to nodes that aren't
+    // in the genome, and hidden nodes with no path to an output,
+    // along with their connections.  The genome still builds the same
+    // network, but can no longer re-enable what was removed, so this
+    // is meant for genomes that are done evolving.
+    pub fn prune(&mut self) {
+        let ids = self.nodes.iter().map(|n| n.id).collect::<HashSet<_>>();
+        self.connections.retain(|conn| {
+            conn.enabled
+                && ids.contains(&conn.origin)
+                && ids.contains(&conn.dest)
+        });
+
+        let mut useful = self
+            .nodes
+            .iter()
+            .filter(|n| n.node_type == NodeType::Output)
+            .map(|n| n.id)
+            .collect::<HashSet<_>>();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            self.connections.iter().for_each(|conn| {
+                if useful.contains(&conn.dest) {
+                    changed |= useful.insert(conn.origin);
+                }
+            });
+        }
+
+        self.nodes.retain(|n| {
+            n.node_type != NodeType::Hidden || useful.contains(&n.id)
+        });
+        self.connections.retain(|conn| useful.contains(&conn.dest));
+    }
+
+    // Disables enabled normal connections until no loops of normal
+    // connections remain.
+    pub fn break_loops(&mut self) {
+        (0..self.connections.len()).for_each(|i| {
+            let conn = self.connections[i];
+            if conn.enabled && conn.connection_type == ConnectionType::Normal {
+                self.connections[i].enabled = false;
+                self.connections[i].enabled =
+                    !self.creates_loop(conn.origin, conn.dest);
+            }
+        });
+    }
+
+    pub fn to_builder(&self) -> NeuralNetBuilder {
+        self.into()
+    }
+
+    // Genome of a network built by hand or imported, such as one from
+    // `NeuralNetBuilder::from_dense_layers`, for seeding evolution.
+    // Nodes take fresh ids from `tracker` in the builder's order, and
+    // keep their type, activation function, bias and memory cell;
+    // aggregation, response and names have no gene and are lost.
+    pub fn from_builder(
+        builder: &NeuralNetBuilder,
+        tracker: &mut InnovationTracker,
+    ) -> Self {
+        let nodes = builder
+            .nodes
+            .iter()
+            .map(|node| NodeGene {
+                id: tracker.new_node_id(),
+                node_type: node.node_type,
+                func: node.func,
+                memory: node.memory,
+                bias: node.bias,
+            })
+            .collect::<Vec<_>>();
+        let connections = builder
+            .connections
+            .iter()
+            .map(|conn| {
+                let origin = nodes[conn.origin as usize].id;
+                let dest = nodes[conn.dest as usize].id;
+                ConnectionGene {
+                    innovation: tracker.connection_innovation(
+                        origin,
+                        dest,
+                        conn.connection_type,
+                    ),
+                    origin,
+                    dest,
+                    weight: conn.weight,
+                    connection_type: conn.connection_type,
+                    enabled: conn.enabled,
+                    plasticity: conn.plasticity,
+                }
+            })
+            .collect();
+        Self {
+            nodes,
+            connections,
+            mutation_rates: None,
+        }
+    }
+
+    // `NeuralNetBuilder::metrics` of the network, also counting the
+    // disabled genes, which the builder leaves out.
+    pub fn metrics(&self) -> NetworkMetrics {
+        let mut metrics = self.to_builder().metrics();
+        metrics.num_disabled =
+            self.connections.iter().filter(|conn| !conn.enabled).count();
+        metrics
+    }
+
+    // `NeuralNetBuilder::topology_hash` of the network, so genomes
+    // that differ only in disabled genes or innovation numbers hash
+    // the same.
+    pub fn topology_hash(&self, include_weights: bool) -> u64 {
+        self.to_builder().topology_hash(include_weights)
+    }
+
+    // Copies the weights of a builder made by `to_builder`, such as
+    // one that has since been trained, back into the matching
+    // connection genes.
+    pub fn set_weights_from(&mut self, builder: &NeuralNetBuilder) {
+        let ids = self.nodes.iter().map(|n| n.id).collect::<HashSet<_>>();
+        self.connections
+            .iter_mut()
+            .filter(|conn| {
+                conn.enabled
+                    && ids.contains(&conn.origin)
+                    && ids.contains(&conn.dest)
+            })
+            .zip(&builder.connections)
+            .for_each(|(conn, template)| conn.weight = template.weight);
+    }
+}
+
+impl From<&Genome> for NeuralNetBuilder {
+    fn from(genome: &Genome) -> Self {
+        let index_of = genome
+            .nodes
+            .iter()
+            .enumerate()
+            .map(|(i, n)| (n.id, i as u32))
+            .collect::<HashMap<_, _>>();
+
+        let mut builder = NeuralNetBuilder::new();
+        genome.nodes.iter().for_each(|n| {
+            let node = builder.add_node(n.node_type, n.func);
+            builder.set_memory(node, n.memory);
+            builder.nodes[node.0 as usize].bias = n.bias;
+        });
+
+        // Disabled genes are kept in the genome, so they can be
+        // re-enabled by later generations, but aren't part of the
+        // network.  Connections to nodes that aren't in the genome
+        // have no meaning in the network either.
+        genome
+            .connections
+            .iter()
+            .filter(|conn| conn.enabled)
+            .for_each(|conn| {
+                if let (Some(origin), Some(dest)) =
+                    (index_of.get(&conn.origin), index_of.get(&conn.dest))
+                {
+                    builder.connections.push(ConnectionTemplate {
+                        origin: *origin,
+                        dest: *dest,
+                        weight: conn.weight,
+                        connection_type: conn.connection_type,
+                        plasticity: conn.plasticity,
+                        enabled: true,
+                    });
+                }
+            });
+
+        builder
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ConsecutiveNeuralNet;
+
+    #[test]
+    fn test_shared_innovations() {
+        let mut tracker = InnovationTracker::new();
+        let func = ActivationFunction::Sigmoid;
+
+        let mut a = Genome::with_io(2, 1, func, &mut tracker);
+        let mut b = Genome::with_io(2, 1, func, &mut tracker);
+        assert_eq!(a, b);
+
+        let first =
+            a.add_connection(0, 2, 1.0, ConnectionType::Normal, &mut tracker);
+        let second =
+            a.add_connection(1, 2, 1.0, ConnectionType::Normal, &mut tracker);
+        assert_ne!(first, second);
+
+        // The same connection in another genome is the same
+        // innovation, regardless of the order it was added in.
+        assert_eq!(
+            b.add_connection(1, 2, -1.0, ConnectionType::Normal, &mut tracker),
+            second
+        );
+        assert_eq!(
+            b.add_connection(0, 2, -1.0, ConnectionType::Normal, &mut tracker),
+            first
+        );
+
+        // New nodes never reuse the ids of inputs or outputs.
+        assert_eq!(a.add_node(NodeType::Hidden, func, &mut tracker), 3);
+    }
+
+    #[test]
+    fn test_to_builder() -> Result<(), Error> {
+        let mut tracker = InnovationTracker::new();
+        let func = ActivationFunction::Identity;
+
+        let mut genome = Genome::with_io(2, 1, func, &mut tracker);
+        let hidden = genome.add_node(NodeType::Hidden, func, &mut tracker);
+        genome.add_connection(
+            0,
+            hidden,
+            2.0,
+            ConnectionType::Normal,
+            &mut tracker,
+        );
+        genome.add_connection(
+            hidden,
+            2,
+            1.5,
+            ConnectionType::Normal,
+            &mut tracker,
+        );
+        genome.add_connection(1, 2, -1.0, ConnectionType::Normal, &mut tracker);
+        genome.add_connection(0, 2, 10.0, ConnectionType::Normal, &mut tracker);
+        genome.connections[3].enabled = false;
+
+        let mut net = genome.to_builder().build::<ConsecutiveNeuralNet>()?;
+        assert_eq!(net.evaluate(&[1.0, 0.5]), vec![2.0 * 1.5 - 0.5]);
+
+        let metrics = genome.metrics();
+        assert_eq!((metrics.num_enabled, metrics.num_disabled), (3, 1));
+        assert_eq!(metrics.max_depth, Some(2));
+
+        let mut other = genome.clone();
+        other.connections[0].weight += 1e-4;
+        assert!(other.approx_eq(&genome, 1e-3));
+        assert!(!other.approx_eq(&genome, 1e-5));
+        assert!(other.to_builder().approx_eq(&genome.to_builder(), 1e-3));
+        other.connections[1].enabled = false;
+        assert!(!other.approx_eq(&genome, 1e-3));
+        assert!(!other.to_builder().approx_eq(&genome.to_builder(), 1e-3));
+        Ok(())
+    }
+
+    #[test]
+    fn test_prune() -> Result<(), Error> {
+        let mut tracker = InnovationTracker::new();
+        let func = ActivationFunction::Tanh;
+        let normal = ConnectionType::Normal;
+
+        // Node 3 leads to the output, node 4 only to node 5, which is
+        // a dead end, and node 6 only through a disabled connection.
+        let mut genome = Genome::with_io(2, 1, func, &mut tracker);
+        (0..4).for_each(|_| {
+            genome.add_node(NodeType::Hidden, func, &mut tracker);
+        });
+        genome.add_connection(0, 3, 1.0, normal, &mut tracker);
+        genome.add_connection(3, 2, 1.0, normal, &mut tracker);
+        genome.add_connection(1, 4, 1.0, normal, &mut tracker);
+        genome.add_connection(4, 5, 1.0, normal, &mut tracker);
+        genome.add_connection(
+            5,
+            5,
+            1.0,
+            ConnectionType::Recurrent,
+            &mut tracker,
+        );
+        genome.add_connection(1, 6, 1.0, normal, &mut tracker);
+        genome.add_connection(6, 2, 1.0, normal, &mut tracker);
+        genome.add_connection(
+            2,
+            3,
+            0.5,
+            ConnectionType::Recurrent,
+            &mut tracker,
+        );
+        genome.connections[6].enabled = false;
+
+        let mut pruned = genome.clone();
+        pruned.prune();
+        let ids = pruned.nodes.iter().map(|n| n.id).collect::<Vec<_>>();
+        assert_eq!(ids, vec![0, 1, 2, 3]);
+        assert_eq!(pruned.connections.len(), 3);
+
+        let mut original =
+            genome.to_builder().build::<ConsecutiveNeuralNet>()?;
+        let mut net = pruned.to_builder().build::<ConsecutiveNeuralNet>()?;
+        (0..3).for_each(|_| {
+            assert_eq!(
+                net.evaluate(&[0.5, 1.0]),
+                original.evaluate(&[0.5, 1.0])
+            );
+        });
+
+        // Disabled connections between kept nodes are removed too.
+        let mut builder = genome.to_builder();
+        builder.add_normal_connection_raw(0, 2, 1.0);
+        builder.set_enabled(builder.connections.len() - 1, false);
+        builder.prune();
+        assert!(builder.connections.iter().all(|conn| conn.enabled));
+        assert!(builder.approx_eq(&pruned.to_builder(), 0.0));
+        Ok(())
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_traits() {
+        fn assert_serde<T: Serialize + for<'de> Deserialize<'de>>() {}
+        assert_serde::<Genome>();
+        assert_serde::<InnovationTracker>();
+        assert_serde::<NeuralNetBuilder>();
+        assert_serde::<crate::ConsecutiveNeuralNet>();
+    }
+}
@@ -0,0 +1,151 @@
+use std::collections::VecDeque;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::genome::*;
+use crate::species::*;
+
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct HallOfFameEntry {
+    pub generation: usize,
+    // Species the genome was the best of, or `None` for the best of
+    // the whole generation.
+    pub species: Option<u32>,
+    pub genome: Genome,
+    pub fitness: f32,
+}
+
+// Archive of the best genome of every species and of the whole
+// population in each generation, oldest first, for replaying the
+// lineage of champions after a run.
+#[derive(Debug, PartialEq, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct HallOfFame {
+    // Most entries kept, with the oldest dropped first.
+    pub capacity: usize,
+    entries: VecDeque<HallOfFameEntry>,
+}
+
+fn best_of(
+    members: impl Iterator<Item = usize>,
+    fitnesses: &[f32],
+) -> Option<usize> {
+    members
+        .filter(|i| *i < fitnesses.len())
+        .max_by(|a, b| fitnesses[*a].total_cmp(&fitnesses[*b]))
+}
+
+impl HallOfFame {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: VecDeque::new(),
+        }
+    }
+
+    // Archives the best of `genomes` overall, then the best member of
+    // each of `species`, whose members index `genomes`.
+    pub fn record(
+        &mut self,
+        generation: usize,
+        genomes: &[Genome],
+        fitnesses: &[f32],
+        species: &[Species],
+    ) {
+        let fitnesses = &fitnesses[..fitnesses.len().min(genomes.len())];
+        let overall = best_of(0..genomes.len(), fitnesses).map(|i| (None, i));
+        let per_species = species.iter().filter_map(|s| {
+            best_of(s.members.iter().cloned(), fitnesses)
+                .map(|i| (Some(s.id), i))
+        });
+        overall
+            .into_iter()
+            .chain(per_species)
+            .for_each(|(species, i)| {
+                self.push(HallOfFameEntry {
+                    generation,
+                    species,
+                    genome: genomes[i].clone(),
+                    fitness: fitnesses[i],
+                })
+            });
+    }
+
+    pub fn push(&mut self, entry: HallOfFameEntry) {
+        self.entries.push_back(entry);
+        while self.entries.len() > self.capacity {
+            self.entries.pop_front();
+        }
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = &HallOfFameEntry> {
+        self.entries.iter()
+    }
+
+    // Best genome of each archived generation.
+    pub fn champions(&self) -> impl Iterator<Item = &HallOfFameEntry> {
+        self.entries.iter().filter(|entry| entry.species.is_none())
+    }
+
+    pub fn species_champions(
+        &self,
+        species: u32,
+    ) -> impl Iterator<Item = &HallOfFameEntry> {
+        self.entries
+            .iter()
+            .filter(move |entry| entry.species == Some(species))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::*;
+
+    #[test]
+    fn test_hall_of_fame() {
+        let mut tracker = InnovationTracker::new();
+        let func = ActivationFunction::Sigmoid;
+        let genomes = (0..4)
+            .map(|_| Genome::with_io(1, 1, func, &mut tracker))
+            .collect::<Vec<_>>();
+        let species = |id, members| Species {
+            id,
+            representative: genomes[0].clone(),
+            members,
+            best_fitness: 0.0,
+            stagnant_generations: 0,
+        };
+        let species = vec![species(3, vec![0, 1]), species(5, vec![2, 3])];
+
+        let mut hall_of_fame = HallOfFame::new(5);
+        hall_of_fame.record(0, &genomes, &[1.0, 4.0, 3.0, 2.0], &species);
+        hall_of_fame.record(1, &genomes, &[1.0, 0.0, 5.0, 2.0], &species);
+        assert_eq!(hall_of_fame.len(), 5);
+
+        let champions = hall_of_fame
+            .champions()
+            .map(|entry| (entry.generation, entry.fitness))
+            .collect::<Vec<_>>();
+        assert_eq!(champions, vec![(1, 5.0)]);
+        let of_species = hall_of_fame
+            .species_champions(3)
+            .map(|entry| (entry.generation, entry.fitness))
+            .collect::<Vec<_>>();
+        assert_eq!(of_species, vec![(0, 4.0), (1, 1.0)]);
+    }
+}
@@ -0,0 +1,1316 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use crate::genome::*;
+use crate::layers::*;
+use crate::mutation::*;
+use crate::neural_net::*;
+
+// Versioned JSON format of genomes and networks, written and read by
+// hand so that it stays the same however the structs change, unlike
+// the serde derives.  A genome is
+//
+//   {
+//     "format": "entendre-genome",
+//     "version": 1,
+//     "metadata": {"name": "value", ...},
+//     "nodes": [
+//       {"id": 0, "type": "input", "activation": "identity",
+//        "bias": 0.0},
+//       ...
+//     ],
+//     "connections": [
+//       {"innovation": 0, "origin": 0, "dest": 2, "weight": 0.5,
+//        "type": "normal", "enabled": true},
+//       ...
+//     ]
+//   }
+//
+// where node types are "input", "output", "hidden" or "bias",
+// connection types are "normal" or "recurrent", and activation
+// functions are given by `ActivationFunction::name`.  Nodes may also
+// have a "memory" cell, as
+//
+//   {"update_gate": <gate>, "reset_gate": <gate>, "memory_weight": x}
+//
+// with each gate {"input_weight": x, "memory_weight": x, "bias": x},
+// and connections a Hebbian "plasticity" rule, as
+// {"learning_rate": x, "a": x, "b": x, "c": x, "d": x}.  A genome with
+// self-adapting mutation rates also has
+//
+//   "mutation_rates": {"weight_mutation_prob": x,
+//                      "weight_perturb_power": x,
+//                      "add_connection_prob": x, "add_node_prob": x}
+//
+// A network has the format "entendre-network", and its nodes have no
+// "id", but have an "aggregation" ("sum", "product", "min", "max",
+// "mean" or "abs_sum"), "bias", "response" and optionally a "name".
+// Its connections have no "innovation".  The settings of the builder
+// are stored alongside the nodes:
+//
+//   "bias_value": 1.0,
+//   "default_activation": "sigmoid",
+//   "default_aggregation": "sum",
+//   "output_transform": "identity" | "softmax" | "argmax",
+//   "propagation": {"passes": 1}
+//                | {"epsilon": x, "max_passes": 10},
+//   "duplicate_policy": "allow" | "reject" | "merge",
+//   "weight_bounds": {"min": x, "max": x,
+//                     "policy": "clamp" | "reject" | "wrap"},
+//   "input_scaling": [{"type": "identity"}
+//                     | {"type": "min_max", "min": x, "max": x}
+//                     | {"type": "z_score", "mean": x, "std_dev": x}],
+//   "output_mapping": [{"type": "affine", "scale": x, "offset": x}
+//                      | {"type": "discrete", "size": n}]
+//
+// Any of these may be left out to take its default, as may "metadata"
+// and the optional fields above.  Unknown fields are ignored, so that
+// later minor additions can still be read.  Numbers that JSON can't
+// represent are written as the strings "NaN", "Infinity" and
+// "-Infinity".
+//
+// Trained dense layers from other frameworks are read from a plain
+// dump, with no format or version, of
+//
+//   {
+//     "inputs": n,
+//     "layers": [
+//       {"weight": [[x, ...], ...], "bias": [x, ...],
+//        "activation": "relu"},
+//       ...
+//     ]
+//   }
+//
+// where each layer has either a PyTorch "weight" matrix, with a row
+// per node of the layer, or a Keras "kernel", with a row per node of
+// the previous layer.  "inputs" defaults to the size of the first
+// layer's input, "bias" to none, and "activation" to "linear".  This
+// is the only layer format read; others, such as NumPy's npz
+// archives, are out of scope and need converting to this dump first.
+
+const GENOME_FORMAT: &str = "entendre-genome";
+const NETWORK_FORMAT: &str = "entendre-network";
+const VERSION: u32 = 1;
+// Deepest nesting of arrays and objects the parser accepts, so that
+// hostile input cannot overflow the stack.
+const MAX_DEPTH: usize = 128;
+
+#[derive(Debug, PartialEq, Clone)]
+enum Json {
+    Null,
+    Bool(bool),
+    // As written, so that floats are parsed straight to `f32`.
+    Number(String),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+fn invalid(message: impl Into<String>) -> Error {
+    Error::InvalidJson {
+        message: message.into(),
+    }
+}
+
+impl Json {
+    fn number(x: impl ToString) -> Self {
+        Json::Number(x.to_string())
+    }
+
+    fn float(x: f32) -> Self {
+        if x.is_nan() {
+            Json::String("NaN".to_string())
+        } else if x.is_infinite() {
+            let sign = if x > 0.0 { "" } else { "-" };
+            Json::String(format!("{}Infinity", sign))
+        } else {
+            Json::number(x)
+        }
+    }
+
+    fn string(x: impl Into<String>) -> Self {
+        Json::String(x.into())
+    }
+
+    fn object(fields: Vec<(&str, Json)>) -> Self {
+        Json::Object(
+            fields
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value))
+                .collect(),
+        )
+    }
+
+    fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(fields) => fields
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    fn field(&self, key: &str) -> Result<&Json, Error> {
+        self.get(key)
+            .ok_or_else(|| invalid(format!("Missing field \"{}\"", key)))
+    }
+
+    fn as_str(&self) -> Result<&str, Error> {
+        match self {
+            Json::String(x) => Ok(x),
+            _ => Err(invalid(format!("Expected a string, got {}", self))),
+        }
+    }
+
+    fn as_bool(&self) -> Result<bool, Error> {
+        match self {
+            Json::Bool(x) => Ok(*x),
+            _ => Err(invalid(format!("Expected a boolean, got {}", self))),
+        }
+    }
+
+    fn as_u32(&self) -> Result<u32, Error> {
+        match self {
+            Json::Number(x) => x.parse().ok(),
+            _ => None,
+        }
+        .ok_or_else(|| invalid(format!("Expected an integer, got {}", self)))
+    }
+
+    fn as_f32(&self) -> Result<f32, Error> {
+        match self {
+            Json::Number(x) => x.parse().ok(),
+            Json::String(x) => match x.as_str() {
+                "NaN" => Some(f32::NAN),
+                "Infinity" => Some(f32::INFINITY),
+                "-Infinity" => Some(f32::NEG_INFINITY),
+                _ => None,
+            },
+            _ => None,
+        }
+        .ok_or_else(|| invalid(format!("Expected a number, got {}", self)))
+    }
+
+    fn as_array(&self) -> Result<&[Json], Error> {
+        match self {
+            Json::Array(x) => Ok(x),
+            _ => Err(invalid(format!("Expected an array, got {}", self))),
+        }
+    }
+
+    // Writes objects and arrays above `inline_depth` one field or
+    // element per line, and the rest on a single line.
+    fn write(&self, out: &mut String, depth: usize, inline_depth: usize) {
+        let multiline = depth < inline_depth;
+        let separate = |out: &mut String, i: usize, depth: usize| {
+            if i > 0 {
+                out.push(',');
+            }
+            if multiline {
+                out.push('\n');
+                (0..depth).for_each(|_| out.push_str("  "));
+            } else if i > 0 {
+                out.push(' ');
+            }
+        };
+        let close = |out: &mut String, empty: bool, bracket: char| {
+            if multiline && !empty {
+                out.push('\n');
+                (0..depth).for_each(|_| out.push_str("  "));
+            }
+            out.push(bracket);
+        };
+        match self {
+            Json::Null => out.push_str("null"),
+            Json::Bool(x) => write!(out, "{}", x).unwrap(),
+            Json::Number(x) => out.push_str(x),
+            Json::String(x) => write_string(out, x),
+            Json::Array(elements) => {
+                out.push('[');
+                elements.iter().enumerate().for_each(|(i, x)| {
+                    separate(out, i, depth + 1);
+                    x.write(out, depth + 1, inline_depth);
+                });
+                close(out, elements.is_empty(), ']');
+            }
+            Json::Object(fields) => {
+                out.push('{');
+                fields.iter().enumerate().for_each(|(i, (key, x))| {
+                    separate(out, i, depth + 1);
+                    write_string(out, key);
+                    out.push_str(": ");
+                    x.write(out, depth + 1, inline_depth);
+                });
+                close(out, fields.is_empty(), '}');
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for Json {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let mut out = String::new();
+        self.write(&mut out, 0, 0);
+        f.write_str(&out)
+    }
+}
+
+fn write_string(out: &mut String, x: &str) {
+    out.push('"');
+    x.chars().for_each(|c| match c {
+        '"' => out.push_str("\\\""),
+        '\\' => out.push_str("\\\\"),
+        '\n' => out.push_str("\\n"),
+        '\r' => out.push_str("\\r"),
+        '\t' => out.push_str("\\t"),
+        c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32).unwrap(),
+        c => out.push(c),
+    });
+    out.push('"');
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    pos: usize,
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, message: &str) -> Error {
+        invalid(format!("{} at byte {}", message, self.pos))
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = &self.text[self.pos..];
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.text.as_bytes().get(self.pos).cloned()
+    }
+
+    fn expect(&mut self, token: &str) -> Result<(), Error> {
+        if self.text[self.pos..].starts_with(token) {
+            self.pos += token.len();
+            Ok(())
+        } else {
+            Err(self.error(&format!("Expected {}", token)))
+        }
+    }
+
+    fn value(&mut self) -> Result<Json, Error> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(b'n') => self.expect("null").map(|_| Json::Null),
+            Some(b't') => self.expect("true").map(|_| Json::Bool(true)),
+            Some(b'f') => self.expect("false").map(|_| Json::Bool(false)),
+            Some(b'"') => self.string().map(Json::String),
+            Some(b'[') => {
+                self.pos += 1;
+                let elements = self.sequence(b']', |p| p.value())?;
+                Ok(Json::Array(elements))
+            }
+            Some(b'{') => {
+                self.pos += 1;
+                let fields = self.sequence(b'}', |p| {
+                    p.skip_whitespace();
+                    let key = p.string()?;
+                    p.skip_whitespace();
+                    p.expect(":")?;
+                    Ok((key, p.value()?))
+                })?;
+                Ok(Json::Object(fields))
+            }
+            Some(b'-' | b'0'..=b'9') => {
+                let start = self.pos;
+                while let Some(b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9') =
+                    self.peek()
+                {
+                    self.pos += 1;
+                }
+                let number = &self.text[start..self.pos];
+                match number.parse::<f64>() {
+                    Ok(_) => Ok(Json::Number(number.to_string())),
+                    Err(_) => Err(self.error("Invalid number")),
+                }
+            }
+            _ => Err(self.error("Expected a value")),
+        }
+    }
+
+    // Elements up to the closing bracket, separated by commas.
+    fn sequence<T>(
+        &mut self,
+        close: u8,
+        mut element: impl FnMut(&mut Self) -> Result<T, Error>,
+    ) -> Result<Vec<T>, Error> {
+        if self.depth == MAX_DEPTH {
+            return Err(self.error("Nesting too deep"));
+        }
+        self.depth += 1;
+        let elements = self.elements(close, &mut element);
+        self.depth -= 1;
+        elements
+    }
+
+    fn elements<T>(
+        &mut self,
+        close: u8,
+        element: &mut impl FnMut(&mut Self) -> Result<T, Error>,
+    ) -> Result<Vec<T>, Error> {
+        let mut elements = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(close) {
+            self.pos += 1;
+            return Ok(elements);
+        }
+        loop {
+            elements.push(element(self)?);
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(c) if c == close => {
+                    self.pos += 1;
+                    return Ok(elements);
+                }
+                _ => return Err(self.error("Expected , or closing bracket")),
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, Error> {
+        self.expect("\"")?;
+        let mut out = String::new();
+        loop {
+            let c = self.text[self.pos..]
+                .chars()
+                .next()
+                .ok_or_else(|| self.error("Unterminated string"))?;
+            self.pos += c.len_utf8();
+            match c {
+                '"' => return Ok(out),
+                '\\' => {
+                    let escape = self.peek();
+                    self.pos += 1;
+                    out.push(match escape {
+                        Some(b'"') => '"',
+                        Some(b'\\') => '\\',
+                        Some(b'/') => '/',
+                        Some(b'b') => '\u{8}',
+                        Some(b'f') => '\u{c}',
+                        Some(b'n') => '\n',
+                        Some(b'r') => '\r',
+                        Some(b't') => '\t',
+                        Some(b'u') => self.unicode_escape()?,
+                        _ => return Err(self.error("Invalid escape")),
+                    });
+                }
+                c => out.push(c),
+            }
+        }
+    }
+
+    // Four hex digits of a `\u` escape.
+    fn hex(&mut self) -> Result<u32, Error> {
+        let digits = self.text.get(self.pos..self.pos + 4);
+        let code = digits.and_then(|x| u32::from_str_radix(x, 16).ok());
+        let code = code.ok_or_else(|| self.error("Invalid \\u escape"))?;
+        self.pos += 4;
+        Ok(code)
+    }
+
+    // The code point of a `\u` escape, after the `u`, which may be
+    // the first of a surrogate pair.
+    fn unicode_escape(&mut self) -> Result<char, Error> {
+        let high = self.hex()?;
+        let code = if (0xd800..0xdc00).contains(&high) {
+            self.expect("\\u")?;
+            let low = self.hex()?;
+            if !(0xdc00..0xe000).contains(&low) {
+                return Err(self.error("Invalid surrogate pair"));
+            }
+            0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00)
+        } else {
+            high
+        };
+        char::from_u32(code).ok_or_else(|| self.error("Invalid \\u escape"))
+    }
+}
+
+fn parse(text: &str) -> Result<Json, Error> {
+    let mut parser = Parser {
+        text,
+        pos: 0,
+        depth: 0,
+    };
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    if parser.pos == text.len() {
+        Ok(value)
+    } else {
+        Err(parser.error("Unexpected trailing characters"))
+    }
+}
+
+// Parses a document of the given format, checking its version.
+fn parse_document(text: &str, format: &str) -> Result<Json, Error> {
+    let json = parse(text)?;
+    let got = json.field("format")?.as_str()?;
+    if got != format {
+        return Err(invalid(format!(
+            "Expected format {}, got {}",
+            format, got
+        )));
+    }
+    let version = json.field("version")?.as_u32()?;
+    if version > VERSION {
+        return Err(Error::UnsupportedJsonVersion { version });
+    }
+    Ok(json)
+}
+
+fn document(
+    format: &str,
+    metadata: &BTreeMap<String, String>,
+    mut fields: Vec<(&str, Json)>,
+) -> String {
+    let metadata = metadata
+        .iter()
+        .map(|(key, value)| (key.clone(), Json::string(value.as_str())))
+        .collect();
+    let mut header = vec![
+        ("format", Json::string(format)),
+        ("version", Json::number(VERSION)),
+        ("metadata", Json::Object(metadata)),
+    ];
+    header.append(&mut fields);
+    let mut out = String::new();
+    // One node or connection per line.
+    Json::object(header).write(&mut out, 0, 2);
+    out.push('\n');
+    out
+}
+
+// Metadata of a JSON genome or network, as written by
+// `to_json_with_metadata`, without reading the rest.
+pub fn read_json_metadata(
+    json: &str,
+) -> Result<BTreeMap<String, String>, Error> {
+    let json = parse(json)?;
+    match json.get("metadata") {
+        Some(Json::Object(fields)) => fields
+            .iter()
+            .map(|(key, value)| Ok((key.clone(), value.as_str()?.to_string())))
+            .collect(),
+        Some(other) => Err(invalid(format!("Invalid metadata {}", other))),
+        None => Ok(BTreeMap::new()),
+    }
+}
+
+// Looks up `name` among the names of a set of values, for enums
+// written as strings.
+fn named<T: Copy>(json: &Json, names: &[(&str, T)]) -> Result<T, Error> {
+    let name = json.as_str()?;
+    names
+        .iter()
+        .find(|(n, _)| *n == name)
+        .map(|(_, x)| *x)
+        .ok_or_else(|| invalid(format!("Unknown value \"{}\"", name)))
+}
+
+fn name_of<T: PartialEq>(x: T, names: &[(&'static str, T)]) -> Json {
+    let name = names.iter().find(|(_, y)| *y == x).map(|(n, _)| *n);
+    Json::string(name.unwrap_or_default())
+}
+
+const NODE_TYPES: [(&str, NodeType); 4] = [
+    ("input", NodeType::Input),
+    ("output", NodeType::Output),
+    ("hidden", NodeType::Hidden),
+    ("bias", NodeType::Bias),
+];
+
+const CONNECTION_TYPES: [(&str, ConnectionType); 2] = [
+    ("normal", ConnectionType::Normal),
+    ("recurrent", ConnectionType::Recurrent),
+];
+
+const AGGREGATIONS: [(&str, AggregationFunction); 6] = [
+    ("sum", AggregationFunction::Sum),
+    ("product", AggregationFunction::Product),
+    ("min", AggregationFunction::Min),
+    ("max", AggregationFunction::Max),
+    ("mean", AggregationFunction::Mean),
+    ("abs_sum", AggregationFunction::AbsSum),
+];
+
+const OUTPUT_TRANSFORMS: [(&str, OutputTransform); 3] = [
+    ("identity", OutputTransform::Identity),
+    ("softmax", OutputTransform::Softmax),
+    ("argmax", OutputTransform::ArgMax),
+];
+
+const DUPLICATE_POLICIES: [(&str, DuplicatePolicy); 3] = [
+    ("allow", DuplicatePolicy::Allow),
+    ("reject", DuplicatePolicy::Reject),
+    ("merge", DuplicatePolicy::Merge),
+];
+
+const BOUNDS_POLICIES: [(&str, BoundsPolicy); 3] = [
+    ("clamp", BoundsPolicy::Clamp),
+    ("reject", BoundsPolicy::Reject),
+    ("wrap", BoundsPolicy::Wrap),
+];
+
+fn func_to_json(func: ActivationFunction) -> Result<Json, Error> {
+    func.name()
+        .map(Json::String)
+        .ok_or(Error::UnsupportedActivationFunction { func })
+}
+
+fn func_from_json(json: &Json) -> Result<ActivationFunction, Error> {
+    let name = json.as_str()?;
+    ActivationFunction::from_name(name).ok_or_else(|| {
+        invalid(format!("Unknown activation function \"{}\"", name))
+    })
+}
+
+fn floats_to_json(fields: &[(&str, f32)]) -> Json {
+    Json::object(
+        fields
+            .iter()
+            .map(|(key, x)| (*key, Json::float(*x)))
+            .collect(),
+    )
+}
+
+fn gate_to_json(gate: &Gate) -> Json {
+    floats_to_json(&[
+        ("input_weight", gate.input_weight),
+        ("memory_weight", gate.memory_weight),
+        ("bias", gate.bias),
+    ])
+}
+
+fn gate_from_json(json: &Json) -> Result<Gate, Error> {
+    Ok(Gate {
+        input_weight: json.field("input_weight")?.as_f32()?,
+        memory_weight: json.field("memory_weight")?.as_f32()?,
+        bias: json.field("bias")?.as_f32()?,
+    })
+}
+
+fn memory_to_json(cell: &MemoryCell) -> Json {
+    Json::object(vec![
+        ("update_gate", gate_to_json(&cell.update_gate)),
+        ("reset_gate", gate_to_json(&cell.reset_gate)),
+        ("memory_weight", Json::float(cell.memory_weight)),
+    ])
+}
+
+fn memory_from_json(json: &Json) -> Result<Option<MemoryCell>, Error> {
+    Ok(match json.get("memory") {
+        None | Some(Json::Null) => None,
+        Some(cell) => Some(MemoryCell {
+            update_gate: gate_from_json(cell.field("update_gate")?)?,
+            reset_gate: gate_from_json(cell.field("reset_gate")?)?,
+            memory_weight: cell.field("memory_weight")?.as_f32()?,
+        }),
+    })
+}
+
+fn plasticity_to_json(rule: &HebbianRule) -> Json {
+    floats_to_json(&[
+        ("learning_rate", rule.learning_rate),
+        ("a", rule.a),
+        ("b", rule.b),
+        ("c", rule.c),
+        ("d", rule.d),
+    ])
+}
+
+fn plasticity_from_json(json: &Json) -> Result<Option<HebbianRule>, Error> {
+    Ok(match json.get("plasticity") {
+        None | Some(Json::Null) => None,
+        Some(rule) => Some(HebbianRule {
+            learning_rate: rule.field("learning_rate")?.as_f32()?,
+            a: rule.field("a")?.as_f32()?,
+            b: rule.field("b")?.as_f32()?,
+            c: rule.field("c")?.as_f32()?,
+            d: rule.field("d")?.as_f32()?,
+        }),
+    })
+}
+
+fn mutation_rates_to_json(rates: &MutationRates) -> Json {
+    floats_to_json(&[
+        ("weight_mutation_prob", rates.weight_mutation_prob),
+        ("weight_perturb_power", rates.weight_perturb_power),
+        ("add_connection_prob", rates.add_connection_prob),
+        ("add_node_prob", rates.add_node_prob),
+    ])
+}
+
+// Optional fields of genomes, nodes and connections, which are only
+// written when set.
+fn push_optional(
+    fields: &mut Vec<(&'static str, Json)>,
+    key: &'static str,
+    value: Option<Json>,
+) {
+    if let Some(value) = value {
+        fields.push((key, value));
+    }
+}
+
+fn input_scaling_to_json(scaling: &InputScaling) -> Json {
+    let (name, params) = match *scaling {
+        InputScaling::Identity => ("identity", vec![]),
+        InputScaling::MinMax { min, max } => {
+            ("min_max", vec![("min", min), ("max", max)])
+        }
+        InputScaling::ZScore { mean, std_dev } => {
+            ("z_score", vec![("mean", mean), ("std_dev", std_dev)])
+        }
+    };
+    let mut fields = vec![("type", Json::string(name))];
+    fields.extend(params.into_iter().map(|(key, x)| (key, Json::float(x))));
+    Json::object(fields)
+}
+
+fn input_scaling_from_json(json: &Json) -> Result<InputScaling, Error> {
+    let float = |key| json.field(key)?.as_f32();
+    match json.field("type")?.as_str()? {
+        "identity" => Ok(InputScaling::Identity),
+        "min_max" => Ok(InputScaling::MinMax {
+            min: float("min")?,
+            max: float("max")?,
+        }),
+        "z_score" => Ok(InputScaling::ZScore {
+            mean: float("mean")?,
+            std_dev: float("std_dev")?,
+        }),
+        other => Err(invalid(format!("Unknown input scaling \"{}\"", other))),
+    }
+}
+
+fn output_mapping_to_json(mapping: &OutputMapping) -> Json {
+    match *mapping {
+        OutputMapping::Affine { scale, offset } => Json::object(vec![
+            ("type", Json::string("affine")),
+            ("scale", Json::float(scale)),
+            ("offset", Json::float(offset)),
+        ]),
+        OutputMapping::Discrete(size) => Json::object(vec![
+            ("type", Json::string("discrete")),
+            ("size", Json::number(size)),
+        ]),
+    }
+}
+
+fn output_mapping_from_json(json: &Json) -> Result<OutputMapping, Error> {
+    match json.field("type")?.as_str()? {
+        "affine" => Ok(OutputMapping::Affine {
+            scale: json.field("scale")?.as_f32()?,
+            offset: json.field("offset")?.as_f32()?,
+        }),
+        "discrete" => {
+            Ok(OutputMapping::Discrete(json.field("size")?.as_u32()?))
+        }
+        other => Err(invalid(format!("Unknown output mapping \"{}\"", other))),
+    }
+}
+
+fn propagation_to_json(propagation: Propagation) -> Json {
+    match propagation {
+        Propagation::Passes(passes) => {
+            Json::object(vec![("passes", Json::number(passes))])
+        }
+        Propagation::UntilStable {
+            epsilon,
+            max_passes,
+        } => Json::object(vec![
+            ("epsilon", Json::float(epsilon)),
+            ("max_passes", Json::number(max_passes)),
+        ]),
+    }
+}
+
+fn propagation_from_json(json: &Json) -> Result<Propagation, Error> {
+    match json.get("passes") {
+        Some(passes) => Ok(Propagation::Passes(passes.as_u32()?)),
+        None => Ok(Propagation::UntilStable {
+            epsilon: json.field("epsilon")?.as_f32()?,
+            max_passes: json.field("max_passes")?.as_u32()?,
+        }),
+    }
+}
+
+impl Genome {
+    pub fn to_json(&self) -> Result<String, Error> {
+        self.to_json_with_metadata(&BTreeMap::new())
+    }
+
+    // Same as `to_json`, along with free-form metadata, such as the
+    // task or fitness, which `read_json_metadata` reads back.  Fails
+    // if a custom activation function isn't registered.
+    pub fn to_json_with_metadata(
+        &self,
+        metadata: &BTreeMap<String, String>,
+    ) -> Result<String, Error> {
+        let nodes = self
+            .nodes
+            .iter()
+            .map(|node| {
+                let mut fields = vec![
+                    ("id", Json::number(node.id)),
+                    ("type", name_of(node.node_type, &NODE_TYPES)),
+                    ("activation", func_to_json(node.func)?),
+                    ("bias", Json::float(node.bias)),
+                ];
+                push_optional(
+                    &mut fields,
+                    "memory",
+                    node.memory.as_ref().map(memory_to_json),
+                );
+                Ok(Json::object(fields))
+            })
+            .collect::<Result<_, Error>>()?;
+        let connections = self
+            .connections
+            .iter()
+            .map(|conn| {
+                let mut fields = vec![
+                    ("innovation", Json::number(conn.innovation)),
+                    ("origin", Json::number(conn.origin)),
+                    ("dest", Json::number(conn.dest)),
+                    ("weight", Json::float(conn.weight)),
+                    ("type", name_of(conn.connection_type, &CONNECTION_TYPES)),
+                    ("enabled", Json::Bool(conn.enabled)),
+                ];
+                push_optional(
+                    &mut fields,
+                    "plasticity",
+                    conn.plasticity.as_ref().map(plasticity_to_json),
+                );
+                Json::object(fields)
+            })
+            .collect();
+        let mut fields = Vec::new();
+        push_optional(
+            &mut fields,
+            "mutation_rates",
+            self.mutation_rates.as_ref().map(mutation_rates_to_json),
+        );
+        fields.push(("nodes", Json::Array(nodes)));
+        fields.push(("connections", Json::Array(connections)));
+        Ok(document(GENOME_FORMAT, metadata, fields))
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, Error> {
+        let json = parse_document(json, GENOME_FORMAT)?;
+        let nodes = json
+            .field("nodes")?
+            .as_array()?
+            .iter()
+            .map(|node| {
+                Ok(NodeGene {
+                    id: node.field("id")?.as_u32()?,
+                    node_type: named(node.field("type")?, &NODE_TYPES)?,
+                    func: func_from_json(node.field("activation")?)?,
+                    memory: memory_from_json(node)?,
+                    bias: match node.get("bias") {
+                        Some(x) => x.as_f32()?,
+                        None => 0.0,
+                    },
+                })
+            })
+            .collect::<Result<_, Error>>()?;
+        let connections = json
+            .field("connections")?
+            .as_array()?
+            .iter()
+            .map(|conn| {
+                Ok(ConnectionGene {
+                    innovation: conn.field("innovation")?.as_u32()?,
+                    origin: conn.field("origin")?.as_u32()?,
+                    dest: conn.field("dest")?.as_u32()?,
+                    weight: conn.field("weight")?.as_f32()?,
+                    connection_type: named(
+                        conn.field("type")?,
+                        &CONNECTION_TYPES,
+                    )?,
+                    enabled: conn.field("enabled")?.as_bool()?,
+                    plasticity: plasticity_from_json(conn)?,
+                })
+            })
+            .collect::<Result<_, Error>>()?;
+        let mutation_rates = match json.get("mutation_rates") {
+            None | Some(Json::Null) => None,
+            Some(rates) => Some(MutationRates {
+                weight_mutation_prob: rates
+                    .field("weight_mutation_prob")?
+                    .as_f32()?,
+                weight_perturb_power: rates
+                    .field("weight_perturb_power")?
+                    .as_f32()?,
+                add_connection_prob: rates
+                    .field("add_connection_prob")?
+                    .as_f32()?,
+                add_node_prob: rates.field("add_node_prob")?.as_f32()?,
+            }),
+        };
+        Ok(Genome {
+            nodes,
+            connections,
+            mutation_rates,
+        })
+    }
+}
+
+impl NeuralNetBuilder {
+    pub fn to_json(&self) -> Result<String, Error> {
+        self.to_json_with_metadata(&BTreeMap::new())
+    }
+
+    pub fn to_json_with_metadata(
+        &self,
+        metadata: &BTreeMap<String, String>,
+    ) -> Result<String, Error> {
+        let nodes = self
+            .nodes
+            .iter()
+            .map(|node| {
+                let mut fields = vec![
+                    ("type", name_of(node.node_type, &NODE_TYPES)),
+                    ("activation", func_to_json(node.func)?),
+                    ("aggregation", name_of(node.aggregation, &AGGREGATIONS)),
+                    ("bias", Json::float(node.bias)),
+                    ("response", Json::float(node.response)),
+                ];
+                push_optional(
+                    &mut fields,
+                    "memory",
+                    node.memory.as_ref().map(memory_to_json),
+                );
+                push_optional(
+                    &mut fields,
+                    "name",
+                    node.name.as_deref().map(Json::string),
+                );
+                Ok(Json::object(fields))
+            })
+            .collect::<Result<_, Error>>()?;
+        let connections = self
+            .connections
+            .iter()
+            .map(|conn| {
+                let mut fields = vec![
+                    ("origin", Json::number(conn.origin)),
+                    ("dest", Json::number(conn.dest)),
+                    ("weight", Json::float(conn.weight)),
+                    ("type", name_of(conn.connection_type, &CONNECTION_TYPES)),
+                    ("enabled", Json::Bool(conn.enabled)),
+                ];
+                push_optional(
+                    &mut fields,
+                    "plasticity",
+                    conn.plasticity.as_ref().map(plasticity_to_json),
+                );
+                Json::object(fields)
+            })
+            .collect();
+
+        let mut fields = vec![
+            ("bias_value", Json::float(self.bias_value())),
+            (
+                "default_activation",
+                func_to_json(self.default_activation())?,
+            ),
+            (
+                "default_aggregation",
+                name_of(self.default_aggregation(), &AGGREGATIONS),
+            ),
+            (
+                "output_transform",
+                name_of(self.output_transform(), &OUTPUT_TRANSFORMS),
+            ),
+            ("propagation", propagation_to_json(self.propagation())),
+            (
+                "duplicate_policy",
+                name_of(self.duplicate_policy(), &DUPLICATE_POLICIES),
+            ),
+        ];
+        push_optional(
+            &mut fields,
+            "weight_bounds",
+            self.weight_bounds().map(|bounds| {
+                Json::object(vec![
+                    ("min", Json::float(bounds.min)),
+                    ("max", Json::float(bounds.max)),
+                    ("policy", name_of(bounds.policy, &BOUNDS_POLICIES)),
+                ])
+            }),
+        );
+        fields.push((
+            "input_scaling",
+            Json::Array(
+                self.input_scaling()
+                    .iter()
+                    .map(input_scaling_to_json)
+                    .collect(),
+            ),
+        ));
+        fields.push((
+            "output_mapping",
+            Json::Array(
+                self.output_mapping()
+                    .iter()
+                    .map(output_mapping_to_json)
+                    .collect(),
+            ),
+        ));
+        fields.push(("nodes", Json::Array(nodes)));
+        fields.push(("connections", Json::Array(connections)));
+        Ok(document(NETWORK_FORMAT, metadata, fields))
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, Error> {
+        let json = parse_document(json, NETWORK_FORMAT)?;
+        let mut builder = NeuralNetBuilder::new();
+        if let Some(x) = json.get("bias_value") {
+            builder.set_bias_value(x.as_f32()?);
+        }
+        if let Some(x) = json.get("default_activation") {
+            builder.set_default_activation(func_from_json(x)?);
+        }
+        if let Some(x) = json.get("default_aggregation") {
+            builder.set_default_aggregation(named(x, &AGGREGATIONS)?);
+        }
+        if let Some(x) = json.get("output_transform") {
+            builder.set_output_transform(named(x, &OUTPUT_TRANSFORMS)?);
+        }
+        if let Some(x) = json.get("propagation") {
+            builder.set_propagation(propagation_from_json(x)?);
+        }
+        if let Some(x) = json.get("duplicate_policy") {
+            builder.set_duplicate_policy(named(x, &DUPLICATE_POLICIES)?);
+        }
+        if let Some(x) = json.get("weight_bounds") {
+            builder.set_weight_bounds(Some(WeightBounds {
+                min: x.field("min")?.as_f32()?,
+                max: x.field("max")?.as_f32()?,
+                policy: match x.get("policy") {
+                    Some(policy) => named(policy, &BOUNDS_POLICIES)?,
+                    None => BoundsPolicy::default(),
+                },
+            }));
+        }
+        if let Some(x) = json.get("input_scaling") {
+            let scaling = x.as_array()?.iter().map(input_scaling_from_json);
+            builder.set_input_scaling(scaling.collect::<Result<_, _>>()?);
+        }
+        if let Some(x) = json.get("output_mapping") {
+            let mapping = x.as_array()?.iter().map(output_mapping_from_json);
+            builder.set_output_mapping(mapping.collect::<Result<_, _>>()?);
+        }
+
+        builder.nodes = json
+            .field("nodes")?
+            .as_array()?
+            .iter()
+            .map(|node| {
+                let float = |key, default| match node.get(key) {
+                    Some(x) => x.as_f32(),
+                    None => Ok(default),
+                };
+                Ok(NodeTemplate {
+                    node_type: named(node.field("type")?, &NODE_TYPES)?,
+                    func: func_from_json(node.field("activation")?)?,
+                    aggregation: match node.get("aggregation") {
+                        Some(x) => named(x, &AGGREGATIONS)?,
+                        None => AggregationFunction::Sum,
+                    },
+                    bias: float("bias", 0.0)?,
+                    response: float("response", 1.0)?,
+                    memory: memory_from_json(node)?,
+                    name: match node.get("name") {
+                        Some(name) => Some(name.as_str()?.to_string()),
+                        None => None,
+                    },
+                })
+            })
+            .collect::<Result<_, Error>>()?;
+        builder.connections = json
+            .field("connections")?
+            .as_array()?
+            .iter()
+            .map(|conn| {
+                Ok(ConnectionTemplate {
+                    origin: conn.field("origin")?.as_u32()?,
+                    dest: conn.field("dest")?.as_u32()?,
+                    weight: conn.field("weight")?.as_f32()?,
+                    connection_type: named(
+                        conn.field("type")?,
+                        &CONNECTION_TYPES,
+                    )?,
+                    plasticity: plasticity_from_json(conn)?,
+                    enabled: match conn.get("enabled") {
+                        Some(x) => x.as_bool()?,
+                        None => true,
+                    },
+                })
+            })
+            .collect::<Result<_, Error>>()?;
+        Ok(builder)
+    }
+}
+
+fn matrix_from_json(json: &Json) -> Result<Vec<Vec<f32>>, Error> {
+    json.as_array()?.iter().map(floats_from_json).collect()
+}
+
+fn floats_from_json(json: &Json) -> Result<Vec<f32>, Error> {
+    json.as_array()?.iter().map(Json::as_f32).collect()
+}
+
+// Activation function and output transform of a layer named as in
+// Keras or PyTorch, where a final softmax becomes the transform.
+fn dense_activation_from_json(
+    json: &Json,
+) -> Result<(ActivationFunction, OutputTransform), Error> {
+    let name = json.as_str()?.to_lowercase();
+    match name.as_str() {
+        "linear" | "none" => {
+            Ok((ActivationFunction::Identity, OutputTransform::Identity))
+        }
+        "softmax" => {
+            Ok((ActivationFunction::Identity, OutputTransform::Softmax))
+        }
+        _ => Ok((
+            func_from_json(&Json::String(name))?,
+            OutputTransform::Identity,
+        )),
+    }
+}
+
+impl NeuralNetBuilder {
+    // Network from a dump of trained dense layers, as described at the
+    // top of this file.  Softmax is only allowed on the last layer.
+    pub fn from_dense_layers_json(json: &str) -> Result<Self, Error> {
+        let json = parse(json)?;
+        let mut output_transform = OutputTransform::Identity;
+        let layers = json.field("layers")?.as_array()?;
+        let layers = layers
+            .iter()
+            .enumerate()
+            .map(|(i, layer)| {
+                let (activation, transform) = match layer.get("activation") {
+                    Some(x) => dense_activation_from_json(x)?,
+                    None => (
+                        ActivationFunction::Identity,
+                        OutputTransform::Identity,
+                    ),
+                };
+                if transform != OutputTransform::Identity {
+                    if i + 1 != layers.len() {
+                        return Err(invalid(format!(
+                            "Softmax on hidden layer {}",
+                            i
+                        )));
+                    }
+                    output_transform = transform;
+                }
+                let biases = match layer.get("bias") {
+                    Some(x) => floats_from_json(x)?,
+                    None => Vec::new(),
+                };
+                match (layer.get("weight"), layer.get("kernel")) {
+                    (Some(x), None) => Ok(DenseLayer {
+                        weights: matrix_from_json(x)?,
+                        biases,
+                        activation,
+                    }),
+                    (None, Some(x)) => Ok(DenseLayer::from_kernel(
+                        &matrix_from_json(x)?,
+                        biases,
+                        activation,
+                    )),
+                    _ => Err(invalid(format!(
+                        "Layer {} needs one of \"weight\" and \"kernel\"",
+                        i
+                    ))),
+                }
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        let input_count = match json.get("inputs") {
+            Some(x) => x.as_u32()?,
+            None => layers
+                .first()
+                .and_then(|layer| layer.weights.first())
+                .map_or(0, |row| row.len() as u32),
+        };
+        let mut builder = Self::from_dense_layers(input_count, &layers)?;
+        builder.set_output_transform(output_transform);
+        Ok(builder)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_json_genome() -> Result<(), Error> {
+        let mut tracker = InnovationTracker::new();
+        let mut genome =
+            Genome::with_io(2, 1, ActivationFunction::Sigmoid, &mut tracker);
+        let hidden = genome.add_node(
+            NodeType::Hidden,
+            ActivationFunction::Elu(0.5),
+            &mut tracker,
+        );
+        genome.add_connection(
+            0,
+            hidden,
+            0.1,
+            ConnectionType::Normal,
+            &mut tracker,
+        );
+        genome.add_connection(
+            hidden,
+            2,
+            f32::NAN,
+            ConnectionType::Recurrent,
+            &mut tracker,
+        );
+        genome.connections[0].enabled = false;
+        genome.connections[1].plasticity = Some(HebbianRule::default());
+        genome.nodes[3].memory = Some(MemoryCell::default());
+        genome.mutation_rates =
+            Some(MutationRates::from_config(&MutationConfig::default()));
+
+        let mut metadata = BTreeMap::new();
+        metadata.insert("task".to_string(), "xor \"2\"\n".to_string());
+        let json = genome.to_json_with_metadata(&metadata)?;
+        assert!(json.contains("\"version\": 1"));
+        assert_eq!(read_json_metadata(&json)?, metadata);
+        let read = Genome::from_json(&json)?;
+        assert!(read.connections[1].weight.is_nan());
+        let weightless = |mut genome: Genome| {
+            genome.connections[1].weight = 0.0;
+            genome
+        };
+        assert_eq!(weightless(read), weightless(genome.clone()));
+
+        // Unknown fields are ignored, but newer versions, other
+        // formats and bad values are rejected.
+        let extra =
+            json.replace("\"nodes\"", "\"extra\": [null, {}], \"nodes\"");
+        assert_eq!(
+            weightless(Genome::from_json(&extra)?),
+            weightless(genome.clone())
+        );
+        let newer = json.replace("\"version\": 1", "\"version\": 2");
+        assert_eq!(
+            Genome::from_json(&newer),
+            Err(Error::UnsupportedJsonVersion { version: 2 })
+        );
+        assert!(NeuralNetBuilder::from_json(&json).is_err());
+        let bad = json.replace("\"hidden\"", "\"hiden\"");
+        assert!(matches!(
+            Genome::from_json(&bad),
+            Err(Error::InvalidJson { .. })
+        ));
+        assert!(Genome::from_json(&json[..json.len() - 3]).is_err());
+        let deep = "[".repeat(200_000);
+        assert!(matches!(
+            read_json_metadata(&deep),
+            Err(Error::InvalidJson { .. })
+        ));
+        let nested = format!("{}{}", "[".repeat(128), "]".repeat(128));
+        assert!(parse(&nested).is_ok());
+        let nested = format!("{}{}", "[".repeat(129), "]".repeat(129));
+        assert!(matches!(parse(&nested), Err(Error::InvalidJson { .. })));
+
+        genome.nodes[3].func = ActivationFunction::Custom(f32::sqrt);
+        assert!(genome.to_json().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_json_builder() -> Result<(), Error> {
+        let mut builder = NeuralNetBuilder::new()
+            .with_default_activation(ActivationFunction::Tanh)
+            .with_output_transform(OutputTransform::Softmax)
+            .with_propagation(Propagation::UntilStable {
+                epsilon: 1e-3,
+                max_passes: 5,
+            })
+            .with_duplicate_policy(DuplicatePolicy::Merge)
+            .with_input_scaling(vec![InputScaling::MinMax {
+                min: -2.0,
+                max: 3.0,
+            }])
+            .with_output_mapping(vec![OutputMapping::Discrete(2)])
+            .with_inputs(1)
+            .with_bias()
+            .with_nodes(NodeType::Output, 2)
+            .with_normal_connection_raw(0, 2, 0.3)
+            .with_normal_connection_raw(1, 3, -1.7)
+            .with_recurrent_connection_raw(3, 2, 1e-8);
+        builder
+            .set_weight_bounds(Some(WeightBounds::default()))
+            .set_default_aggregation(AggregationFunction::Max)
+            .set_enabled(1, false);
+        builder.nodes[2].bias = 0.25;
+        builder.nodes[3].name = Some("out".to_string());
+        builder.nodes[3].aggregation = AggregationFunction::AbsSum;
+
+        let json = builder.to_json()?;
+        assert_eq!(NeuralNetBuilder::from_json(&json)?, builder);
+
+        // Everything but the nodes and connections may be left out.
+        let minimal = r#"{"format": "entendre-network", "version": 1,
+            "nodes": [{"type": "input", "activation": "identity"},
+                      {"type": "output", "activation": "relu"}],
+            "connections": [{"origin": 0, "dest": 1, "weight": -2e0,
+                             "type": "normal"}]}"#;
+        let mut net = NeuralNetBuilder::from_json(minimal)?
+            .build::<crate::ConsecutiveNeuralNet>()?;
+        assert_eq!(net.evaluate(&[-1.5]), [3.0]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_json_dense_layers() -> Result<(), Error> {
+        let dump = r#"{"layers": [
+            {"weight": [[1.0, -1.0], [0.5, 0.0]], "bias": [0.0, 1.0],
+             "activation": "ReLU"},
+            {"kernel": [[1.0, 0.0], [-2.0, 0.0]], "activation": "softmax"}
+        ]}"#;
+        let builder = NeuralNetBuilder::from_dense_layers_json(dump)?;
+        assert_eq!(builder.num_inputs(), 2);
+        assert_eq!(builder.num_outputs(), 2);
+        assert_eq!(builder.output_transform(), OutputTransform::Softmax);
+        assert_eq!(builder.nodes[2].func, ActivationFunction::Relu);
+        assert_eq!(builder.nodes[3].bias, 1.0);
+
+        // Hidden values (1, 2.5), so logits (1 - 5, 0).
+        let mut net = builder.build::<crate::ConsecutiveNeuralNet>()?;
+        let outputs = net.evaluate(&[3.0, 2.0]);
+        assert!((outputs[0] - 1.0 / (1.0 + 4f32.exp())).abs() < 1e-6);
+
+        let hidden_softmax = r#"{"layers": [
+            {"weight": [[1.0]], "activation": "softmax"},
+            {"weight": [[1.0]]}]}"#;
+        let res = NeuralNetBuilder::from_dense_layers_json(hidden_softmax);
+        assert!(matches!(res, Err(Error::InvalidJson { .. })));
+        let wrong_inputs = r#"{"inputs": 3, "layers": [{"weight": [[1.0]]}]}"#;
+        let res = NeuralNetBuilder::from_dense_layers_json(wrong_inputs);
+        assert!(matches!(res, Err(Error::InvalidDenseLayer { layer: 0 })));
+        Ok(())
+    }
+}
@@ -0,0 +1,246 @@
+use core::ops::Range;
+
+use rand::Rng;
+
+use crate::evolution_strategy::gaussian;
+use crate::neural_net::*;
+
+// Distribution of the initial weights of a fully connected layer.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum WeightInit {
+    Constant(f32),
+    Uniform { low: f32, high: f32 },
+    Normal { mean: f32, std_dev: f32 },
+    // Uniform in ±sqrt(6 / (fan_in + fan_out)), as in Glorot and
+    // Bengio, which suits sigmoid and tanh layers.
+    Xavier,
+}
+
+impl WeightInit {
+    fn sample<R: Rng + ?Sized>(
+        &self,
+        fan_in: usize,
+        fan_out: usize,
+        rng: &mut R,
+    ) -> f32 {
+        use WeightInit::*;
+        match *self {
+            Constant(weight) => weight,
+            Uniform { low, high } if low < high => rng.gen_range(low..high),
+            Uniform { low, .. } => low,
+            Normal { mean, std_dev } => mean + std_dev * gaussian(rng),
+            Xavier => {
+                let limit = (6.0 / (fan_in + fan_out).max(1) as f32).sqrt();
+                rng.gen_range(-limit..=limit)
+            }
+        }
+    }
+}
+
+// Trained fully connected layer, as exported from another framework.
+// `weights[i][j]` is the weight from node `j` of the previous layer to
+// node `i` of this one, as in PyTorch's `nn.Linear.weight`, and
+// `biases` is empty for a layer without biases.
+#[derive(Debug, PartialEq, Clone)]
+pub struct DenseLayer {
+    pub weights: Vec<Vec<f32>>,
+    pub biases: Vec<f32>,
+    pub activation: ActivationFunction,
+}
+
+impl DenseLayer {
+    // Layer from a Keras `Dense` kernel, which is indexed the other
+    // way round, by previous node and then by node of this layer.
+    pub fn from_kernel(
+        kernel: &[Vec<f32>],
+        biases: Vec<f32>,
+        activation: ActivationFunction,
+    ) -> Self {
+        // Ragged kernels give short rows, which building rejects.
+        let size = kernel.iter().map(Vec::len).max().unwrap_or(0);
+        let weights = (0..size)
+            .map(|i| {
+                kernel
+                    .iter()
+                    .filter_map(|row| row.get(i))
+                    .copied()
+                    .collect()
+            })
+            .collect();
+        DenseLayer {
+            weights,
+            biases,
+            activation,
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.weights.len()
+    }
+}
+
+impl NeuralNetBuilder {
+    // Network computing the same function as a stack of trained dense
+    // layers on `input_count` inputs, for seeding evolution with a
+    // network trained elsewhere.  Nodes are the inputs followed by
+    // the nodes of each layer in order, the last layer being the
+    // outputs, with every weight kept as a connection, zero or not, so
+    // that the topology matches the original.  Layers are only read
+    // from the JSON dump of `from_dense_layers_json`; other formats,
+    // such as npz, are out of scope.  `Genome::from_builder` turns the
+    // result into a genome to seed a population with.
+    pub fn from_dense_layers(
+        input_count: u32,
+        layers: &[DenseLayer],
+    ) -> Result<Self, Error> {
+        let mut builder = Self::new();
+        builder.add_inputs(input_count);
+        let mut prev_layer = 0..input_count;
+        for (i, layer) in layers.iter().enumerate() {
+            let valid = layer
+                .weights
+                .iter()
+                .all(|row| row.len() == prev_layer.len())
+                && (layer.biases.is_empty()
+                    || layer.biases.len() == layer.size());
+            if !valid {
+                return Err(Error::InvalidDenseLayer { layer: i });
+            }
+
+            let node_type = if i + 1 == layers.len() {
+                NodeType::Output
+            } else {
+                NodeType::Hidden
+            };
+            let start = builder.nodes.len() as u32;
+            layer.weights.iter().enumerate().for_each(|(j, row)| {
+                builder.add_node(node_type, layer.activation);
+                if let Some(bias) = layer.biases.get(j) {
+                    builder.nodes[start as usize + j].bias = *bias;
+                }
+                let dest = start + j as u32;
+                prev_layer.clone().zip(row).for_each(|(origin, w)| {
+                    builder.add_normal_connection_raw(origin, dest, *w);
+                });
+            });
+            prev_layer = start..start + layer.size() as u32;
+        }
+        Ok(builder)
+    }
+
+    // Adds `size` nodes of `node_type`, each with a normal connection
+    // from every node of `prev_layer`, and returns the range of the
+    // new nodes, which can be passed as the previous layer of the
+    // next call.  Weights are drawn from `weight_init` in the order
+    // of the new connections.
+    pub fn add_fully_connected_layer<R: Rng + ?Sized>(
+        &mut self,
+        prev_layer: Range<u32>,
+        node_type: NodeType,
+        size: u32,
+        func: ActivationFunction,
+        weight_init: WeightInit,
+        rng: &mut R,
+    ) -> Range<u32> {
+        let start = self.nodes.len() as u32;
+        (0..size).for_each(|_| {
+            self.add_node(node_type, func);
+        });
+        let layer = start..start + size;
+
+        let fan_in = prev_layer.len();
+        layer.clone().for_each(|dest| {
+            prev_layer.clone().for_each(|origin| {
+                let weight = weight_init.sample(fan_in, size as usize, rng);
+                self.add_normal_connection_raw(origin, dest, weight);
+            });
+        });
+        layer
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_fully_connected_layer() -> Result<(), Error> {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut builder = NeuralNetBuilder::new();
+        builder.add_inputs(3);
+        let hidden = builder.add_fully_connected_layer(
+            0..3,
+            NodeType::Hidden,
+            4,
+            ActivationFunction::Tanh,
+            WeightInit::Xavier,
+            &mut rng,
+        );
+        assert_eq!(hidden, 3..7);
+        let output = builder.add_fully_connected_layer(
+            hidden,
+            NodeType::Output,
+            2,
+            ActivationFunction::Identity,
+            WeightInit::Constant(0.5),
+            &mut rng,
+        );
+        assert_eq!(output, 7..9);
+        assert_eq!(builder.connections.len(), 3 * 4 + 4 * 2);
+
+        let limit = (6.0f32 / 7.0).sqrt();
+        assert!(builder.connections[..12]
+            .iter()
+            .all(|conn| conn.weight.abs() <= limit));
+        assert!(builder.connections[12..]
+            .iter()
+            .all(|conn| conn.origin >= 3 && conn.weight == 0.5));
+
+        let mut net: ConsecutiveNeuralNet = builder.build()?;
+        assert_eq!(net.evaluate(&[1.0, -1.0, 0.5]).len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_dense_layers() -> Result<(), Error> {
+        let hidden = DenseLayer {
+            weights: vec![vec![1.0, -1.0], vec![0.5, 0.0], vec![2.0, 1.0]],
+            biases: vec![0.0, 1.0, -3.0],
+            activation: ActivationFunction::Relu,
+        };
+        let output = DenseLayer::from_kernel(
+            &[vec![1.0], vec![-2.0], vec![0.5]],
+            vec![],
+            ActivationFunction::Identity,
+        );
+        assert_eq!(output.weights, vec![vec![1.0, -2.0, 0.5]]);
+
+        let builder = NeuralNetBuilder::from_dense_layers(
+            2,
+            &[hidden.clone(), output.clone()],
+        )?;
+        assert_eq!(builder.num_hidden(), 3);
+        assert_eq!(builder.num_outputs(), 1);
+        assert_eq!(builder.connections.len(), 2 * 3 + 3);
+
+        // Hidden values relu(1 + 0, 1.5 + 1, 8 - 3) = (1, 2.5, 5).
+        let mut net: ConsecutiveNeuralNet = builder.build()?;
+        assert_eq!(net.evaluate(&[3.0, 2.0]), vec![1.0 - 5.0 + 2.5]);
+
+        let res = NeuralNetBuilder::from_dense_layers(
+            3,
+            std::slice::from_ref(&hidden),
+        );
+        assert!(matches!(res, Err(Error::InvalidDenseLayer { layer: 0 })));
+        let short = DenseLayer {
+            biases: vec![1.0, 2.0],
+            ..output
+        };
+        let res = NeuralNetBuilder::from_dense_layers(2, &[hidden, short]);
+        assert!(matches!(res, Err(Error::InvalidDenseLayer { layer: 1 })));
+        Ok(())
+    }
+}
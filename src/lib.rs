@@ -1,5 +1,212 @@
+// Only networks are available without the `std` feature, which
+// still need an allocator.
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
+mod float;
+pub use float::*;
+
 mod neural_net;
 pub use neural_net::*;
 
+mod validation;
+pub use validation::*;
+
+mod network_metrics;
+pub use network_metrics::*;
+
 mod neural_net_consecutive;
 pub use neural_net_consecutive::*;
+
+mod neural_net_layered;
+pub use neural_net_layered::*;
+
+mod neural_net_sparse;
+pub use neural_net_sparse::*;
+
+mod neural_net_quantized;
+pub use neural_net_quantized::*;
+
+mod neural_net_named;
+pub use neural_net_named::*;
+
+#[cfg(all(test, feature = "std"))]
+mod neural_net_properties;
+
+#[cfg(feature = "std")]
+mod activation_registry;
+#[cfg(feature = "std")]
+pub use activation_registry::*;
+
+#[cfg(feature = "std")]
+mod dot;
+
+#[cfg(feature = "std")]
+mod neat_format;
+#[cfg(feature = "std")]
+pub use neat_format::*;
+
+#[cfg(feature = "std")]
+mod json_format;
+#[cfg(feature = "std")]
+pub use json_format::*;
+
+#[cfg(feature = "std")]
+mod layers;
+#[cfg(feature = "std")]
+pub use layers::*;
+
+#[cfg(feature = "std")]
+mod backprop;
+#[cfg(feature = "std")]
+pub use backprop::*;
+
+#[cfg(feature = "std")]
+mod evolution_strategy;
+#[cfg(feature = "std")]
+pub use evolution_strategy::*;
+
+#[cfg(feature = "std")]
+mod genome;
+#[cfg(feature = "std")]
+pub use genome::*;
+
+#[cfg(feature = "std")]
+mod crossover;
+#[cfg(feature = "std")]
+pub use crossover::*;
+
+#[cfg(feature = "std")]
+mod mutation;
+#[cfg(feature = "std")]
+pub use mutation::*;
+
+#[cfg(feature = "std")]
+mod species;
+#[cfg(feature = "std")]
+pub use species::*;
+
+#[cfg(feature = "std")]
+mod selection;
+#[cfg(feature = "std")]
+pub use selection::*;
+
+#[cfg(feature = "std")]
+mod environment;
+#[cfg(feature = "std")]
+pub use environment::*;
+
+#[cfg(feature = "std")]
+mod reporting;
+#[cfg(feature = "std")]
+pub use reporting::*;
+
+#[cfg(feature = "std")]
+mod phased_search;
+#[cfg(feature = "std")]
+pub use phased_search::*;
+
+#[cfg(feature = "std")]
+mod population;
+#[cfg(feature = "std")]
+pub use population::*;
+
+#[cfg(feature = "std")]
+mod phenotype_cache;
+#[cfg(feature = "std")]
+pub use phenotype_cache::*;
+
+#[cfg(feature = "std")]
+mod hall_of_fame;
+#[cfg(feature = "std")]
+pub use hall_of_fame::*;
+
+#[cfg(feature = "std")]
+mod lineage;
+#[cfg(feature = "std")]
+pub use lineage::*;
+
+#[cfg(feature = "std")]
+mod archipelago;
+#[cfg(feature = "std")]
+pub use archipelago::*;
+
+#[cfg(feature = "std")]
+mod coevolution;
+#[cfg(feature = "std")]
+pub use coevolution::*;
+
+#[cfg(feature = "std")]
+mod steady_state;
+#[cfg(feature = "std")]
+pub use steady_state::*;
+
+#[cfg(feature = "std")]
+mod neat_python_config;
+
+#[cfg(feature = "std")]
+mod benchmarks;
+#[cfg(feature = "std")]
+pub use benchmarks::*;
+
+#[cfg(feature = "std")]
+mod novelty;
+#[cfg(feature = "std")]
+pub use novelty::*;
+
+#[cfg(feature = "std")]
+mod map_elites;
+#[cfg(feature = "std")]
+pub use map_elites::*;
+
+#[cfg(feature = "std")]
+mod weight_agnostic;
+#[cfg(feature = "std")]
+pub use weight_agnostic::*;
+
+#[cfg(feature = "std")]
+mod multi_objective;
+#[cfg(feature = "std")]
+pub use multi_objective::*;
+
+#[cfg(feature = "std")]
+mod cppn;
+#[cfg(feature = "std")]
+pub use cppn::*;
+
+#[cfg(feature = "std")]
+mod substrate;
+#[cfg(feature = "std")]
+pub use substrate::*;
+
+#[cfg(feature = "std")]
+mod es_hyperneat;
+#[cfg(feature = "std")]
+pub use es_hyperneat::*;
+
+#[cfg(feature = "std")]
+mod checkpoint;
+
+#[cfg(feature = "gpu")]
+mod neural_net_gpu;
+#[cfg(feature = "gpu")]
+pub use neural_net_gpu::*;
+
+#[cfg(feature = "onnx")]
+mod onnx;
+
+#[cfg(feature = "distributed")]
+mod distributed;
+#[cfg(feature = "distributed")]
+pub use distributed::*;
+
+#[cfg(feature = "async")]
+mod async_evaluation;
+#[cfg(feature = "async")]
+pub use async_evaluation::*;
+
+#[cfg(feature = "wasm")]
+mod wasm;
+#[cfg(feature = "wasm")]
+pub use wasm::*;
@@ -0,0 +1,178 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::mutation::*;
+
+// How a genome came about.  Genomes of the first generation have no
+// parents, mutated copies have one and children of crossover two, in
+// the order they were selected.  Elites keep the id of the genome
+// they copy.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct LineageRecord {
+    pub id: u64,
+    // Generation the genome first appeared in.
+    pub generation: usize,
+    pub parents: Vec<u64>,
+    pub mutations: Vec<MutationKind>,
+    // Fitness of the most recent evaluation, if any.
+    pub fitness: Option<f32>,
+}
+
+// Ancestry of every genome of a run, keyed by genome id.
+#[derive(Debug, PartialEq, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Lineage {
+    pub(crate) records: BTreeMap<u64, LineageRecord>,
+    pub(crate) next_id: u64,
+}
+
+impl Lineage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Records a new genome and returns its id.
+    pub fn record(
+        &mut self,
+        generation: usize,
+        parents: Vec<u64>,
+        mutations: Vec<MutationKind>,
+    ) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.records.insert(
+            id,
+            LineageRecord {
+                id,
+                generation,
+                parents,
+                mutations,
+                fitness: None,
+            },
+        );
+        id
+    }
+
+    pub fn get(&self, id: u64) -> Option<&LineageRecord> {
+        self.records.get(&id)
+    }
+
+    pub fn set_fitness(&mut self, id: u64, fitness: f32) {
+        if let Some(record) = self.records.get_mut(&id) {
+            record.fitness = Some(fitness);
+        }
+    }
+
+    pub fn records(&self) -> impl Iterator<Item = &LineageRecord> {
+        self.records.values()
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    // The genome with the given id and all of its recorded ancestors,
+    // oldest first.
+    pub fn ancestry(&self, id: u64) -> Vec<&LineageRecord> {
+        let mut seen = BTreeSet::new();
+        let mut stack = vec![id];
+        while let Some(id) = stack.pop() {
+            if let Some(record) = self.records.get(&id) {
+                if seen.insert(id) {
+                    stack.extend(record.parents.iter().cloned());
+                }
+            }
+        }
+        // Ids grow with time, so parents come before their children.
+        seen.iter().map(|id| &self.records[id]).collect()
+    }
+
+    // Graphviz DOT description of the ancestry of a genome, with an
+    // edge from each parent to its child labeled by the mutations
+    // that produced the child.
+    pub fn ancestry_dot(&self, id: u64) -> String {
+        let mut dot = String::new();
+        self.write_dot(&mut dot, id)
+            .expect("Writing to a String cannot fail");
+        dot
+    }
+
+    fn write_dot(&self, dot: &mut String, id: u64) -> std::fmt::Result {
+        let ancestry = self.ancestry(id);
+        writeln!(dot, "digraph {{")?;
+        ancestry.iter().try_for_each(|record| {
+            let fitness = match record.fitness {
+                Some(fitness) => format!("\\nfitness {}", fitness),
+                None => String::new(),
+            };
+            writeln!(
+                dot,
+                "    g{} [label=\"{}: generation {}{}\"];",
+                record.id, record.id, record.generation, fitness
+            )
+        })?;
+        ancestry.iter().try_for_each(|record| {
+            let mutations = record
+                .mutations
+                .iter()
+                .map(|kind| format!("{:?}", kind))
+                .collect::<Vec<_>>()
+                .join(", ");
+            record.parents.iter().try_for_each(|parent| {
+                writeln!(
+                    dot,
+                    "    g{} -> g{} [label=\"{}\"];",
+                    parent, record.id, mutations
+                )
+            })
+        })?;
+        writeln!(dot, "}}")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_ancestry() {
+        let mut lineage = Lineage::new();
+        let a = lineage.record(0, vec![], vec![]);
+        let b = lineage.record(0, vec![], vec![]);
+        let c = lineage.record(1, vec![a], vec![MutationKind::AddNode]);
+        lineage.record(1, vec![b], vec![]);
+        let e = lineage.record(
+            2,
+            vec![c, b],
+            vec![MutationKind::Weights, MutationKind::AddConnection],
+        );
+        lineage.set_fitness(e, 2.5);
+
+        let ancestry = lineage
+            .ancestry(e)
+            .iter()
+            .map(|record| record.id)
+            .collect::<Vec<_>>();
+        assert_eq!(ancestry, vec![a, b, c, e]);
+
+        let expected = "digraph {
+    g0 [label=\"0: generation 0\"];
+    g1 [label=\"1: generation 0\"];
+    g2 [label=\"2: generation 1\"];
+    g4 [label=\"4: generation 2\\nfitness 2.5\"];
+    g0 -> g2 [label=\"AddNode\"];
+    g2 -> g4 [label=\"Weights, AddConnection\"];
+    g1 -> g4 [label=\"Weights, AddConnection\"];
+}
+";
+        assert_eq!(lineage.ancestry_dot(e), expected);
+    }
+}
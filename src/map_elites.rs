@@ -0,0 +1,383 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::genome::*;
+use crate::mutation::*;
+use crate::neural_net::*;
+use crate::population::*;
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct MapElitesConfig {
+    pub num_inputs: u32,
+    pub num_outputs: u32,
+    pub output_activation: ActivationFunction,
+    pub mutation: MutationConfig,
+    // Number of cells along each dimension of the behavior
+    // descriptor, and the range of values each dimension covers.
+    // Values outside of the range fall into the cells at its edges.
+    pub cells_per_dimension: Vec<usize>,
+    pub behavior_bounds: Vec<(f32, f32)>,
+    // Number of random genomes asked for before any are bred from the
+    // archive.
+    pub num_initial: usize,
+    // Change in the curiosity of an elite when a child of it enters
+    // the archive, and when it doesn't.  Parents are chosen in
+    // proportion to their curiosity, so that elites whose children
+    // keep improving the archive are bred from more often.
+    pub curiosity_reward: f32,
+    pub curiosity_penalty: f32,
+    pub seed: Option<u64>,
+}
+
+impl Default for MapElitesConfig {
+    fn default() -> Self {
+        Self {
+            num_inputs: 1,
+            num_outputs: 1,
+            output_activation: ActivationFunction::Sigmoid,
+            mutation: MutationConfig::default(),
+            cells_per_dimension: vec![10, 10],
+            behavior_bounds: vec![(0.0, 1.0), (0.0, 1.0)],
+            num_initial: 100,
+            curiosity_reward: 1.0,
+            curiosity_penalty: 0.5,
+            seed: None,
+        }
+    }
+}
+
+// The fittest genome found for a cell of the archive.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Elite {
+    pub genome: Genome,
+    pub fitness: f32,
+    pub behavior: Vec<f32>,
+    pub curiosity: f32,
+}
+
+// A genome to be evaluated and given to `MapElites::tell`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Candidate {
+    pub genome: Genome,
+    // Cell of the elite the genome was bred from, if any.
+    pub parent: Option<Vec<usize>>,
+}
+
+// Quality-diversity search, which keeps the fittest genome of every
+// cell of a grid over behavior descriptors, rather than the fittest
+// genomes overall.
+#[derive(Debug)]
+pub struct MapElites {
+    pub config: MapElitesConfig,
+    // Ordered, so that seeded runs choose parents reproducibly.
+    cells: BTreeMap<Vec<usize>, Elite>,
+    tracker: InnovationTracker,
+    rng: ChaCha8Rng,
+    num_asked: usize,
+}
+
+impl MapElites {
+    pub fn new(config: MapElitesConfig) -> Self {
+        let rng = match config.seed {
+            Some(seed) => ChaCha8Rng::seed_from_u64(seed),
+            None => ChaCha8Rng::from_entropy(),
+        };
+        Self {
+            config,
+            cells: BTreeMap::new(),
+            tracker: InnovationTracker::new(),
+            rng,
+            num_asked: 0,
+        }
+    }
+
+    // Cell of the grid that a behavior descriptor falls into.
+    // Missing trailing values count as zero.
+    pub fn cell(&self, behavior: &[f32]) -> Vec<usize> {
+        let config = &self.config;
+        config
+            .cells_per_dimension
+            .iter()
+            .zip(&config.behavior_bounds)
+            .enumerate()
+            .map(|(i, (num_cells, (low, high)))| {
+                let value = behavior.get(i).cloned().unwrap_or(0.0);
+                let fraction = (value - low) / (high - low);
+                let last = num_cells.saturating_sub(1);
+                match fraction.is_nan() {
+                    true => 0,
+                    false => {
+                        ((fraction * *num_cells as f32) as usize).min(last)
+                    }
+                }
+            })
+            .collect()
+    }
+
+    pub fn get(&self, cell: &[usize]) -> Option<&Elite> {
+        self.cells.get(cell)
+    }
+
+    // Filled cells and their elites, in lexicographic order of cell.
+    pub fn elites(&self) -> impl Iterator<Item = (&Vec<usize>, &Elite)> {
+        self.cells.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    pub fn num_cells(&self) -> usize {
+        self.config.cells_per_dimension.iter().product()
+    }
+
+    // Fraction of cells that are filled.
+    pub fn coverage(&self) -> f32 {
+        self.len() as f32 / self.num_cells().max(1) as f32
+    }
+
+    // Total fitness of the elites.
+    pub fn qd_score(&self) -> f32 {
+        self.cells.values().map(|elite| elite.fitness).sum()
+    }
+
+    pub fn best(&self) -> Option<&Elite> {
+        self.cells
+            .values()
+            .max_by(|a, b| a.fitness.total_cmp(&b.fitness))
+    }
+
+    // The whole grid, with the last dimension varying fastest, and
+    // `None` for empty cells.
+    pub fn grid(&self) -> Vec<Option<&Elite>> {
+        let dims = &self.config.cells_per_dimension;
+        (0..self.num_cells())
+            .map(|mut index| {
+                let mut cell = vec![0; dims.len()];
+                dims.iter().enumerate().rev().for_each(|(i, n)| {
+                    cell[i] = index % n;
+                    index /= n;
+                });
+                self.cells.get(&cell)
+            })
+            .collect()
+    }
+
+    // Writes a row per filled cell, after a header row.  Coordinates
+    // of the cell and values of the behavior are separated by
+    // semicolons within their columns.
+    pub fn write_csv<W: Write>(&self, mut writer: W) -> std::io::Result<()> {
+        let join = |values: Vec<String>| values.join(";");
+        writeln!(writer, "cell,fitness,behavior,curiosity,nodes,connections")?;
+        self.cells.iter().try_for_each(|(cell, elite)| {
+            writeln!(
+                writer,
+                "{},{},{},{},{},{}",
+                join(cell.iter().map(|c| c.to_string()).collect()),
+                elite.fitness,
+                join(elite.behavior.iter().map(|b| b.to_string()).collect()),
+                elite.curiosity,
+                elite.genome.nodes.len(),
+                elite.genome.connections.len(),
+            )
+        })
+    }
+
+    // Genomes to evaluate next.  The first `num_initial` are random,
+    // and the rest are mutated copies of elites.
+    pub fn ask(&mut self, batch_size: usize) -> Vec<Candidate> {
+        (0..batch_size).map(|_| self.candidate()).collect()
+    }
+
+    fn candidate(&mut self) -> Candidate {
+        self.num_asked += 1;
+        let config = &self.config;
+        if self.num_asked <= config.num_initial || self.cells.is_empty() {
+            let genome = initial_genome(
+                InitialTopology::FullyConnected,
+                config.num_inputs,
+                config.num_outputs,
+                config.output_activation,
+                &config.mutation,
+                &mut self.tracker,
+                &mut self.rng,
+            );
+            return Candidate {
+                genome,
+                parent: None,
+            };
+        }
+
+        // Curiosity is shifted so that every elite has some chance.
+        let min_curiosity = self
+            .cells
+            .values()
+            .map(|elite| elite.curiosity)
+            .fold(f32::INFINITY, f32::min);
+        let weights = self
+            .cells
+            .values()
+            .map(|elite| elite.curiosity - min_curiosity + 1.0);
+        let chosen = match WeightedIndex::new(weights) {
+            Ok(distribution) => distribution.sample(&mut self.rng),
+            Err(_) => 0,
+        };
+        let (cell, elite) = self.cells.iter().nth(chosen).unwrap();
+        let mut genome = elite.genome.clone();
+        genome.mutate(&config.mutation, &mut self.tracker, &mut self.rng);
+        Candidate {
+            genome,
+            parent: Some(cell.clone()),
+        }
+    }
+
+    // Adds an evaluated candidate to the archive if its cell is empty
+    // or holds a less fit elite, and updates the curiosity of its
+    // parent.  Returns whether it was added.
+    pub fn tell(
+        &mut self,
+        candidate: Candidate,
+        fitness: f32,
+        behavior: &[f32],
+    ) -> bool {
+        let cell = self.cell(behavior);
+        let added = match self.cells.get(&cell) {
+            Some(elite) => fitness > elite.fitness,
+            None => true,
+        };
+        if let Some(parent) = candidate.parent {
+            let (reward, penalty) =
+                (self.config.curiosity_reward, self.config.curiosity_penalty);
+            if let Some(elite) = self.cells.get_mut(&parent) {
+                elite.curiosity += if added { reward } else { -penalty };
+            }
+        }
+        if added {
+            let elite = Elite {
+                genome: candidate.genome,
+                fitness,
+                behavior: behavior.to_vec(),
+                curiosity: 0.0,
+            };
+            self.cells.insert(cell, elite);
+        }
+        added
+    }
+
+    // Asks for a batch of genomes, evaluates them with networks built
+    // with backend `N`, and tells the results.  `evaluate` returns the
+    // fitness and behavior descriptor of a network.  Returns the
+    // number of genomes added to the archive.
+    pub fn run_batch<N, F>(
+        &mut self,
+        batch_size: usize,
+        evaluate: &F,
+    ) -> Result<usize, Error>
+    where
+        N: NeuralNet + Send,
+        F: Fn(&mut N) -> (f32, Vec<f32>) + Sync,
+    {
+        let candidates = self.ask(batch_size);
+        let mut nets = candidates
+            .iter()
+            .map(|candidate| candidate.genome.to_builder().build::<N>())
+            .collect::<Result<Vec<_>, _>>()?;
+
+        #[cfg(feature = "parallel")]
+        let results = nets.par_iter_mut().map(evaluate).collect::<Vec<_>>();
+        #[cfg(not(feature = "parallel"))]
+        let results = nets.iter_mut().map(evaluate).collect::<Vec<_>>();
+
+        Ok(candidates
+            .into_iter()
+            .zip(results)
+            .map(|(candidate, (fitness, behavior))| {
+                self.tell(candidate, fitness, &behavior)
+            })
+            .filter(|added| *added)
+            .count())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::*;
+
+    #[test]
+    fn test_cell() {
+        let archive = MapElites::new(MapElitesConfig {
+            cells_per_dimension: vec![4, 2],
+            behavior_bounds: vec![(0.0, 1.0), (-1.0, 1.0)],
+            ..MapElitesConfig::default()
+        });
+        assert_eq!(archive.cell(&[0.3, 0.5]), vec![1, 1]);
+        assert_eq!(archive.cell(&[1.0, -0.5]), vec![3, 0]);
+        assert_eq!(archive.cell(&[-2.0, 7.0]), vec![0, 1]);
+        assert_eq!(archive.cell(&[0.9]), vec![3, 1]);
+    }
+
+    #[test]
+    fn test_map_elites() -> Result<(), Error> {
+        let config = MapElitesConfig {
+            num_inputs: 2,
+            num_outputs: 2,
+            output_activation: ActivationFunction::Sigmoid,
+            num_initial: 20,
+            seed: Some(9),
+            ..MapElitesConfig::default()
+        };
+        let mut archive = MapElites::new(config);
+        // Behavior is the outputs, and fitness prefers them equal.
+        let evaluate = |net: &mut ConsecutiveNeuralNet| {
+            let outputs = net.evaluate(&[1.0, -1.0]);
+            (-(outputs[0] - outputs[1]).abs(), outputs)
+        };
+        (0..10)
+            .try_for_each(|_| archive.run_batch(20, &evaluate).map(|_| ()))?;
+
+        assert!(!archive.is_empty());
+        assert!(archive.coverage() > 0.0 && archive.coverage() <= 1.0);
+        let grid = archive.grid();
+        assert_eq!(grid.len(), 100);
+        assert_eq!(grid.iter().flatten().count(), archive.len());
+        archive.elites().for_each(|(cell, elite)| {
+            assert_eq!(&archive.cell(&elite.behavior), cell);
+            assert_eq!(grid[cell[0] * 10 + cell[1]], Some(elite));
+        });
+
+        let mut csv = Vec::new();
+        archive.write_csv(&mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        assert_eq!(csv.lines().count(), archive.len() + 1);
+
+        // A fitter genome of the same behavior replaces the elite.
+        let (cell, elite) = archive.elites().next().unwrap();
+        let (cell, elite) = (cell.clone(), elite.clone());
+        let candidate = Candidate {
+            genome: elite.genome.clone(),
+            parent: None,
+        };
+        assert!(!archive.tell(
+            candidate.clone(),
+            elite.fitness,
+            &elite.behavior
+        ));
+        assert!(archive.tell(candidate, elite.fitness + 1.0, &elite.behavior));
+        assert_eq!(archive.get(&cell).unwrap().fitness, elite.fitness + 1.0);
+        Ok(())
+    }
+}
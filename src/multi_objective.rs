@@ -0,0 +1,134 @@
+// NSGA-II selection over several objectives, all of which are
+// maximized.
+
+// Whether `a` is at least as good as `b` in every objective, and
+// better in at least one.
+pub fn dominates(a: &[f32], b: &[f32]) -> bool {
+    let no_worse = a.iter().zip(b.iter()).all(|(x, y)| x >= y);
+    let better = a.iter().zip(b.iter()).any(|(x, y)| x > y);
+    no_worse && better
+}
+
+// Indices of `objectives` grouped into fronts, the first of which is
+// dominated by no other, the second only by members of the first, and
+// so on.
+pub fn non_dominated_sort(objectives: &[Vec<f32>]) -> Vec<Vec<usize>> {
+    let n = objectives.len();
+    let dominated = (0..n)
+        .map(|i| {
+            (0..n)
+                .filter(|j| dominates(&objectives[i], &objectives[*j]))
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    let mut num_dominating = vec![0; n];
+    dominated
+        .iter()
+        .flatten()
+        .for_each(|j| num_dominating[*j] += 1);
+
+    let mut fronts = Vec::new();
+    let mut front = (0..n)
+        .filter(|i| num_dominating[*i] == 0)
+        .collect::<Vec<_>>();
+    while !front.is_empty() {
+        let mut next = Vec::new();
+        front.iter().for_each(|i| {
+            dominated[*i].iter().for_each(|j| {
+                num_dominating[*j] -= 1;
+                if num_dominating[*j] == 0 {
+                    next.push(*j);
+                }
+            });
+        });
+        fronts.push(front);
+        front = next;
+    }
+    fronts
+}
+
+// Crowding distance of each member of `front`, in the same order.
+// Members at either end of any objective are infinitely far from
+// the others, so that the extremes of the front are kept.
+pub fn crowding_distances(
+    objectives: &[Vec<f32>],
+    front: &[usize],
+) -> Vec<f32> {
+    let mut distances = vec![0.0; front.len()];
+    let num_objectives = front
+        .iter()
+        .map(|i| objectives[*i].len())
+        .min()
+        .unwrap_or(0);
+    (0..num_objectives).for_each(|m| {
+        let value = |k: usize| objectives[front[k]][m];
+        let mut sorted = (0..front.len()).collect::<Vec<_>>();
+        sorted.sort_by(|a, b| value(*a).total_cmp(&value(*b)));
+
+        let (first, last) = (sorted[0], sorted[sorted.len() - 1]);
+        distances[first] = f32::INFINITY;
+        distances[last] = f32::INFINITY;
+        let range = value(last) - value(first);
+        if range > 0.0 {
+            sorted.windows(3).for_each(|w| {
+                distances[w[1]] += (value(w[2]) - value(w[0])) / range;
+            });
+        }
+    });
+    distances
+}
+
+// Scalar fitness that orders genomes as NSGA-II does: by front, and
+// within a front by decreasing crowding distance.  The fitness of a
+// genome is the number of genomes ranked below it.
+pub fn nsga2_fitness(objectives: &[Vec<f32>]) -> Vec<f32> {
+    let n = objectives.len();
+    let mut fitnesses = vec![0.0; n];
+    let mut rank = 0;
+    non_dominated_sort(objectives).iter().for_each(|front| {
+        let distances = crowding_distances(objectives, front);
+        let mut order = (0..front.len()).collect::<Vec<_>>();
+        order.sort_by(|a, b| distances[*b].total_cmp(&distances[*a]));
+        order.iter().for_each(|k| {
+            fitnesses[front[*k]] = (n - 1 - rank) as f32;
+            rank += 1;
+        });
+    });
+    fitnesses
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_nsga2() {
+        let objectives = vec![
+            vec![1.0, 4.0],
+            vec![2.0, 2.0],
+            vec![1.0, 1.0],
+            vec![4.0, 1.0],
+            vec![2.5, 2.5],
+            vec![0.0, 0.0],
+        ];
+        assert!(dominates(&objectives[4], &objectives[1]));
+        assert!(!dominates(&objectives[0], &objectives[3]));
+        assert!(!dominates(&objectives[1], &objectives[1]));
+
+        let fronts = non_dominated_sort(&objectives);
+        assert_eq!(fronts, vec![vec![0, 3, 4], vec![1], vec![2], vec![5]]);
+
+        let distances = crowding_distances(&objectives, &fronts[0]);
+        assert_eq!(distances[0], f32::INFINITY);
+        assert_eq!(distances[1], f32::INFINITY);
+        assert_eq!(distances[2], 2.0);
+
+        // The middle of the first front is less isolated than its
+        // extremes, but still beats every other front.
+        let fitnesses = nsga2_fitness(&objectives);
+        assert_eq!(fitnesses[4], 3.0);
+        assert_eq!(fitnesses[1..3], [2.0, 1.0]);
+        assert_eq!(fitnesses[5], 0.0);
+        assert!(fitnesses[0] > 3.0 && fitnesses[3] > 3.0);
+    }
+}
@@ -0,0 +1,880 @@
+use std::collections::HashSet;
+
+use rand::seq::{IteratorRandom, SliceRandom};
+use rand::Rng;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::evolution_strategy::gaussian;
+use crate::genome::*;
+use crate::neural_net::*;
+
+// Kinds of mutation that `Genome::mutate` applies.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum MutationKind {
+    Weights,
+    AddConnection,
+    AddNode,
+    ToggleEnable,
+    Activation,
+    DeleteConnection,
+    DeleteNode,
+    TogglePlasticity,
+    Plasticity,
+    ToggleMemory,
+    Memory,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct MutationConfig {
+    // Probability that a genome's weights are mutated at all.
+    pub weight_mutation_prob: f32,
+    // Once weights are mutated, the probability that each weight is
+    // perturbed, rather than replaced by a new random value.
+    pub weight_perturb_prob: f32,
+    // Perturbations are drawn uniformly from
+    // `-weight_perturb_power..weight_perturb_power`.
+    pub weight_perturb_power: f32,
+    // Replacement and initial weights are drawn uniformly from
+    // `-weight_init_range..weight_init_range`.
+    pub weight_init_range: f32,
+    // If set, mutated and new weights are kept within these bounds.
+    // A rejected perturbation leaves the weight as it was, and a
+    // rejected new weight is clamped.
+    pub weight_bounds: Option<WeightBounds>,
+    pub add_connection_prob: f32,
+    // Fraction of new connections that are recurrent.
+    pub recurrent_connection_prob: f32,
+    // Number of random node pairs that are tried when adding a
+    // connection, before giving up.
+    pub add_connection_attempts: u32,
+    pub add_node_prob: f32,
+    pub toggle_enable_prob: f32,
+    pub change_activation_prob: f32,
+    // Deletions, which shrink genomes.  Usually only used while
+    // simplifying in a phased search.
+    pub delete_connection_prob: f32,
+    pub delete_node_prob: f32,
+    // Makes a random connection plastic, with the default Hebbian
+    // rule, or static again.
+    pub toggle_plasticity_prob: f32,
+    // Probability that the rules of a genome's plastic connections
+    // are mutated, perturbing each parameter uniformly by up to
+    // `plasticity_perturb_power`.
+    pub plasticity_mutation_prob: f32,
+    pub plasticity_perturb_power: f32,
+    // Makes a random hidden or output node a memory cell, with the
+    // default parameters, or a plain node again.
+    pub toggle_memory_prob: f32,
+    // Probability that the parameters of a genome's memory cells are
+    // mutated, perturbing each uniformly by up to
+    // `memory_perturb_power`.
+    pub memory_mutation_prob: f32,
+    pub memory_perturb_power: f32,
+    // Activation functions used for new nodes and when changing the
+    // activation of an existing node.
+    pub activation_functions: Vec<ActivationFunction>,
+    // If set, each genome carries its own `MutationRates`, starting
+    // from the rates above, which are adapted before every mutation
+    // and then used in place of the rates above.
+    pub self_adaptation: Option<SelfAdaptation>,
+}
+
+// Rates of a genome whose mutation rates adapt, as used in place of
+// the fields of `MutationConfig` with the same names.
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MutationRates {
+    pub weight_mutation_prob: f32,
+    pub weight_perturb_power: f32,
+    pub add_connection_prob: f32,
+    pub add_node_prob: f32,
+}
+
+// Log-normal self-adaptation: each rate is multiplied by
+// `exp(learning_rate * N(0, 1))`, and kept within
+// `min_rate..=max_rate`, and at most 1 for probabilities.  Rates that
+// led to fitter children survive with them, so the rates follow what
+// the task currently rewards.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct SelfAdaptation {
+    pub learning_rate: f32,
+    pub min_rate: f32,
+    pub max_rate: f32,
+}
+
+impl Default for SelfAdaptation {
+    fn default() -> Self {
+        Self {
+            learning_rate: 0.2,
+            min_rate: 1e-3,
+            max_rate: 5.0,
+        }
+    }
+}
+
+impl MutationRates {
+    pub fn from_config(config: &MutationConfig) -> Self {
+        Self {
+            weight_mutation_prob: config.weight_mutation_prob,
+            weight_perturb_power: config.weight_perturb_power,
+            add_connection_prob: config.add_connection_prob,
+            add_node_prob: config.add_node_prob,
+        }
+    }
+
+    pub fn adapt<R: Rng + ?Sized>(
+        &mut self,
+        adaptation: &SelfAdaptation,
+        rng: &mut R,
+    ) {
+        let mut adapt = |rate: &mut f32, max: f32| {
+            let factor = (adaptation.learning_rate * gaussian(rng)).exp();
+            *rate = (*rate * factor).max(adaptation.min_rate).min(max);
+        };
+        let max_prob = adaptation.max_rate.min(1.0);
+        adapt(&mut self.weight_mutation_prob, max_prob);
+        adapt(&mut self.weight_perturb_power, adaptation.max_rate);
+        adapt(&mut self.add_connection_prob, max_prob);
+        adapt(&mut self.add_node_prob, max_prob);
+    }
+
+    // `config` with these rates.
+    pub fn apply(&self, config: &MutationConfig) -> MutationConfig {
+        MutationConfig {
+            weight_mutation_prob: self.weight_mutation_prob,
+            weight_perturb_power: self.weight_perturb_power,
+            add_connection_prob: self.add_connection_prob,
+            add_node_prob: self.add_node_prob,
+            ..config.clone()
+        }
+    }
+}
+
+impl Default for MutationConfig {
+    fn default() -> Self {
+        Self {
+            weight_mutation_prob: 0.8,
+            weight_perturb_prob: 0.9,
+            weight_perturb_power: 0.5,
+            weight_init_range: 1.0,
+            weight_bounds: None,
+            add_connection_prob: 0.05,
+            recurrent_connection_prob: 0.0,
+            add_connection_attempts: 20,
+            add_node_prob: 0.03,
+            toggle_enable_prob: 0.01,
+            change_activation_prob: 0.0,
+            delete_connection_prob: 0.0,
+            delete_node_prob: 0.0,
+            toggle_plasticity_prob: 0.0,
+            plasticity_mutation_prob: 0.0,
+            plasticity_perturb_power: 0.1,
+            toggle_memory_prob: 0.0,
+            memory_mutation_prob: 0.0,
+            memory_perturb_power: 0.5,
+            activation_functions: vec![ActivationFunction::Sigmoid],
+            self_adaptation: None,
+        }
+    }
+}
+
+impl MutationConfig {
+    pub(crate) fn random_weight<R: Rng + ?Sized>(&self, rng: &mut R) -> f32 {
+        let weight = if self.weight_init_range > 0.0 {
+            rng.gen_range(-self.weight_init_range..self.weight_init_range)
+        } else {
+            0.0
+        };
+        self.bound_weight(weight, None)
+    }
+
+    // `weight` within the weight bounds, or `previous` if the bounds
+    // reject it.  Without a previous weight, rejected weights are
+    // clamped.
+    pub(crate) fn bound_weight(
+        &self,
+        weight: f32,
+        previous: Option<f32>,
+    ) -> f32 {
+        let bounds = match self.weight_bounds {
+            Some(bounds) => bounds,
+            None => return weight,
+        };
+        bounds
+            .apply(weight)
+            .or(previous)
+            .unwrap_or_else(|| weight.max(bounds.min).min(bounds.max))
+    }
+
+    fn random_activation<R: Rng + ?Sized>(
+        &self,
+        rng: &mut R,
+    ) -> ActivationFunction {
+        self.activation_functions
+            .choose(rng)
+            .cloned()
+            .unwrap_or(ActivationFunction::Sigmoid)
+    }
+}
+
+impl Genome {
+    // Applies each kind of mutation with the probability given in
+    // the config, and returns those that changed the genome, in the
+    // order they were applied.  With self-adaptation, the genome's own
+    // rates are adapted first and used instead.
+    pub fn mutate<R: Rng + ?Sized>(
+        &mut self,
+        config: &MutationConfig,
+        tracker: &mut InnovationTracker,
+        rng: &mut R,
+    ) -> Vec<MutationKind> {
+        match config.self_adaptation {
+            Some(adaptation) => {
+                let mut rates = self
+                    .mutation_rates
+                    .unwrap_or_else(|| MutationRates::from_config(config));
+                rates.adapt(&adaptation, rng);
+                self.mutation_rates = Some(rates);
+                self.mutate_with(&rates.apply(config), tracker, rng)
+            }
+            None => self.mutate_with(config, tracker, rng),
+        }
+    }
+
+    fn mutate_with<R: Rng + ?Sized>(
+        &mut self,
+        config: &MutationConfig,
+        tracker: &mut InnovationTracker,
+        rng: &mut R,
+    ) -> Vec<MutationKind> {
+        use MutationKind::*;
+        let mut applied = Vec::new();
+        let mut apply = |kind, changed| {
+            if changed {
+                applied.push(kind);
+            }
+        };
+        if rng.gen::<f32>() < config.weight_mutation_prob {
+            self.mutate_weights(config, rng);
+            apply(Weights, true);
+        }
+        if rng.gen::<f32>() < config.add_connection_prob {
+            apply(
+                AddConnection,
+                self.mutate_add_connection(config, tracker, rng),
+            );
+        }
+        if rng.gen::<f32>() < config.add_node_prob {
+            apply(AddNode, self.mutate_add_node(config, tracker, rng));
+        }
+        if rng.gen::<f32>() < config.toggle_enable_prob {
+            self.mutate_toggle_enable(rng);
+            apply(ToggleEnable, true);
+        }
+        if rng.gen::<f32>() < config.change_activation_prob {
+            self.mutate_activation(config, rng);
+            apply(Activation, true);
+        }
+        if rng.gen::<f32>() < config.delete_connection_prob {
+            apply(DeleteConnection, self.mutate_delete_connection(rng));
+        }
+        if rng.gen::<f32>() < config.delete_node_prob {
+            apply(DeleteNode, self.mutate_delete_node(tracker, rng));
+        }
+        // Plasticity and memory cells are off by default, and then
+        // draw nothing from `rng`, so that seeded runs without them
+        // are unchanged.
+        let plastic = config.toggle_plasticity_prob > 0.0
+            || config.plasticity_mutation_prob > 0.0;
+        if plastic {
+            if rng.gen::<f32>() < config.toggle_plasticity_prob {
+                self.mutate_toggle_plasticity(rng);
+                apply(TogglePlasticity, true);
+            }
+            if rng.gen::<f32>() < config.plasticity_mutation_prob {
+                self.mutate_plasticity(config, rng);
+                apply(Plasticity, true);
+            }
+        }
+        let memory = config.toggle_memory_prob > 0.0
+            || config.memory_mutation_prob > 0.0;
+        if memory {
+            if rng.gen::<f32>() < config.toggle_memory_prob {
+                self.mutate_toggle_memory(rng);
+                apply(ToggleMemory, true);
+            }
+            if rng.gen::<f32>() < config.memory_mutation_prob {
+                self.mutate_memory(config, rng);
+                apply(Memory, true);
+            }
+        }
+        applied
+    }
+
+    pub fn mutate_weights<R: Rng + ?Sized>(
+        &mut self,
+        config: &MutationConfig,
+        rng: &mut R,
+    ) {
+        self.connections.iter_mut().for_each(|conn| {
+            if rng.gen::<f32>() < config.weight_perturb_prob {
+                if config.weight_perturb_power > 0.0 {
+                    let perturbed = conn.weight
+                        + rng.gen_range(
+                            -config.weight_perturb_power
+                                ..config.weight_perturb_power,
+                        );
+                    conn.weight =
+                        config.bound_weight(perturbed, Some(conn.weight));
+                }
+            } else {
+                conn.weight = config.random_weight(rng);
+            }
+        });
+    }
+
+    // Connects two previously unconnected nodes.  Returns false if no
+    // valid pair of nodes was found.
+    pub fn mutate_add_connection<R: Rng + ?Sized>(
+        &mut self,
+        config: &MutationConfig,
+        tracker: &mut InnovationTracker,
+        rng: &mut R,
+    ) -> bool {
+        let connection_type =
+            if rng.gen::<f32>() < config.recurrent_connection_prob {
+                ConnectionType::Recurrent
+            } else {
+                ConnectionType::Normal
+            };
+
+        let dests = self
+            .nodes
+            .iter()
+            .filter(|n| {
+                n.node_type != NodeType::Input && n.node_type != NodeType::Bias
+            })
+            .map(|n| n.id)
+            .collect::<Vec<_>>();
+        if dests.is_empty() {
+            return false;
+        }
+
+        for _ in 0..config.add_connection_attempts {
+            let origin = self.nodes[rng.gen_range(0..self.nodes.len())].id;
+            let dest = dests[rng.gen_range(0..dests.len())];
+
+            let valid = !self.has_connection(origin, dest, connection_type)
+                && (connection_type == ConnectionType::Recurrent
+                    || !self.creates_loop(origin, dest));
+            if valid {
+                let weight = config.random_weight(rng);
+                self.add_connection(
+                    origin,
+                    dest,
+                    weight,
+                    connection_type,
+                    tracker,
+                );
+                return true;
+            }
+        }
+
+        false
+    }
+
+    // Splits an enabled connection in two, with a new node in the
+    // middle.  The incoming connection has a weight of one and the
+    // outgoing connection keeps the original weight, so the network
+    // initially behaves much like before.  Returns false if there
+    // were no enabled connections to split.
+    pub fn mutate_add_node<R: Rng + ?Sized>(
+        &mut self,
+        config: &MutationConfig,
+        tracker: &mut InnovationTracker,
+        rng: &mut R,
+    ) -> bool {
+        let enabled = (0..self.connections.len())
+            .filter(|i| self.connections[*i].enabled)
+            .collect::<Vec<_>>();
+        let split = match enabled.choose(rng) {
+            Some(i) => *i,
+            None => return false,
+        };
+
+        let old = self.connections[split];
+        self.connections[split].enabled = false;
+
+        // If this genome already made the same split before, the
+        // shared node id is taken, and a fresh one is needed.
+        let mut id = tracker.split_node_id(old.innovation);
+        if self.node(id).is_some() {
+            id = tracker.new_node_id();
+        }
+        self.nodes.push(NodeGene {
+            id,
+            node_type: NodeType::Hidden,
+            func: config.random_activation(rng),
+            memory: None,
+            bias: 0.0,
+        });
+
+        // The new node has no outgoing connections yet, so a normal
+        // connection into it can't create a loop.
+        self.add_connection(
+            old.origin,
+            id,
+            config.bound_weight(1.0, None),
+            ConnectionType::Normal,
+            tracker,
+        );
+        self.add_connection(
+            id,
+            old.dest,
+            old.weight,
+            old.connection_type,
+            tracker,
+        );
+
+        true
+    }
+
+    // Flips a random connection between enabled and disabled.  A
+    // normal connection is only re-enabled if it wouldn't close a
+    // loop.
+    pub fn mutate_toggle_enable<R: Rng + ?Sized>(&mut self, rng: &mut R) {
+        if self.connections.is_empty() {
+            return;
+        }
+        let i = rng.gen_range(0..self.connections.len());
+        let conn = self.connections[i];
+
+        let can_toggle = conn.enabled
+            || conn.connection_type == ConnectionType::Recurrent
+            || !self.creates_loop(conn.origin, conn.dest);
+        if can_toggle {
+            self.connections[i].enabled = !conn.enabled;
+        }
+    }
+
+    pub fn mutate_toggle_plasticity<R: Rng + ?Sized>(&mut self, rng: &mut R) {
+        if let Some(conn) = self.connections.choose_mut(rng) {
+            conn.plasticity = match conn.plasticity {
+                Some(_) => None,
+                None => Some(HebbianRule::default()),
+            };
+        }
+    }
+
+    pub fn mutate_plasticity<R: Rng + ?Sized>(
+        &mut self,
+        config: &MutationConfig,
+        rng: &mut R,
+    ) {
+        let power = config.plasticity_perturb_power;
+        if power <= 0.0 {
+            return;
+        }
+        self.connections
+            .iter_mut()
+            .filter_map(|conn| conn.plasticity.as_mut())
+            .for_each(|rule| {
+                [
+                    &mut rule.learning_rate,
+                    &mut rule.a,
+                    &mut rule.b,
+                    &mut rule.c,
+                    &mut rule.d,
+                ]
+                .iter_mut()
+                .for_each(|x| **x += rng.gen_range(-power..power));
+            });
+    }
+
+    pub fn mutate_toggle_memory<R: Rng + ?Sized>(&mut self, rng: &mut R) {
+        let candidates = self
+            .nodes
+            .iter_mut()
+            .filter(|n| {
+                n.node_type == NodeType::Hidden
+                    || n.node_type == NodeType::Output
+            })
+            .collect::<Vec<_>>();
+        if let Some(node) = candidates.into_iter().choose(rng) {
+            node.memory = match node.memory {
+                Some(_) => None,
+                None => Some(MemoryCell::default()),
+            };
+        }
+    }
+
+    pub fn mutate_memory<R: Rng + ?Sized>(
+        &mut self,
+        config: &MutationConfig,
+        rng: &mut R,
+    ) {
+        let power = config.memory_perturb_power;
+        if power <= 0.0 {
+            return;
+        }
+        self.nodes
+            .iter_mut()
+            .filter_map(|node| node.memory.as_mut())
+            .for_each(|cell| {
+                let (z, r) = (&mut cell.update_gate, &mut cell.reset_gate);
+                [
+                    &mut z.input_weight,
+                    &mut z.memory_weight,
+                    &mut z.bias,
+                    &mut r.input_weight,
+                    &mut r.memory_weight,
+                    &mut r.bias,
+                    &mut cell.memory_weight,
+                ]
+                .iter_mut()
+                .for_each(|x| **x += rng.gen_range(-power..power));
+            });
+    }
+
+    // Removes a random connection, along with any hidden nodes left
+    // without connections to other nodes.  Returns false if there
+    // were no connections.
+    pub fn mutate_delete_connection<R: Rng + ?Sized>(
+        &mut self,
+        rng: &mut R,
+    ) -> bool {
+        if self.connections.is_empty() {
+            return false;
+        }
+        self.connections
+            .remove(rng.gen_range(0..self.connections.len()));
+        self.remove_orphans();
+        true
+    }
+
+    // Removes a random hidden node and every connection to or from
+    // it.  A node with a single enabled connection in and a single
+    // one out, as left by splitting a connection, is replaced by a
+    // direct connection with the outgoing weight, undoing the split.
+    // Returns false if there were no hidden nodes.
+    pub fn mutate_delete_node<R: Rng + ?Sized>(
+        &mut self,
+        tracker: &mut InnovationTracker,
+        rng: &mut R,
+    ) -> bool {
+        let hidden = self
+            .nodes
+            .iter()
+            .filter(|n| n.node_type == NodeType::Hidden)
+            .map(|n| n.id)
+            .collect::<Vec<_>>();
+        let id = match hidden.choose(rng) {
+            Some(id) => *id,
+            None => return false,
+        };
+
+        let enabled = |f: fn(&ConnectionGene) -> u32| {
+            self.connections
+                .iter()
+                .filter(|c| c.enabled && f(c) == id && c.origin != c.dest)
+                .cloned()
+                .collect::<Vec<_>>()
+        };
+        let incoming = enabled(|c| c.dest);
+        let outgoing = enabled(|c| c.origin);
+
+        self.nodes.retain(|n| n.id != id);
+        self.connections.retain(|c| c.origin != id && c.dest != id);
+
+        if let ([a], [b]) = (&incoming[..], &outgoing[..]) {
+            let connection_type = if a.connection_type
+                == ConnectionType::Recurrent
+                || b.connection_type == ConnectionType::Recurrent
+            {
+                ConnectionType::Recurrent
+            } else {
+                ConnectionType::Normal
+            };
+            let existing = self.connections.iter().position(|c| {
+                (c.origin, c.dest, c.connection_type)
+                    == (a.origin, b.dest, connection_type)
+            });
+            let valid = connection_type == ConnectionType::Recurrent
+                || !self.creates_loop(a.origin, b.dest);
+            match existing {
+                // The connection that was split is usually still
+                // there, disabled.
+                Some(i) if valid && !self.connections[i].enabled => {
+                    self.connections[i].enabled = true;
+                    self.connections[i].weight = b.weight;
+                }
+                None if valid => {
+                    self.add_connection(
+                        a.origin,
+                        b.dest,
+                        b.weight,
+                        connection_type,
+                        tracker,
+                    );
+                }
+                _ => {}
+            }
+        }
+        self.remove_orphans();
+        true
+    }
+
+    // Removes hidden nodes without connections to other nodes, and
+    // their connections to themselves.
+    fn remove_orphans(&mut self) {
+        let connected = self
+            .connections
+            .iter()
+            .filter(|c| c.origin != c.dest)
+            .flat_map(|c| vec![c.origin, c.dest])
+            .collect::<HashSet<_>>();
+        self.nodes.retain(|n| {
+            n.node_type != NodeType::Hidden || connected.contains(&n.id)
+        });
+        let ids = self.nodes.iter().map(|n| n.id).collect::<HashSet<_>>();
+        self.connections
+            .retain(|c| ids.contains(&c.origin) && ids.contains(&c.dest));
+    }
+
+    // Picks a new activation function for a random hidden or output
+    // node.
+    pub fn mutate_activation<R: Rng + ?Sized>(
+        &mut self,
+        config: &MutationConfig,
+        rng: &mut R,
+    ) {
+        let candidates = (0..self.nodes.len())
+            .filter(|i| {
+                let node_type = self.nodes[*i].node_type;
+                node_type == NodeType::Hidden || node_type == NodeType::Output
+            })
+            .collect::<Vec<_>>();
+        if let Some(i) = candidates.choose(rng) {
+            self.nodes[*i].func = config.random_activation(rng);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ConsecutiveNeuralNet;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn connected_genome(tracker: &mut InnovationTracker) -> Genome {
+        let mut genome =
+            Genome::with_io(2, 1, ActivationFunction::Sigmoid, tracker);
+        genome.add_connection(0, 2, 0.5, ConnectionType::Normal, tracker);
+        genome.add_connection(1, 2, -0.5, ConnectionType::Normal, tracker);
+        genome
+    }
+
+    #[test]
+    fn test_add_node() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut tracker = InnovationTracker::new();
+        let config = MutationConfig::default();
+
+        let mut a = connected_genome(&mut tracker);
+        let mut b = a.clone();
+        assert!(a.mutate_add_node(&config, &mut tracker, &mut rng));
+
+        let split = a.connections.iter().find(|c| !c.enabled).unwrap();
+        let new_node = a.nodes.last().unwrap().id;
+        assert_eq!(a.nodes.len(), 4);
+        assert_eq!(a.connections.len(), 4);
+        assert!(a.has_connection(
+            split.origin,
+            new_node,
+            ConnectionType::Normal
+        ));
+        assert!(a.has_connection(new_node, split.dest, ConnectionType::Normal));
+
+        // Making the same split in another genome gives the same
+        // node and the same innovation numbers.
+        let split = *split;
+        let index = b
+            .connections
+            .iter()
+            .position(|c| c.innovation == split.innovation)
+            .unwrap();
+        b.connections.iter_mut().enumerate().for_each(|(i, c)| {
+            c.enabled = i == index;
+        });
+        assert!(b.mutate_add_node(&config, &mut tracker, &mut rng));
+        assert_eq!(a.nodes.last(), b.nodes.last());
+        assert_eq!(a.connections[2..], b.connections[2..]);
+    }
+
+    #[test]
+    fn test_delete() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let mut tracker = InnovationTracker::new();
+        let config = MutationConfig::default();
+
+        let mut genome = connected_genome(&mut tracker);
+        assert!(!genome.mutate_delete_node(&mut tracker, &mut rng));
+        let original = genome.clone();
+        genome.mutate_add_node(&config, &mut tracker, &mut rng);
+        assert_eq!(genome.nodes.len(), 4);
+
+        // Deleting the new node restores the split connection, with
+        // the same innovation number.
+        let mut without_node = genome.clone();
+        assert!(without_node.mutate_delete_node(&mut tracker, &mut rng));
+        assert_eq!(without_node.nodes, original.nodes);
+        let innovations = |g: &Genome| {
+            let mut innovations = g
+                .connections
+                .iter()
+                .filter(|c| c.enabled)
+                .map(|c| c.innovation)
+                .collect::<Vec<_>>();
+            innovations.sort();
+            innovations
+        };
+        assert_eq!(innovations(&without_node), innovations(&original));
+
+        // Deleting every connection also deletes the hidden node, but
+        // never the inputs and outputs.
+        genome.add_connection(
+            3,
+            3,
+            1.0,
+            ConnectionType::Recurrent,
+            &mut tracker,
+        );
+        while genome.mutate_delete_connection(&mut rng) {}
+        assert!(genome.connections.is_empty());
+        assert_eq!(genome.nodes, original.nodes);
+    }
+
+    #[test]
+    fn test_weight_bounds() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let mut tracker = InnovationTracker::new();
+        let config = MutationConfig {
+            weight_mutation_prob: 1.0,
+            weight_perturb_power: 2.0,
+            weight_init_range: 3.0,
+            weight_bounds: Some(WeightBounds {
+                min: -0.5,
+                max: 0.5,
+                policy: BoundsPolicy::Reject,
+            }),
+            add_connection_prob: 0.5,
+            add_node_prob: 0.5,
+            ..MutationConfig::default()
+        };
+
+        let mut genome = connected_genome(&mut tracker);
+        for _ in 0..50 {
+            genome.mutate(&config, &mut tracker, &mut rng);
+            assert!(genome
+                .connections
+                .iter()
+                .all(|conn| (-0.5..=0.5).contains(&conn.weight)));
+        }
+    }
+
+    #[test]
+    fn test_self_adaptation() {
+        let mut rng = StdRng::seed_from_u64(4);
+        let mut tracker = InnovationTracker::new();
+        let adaptation = SelfAdaptation {
+            learning_rate: 0.5,
+            min_rate: 0.01,
+            max_rate: 2.0,
+        };
+        let config = MutationConfig {
+            self_adaptation: Some(adaptation),
+            ..MutationConfig::default()
+        };
+
+        let mut genome = connected_genome(&mut tracker);
+        genome.mutate(&MutationConfig::default(), &mut tracker, &mut rng);
+        assert_eq!(genome.mutation_rates, None);
+
+        let initial = MutationRates::from_config(&config);
+        genome.mutate(&config, &mut tracker, &mut rng);
+        let first = genome.mutation_rates.unwrap();
+        assert_ne!(first, initial);
+        for _ in 0..200 {
+            genome.mutate(&config, &mut tracker, &mut rng);
+            let rates = genome.mutation_rates.unwrap();
+            [
+                rates.weight_mutation_prob,
+                rates.add_connection_prob,
+                rates.add_node_prob,
+            ]
+            .iter()
+            .for_each(|prob| assert!((0.01..=1.0).contains(prob)));
+            assert!((0.01..=2.0).contains(&rates.weight_perturb_power));
+        }
+
+        // The adapted rates are what the genome is mutated with.
+        let mut rates = MutationRates {
+            weight_mutation_prob: 0.0,
+            weight_perturb_power: 1.0,
+            add_connection_prob: 0.0,
+            add_node_prob: 1.0,
+        };
+        let frozen = SelfAdaptation {
+            learning_rate: 0.0,
+            ..adaptation
+        };
+        rates.adapt(&frozen, &mut rng);
+        assert_eq!(rates.add_node_prob, 1.0);
+        let config = MutationConfig {
+            self_adaptation: Some(frozen),
+            ..MutationConfig::default()
+        };
+        let mut genome = connected_genome(&mut tracker);
+        genome.mutation_rates = Some(rates);
+        let applied = genome.mutate(&config, &mut tracker, &mut rng);
+        assert_eq!(applied, vec![MutationKind::AddNode]);
+    }
+
+    #[test]
+    fn test_mutations_keep_genome_buildable() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut tracker = InnovationTracker::new();
+        let config = MutationConfig {
+            add_connection_prob: 0.5,
+            recurrent_connection_prob: 0.2,
+            add_node_prob: 0.3,
+            toggle_enable_prob: 0.3,
+            change_activation_prob: 0.3,
+            delete_connection_prob: 0.1,
+            delete_node_prob: 0.05,
+            toggle_plasticity_prob: 0.2,
+            plasticity_mutation_prob: 0.5,
+            toggle_memory_prob: 0.2,
+            memory_mutation_prob: 0.5,
+            activation_functions: vec![
+                ActivationFunction::Sigmoid,
+                ActivationFunction::Tanh,
+                ActivationFunction::Relu,
+            ],
+            ..MutationConfig::default()
+        };
+
+        let mut genome = connected_genome(&mut tracker);
+        for _ in 0..200 {
+            genome.mutate(&config, &mut tracker, &mut rng);
+            let net = genome.to_builder().build::<ConsecutiveNeuralNet>();
+            assert!(net.is_ok());
+        }
+        assert!(genome.nodes.len() > 3);
+        assert!(genome.connections.len() > 2);
+    }
+}
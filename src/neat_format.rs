@@ -0,0 +1,231 @@
+use std::fmt::Write;
+
+use crate::genome::*;
+use crate::neural_net::*;
+
+// Text format of Ken Stanley's original C++ NEAT, one genome per
+// `genomestart`/`genomeend` block:
+//
+//   genomestart 1
+//   node <id> <trait> <type> <placement>
+//   gene <trait> <origin> <dest> <weight> <recurrent> <innovation>
+//        <mutation number> <enabled>
+//   genomeend 1
+//
+// where the node type is 1 for sensors and 0 for neurons, and the
+// placement is 0 for hidden, 1 for input, 2 for output and 3 for bias
+// nodes.  Traits and comments are ignored, since they have no
+// equivalent here.  The format doesn't store activation functions,
+// so these are given when reading.
+
+fn node_codes(node_type: NodeType) -> (u32, u32) {
+    use NodeType::*;
+    match node_type {
+        Hidden => (0, 0),
+        Input => (1, 1),
+        Output => (0, 2),
+        Bias => (1, 3),
+    }
+}
+
+fn node_type(placement: u32) -> Option<NodeType> {
+    use NodeType::*;
+    match placement {
+        0 => Some(Hidden),
+        1 => Some(Input),
+        2 => Some(Output),
+        3 => Some(Bias),
+        _ => None,
+    }
+}
+
+fn parse_node(fields: &[&str], func: ActivationFunction) -> Option<NodeGene> {
+    match fields {
+        [id, _trait, _type, placement] => {
+            let node_type = node_type(placement.parse().ok()?)?;
+            let func = match node_type {
+                NodeType::Input | NodeType::Bias => {
+                    ActivationFunction::Identity
+                }
+                _ => func,
+            };
+            Some(NodeGene {
+                id: id.parse().ok()?,
+                node_type,
+                func,
+                memory: None,
+                bias: 0.0,
+            })
+        }
+        _ => None,
+    }
+}
+
+fn parse_gene(fields: &[&str]) -> Option<ConnectionGene> {
+    // Trait, origin, dest, weight, recurrent, innovation, mutation
+    // number, enabled
+    if fields.len() != 8 {
+        return None;
+    }
+    let flag = |field: &str| match field {
+        "0" => Some(false),
+        "1" => Some(true),
+        _ => None,
+    };
+    let connection_type = match flag(fields[4])? {
+        false => ConnectionType::Normal,
+        true => ConnectionType::Recurrent,
+    };
+    Some(ConnectionGene {
+        innovation: fields[5].parse().ok()?,
+        origin: fields[1].parse().ok()?,
+        dest: fields[2].parse().ok()?,
+        weight: fields[3].parse().ok()?,
+        connection_type,
+        enabled: flag(fields[7])?,
+        plasticity: None,
+    })
+}
+
+// Reads every genome in a NEAT population file, which is a sequence
+// of genome blocks.  A lone genome file is a population of one.
+pub fn read_neat_population(
+    text: &str,
+    func: ActivationFunction,
+) -> Result<Vec<Genome>, Error> {
+    let mut genomes = Vec::new();
+    let mut current: Option<Genome> = None;
+
+    text.lines().enumerate().try_for_each(|(i, line)| {
+        let invalid = || Error::InvalidNeatFormat { line: i + 1 };
+        let fields = line.split_whitespace().collect::<Vec<_>>();
+
+        match (fields.first().copied(), current.as_mut()) {
+            (None, _) | (Some("trait"), _) => {}
+            (Some(comment), _) if comment.starts_with("/*") => {}
+            (Some("genomestart"), None) => current = Some(Genome::new()),
+            (Some("genomeend"), Some(_)) => genomes.extend(current.take()),
+            (Some("node"), Some(genome)) => genome
+                .nodes
+                .push(parse_node(&fields[1..], func).ok_or_else(invalid)?),
+            (Some("gene"), Some(genome)) => genome
+                .connections
+                .push(parse_gene(&fields[1..]).ok_or_else(invalid)?),
+            _ => return Err(invalid()),
+        }
+        Ok(())
+    })?;
+
+    match current {
+        Some(_) => Err(Error::InvalidNeatFormat {
+            line: text.lines().count(),
+        }),
+        None => Ok(genomes),
+    }
+}
+
+pub fn write_neat_population(genomes: &[Genome]) -> String {
+    genomes
+        .iter()
+        .enumerate()
+        .map(|(i, genome)| genome.to_neat(i as u32 + 1))
+        .collect()
+}
+
+impl Genome {
+    // Reads a file holding a single genome.
+    pub fn from_neat(
+        text: &str,
+        func: ActivationFunction,
+    ) -> Result<Genome, Error> {
+        let mut genomes = read_neat_population(text, func)?;
+        match genomes.len() {
+            1 => Ok(genomes.remove(0)),
+            _ => Err(Error::InvalidNeatFormat { line: 1 }),
+        }
+    }
+
+    pub fn to_neat(&self, genome_id: u32) -> String {
+        let mut text = String::new();
+        // Writing to a String cannot fail.
+        writeln!(text, "genomestart {}", genome_id).unwrap();
+        self.nodes.iter().for_each(|node| {
+            let (code, placement) = node_codes(node.node_type);
+            writeln!(text, "node {} 0 {} {}", node.id, code, placement)
+                .unwrap();
+        });
+        self.connections.iter().for_each(|conn| {
+            writeln!(
+                text,
+                "gene 0 {} {} {} {} {} 0 {}",
+                conn.origin,
+                conn.dest,
+                conn.weight,
+                (conn.connection_type == ConnectionType::Recurrent) as u8,
+                conn.innovation,
+                conn.enabled as u8,
+            )
+            .unwrap();
+        });
+        writeln!(text, "genomeend {}", genome_id).unwrap();
+        text
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_read_neat() -> Result<(), Error> {
+        let text = "genomestart 1
+trait 1 0.1 0 0 0 0 0 0 0
+node 1 0 1 1
+node 2 0 1 1
+node 3 0 1 3
+node 4 0 0 2
+gene 1 1 4 0.5 0 1 0 1
+gene 1 2 4 -1.5 0 2 0 0
+gene 1 3 4 2 0 3 0 1
+gene 1 4 4 0.25 1 4 0 1
+genomeend 1
+";
+        let genome = Genome::from_neat(text, ActivationFunction::Sigmoid)?;
+        let node_types =
+            genome.nodes.iter().map(|n| n.node_type).collect::<Vec<_>>();
+        use NodeType::*;
+        assert_eq!(node_types, vec![Input, Input, Bias, Output]);
+        assert_eq!(genome.nodes[3].func, ActivationFunction::Sigmoid);
+        assert_eq!(
+            genome.connections[3],
+            ConnectionGene {
+                innovation: 4,
+                origin: 4,
+                dest: 4,
+                weight: 0.25,
+                connection_type: ConnectionType::Recurrent,
+                enabled: true,
+                plasticity: None,
+            }
+        );
+        assert!(!genome.connections[1].enabled);
+
+        // Round trips through the writer, apart from the ignored traits
+        let population = write_neat_population(&[genome.clone(), genome]);
+        let genomes =
+            read_neat_population(&population, ActivationFunction::Sigmoid)?;
+        assert_eq!(genomes.len(), 2);
+        let expected = text
+            .replace("trait 1 0.1 0 0 0 0 0 0 0\n", "")
+            .replace("gene 1 ", "gene 0 ");
+        assert_eq!(genomes[1].to_neat(1), expected);
+
+        let res = Genome::from_neat(
+            "genomestart 1\nnode 1 0 1 7\ngenomeend 1\n",
+            ActivationFunction::Sigmoid,
+        );
+        assert!(matches!(res, Err(Error::InvalidNeatFormat { line: 2 })));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,301 @@
+use crate::neural_net::*;
+use crate::population::*;
+use crate::selection::*;
+
+// Reads the INI-style config files of NEAT-Python:
+//
+//   [NEAT]
+//   pop_size = 150
+//
+//   [DefaultGenome]
+//   num_inputs = 2
+//   conn_add_prob = 0.5
+//
+// Keys are read from these sections:
+//
+//   NEAT                 pop_size
+//   DefaultGenome        num_inputs, num_outputs, feed_forward,
+//                        activation_default,
+//                        activation_options, activation_mutate_rate,
+//                        conn_add_prob, conn_delete_prob, node_add_prob,
+//                        node_delete_prob, enabled_mutate_rate,
+//                        weight_mutate_rate, weight_replace_rate,
+//                        weight_mutate_power, weight_init_stdev,
+//                        weight_min_value, weight_max_value,
+//                        compatibility_disjoint_coefficient,
+//                        compatibility_weight_coefficient
+//   DefaultSpeciesSet    compatibility_threshold
+//   DefaultStagnation    max_stagnation
+//   DefaultReproduction  elitism, survival_threshold
+//
+// Every other key is ignored, since it either has no equivalent here
+// or only affects things like bias and response genes.  NEAT-Python
+// mutates each weight independently, perturbing it with probability
+// `weight_mutate_rate` and replacing it with probability
+// `weight_replace_rate`, which is approximated by mutating a genome's
+// weights with the sum of the two.  Its Gaussian perturbations and
+// initial weights become uniform ones of the same scale.
+
+// Closest equivalent of a NEAT-Python activation function.  Its
+// sigmoid is steepened by a factor of five, and its tanh, sin and
+// gauss functions are also scaled up, which is only kept for the
+// sigmoid.  Other names are those of `ActivationFunction::from_name`,
+// including registered custom functions.
+fn activation(name: &str) -> Option<ActivationFunction> {
+    use ActivationFunction::*;
+    match name {
+        "sigmoid" => Some(SteepenedSigmoid(5.0)),
+        "tanh" => Some(Tanh),
+        "sin" => Some(Sin),
+        "gauss" => Some(Gaussian),
+        "relu" => Some(Relu),
+        "elu" => Some(Elu(1.0)),
+        "lelu" => Some(LeakyRelu(0.005)),
+        "identity" => Some(Identity),
+        "abs" => Some(Abs),
+        "square" => Some(Square),
+        "clamped" => Some(Clamped),
+        "inv" => Some(Inverse),
+        "log" => Some(Log),
+        _ => ActivationFunction::from_name(name),
+    }
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match value.to_lowercase().as_str() {
+        "true" | "yes" | "on" | "1" => Some(true),
+        "false" | "no" | "off" | "0" => Some(false),
+        _ => None,
+    }
+}
+
+impl PopulationConfig {
+    pub fn from_neat_python(text: &str) -> Result<Self, Error> {
+        let mut config = PopulationConfig::default();
+        let mut section = String::new();
+        let mut weight_mutate_rate = None;
+        let mut weight_replace_rate = None;
+        let mut weight_min_value = None;
+        let mut weight_max_value = None;
+        let mut activation_default = None;
+
+        text.lines().enumerate().try_for_each(|(i, line)| {
+            let invalid = || Error::InvalidNeatPythonConfig { line: i + 1 };
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';')
+            {
+                return Ok(());
+            }
+            if line.starts_with('[') {
+                section = line
+                    .strip_prefix('[')
+                    .and_then(|s| s.strip_suffix(']'))
+                    .ok_or_else(invalid)?
+                    .trim()
+                    .to_string();
+                return Ok(());
+            }
+
+            let (key, value) = line.split_once('=').ok_or_else(invalid)?;
+            let (key, value) = (key.trim(), value.trim());
+            let float = || value.parse::<f32>().map_err(|_| invalid());
+            let int = || value.parse::<usize>().map_err(|_| invalid());
+            let mutation = &mut config.mutation;
+            let speciation = &mut config.speciation;
+            match (section.as_str(), key) {
+                ("NEAT", "pop_size") => config.population_size = int()?,
+                ("DefaultGenome", "num_inputs") => {
+                    config.num_inputs = int()? as u32
+                }
+                ("DefaultGenome", "num_outputs") => {
+                    config.num_outputs = int()? as u32
+                }
+                // "random" picks the first of the options.
+                ("DefaultGenome", "activation_default")
+                    if value == "random" => {}
+                ("DefaultGenome", "activation_default") => {
+                    activation_default =
+                        Some(activation(value).ok_or_else(invalid)?);
+                }
+                ("DefaultGenome", "activation_options") => {
+                    mutation.activation_functions = value
+                        .split_whitespace()
+                        .map(|name| activation(name).ok_or_else(invalid))
+                        .collect::<Result<Vec<_>, _>>()?;
+                }
+                ("DefaultGenome", "activation_mutate_rate") => {
+                    mutation.change_activation_prob = float()?
+                }
+                ("DefaultGenome", "conn_add_prob") => {
+                    mutation.add_connection_prob = float()?
+                }
+                ("DefaultGenome", "conn_delete_prob") => {
+                    mutation.delete_connection_prob = float()?
+                }
+                ("DefaultGenome", "node_add_prob") => {
+                    mutation.add_node_prob = float()?
+                }
+                ("DefaultGenome", "node_delete_prob") => {
+                    mutation.delete_node_prob = float()?
+                }
+                ("DefaultGenome", "enabled_mutate_rate") => {
+                    mutation.toggle_enable_prob = float()?
+                }
+                ("DefaultGenome", "weight_mutate_rate") => {
+                    weight_mutate_rate = Some(float()?)
+                }
+                ("DefaultGenome", "weight_replace_rate") => {
+                    weight_replace_rate = Some(float()?)
+                }
+                ("DefaultGenome", "weight_mutate_power") => {
+                    mutation.weight_perturb_power = float()?
+                }
+                ("DefaultGenome", "weight_init_stdev") => {
+                    mutation.weight_init_range = float()?
+                }
+                ("DefaultGenome", "weight_min_value") => {
+                    weight_min_value = Some(float()?)
+                }
+                ("DefaultGenome", "weight_max_value") => {
+                    weight_max_value = Some(float()?)
+                }
+                ("DefaultGenome", "feed_forward") => {
+                    let feed_forward = parse_bool(value).ok_or_else(invalid)?;
+                    if !feed_forward
+                        && mutation.recurrent_connection_prob == 0.0
+                    {
+                        mutation.recurrent_connection_prob = 0.5;
+                    }
+                }
+                ("DefaultGenome", "compatibility_disjoint_coefficient") => {
+                    speciation.disjoint_coefficient = float()?;
+                    speciation.excess_coefficient = float()?;
+                }
+                ("DefaultGenome", "compatibility_weight_coefficient") => {
+                    speciation.weight_coefficient = float()?
+                }
+                ("DefaultSpeciesSet", "compatibility_threshold") => {
+                    speciation.compatibility_threshold = float()?
+                }
+                ("DefaultStagnation", "max_stagnation") => {
+                    speciation.max_stagnation = Some(int()?)
+                }
+                ("DefaultReproduction", "elitism") => config.elitism = int()?,
+                ("DefaultReproduction", "survival_threshold") => {
+                    config.selection = SelectionStrategy::Truncation {
+                        survival_threshold: float()?,
+                    }
+                }
+                _ => {}
+            }
+            Ok(())
+        })?;
+
+        let weight = |rate: Option<f32>| rate.unwrap_or(0.0);
+        if weight_mutate_rate.is_some() || weight_replace_rate.is_some() {
+            let (perturb, replace) =
+                (weight(weight_mutate_rate), weight(weight_replace_rate));
+            let mutation = &mut config.mutation;
+            mutation.weight_mutation_prob = (perturb + replace).min(1.0);
+            mutation.weight_perturb_prob = match perturb + replace {
+                total if total > 0.0 => perturb / total,
+                _ => 1.0,
+            };
+        }
+        // NEAT-Python clamps weights to its bounds.
+        if weight_min_value.is_some() || weight_max_value.is_some() {
+            let default = WeightBounds::default();
+            config.mutation.weight_bounds = Some(WeightBounds {
+                min: weight_min_value.unwrap_or(default.min),
+                max: weight_max_value.unwrap_or(default.max),
+                policy: BoundsPolicy::Clamp,
+            });
+        }
+        config.output_activation = activation_default
+            .or_else(|| config.mutation.activation_functions.first().cloned())
+            .unwrap_or(ActivationFunction::Sigmoid);
+
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_from_neat_python() -> Result<(), Error> {
+        let text = "
+# NEAT-Python's XOR example
+[NEAT]
+fitness_criterion     = max
+fitness_threshold     = 3.9
+pop_size              = 150
+
+[DefaultGenome]
+activation_default      = sigmoid
+activation_mutate_rate  = 0.1
+activation_options      = sigmoid relu
+compatibility_disjoint_coefficient = 1.0
+compatibility_weight_coefficient   = 0.5
+conn_add_prob           = 0.5
+conn_delete_prob        = 0.5
+enabled_mutate_rate     = 0.01
+feed_forward            = True
+node_add_prob           = 0.2
+node_delete_prob        = 0.2
+num_inputs              = 2
+num_outputs             = 1
+weight_max_value        = 30
+weight_min_value        = -30
+weight_mutate_power     = 0.5
+weight_mutate_rate      = 0.8
+weight_replace_rate     = 0.1
+
+[DefaultSpeciesSet]
+compatibility_threshold = 3.0
+
+[DefaultStagnation]
+max_stagnation       = 20
+
+[DefaultReproduction]
+elitism            = 2
+survival_threshold = 0.2
+";
+        let config = PopulationConfig::from_neat_python(text)?;
+        use ActivationFunction::*;
+        assert_eq!(config.population_size, 150);
+        assert_eq!((config.num_inputs, config.num_outputs), (2, 1));
+        assert_eq!(config.output_activation, SteepenedSigmoid(5.0));
+        assert_eq!(
+            config.mutation.activation_functions,
+            vec![SteepenedSigmoid(5.0), Relu]
+        );
+        assert_eq!(config.mutation.delete_node_prob, 0.2);
+        assert!((config.mutation.weight_mutation_prob - 0.9).abs() < 1e-6);
+        assert!((config.mutation.weight_perturb_prob - 0.8 / 0.9).abs() < 1e-6);
+        assert_eq!(
+            config.mutation.weight_bounds,
+            Some(WeightBounds {
+                min: -30.0,
+                max: 30.0,
+                policy: BoundsPolicy::Clamp,
+            })
+        );
+        assert_eq!(config.speciation.weight_coefficient, 0.5);
+        assert_eq!(config.speciation.max_stagnation, Some(20));
+        assert_eq!(config.elitism, 2);
+        assert_eq!(
+            config.selection,
+            SelectionStrategy::Truncation {
+                survival_threshold: 0.2
+            }
+        );
+
+        let res = PopulationConfig::from_neat_python(
+            "[DefaultGenome]\nactivation_options = sigmoid cube\n",
+        );
+        assert_eq!(res, Err(Error::InvalidNeatPythonConfig { line: 2 }));
+        Ok(())
+    }
+}
@@ -0,0 +1,174 @@
+use alloc::collections::VecDeque;
+#[cfg(not(feature = "std"))]
+use alloc::{vec, vec::Vec};
+
+use crate::neural_net::*;
+
+// Size and shape of a network, for complexity penalties and
+// reporting.  Connection counts other than `num_disabled` are of
+// enabled connections only, and connections between nodes that don't
+// exist count towards the totals but not towards depth or fan-in/out.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct NetworkMetrics {
+    pub num_inputs: usize,
+    pub num_bias: usize,
+    pub num_hidden: usize,
+    pub num_outputs: usize,
+    pub num_enabled: usize,
+    pub num_disabled: usize,
+    pub num_normal: usize,
+    pub num_recurrent: usize,
+    // Number of normal connections on the longest path, or `None` if
+    // they form a loop.
+    pub max_depth: Option<usize>,
+    // Mean number of connections into each hidden and output node,
+    // and out of each input, bias and hidden node.
+    pub mean_fan_in: f32,
+    pub mean_fan_out: f32,
+}
+
+impl NetworkMetrics {
+    pub fn num_nodes(&self) -> usize {
+        self.num_inputs + self.num_bias + self.num_hidden + self.num_outputs
+    }
+
+    // Enabled and disabled.
+    pub fn num_connections(&self) -> usize {
+        self.num_enabled + self.num_disabled
+    }
+}
+
+impl NeuralNetBuilder {
+    pub fn metrics(&self) -> NetworkMetrics {
+        let count = |node_type| {
+            self.nodes
+                .iter()
+                .filter(|n| n.node_type == node_type)
+                .count()
+        };
+        let enabled = self
+            .connections
+            .iter()
+            .filter(|conn| conn.enabled)
+            .collect::<Vec<_>>();
+        let num_recurrent = enabled
+            .iter()
+            .filter(|conn| conn.connection_type == ConnectionType::Recurrent)
+            .count();
+        let valid = enabled
+            .iter()
+            .filter(|conn| {
+                (conn.origin as usize) < self.nodes.len()
+                    && (conn.dest as usize) < self.nodes.len()
+            })
+            .collect::<Vec<_>>();
+
+        let mean = |total: usize, nodes: usize| {
+            if nodes == 0 {
+                0.0
+            } else {
+                total as f32 / nodes as f32
+            }
+        };
+        let num_hidden = count(NodeType::Hidden);
+        let num_outputs = count(NodeType::Output);
+        let num_inputs = count(NodeType::Input);
+        let num_bias = count(NodeType::Bias);
+        NetworkMetrics {
+            num_inputs,
+            num_bias,
+            num_hidden,
+            num_outputs,
+            num_enabled: enabled.len(),
+            num_disabled: self.connections.len() - enabled.len(),
+            num_normal: enabled.len() - num_recurrent,
+            num_recurrent,
+            max_depth: self.max_depth(),
+            mean_fan_in: mean(valid.len(), num_hidden + num_outputs),
+            mean_fan_out: mean(valid.len(), num_inputs + num_bias + num_hidden),
+        }
+    }
+
+    // Longest path of enabled normal connections, visiting nodes once
+    // all the nodes feeding them have been visited.
+    fn max_depth(&self) -> Option<usize> {
+        let num_nodes = self.nodes.len();
+        let mut outgoing = vec![Vec::new(); num_nodes];
+        let mut num_incoming = vec![0; num_nodes];
+        self.connections
+            .iter()
+            .filter(|conn| {
+                conn.enabled
+                    && conn.connection_type == ConnectionType::Normal
+                    && (conn.origin as usize) < num_nodes
+                    && (conn.dest as usize) < num_nodes
+            })
+            .for_each(|conn| {
+                outgoing[conn.origin as usize].push(conn.dest as usize);
+                num_incoming[conn.dest as usize] += 1;
+            });
+
+        let mut depths = vec![0; num_nodes];
+        let mut ready = (0..num_nodes)
+            .filter(|i| num_incoming[*i] == 0)
+            .collect::<VecDeque<_>>();
+        let mut num_visited = 0;
+        while let Some(node) = ready.pop_front() {
+            num_visited += 1;
+            outgoing[node].iter().for_each(|dest| {
+                depths[*dest] = depths[*dest].max(depths[node] + 1);
+                num_incoming[*dest] -= 1;
+                if num_incoming[*dest] == 0 {
+                    ready.push_back(*dest);
+                }
+            });
+        }
+        if num_visited == num_nodes {
+            Some(depths.into_iter().max().unwrap_or(0))
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_metrics() {
+        let mut builder = NeuralNetBuilder::new();
+        builder
+            .set_default_activation(ActivationFunction::Sigmoid)
+            .add_nodes(NodeType::Input, 2)
+            .add_bias();
+        builder
+            .add_nodes(NodeType::Hidden, 2)
+            .add_nodes(NodeType::Output, 1)
+            .add_normal_connection_raw(0, 3, 1.0)
+            .add_normal_connection_raw(1, 3, 1.0)
+            .add_normal_connection_raw(3, 4, 1.0)
+            .add_normal_connection_raw(4, 5, 1.0)
+            .add_normal_connection_raw(2, 5, 1.0)
+            .add_recurrent_connection_raw(5, 3, 1.0)
+            .add_normal_connection_raw(0, 5, 1.0)
+            .set_enabled(6, false);
+
+        let metrics = builder.metrics();
+        assert_eq!(metrics.num_nodes(), 6);
+        assert_eq!(metrics.num_inputs, 2);
+        assert_eq!(metrics.num_bias, 1);
+        assert_eq!(metrics.num_hidden, 2);
+        assert_eq!(metrics.num_outputs, 1);
+        assert_eq!(metrics.num_connections(), 7);
+        assert_eq!(metrics.num_disabled, 1);
+        assert_eq!(metrics.num_normal, 5);
+        assert_eq!(metrics.num_recurrent, 1);
+        assert_eq!(metrics.max_depth, Some(3));
+        assert_eq!(metrics.mean_fan_in, 2.0);
+        assert_eq!(metrics.mean_fan_out, 6.0 / 5.0);
+
+        builder.add_normal_connection_raw(5, 3, 1.0);
+        assert_eq!(builder.metrics().max_depth, None);
+    }
+}
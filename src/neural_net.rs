@@ -1,4 +1,16 @@
+use alloc::borrow::Cow;
+use alloc::collections::btree_map::Entry;
+use alloc::collections::{BTreeMap, BTreeSet, VecDeque};
+#[cfg(not(feature = "std"))]
+use alloc::{borrow::ToOwned, string::String, vec, vec::Vec};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::float::*;
+
 #[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum NodeType {
     Bias,
     Input,
@@ -16,13 +28,19 @@ impl NodeType {
     }
 }
 
-#[derive(Debug, PartialEq, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum ConnectionType {
     Normal,
     Recurrent,
 }
 
+// Custom functions compare by address, so the same function may
+// occasionally compare unequal to itself.  At worst this treats two
+// nodes as having different functions.
+#[allow(unpredictable_function_pointer_comparisons)]
 #[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum ActivationFunction {
     Sigmoid,
     Identity,
@@ -33,41 +51,1051 @@ pub enum ActivationFunction {
     Cos,
     Abs,
     Square,
+    // Slope `alpha` for negative inputs.
+    LeakyRelu(f32),
+    // `alpha * (exp(x) - 1)` for negative inputs.
+    Elu(f32),
+    // Sigmoid of `slope * x`.  The original NEAT uses a slope of
+    // 4.924273.
+    SteepenedSigmoid(f32),
+    // One for positive inputs, and zero otherwise.
+    Step,
+    // `ln(1 + exp(x))`, a smooth Relu.
+    Softplus,
+    // `sin(x) / x`, and one at zero.
+    Sinc,
+    // Identity clamped to [-1, 1].
+    Clamped,
+    // `1 / x`, and zero at zero.
+    Inverse,
+    // Natural logarithm, with inputs raised to at least 1e-7.
+    Log,
+    // Any other function.  Only the CPU backends can evaluate it, and
+    // it can only be serialized once given a name with
+    // `register_activation`.
+    #[cfg_attr(
+        all(feature = "serde", feature = "std"),
+        serde(
+            serialize_with = "crate::activation_registry::serialize_custom",
+            deserialize_with = "crate::activation_registry::deserialize_custom"
+        )
+    )]
+    #[cfg_attr(all(feature = "serde", not(feature = "std")), serde(skip))]
+    Custom(fn(f32) -> f32),
 }
 
 impl ActivationFunction {
     pub fn apply(&self, x: f32) -> f32 {
+        self.apply_float(x)
+    }
+
+    // Same as `apply`, for any scalar.  Custom functions are still
+    // evaluated in `f32`.
+    pub fn apply_float<F: Float>(&self, x: F) -> F {
         use ActivationFunction::*;
+        let sigmoid = |x: F| F::ONE / (F::ONE + (-x).exp());
         match self {
-            Sigmoid => 1.0 / (1.0 + (-x).exp()),
+            Sigmoid => sigmoid(x),
             Identity => x,
             Tanh => x.tanh(),
-            Relu => x.max(0.0),
-            Gaussian => (-x * x / 2.0).exp(),
+            Relu => x.max(F::ZERO),
+            Gaussian => (-x * x / F::from_f32(2.0)).exp(),
+            Sin => x.sin(),
+            Cos => x.cos(),
+            Abs => x.abs(),
+            Square => x * x,
+            LeakyRelu(alpha) => {
+                if x > F::ZERO {
+                    x
+                } else {
+                    F::from_f32(*alpha) * x
+                }
+            }
+            Elu(alpha) => {
+                if x > F::ZERO {
+                    x
+                } else {
+                    F::from_f32(*alpha) * x.exp_m1()
+                }
+            }
+            SteepenedSigmoid(slope) => sigmoid(F::from_f32(*slope) * x),
+            Step => {
+                if x > F::ZERO {
+                    F::ONE
+                } else {
+                    F::ZERO
+                }
+            }
+            // Rearranged so that the exponential can't overflow.
+            Softplus => x.max(F::ZERO) + (-x.abs()).exp().ln_1p(),
+            Sinc => {
+                if x == F::ZERO {
+                    F::ONE
+                } else {
+                    x.sin() / x
+                }
+            }
+            Clamped => x.max(-F::ONE).min(F::ONE),
+            Inverse => {
+                if x == F::ZERO {
+                    F::ZERO
+                } else {
+                    F::ONE / x
+                }
+            }
+            Log => x.max(F::from_f32(1e-7)).ln(),
+            Custom(f) => F::from_f32(f(x.to_f32())),
+        }
+    }
+
+    // Slope of the function at `x`, for gradient-based training.
+    // Custom functions have no known derivative.  Where the slope
+    // jumps, as for Relu at zero, it is taken from the left.
+    pub fn derivative(&self, x: f32) -> Option<f32> {
+        use ActivationFunction::*;
+        let sigmoid_slope = |s: f32| s * (1.0 - s);
+        match self {
+            Sigmoid => Some(sigmoid_slope(self.apply(x))),
+            Identity => Some(1.0),
+            Tanh => Some(1.0 - Float::tanh(x) * Float::tanh(x)),
+            Relu => Some(if x > 0.0 { 1.0 } else { 0.0 }),
+            Gaussian => Some(-x * self.apply(x)),
+            Sin => Some(Float::cos(x)),
+            Cos => Some(-Float::sin(x)),
+            Abs => Some(if x > 0.0 { 1.0 } else { -1.0 }),
+            Square => Some(2.0 * x),
+            LeakyRelu(alpha) => Some(if x > 0.0 { 1.0 } else { *alpha }),
+            Elu(alpha) => {
+                Some(if x > 0.0 { 1.0 } else { alpha * Float::exp(x) })
+            }
+            SteepenedSigmoid(slope) => {
+                Some(slope * sigmoid_slope(self.apply(x)))
+            }
+            Step => Some(0.0),
+            Softplus => Some(Sigmoid.apply(x)),
+            Sinc if x == 0.0 => Some(0.0),
+            Sinc => Some((Float::cos(x) - Float::sin(x) / x) / x),
+            Clamped => Some(if x > -1.0 && x <= 1.0 { 1.0 } else { 0.0 }),
+            Inverse if x == 0.0 => Some(0.0),
+            Inverse => Some(-1.0 / (x * x)),
+            Log => Some(if x > 1e-7 { 1.0 / x } else { 0.0 }),
+            Custom(_) => None,
+        }
+    }
+
+    // Same as `derivative`, given the output `y` of the function
+    // rather than its input, for functions whose slope is cheaper to
+    // find that way, as it avoids a second exponential for sigmoids.
+    // Functions whose slope the output doesn't determine have none.
+    pub fn derivative_from_output(&self, y: f32) -> Option<f32> {
+        use ActivationFunction::*;
+        match self {
+            Sigmoid => Some(y * (1.0 - y)),
+            SteepenedSigmoid(slope) => Some(slope * y * (1.0 - y)),
+            Tanh => Some(1.0 - y * y),
+            Identity => Some(1.0),
+            Relu => Some(if y > 0.0 { 1.0 } else { 0.0 }),
+            // Negative slopes flip the sign, so that the output no
+            // longer tells which side the input was on.
+            LeakyRelu(alpha) if *alpha > 0.0 => {
+                Some(if y > 0.0 { 1.0 } else { *alpha })
+            }
+            Elu(alpha) if *alpha > 0.0 => {
+                Some(if y > 0.0 { 1.0 } else { y + alpha })
+            }
+            Step => Some(0.0),
+            Inverse => Some(-y * y),
+            _ => None,
+        }
+    }
+
+    // Slope of the function at `x` by central differences, which
+    // works for any function, including custom ones, at the cost of
+    // two evaluations and some accuracy.
+    pub fn numerical_derivative(&self, x: f32) -> f32 {
+        let h = 1e-3 * x.abs().max(1.0);
+        (self.apply(x + h) - self.apply(x - h)) / (2.0 * h)
+    }
+
+    // Same as `apply` on each of `xs`, in place.  The function is
+    // matched once for the whole slice, so that the simpler functions
+    // vectorize, and with the `simd` feature 8 values are evaluated at
+    // a time.  With the `fast-math` feature, exponentials and tanh are
+    // approximated with `fast_exp` and `fast_tanh`, which dominate the
+    // cost of sigmoid networks otherwise.
+    pub fn apply_slice(&self, xs: &mut [f32]) {
+        #[cfg(feature = "simd")]
+        let xs = {
+            let mut chunks = xs.chunks_exact_mut(8);
+            chunks.by_ref().for_each(|chunk| {
+                let mut lanes = [0.0; 8];
+                lanes.copy_from_slice(chunk);
+                let y = self.apply_x8(wide::f32x8::from(lanes));
+                chunk.copy_from_slice(&y.to_array());
+            });
+            chunks.into_remainder()
+        };
+
+        #[cfg(feature = "fast-math")]
+        let (exp, tanh) = (fast_exp, fast_tanh);
+        #[cfg(not(feature = "fast-math"))]
+        let (exp, tanh) = (<f32 as Float>::exp, <f32 as Float>::tanh);
+
+        fn map(xs: &mut [f32], f: impl Fn(f32) -> f32) {
+            xs.iter_mut().for_each(|x| *x = f(*x));
+        }
+        use ActivationFunction::*;
+        match *self {
+            Sigmoid => map(xs, |x| 1.0 / (1.0 + exp(-x))),
+            Identity => {}
+            Tanh => map(xs, tanh),
+            Relu => map(xs, |x| x.max(0.0)),
+            Step => map(xs, |x| if x > 0.0 { 1.0 } else { 0.0 }),
+            Clamped => map(xs, |x| x.clamp(-1.0, 1.0)),
+            Gaussian => map(xs, |x| exp(-x * x / 2.0)),
+            LeakyRelu(alpha) => {
+                map(xs, |x| if x > 0.0 { x } else { alpha * x })
+            }
+            SteepenedSigmoid(slope) => {
+                map(xs, |x| 1.0 / (1.0 + exp(-slope * x)))
+            }
+            _ => map(xs, |x| self.apply(x)),
+        }
+    }
+
+    // Stable name of the function, such as "tanh", with any parameter
+    // in parentheses, as in "leaky_relu(0.01)".  Custom functions only
+    // have the name they were given with `register_activation`, so
+    // unregistered ones, and all of them without the `std` feature,
+    // have none.
+    pub fn name(&self) -> Option<String> {
+        use ActivationFunction::*;
+        let name = match self {
+            Sigmoid => "sigmoid",
+            Identity => "identity",
+            Tanh => "tanh",
+            Relu => "relu",
+            Gaussian => "gaussian",
+            Sin => "sin",
+            Cos => "cos",
+            Abs => "abs",
+            Square => "square",
+            Step => "step",
+            Softplus => "softplus",
+            Sinc => "sinc",
+            Clamped => "clamped",
+            Inverse => "inverse",
+            Log => "log",
+            LeakyRelu(alpha) => {
+                return Some(alloc::format!("leaky_relu({})", alpha))
+            }
+            Elu(alpha) => return Some(alloc::format!("elu({})", alpha)),
+            SteepenedSigmoid(slope) => {
+                return Some(alloc::format!("steepened_sigmoid({})", slope))
+            }
+            #[cfg(feature = "std")]
+            Custom(f) => return crate::activation_registry::custom_name(*f),
+            #[cfg(not(feature = "std"))]
+            Custom(_) => return None,
+        };
+        Some(name.to_owned())
+    }
+
+    // The function named `name`, as given by `name`.  The parameter of
+    // a parameterized function may be left out for its usual value.
+    pub fn from_name(name: &str) -> Option<Self> {
+        use ActivationFunction::*;
+        let (base, param) = match name.strip_suffix(')') {
+            Some(rest) => {
+                let (base, param) = rest.split_once('(')?;
+                (base, Some(param.trim().parse::<f32>().ok()?))
+            }
+            None => (name, None),
+        };
+        let unparameterized = |func| match param {
+            Some(_) => None,
+            None => Some(func),
+        };
+        match base.trim() {
+            "sigmoid" => unparameterized(Sigmoid),
+            "identity" => unparameterized(Identity),
+            "tanh" => unparameterized(Tanh),
+            "relu" => unparameterized(Relu),
+            "gaussian" => unparameterized(Gaussian),
+            "sin" => unparameterized(Sin),
+            "cos" => unparameterized(Cos),
+            "abs" => unparameterized(Abs),
+            "square" => unparameterized(Square),
+            "step" => unparameterized(Step),
+            "softplus" => unparameterized(Softplus),
+            "sinc" => unparameterized(Sinc),
+            "clamped" => unparameterized(Clamped),
+            "inverse" => unparameterized(Inverse),
+            "log" => unparameterized(Log),
+            "leaky_relu" => Some(LeakyRelu(param.unwrap_or(0.01))),
+            "elu" => Some(Elu(param.unwrap_or(1.0))),
+            "steepened_sigmoid" => {
+                Some(SteepenedSigmoid(param.unwrap_or(4.924273)))
+            }
+            #[cfg(feature = "std")]
+            _ if param.is_none() => {
+                crate::activation_registry::custom(name).map(Custom)
+            }
+            _ => None,
+        }
+    }
+
+    // Applies the function independently to each of 8 lanes.
+    #[cfg(feature = "simd")]
+    pub fn apply_x8(&self, x: wide::f32x8) -> wide::f32x8 {
+        use wide::{f32x8, CmpEq, CmpGt};
+        use ActivationFunction::*;
+        // `f32x8::exp` is garbage outside the range of finite results
+        // rather than saturating, so arguments are clamped to it.
+        let exp =
+            |x: f32x8| x.max(f32x8::splat(-87.0)).min(f32x8::splat(87.0)).exp();
+        match self {
+            Sigmoid => f32x8::ONE / (f32x8::ONE + exp(-x)),
+            Identity => x,
+            Tanh => {
+                let two = f32x8::splat(2.0);
+                f32x8::ONE - two / (exp(two * x) + f32x8::ONE)
+            }
+            Relu => x.max(f32x8::ZERO),
+            Gaussian => exp(-x * x / f32x8::splat(2.0)),
             Sin => x.sin(),
             Cos => x.cos(),
             Abs => x.abs(),
             Square => x * x,
+            LeakyRelu(alpha) => {
+                x.cmp_gt(f32x8::ZERO).blend(x, f32x8::splat(*alpha) * x)
+            }
+            Elu(alpha) => x
+                .cmp_gt(f32x8::ZERO)
+                .blend(x, f32x8::splat(*alpha) * (exp(x) - f32x8::ONE)),
+            SteepenedSigmoid(slope) => {
+                f32x8::ONE / (f32x8::ONE + exp(-f32x8::splat(*slope) * x))
+            }
+            Step => x.cmp_gt(f32x8::ZERO).blend(f32x8::ONE, f32x8::ZERO),
+            Softplus => x.max(f32x8::ZERO) + (f32x8::ONE + exp(-x.abs())).ln(),
+            Sinc => x.cmp_eq(f32x8::ZERO).blend(f32x8::ONE, x.sin() / x),
+            Clamped => x.max(-f32x8::ONE).min(f32x8::ONE),
+            Inverse => x.cmp_eq(f32x8::ZERO).blend(f32x8::ZERO, f32x8::ONE / x),
+            Log => x.max(f32x8::splat(1e-7)).ln(),
+            Custom(f) => x.to_array().map(*f).into(),
+        }
+    }
+}
+
+// How a node combines its incoming values before applying its
+// activation function.  A node without incoming values aggregates
+// to zero, whatever the function.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum AggregationFunction {
+    Sum,
+    Product,
+    Min,
+    Max,
+    Mean,
+    // Sum of absolute values.
+    AbsSum,
+}
+
+impl AggregationFunction {
+    pub fn aggregate(&self, values: impl Iterator<Item = f32>) -> f32 {
+        let (acc, count) = values.fold((0.0, 0), |(acc, count), x| {
+            (self.combine(acc, count, x), count + 1)
+        });
+        self.finish(acc, count)
+    }
+
+    // Folds `x` into `acc`, the aggregate of the `count` values before
+    // it.  Backends that receive values one at a time use this along
+    // with `finish`.
+    pub(crate) fn combine<F: Float>(&self, acc: F, count: u32, x: F) -> F {
+        use AggregationFunction::*;
+        match (self, count) {
+            (AbsSum, _) => acc + x.abs(),
+            (_, 0) => x,
+            (Sum, _) | (Mean, _) => acc + x,
+            (Product, _) => acc * x,
+            (Min, _) => acc.min(x),
+            (Max, _) => acc.max(x),
+        }
+    }
+
+    pub(crate) fn finish<F: Float>(&self, acc: F, count: u32) -> F {
+        match self {
+            AggregationFunction::Mean if count > 0 => {
+                acc / F::from_f32(count as f32)
+            }
+            _ => acc,
+        }
+    }
+}
+
+// Applied to the outputs as a whole after each evaluation, for
+// transforms that couple the outputs and so can't be expressed as
+// activation functions.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum OutputTransform {
+    Identity,
+    Softmax,
+    // One for the largest output, and zero for every other.  Ties go
+    // to the first.
+    ArgMax,
+}
+
+impl OutputTransform {
+    pub fn apply(&self, outputs: &mut [f32]) {
+        self.apply_float(outputs)
+    }
+
+    // Same as `apply`, for any scalar.
+    pub fn apply_float<F: Float>(&self, outputs: &mut [F]) {
+        use OutputTransform::*;
+        match self {
+            Identity => {}
+            Softmax => {
+                // Subtracting the largest output first keeps the
+                // exponentials from overflowing.
+                let max = outputs.iter().cloned().reduce(F::max);
+                let max = max.unwrap_or(F::ZERO);
+                outputs.iter_mut().for_each(|x| *x = (*x - max).exp());
+                let sum = outputs.iter().fold(F::ZERO, |sum, x| sum + *x);
+                outputs.iter_mut().for_each(|x| *x = *x / sum);
+            }
+            ArgMax => {
+                let best = argmax(outputs);
+                outputs.iter_mut().enumerate().for_each(|(i, x)| {
+                    *x = if Some(i) == best { F::ONE } else { F::ZERO };
+                });
+            }
+        }
+    }
+
+    // Writes the transformed `values` into `outputs`, dropping any that
+    // don't fit.  Only allocates if some are dropped, since the
+    // transform needs all of them.
+    pub(crate) fn apply_into<F: Float>(
+        &self,
+        values: impl ExactSizeIterator<Item = F>,
+        outputs: &mut [F],
+    ) {
+        let n = values.len();
+        if outputs.len() >= n {
+            outputs.iter_mut().zip(values).for_each(|(out, x)| *out = x);
+            self.apply_float(&mut outputs[..n]);
+        } else {
+            let mut values = values.collect::<Vec<_>>();
+            self.apply_float(&mut values);
+            outputs.copy_from_slice(&values[..outputs.len()]);
+        }
+    }
+}
+
+// Position of the largest value, with ties going to the first, and
+// NaN counting as the largest.
+fn argmax<F: Float>(values: &[F]) -> Option<usize> {
+    (0..values.len()).rev().max_by(|a, b| {
+        let (a, b) = (values[*a], values[*b]);
+        match a.partial_cmp(&b) {
+            Some(ordering) => ordering,
+            None => a.is_nan().cmp(&b.is_nan()),
+        }
+    })
+}
+
+// Mapping from the outputs of a network to the values returned by
+// `evaluate`, applied after the `OutputTransform`, so that a network
+// emits actions in the units of its task.  A list of mappings takes
+// the outputs in order, and any left over are returned as they are.
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum OutputMapping {
+    // `x * scale + offset` of the next output.
+    Affine { scale: f32, offset: f32 },
+    // Index of the largest of the next `n` outputs, as a single
+    // value, for choosing one of `n` discrete actions.
+    Discrete(u32),
+}
+
+impl OutputMapping {
+    // Number of outputs this mapping takes, of the `remaining`.
+    pub(crate) fn group_size(&self, remaining: usize) -> usize {
+        match *self {
+            OutputMapping::Affine { .. } => 1,
+            OutputMapping::Discrete(n) => (n as usize).clamp(1, remaining),
+        }
+    }
+
+    // Number of values `map` returns for `num_outputs` outputs.
+    pub fn num_mapped(mappings: &[Self], num_outputs: usize) -> usize {
+        let mut remaining = num_outputs;
+        let mut count = 0;
+        for mapping in mappings {
+            if remaining == 0 {
+                break;
+            }
+            remaining -= mapping.group_size(remaining);
+            count += 1;
+        }
+        count + remaining
+    }
+
+    // Maps the outputs of a network.  Mappings past the last output
+    // are ignored, and a discrete mapping takes the outputs that are
+    // left if there are fewer than `n`.
+    pub fn map<F: Float>(mappings: &[Self], outputs: &[F]) -> Vec<F> {
+        let mut rest = outputs;
+        let mut mapped = Vec::with_capacity(outputs.len());
+        for mapping in mappings {
+            if rest.is_empty() {
+                break;
+            }
+            let (group, tail) = rest.split_at(mapping.group_size(rest.len()));
+            mapped.push(match *mapping {
+                OutputMapping::Affine { scale, offset } => {
+                    group[0] * F::from_f32(scale) + F::from_f32(offset)
+                }
+                OutputMapping::Discrete(_) => {
+                    F::from_f32(argmax(group).unwrap_or(0) as f32)
+                }
+            });
+            rest = tail;
+        }
+        mapped.extend_from_slice(rest);
+        mapped
+    }
+}
+
+// Writes the outputs of a network into `outputs`, given the values of
+// its output nodes, after the transform and mapping.  As with
+// `OutputTransform::apply_into`, values that don't fit are dropped.
+pub(crate) fn finish_outputs<F: Float>(
+    transform: OutputTransform,
+    mappings: &[OutputMapping],
+    values: impl ExactSizeIterator<Item = F>,
+    outputs: &mut [F],
+) {
+    if mappings.is_empty() {
+        transform.apply_into(values, outputs);
+    } else {
+        let mut values = values.collect::<Vec<_>>();
+        transform.apply_float(&mut values);
+        let mapped = OutputMapping::map(mappings, &values);
+        outputs.iter_mut().zip(mapped).for_each(|(out, x)| *out = x);
+    }
+}
+
+// Normalization of one input, applied by `evaluate` before the value
+// reaches the network, so that a network deployed on raw
+// observations sees them scaled the same way as during training.
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum InputScaling {
+    Identity,
+    // Maps `min` to zero and `max` to one.  Every value maps to zero
+    // if they're equal.
+    MinMax { min: f32, max: f32 },
+    // Subtracts the mean, and divides by the standard deviation if it
+    // isn't zero.
+    ZScore { mean: f32, std_dev: f32 },
+}
+
+impl InputScaling {
+    // Scale and offset, such that `apply(x)` is `x * scale + offset`.
+    pub fn affine(&self) -> (f32, f32) {
+        use InputScaling::*;
+        match *self {
+            Identity => (1.0, 0.0),
+            MinMax { min, max } if max != min => {
+                let scale = 1.0 / (max - min);
+                (scale, -min * scale)
+            }
+            MinMax { .. } => (0.0, 0.0),
+            ZScore { mean, std_dev } if std_dev != 0.0 => {
+                (1.0 / std_dev, -mean / std_dev)
+            }
+            ZScore { mean, .. } => (1.0, -mean),
+        }
+    }
+
+    pub fn apply(&self, x: f32) -> f32 {
+        self.apply_float(x)
+    }
+
+    // Same as `apply`, for any scalar.
+    pub fn apply_float<F: Float>(&self, x: F) -> F {
+        let (scale, offset) = self.affine();
+        x * F::from_f32(scale) + F::from_f32(offset)
+    }
+
+    // Min-max scaling of each input over the samples, which are
+    // input vectors as passed to `evaluate`.
+    pub fn fit_min_max(samples: &[Vec<f32>]) -> Vec<Self> {
+        Self::fit(samples, |column| {
+            let min = column.iter().cloned().fold(f32::INFINITY, f32::min);
+            let max = column.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+            InputScaling::MinMax { min, max }
+        })
+    }
+
+    // Z-score scaling of each input over the samples.
+    pub fn fit_z_score(samples: &[Vec<f32>]) -> Vec<Self> {
+        Self::fit(samples, |column| {
+            let n = column.len() as f32;
+            let mean = column.iter().sum::<f32>() / n;
+            let variance =
+                column.iter().map(|x| (x - mean) * (x - mean)).sum::<f32>() / n;
+            InputScaling::ZScore {
+                mean,
+                std_dev: libm::sqrtf(variance),
+            }
+        })
+    }
+
+    // Applies `fit` to the values of each input, up to the longest
+    // sample.  Inputs missing from a sample are left out of its
+    // column.
+    fn fit(samples: &[Vec<f32>], fit: impl Fn(&[f32]) -> Self) -> Vec<Self> {
+        let num_inputs = samples.iter().map(|x| x.len()).max().unwrap_or(0);
+        (0..num_inputs)
+            .map(|j| {
+                let column = samples
+                    .iter()
+                    .filter_map(|x| x.get(j).cloned())
+                    .collect::<Vec<_>>();
+                fit(&column)
+            })
+            .collect()
+    }
+}
+
+// Scaled value of input `j`, given the scaling of each input.  Inputs
+// past the end of `scaling` are left as they are.
+pub(crate) fn scale_input<F: Float>(
+    scaling: &[InputScaling],
+    j: usize,
+    x: F,
+) -> F {
+    match scaling.get(j) {
+        Some(scaling) => scaling.apply_float(x),
+        None => x,
+    }
+}
+
+// How many times `evaluate` propagates the inputs through a network
+// that carries values between passes.  Each pass is a full
+// evaluation, so recurrent connections move values one step per
+// pass, as in the original NEAT, which activated networks until
+// their outputs settled.  Feed-forward networks give the same
+// outputs on every pass, so their backends ignore this.
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Propagation {
+    // A fixed number of passes, with zero counting as one.
+    Passes(u32),
+    // Passes until no output changes by more than `epsilon` from one
+    // pass to the next, up to `max_passes`.
+    UntilStable { epsilon: f32, max_passes: u32 },
+}
+
+impl Default for Propagation {
+    fn default() -> Self {
+        Propagation::Passes(1)
+    }
+}
+
+// What building does with a connection that has the same origin,
+// destination and type as an earlier one.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum DuplicatePolicy {
+    // Each copy is a separate input to the destination, so with sum
+    // aggregation their weights add up.
+    #[default]
+    Allow,
+    // Fails with `Error::DuplicateConnection`.
+    Reject,
+    // Replaces the copies with the first one, carrying the sum of
+    // their weights.
+    Merge,
+}
+
+// What is done with a weight outside of `WeightBounds`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum BoundsPolicy {
+    // Moves the weight to the nearest bound.
+    #[default]
+    Clamp,
+    // Fails building with `Error::WeightOutOfBounds`, and leaves the
+    // weight unchanged when mutating.
+    Reject,
+    // Wraps the weight around, as if the bounds were joined.
+    Wrap,
+}
+
+// Range that connection weights are kept within, since unbounded
+// weights can make recurrent networks blow up.
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct WeightBounds {
+    pub min: f32,
+    pub max: f32,
+    pub policy: BoundsPolicy,
+}
+
+impl Default for WeightBounds {
+    fn default() -> Self {
+        Self {
+            min: -30.0,
+            max: 30.0,
+            policy: BoundsPolicy::Clamp,
+        }
+    }
+}
+
+impl WeightBounds {
+    pub fn contains(&self, weight: f32) -> bool {
+        (self.min..=self.max).contains(&weight)
+    }
+
+    // Whether some weight is within bounds, which fails for bounds
+    // that are NaN or the wrong way round.
+    pub fn is_valid(&self) -> bool {
+        self.min <= self.max
+    }
+
+    // The weight brought within bounds, or `None` if it is outside of
+    // them and the policy is to reject it.
+    pub fn apply(&self, weight: f32) -> Option<f32> {
+        if self.contains(weight) {
+            return Some(weight);
+        }
+        match self.policy {
+            // Unlike `clamp`, doesn't panic on invalid bounds.
+            BoundsPolicy::Clamp => Some(weight.max(self.min).min(self.max)),
+            BoundsPolicy::Reject => None,
+            BoundsPolicy::Wrap if self.max > self.min => {
+                let range = self.max - self.min;
+                let offset = libm::fmodf(weight - self.min, range);
+                let offset = if offset < 0.0 { offset + range } else { offset };
+                Some(self.min + offset)
+            }
+            BoundsPolicy::Wrap => Some(self.min),
         }
     }
 }
 
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct NodeTemplate {
     pub node_type: NodeType,
     pub func: ActivationFunction,
+    pub aggregation: AggregationFunction,
+    // A node outputs `func(response * (aggregate + bias))`.  Ignored
+    // for input and bias nodes.
+    pub bias: f32,
+    pub response: f32,
+    // Makes the node a gated memory cell.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub memory: Option<MemoryCell>,
+    // Label for looking the node up by name, mainly for inputs and
+    // outputs.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub name: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Gate {
+    pub input_weight: f32,
+    pub memory_weight: f32,
+    pub bias: f32,
+}
+
+impl Gate {
+    fn open<F: Float>(&self, x: F, memory: F) -> F {
+        let [input_weight, memory_weight, bias] =
+            [self.input_weight, self.memory_weight, self.bias].map(F::from_f32);
+        ActivationFunction::Sigmoid
+            .apply_float(input_weight * x + memory_weight * memory + bias)
+    }
+}
+
+// Single-unit GRU.  A memory cell's output is also its memory `h`,
+// which starts at zero and is updated once per evaluation from the
+// node's input `x = response * (aggregate + bias)`:
+//
+//   z = sigmoid(update_gate)
+//   r = sigmoid(reset_gate)
+//   h = (1 - z) * h + z * func(x + memory_weight * r * h)
+//
+// where each gate is `input_weight * x + memory_weight * h + bias`.
+// With the update gate closed the cell holds its value, which lets
+// it carry information over many more evaluations than a recurrent
+// connection.
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MemoryCell {
+    pub update_gate: Gate,
+    pub reset_gate: Gate,
+    pub memory_weight: f32,
+}
+
+impl Default for MemoryCell {
+    fn default() -> Self {
+        let gate = Gate {
+            input_weight: 1.0,
+            memory_weight: 0.0,
+            bias: 0.0,
+        };
+        Self {
+            update_gate: gate,
+            reset_gate: gate,
+            memory_weight: 1.0,
+        }
+    }
+}
+
+impl MemoryCell {
+    // New memory, given the input and the previous memory.
+    pub fn update<F: Float>(
+        &self,
+        func: ActivationFunction,
+        x: F,
+        memory: F,
+    ) -> F {
+        let z = self.update_gate.open(x, memory);
+        let r = self.reset_gate.open(x, memory);
+        let memory_weight = F::from_f32(self.memory_weight);
+        let candidate = func.apply_float(x + memory_weight * r * memory);
+        (F::ONE - z) * memory + z * candidate
+    }
+}
+
+// Generalized Hebbian rule of a plastic connection, whose weight
+// changes after every evaluation by
+//
+//   learning_rate * (a * pre * post + b * pre + c * post + d)
+//
+// where `pre` and `post` are the activations of its origin and
+// destination.  Weights aren't bounded, so rules that only grow them
+// should be paired with bounded activation functions.
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct HebbianRule {
+    pub learning_rate: f32,
+    pub a: f32,
+    pub b: f32,
+    pub c: f32,
+    pub d: f32,
+}
+
+impl Default for HebbianRule {
+    // Plain Hebbian learning.
+    fn default() -> Self {
+        Self {
+            learning_rate: 0.1,
+            a: 1.0,
+            b: 0.0,
+            c: 0.0,
+            d: 0.0,
+        }
+    }
+}
+
+impl HebbianRule {
+    pub fn weight_change<F: Float>(&self, pre: F, post: F) -> F {
+        let [learning_rate, a, b, c, d] =
+            [self.learning_rate, self.a, self.b, self.c, self.d]
+                .map(F::from_f32);
+        learning_rate * (a * pre * post + b * pre + c * post + d)
+    }
 }
 
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct ConnectionTemplate {
     pub origin: u32,
     pub dest: u32,
+    // Initial weight, for plastic connections.
     pub weight: f32,
     pub connection_type: ConnectionType,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub plasticity: Option<HebbianRule>,
+    // Disabled connections are kept in the builder, but left out of
+    // the built network.
+    #[cfg_attr(feature = "serde", serde(default = "enabled_by_default"))]
+    pub enabled: bool,
+}
+
+#[cfg(feature = "serde")]
+fn enabled_by_default() -> bool {
+    true
+}
+
+// 64-bit FNV-1a, whose values, unlike those of the hashers in std,
+// don't change between releases.
+struct Fnv1a(u64);
+
+impl Default for Fnv1a {
+    fn default() -> Self {
+        Self(0xcbf29ce484222325)
+    }
+}
+
+impl core::fmt::Write for Fnv1a {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        s.bytes().for_each(|byte| {
+            self.0 = (self.0 ^ byte as u64).wrapping_mul(0x100000001b3);
+        });
+        Ok(())
+    }
+}
+
+// Floats as hashed by `topology_hash`, by their bits.
+struct HashBits<'a>(&'a [f32]);
+
+impl core::fmt::Display for HashBits<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        self.0
+            .iter()
+            .try_for_each(|x| write!(f, "{:x},", x.to_bits()))
+    }
+}
+
+// Name of a variant as hashed by `topology_hash`, spelled out rather
+// than taken from `Debug`, so that renaming a variant doesn't change
+// any hash.  Parameters are left out, to be hashed with the weights.
+trait HashName {
+    fn hash_name(&self) -> &'static str;
+}
+
+impl HashName for NodeType {
+    fn hash_name(&self) -> &'static str {
+        match self {
+            NodeType::Bias => "Bias",
+            NodeType::Input => "Input",
+            NodeType::Output => "Output",
+            NodeType::Hidden => "Hidden",
+        }
+    }
+}
+
+impl HashName for ConnectionType {
+    fn hash_name(&self) -> &'static str {
+        match self {
+            ConnectionType::Normal => "Normal",
+            ConnectionType::Recurrent => "Recurrent",
+        }
+    }
 }
 
+impl HashName for ActivationFunction {
+    fn hash_name(&self) -> &'static str {
+        use ActivationFunction::*;
+        match self {
+            Sigmoid => "Sigmoid",
+            Identity => "Identity",
+            Tanh => "Tanh",
+            Relu => "Relu",
+            Gaussian => "Gaussian",
+            Sin => "Sin",
+            Cos => "Cos",
+            Abs => "Abs",
+            Square => "Square",
+            LeakyRelu(_) => "LeakyRelu",
+            Elu(_) => "Elu",
+            SteepenedSigmoid(_) => "SteepenedSigmoid",
+            Step => "Step",
+            Softplus => "Softplus",
+            Sinc => "Sinc",
+            Clamped => "Clamped",
+            Inverse => "Inverse",
+            Log => "Log",
+            Custom(_) => "Custom",
+        }
+    }
+}
+
+impl HashName for AggregationFunction {
+    fn hash_name(&self) -> &'static str {
+        use AggregationFunction::*;
+        match self {
+            Sum => "Sum",
+            Product => "Product",
+            Min => "Min",
+            Max => "Max",
+            Mean => "Mean",
+            AbsSum => "AbsSum",
+        }
+    }
+}
+
+impl HashName for OutputTransform {
+    fn hash_name(&self) -> &'static str {
+        match self {
+            OutputTransform::Identity => "Identity",
+            OutputTransform::Softmax => "Softmax",
+            OutputTransform::ArgMax => "ArgMax",
+        }
+    }
+}
+
+impl HashName for DuplicatePolicy {
+    fn hash_name(&self) -> &'static str {
+        match self {
+            DuplicatePolicy::Allow => "Allow",
+            DuplicatePolicy::Reject => "Reject",
+            DuplicatePolicy::Merge => "Merge",
+        }
+    }
+}
+
+impl HashName for BoundsPolicy {
+    fn hash_name(&self) -> &'static str {
+        match self {
+            BoundsPolicy::Clamp => "Clamp",
+            BoundsPolicy::Reject => "Reject",
+            BoundsPolicy::Wrap => "Wrap",
+        }
+    }
+}
+
+// Index of a node in a builder, as returned when adding it.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
+pub struct NodeId(pub u32);
+
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct NeuralNetBuilder {
     pub nodes: Vec<NodeTemplate>,
     pub connections: Vec<ConnectionTemplate>,
     default_func: ActivationFunction,
+    default_aggregation: AggregationFunction,
+    bias_value: f32,
+    output_transform: OutputTransform,
+    #[cfg_attr(feature = "serde", serde(default))]
+    propagation: Propagation,
+    #[cfg_attr(feature = "serde", serde(default))]
+    duplicate_policy: DuplicatePolicy,
+    #[cfg_attr(feature = "serde", serde(default))]
+    weight_bounds: Option<WeightBounds>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    input_scaling: Vec<InputScaling>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    output_mapping: Vec<OutputMapping>,
+}
+
+impl Default for NeuralNetBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl NeuralNetBuilder {
@@ -76,101 +1104,1506 @@ impl NeuralNetBuilder {
             nodes: Vec::new(),
             connections: Vec::new(),
             default_func: ActivationFunction::Sigmoid,
+            default_aggregation: AggregationFunction::Sum,
+            bias_value: 1.0,
+            output_transform: OutputTransform::Identity,
+            propagation: Propagation::default(),
+            duplicate_policy: DuplicatePolicy::default(),
+            weight_bounds: None,
+            input_scaling: Vec::new(),
+            output_mapping: Vec::new(),
         }
     }
 
-    pub fn set_default_activation(
+    // Constant output of every bias node.
+    pub fn bias_value(&self) -> f32 {
+        self.bias_value
+    }
+
+    pub fn set_bias_value(&mut self, value: f32) -> &mut Self {
+        self.bias_value = value;
+        self
+    }
+
+    pub fn output_transform(&self) -> OutputTransform {
+        self.output_transform
+    }
+
+    pub fn set_output_transform(
         &mut self,
-        func: ActivationFunction,
+        transform: OutputTransform,
     ) -> &mut Self {
-        self.default_func = func;
+        self.output_transform = transform;
         self
     }
 
-    pub fn add_input(&mut self) -> &mut Self {
-        self.nodes.push(NodeTemplate {
-            node_type: NodeType::Input,
-            func: ActivationFunction::Identity,
-        });
-        self
+    pub fn propagation(&self) -> Propagation {
+        self.propagation
     }
 
-    pub fn add_inputs(&mut self, n: u32) -> &mut Self {
-        (0..n).for_each(|_| {
-            self.add_input();
-        });
+    pub fn set_propagation(&mut self, propagation: Propagation) -> &mut Self {
+        self.propagation = propagation;
         self
     }
 
-    pub fn add_node(
+    pub fn duplicate_policy(&self) -> DuplicatePolicy {
+        self.duplicate_policy
+    }
+
+    pub fn set_duplicate_policy(
         &mut self,
-        node_type: NodeType,
-        func: ActivationFunction,
+        policy: DuplicatePolicy,
     ) -> &mut Self {
-        self.nodes.push(NodeTemplate { node_type, func });
+        self.duplicate_policy = policy;
         self
     }
 
-    pub fn add_nodes(&mut self, node_type: NodeType, n: u32) -> &mut Self {
-        (0..n).for_each(|_| {
-            self.add_node(node_type, self.default_func);
-        });
-        self
+    pub fn weight_bounds(&self) -> Option<WeightBounds> {
+        self.weight_bounds
     }
 
-    pub fn add_connection(
+    // Bounds applied to every weight when building, or none to build
+    // with the weights as they are.
+    pub fn set_weight_bounds(
         &mut self,
-        origin: u32,
-        dest: u32,
-        weight: f32,
-        connection_type: ConnectionType,
+        bounds: Option<WeightBounds>,
+    ) -> &mut Self {
+        self.weight_bounds = bounds;
+        self
+    }
+
+    pub fn input_scaling(&self) -> &[InputScaling] {
+        &self.input_scaling
+    }
+
+    // Scaling of each input, in the order of the input nodes.  Inputs
+    // without one are passed to the network as they are.
+    pub fn set_input_scaling(
+        &mut self,
+        scaling: Vec<InputScaling>,
+    ) -> &mut Self {
+        self.input_scaling = scaling;
+        self
+    }
+
+    pub fn output_mapping(&self) -> &[OutputMapping] {
+        &self.output_mapping
+    }
+
+    // Mapping of the outputs, after the output transform.  Discrete
+    // mappings change the number of values `evaluate` returns.
+    pub fn set_output_mapping(
+        &mut self,
+        mapping: Vec<OutputMapping>,
+    ) -> &mut Self {
+        self.output_mapping = mapping;
+        self
+    }
+
+    // Activation function of the nodes added by `add_node` and
+    // `add_nodes`.
+    pub fn default_activation(&self) -> ActivationFunction {
+        self.default_func
+    }
+
+    pub fn default_aggregation(&self) -> AggregationFunction {
+        self.default_aggregation
+    }
+
+    pub fn set_default_activation(
+        &mut self,
+        func: ActivationFunction,
+    ) -> &mut Self {
+        self.default_func = func;
+        self
+    }
+
+    // Aggregation of the nodes added by `add_node` and `add_nodes`.
+    pub fn set_default_aggregation(
+        &mut self,
+        aggregation: AggregationFunction,
+    ) -> &mut Self {
+        self.default_aggregation = aggregation;
+        self
+    }
+
+    // Index of the next node to be added.
+    fn next_node(&self) -> NodeId {
+        NodeId(self.nodes.len() as u32)
+    }
+
+    pub fn add_input(&mut self) -> NodeId {
+        let id = self.next_node();
+        self.nodes.push(NodeTemplate {
+            node_type: NodeType::Input,
+            func: ActivationFunction::Identity,
+            aggregation: AggregationFunction::Sum,
+            bias: 0.0,
+            response: 1.0,
+            memory: None,
+            name: None,
+        });
+        id
+    }
+
+    // Bias nodes behave like inputs that always receive
+    // `bias_value()`.
+    pub fn add_bias(&mut self) -> NodeId {
+        let id = self.next_node();
+        self.nodes.push(NodeTemplate {
+            node_type: NodeType::Bias,
+            func: ActivationFunction::Identity,
+            aggregation: AggregationFunction::Sum,
+            bias: 0.0,
+            response: 1.0,
+            memory: None,
+            name: None,
+        });
+        id
+    }
+
+    pub fn add_named_input(&mut self, name: &str) -> NodeId {
+        let id = self.add_input();
+        self.set_name(id, Some(name));
+        id
+    }
+
+    // Output with the default activation function.
+    pub fn add_named_output(&mut self, name: &str) -> NodeId {
+        let id = self.add_node(NodeType::Output, self.default_func);
+        self.set_name(id, Some(name));
+        id
+    }
+
+    // Names an existing node, or removes its name with `None`.
+    pub fn set_name(&mut self, node: NodeId, name: Option<&str>) -> &mut Self {
+        if let Some(node) = self.nodes.get_mut(node.0 as usize) {
+            node.name = name.map(|name| name.to_owned());
+        }
+        self
+    }
+
+    // First node with the given name.
+    pub fn find_node(&self, name: &str) -> Option<NodeId> {
+        self.nodes
+            .iter()
+            .position(|n| n.name.as_deref() == Some(name))
+            .map(|i| NodeId(i as u32))
+    }
+
+    // Names of the inputs and outputs, in the order of the values
+    // passed to and returned by `evaluate`.
+    pub fn input_names(&self) -> Vec<Option<&str>> {
+        self.node_names(NodeType::Input)
+    }
+
+    pub fn output_names(&self) -> Vec<Option<&str>> {
+        self.node_names(NodeType::Output)
+    }
+
+    fn node_names(&self, node_type: NodeType) -> Vec<Option<&str>> {
+        self.nodes
+            .iter()
+            .filter(|n| n.node_type == node_type)
+            .map(|n| n.name.as_deref())
+            .collect()
+    }
+
+    pub fn add_inputs(&mut self, n: u32) -> &mut Self {
+        (0..n).for_each(|_| {
+            self.add_input();
+        });
+        self
+    }
+
+    pub fn add_node(
+        &mut self,
+        node_type: NodeType,
+        func: ActivationFunction,
+    ) -> NodeId {
+        let id = self.next_node();
+        self.nodes.push(NodeTemplate {
+            node_type,
+            func,
+            aggregation: self.default_aggregation,
+            bias: 0.0,
+            response: 1.0,
+            memory: None,
+            name: None,
+        });
+        id
+    }
+
+    pub fn add_nodes(&mut self, node_type: NodeType, n: u32) -> &mut Self {
+        (0..n).for_each(|_| {
+            self.add_node(node_type, self.default_func);
+        });
+        self
+    }
+
+    pub fn add_connection(
+        &mut self,
+        origin: NodeId,
+        dest: NodeId,
+        weight: f32,
+        connection_type: ConnectionType,
+    ) -> &mut Self {
+        self.add_connection_raw(origin.0, dest.0, weight, connection_type)
+    }
+
+    // Takes the indices of the nodes in the order they were added,
+    // for nodes added by `add_inputs` or `add_nodes`, or indices
+    // computed elsewhere.
+    pub fn add_connection_raw(
+        &mut self,
+        origin: u32,
+        dest: u32,
+        weight: f32,
+        connection_type: ConnectionType,
     ) -> &mut Self {
         self.connections.push(ConnectionTemplate {
             origin,
             dest,
             weight,
             connection_type,
+            plasticity: None,
+            enabled: true,
         });
         self
     }
 
+    // Makes an existing node a memory cell, or a plain node again
+    // with `None`.  Input and bias nodes ignore this.
+    pub fn set_memory(
+        &mut self,
+        node: NodeId,
+        memory: Option<MemoryCell>,
+    ) -> &mut Self {
+        if let Some(node) = self.nodes.get_mut(node.0 as usize) {
+            node.memory = memory;
+        }
+        self
+    }
+
+    // Makes an existing connection plastic, or static again with
+    // `None`.
+    pub fn set_plasticity(
+        &mut self,
+        connection: usize,
+        plasticity: Option<HebbianRule>,
+    ) -> &mut Self {
+        if let Some(conn) = self.connections.get_mut(connection) {
+            conn.plasticity = plasticity;
+        }
+        self
+    }
+
+    // Leaves an existing connection out of the built network, or puts
+    // it back in.
+    pub fn set_enabled(
+        &mut self,
+        connection: usize,
+        enabled: bool,
+    ) -> &mut Self {
+        if let Some(conn) = self.connections.get_mut(connection) {
+            conn.enabled = enabled;
+        }
+        self
+    }
+
     pub fn add_normal_connection(
+        &mut self,
+        origin: NodeId,
+        dest: NodeId,
+        weight: f32,
+    ) -> &mut Self {
+        self.add_connection(origin, dest, weight, ConnectionType::Normal)
+    }
+
+    pub fn add_recurrent_connection(
+        &mut self,
+        origin: NodeId,
+        dest: NodeId,
+        weight: f32,
+    ) -> &mut Self {
+        self.add_connection(origin, dest, weight, ConnectionType::Recurrent)
+    }
+
+    pub fn add_normal_connection_raw(
         &mut self,
         origin: u32,
         dest: u32,
         weight: f32,
     ) -> &mut Self {
-        self.add_connection(origin, dest, weight, ConnectionType::Normal);
-        self
+        self.add_connection_raw(origin, dest, weight, ConnectionType::Normal)
     }
 
-    pub fn add_recurrent_connection(
+    pub fn add_recurrent_connection_raw(
         &mut self,
         origin: u32,
         dest: u32,
         weight: f32,
     ) -> &mut Self {
-        self.add_connection(origin, dest, weight, ConnectionType::Recurrent);
+        self.add_connection_raw(origin, dest, weight, ConnectionType::Recurrent)
+    }
+
+    // Number of values expected by `evaluate` of the built network.
+    pub fn num_inputs(&self) -> usize {
+        self.count_nodes(NodeType::Input)
+    }
+
+    // Number of values returned by `evaluate` of the built network.
+    pub fn num_outputs(&self) -> usize {
+        let num_outputs = self.count_nodes(NodeType::Output);
+        OutputMapping::num_mapped(&self.output_mapping, num_outputs)
+    }
+
+    // Whether both builders have the same nodes and connections, in
+    // the same order, with every weight, bias and response within
+    // `tolerance` of the other's.
+    pub fn approx_eq(&self, other: &Self, tolerance: f32) -> bool {
+        let close = |a: f32, b: f32| (a - b).abs() <= tolerance;
+        let nodes_match = self.nodes.len() == other.nodes.len()
+            && self.nodes.iter().zip(other.nodes.iter()).all(|(a, b)| {
+                a.node_type == b.node_type
+                    && a.func == b.func
+                    && a.aggregation == b.aggregation
+                    && close(a.bias, b.bias)
+                    && close(a.response, b.response)
+                    && a.memory == b.memory
+            });
+        let connections_match = self.connections.len()
+            == other.connections.len()
+            && self.connections.iter().zip(other.connections.iter()).all(
+                |(a, b)| {
+                    (a.origin, a.dest, a.connection_type)
+                        == (b.origin, b.dest, b.connection_type)
+                        && close(a.weight, b.weight)
+                        && a.plasticity == b.plasticity
+                        && a.enabled == b.enabled
+                },
+            );
+        nodes_match
+            && connections_match
+            && close(self.bias_value, other.bias_value)
+            && self.output_transform == other.output_transform
+            && self.propagation == other.propagation
+            && self.duplicate_policy == other.duplicate_policy
+            && self.weight_bounds == other.weight_bounds
+            && self.input_scaling == other.input_scaling
+            && self.output_mapping == other.output_mapping
+    }
+
+    // Hash of the structure of the network, which is the same on
+    // every platform and release, for deduplicating networks or
+    // caching their compiled backends.  Covers the nodes in order and
+    // the connections in any order, along with the parameters that
+    // aren't numbers, such as activation functions, and settings such
+    // as the propagation, duplicate policy and weight bounds.  With
+    // `include_weights`, the weights, biases, responses and other
+    // numeric parameters, including those of activation functions,
+    // are covered too.  Custom functions are hashed by the name they
+    // were registered under with `register_activation`, so unregistered
+    // ones all hash the same.
+    pub fn topology_hash(&self, include_weights: bool) -> u64 {
+        let mut hasher = Fnv1a::default();
+        // Writing to the hasher can't fail.
+        let mut write = |args: core::fmt::Arguments| {
+            let _ = core::fmt::Write::write_fmt(&mut hasher, args);
+        };
+        self.nodes.iter().for_each(|node| {
+            write(format_args!(
+                "n{},{},{},{};",
+                node.node_type.hash_name(),
+                node.func.hash_name(),
+                node.aggregation.hash_name(),
+                node.memory.is_some()
+            ));
+            if let ActivationFunction::Custom(_) = node.func {
+                if let Some(name) = node.func.name() {
+                    write(format_args!("{};", name));
+                }
+            }
+            if include_weights {
+                write(format_args!(
+                    "{:x},{:x},",
+                    node.bias.to_bits(),
+                    node.response.to_bits(),
+                ));
+                match node.memory {
+                    Some(memory) => write(format_args!(
+                        "m{};",
+                        HashBits(&[
+                            memory.update_gate.input_weight,
+                            memory.update_gate.memory_weight,
+                            memory.update_gate.bias,
+                            memory.reset_gate.input_weight,
+                            memory.reset_gate.memory_weight,
+                            memory.reset_gate.bias,
+                            memory.memory_weight,
+                        ])
+                    )),
+                    None => write(format_args!("None;")),
+                }
+                use ActivationFunction::*;
+                if let LeakyRelu(x) | Elu(x) | SteepenedSigmoid(x) = node.func {
+                    write(format_args!("f{:x};", x.to_bits()));
+                }
+            }
+        });
+
+        let mut connections = self
+            .connections
+            .iter()
+            .filter(|conn| conn.enabled)
+            .collect::<Vec<_>>();
+        connections.sort_by(|a, b| {
+            (a.origin, a.dest, a.connection_type)
+                .cmp(&(b.origin, b.dest, b.connection_type))
+                .then(a.weight.total_cmp(&b.weight))
+        });
+        connections.iter().for_each(|conn| {
+            write(format_args!(
+                "c{},{},{},{};",
+                conn.origin,
+                conn.dest,
+                conn.connection_type.hash_name(),
+                conn.plasticity.is_some()
+            ));
+            if include_weights {
+                write(format_args!("{:x},", conn.weight.to_bits()));
+                match conn.plasticity {
+                    Some(rule) => write(format_args!(
+                        "h{};",
+                        HashBits(&[
+                            rule.learning_rate,
+                            rule.a,
+                            rule.b,
+                            rule.c,
+                            rule.d,
+                        ])
+                    )),
+                    None => write(format_args!("None;")),
+                }
+            }
+        });
+
+        write(format_args!("o{};", self.output_transform.hash_name()));
+        self.output_mapping
+            .iter()
+            .for_each(|mapping| match mapping {
+                OutputMapping::Affine { scale, offset } => {
+                    write(format_args!("a;"));
+                    if include_weights {
+                        write(format_args!(
+                            "{:x},{:x};",
+                            scale.to_bits(),
+                            offset.to_bits()
+                        ));
+                    }
+                }
+                OutputMapping::Discrete(n) => write(format_args!("d{};", n)),
+            });
+
+        // The settings below are left out at their defaults.
+        match self.propagation {
+            Propagation::Passes(1) => {}
+            Propagation::Passes(n) => write(format_args!("p{};", n)),
+            Propagation::UntilStable {
+                epsilon,
+                max_passes,
+            } => {
+                write(format_args!("u{};", max_passes));
+                if include_weights {
+                    write(format_args!("{:x};", epsilon.to_bits()));
+                }
+            }
+        }
+        if self.duplicate_policy != DuplicatePolicy::default() {
+            write(format_args!("D{};", self.duplicate_policy.hash_name()));
+        }
+        if let Some(bounds) = self.weight_bounds {
+            write(format_args!("b{};", bounds.policy.hash_name()));
+            if include_weights {
+                write(format_args!("{};", HashBits(&[bounds.min, bounds.max])));
+            }
+        }
+
+        if include_weights {
+            write(format_args!("{:x};", self.bias_value.to_bits()));
+            self.input_scaling.iter().for_each(|scaling| {
+                let (scale, offset) = scaling.affine();
+                write(format_args!(
+                    "i{:x},{:x};",
+                    scale.to_bits(),
+                    offset.to_bits()
+                ));
+            });
+        }
+        hasher.0
+    }
+
+    pub fn num_hidden(&self) -> usize {
+        self.count_nodes(NodeType::Hidden)
+    }
+
+    // Network whose square `weight_matrix` has a row and a column per
+    // node, with the weight of the normal connection from node `i` to
+    // node `j` at `weight_matrix[i][j]`, and zero for no connection.
+    // The first `input_count` nodes are inputs, the next
+    // `output_count` are outputs, and the rest are hidden.  Nodes get
+    // the default activation function, which can be changed through
+    // `nodes` afterwards.
+    pub fn from_matrices(
+        input_count: u32,
+        output_count: u32,
+        weight_matrix: &[Vec<f32>],
+    ) -> Result<Self, Error> {
+        let size = weight_matrix.len();
+        // Counts too large for the matrix, or to add up, are invalid
+        // rather than overflowing.
+        let num_io = (input_count as usize).checked_add(output_count as usize);
+        let num_io = match num_io {
+            Some(num_io) if num_io <= size && size <= u32::MAX as usize => {
+                num_io
+            }
+            _ => return Err(Error::InvalidWeightMatrix { row: size }),
+        };
+        if let Some(row) =
+            weight_matrix.iter().position(|row| row.len() != size)
+        {
+            return Err(Error::InvalidWeightMatrix { row });
+        }
+
+        let mut builder = Self::new();
+        builder
+            .add_inputs(input_count)
+            .add_nodes(NodeType::Output, output_count)
+            .add_nodes(NodeType::Hidden, (size - num_io) as u32);
+        weight_matrix.iter().enumerate().for_each(|(i, row)| {
+            row.iter()
+                .enumerate()
+                .filter(|(_j, w)| **w != 0.0)
+                .for_each(|(j, w)| {
+                    builder.add_normal_connection_raw(i as u32, j as u32, *w);
+                });
+        });
+        Ok(builder)
+    }
+
+    // Reverse of `from_matrices`, indexed by the nodes in builder
+    // order.  Recurrent and disabled connections are left out, and
+    // the weights of parallel connections are summed.
+    pub fn to_weight_matrix(&self) -> Vec<Vec<f32>> {
+        let size = self.nodes.len();
+        let mut matrix = vec![vec![0.0; size]; size];
+        self.connections
+            .iter()
+            .filter(|conn| {
+                conn.enabled && conn.connection_type == ConnectionType::Normal
+            })
+            .for_each(|conn| {
+                if let Some(w) = matrix
+                    .get_mut(conn.origin as usize)
+                    .and_then(|row| row.get_mut(conn.dest as usize))
+                {
+                    *w += conn.weight;
+                }
+            });
+        matrix
+    }
+
+    // Gives every connection the same weight, for evaluating a
+    // topology independently of its weights.
+    pub fn set_shared_weight(&mut self, weight: f32) -> &mut Self {
+        self.connections
+            .iter_mut()
+            .for_each(|conn| conn.weight = weight);
         self
     }
 
-    pub fn build<N>(&mut self) -> Result<N, Error>
+    fn count_nodes(&self, node_type: NodeType) -> usize {
+        self.nodes
+            .iter()
+            .filter(|n| n.node_type == node_type)
+            .count()
+    }
+
+    // Every backend calls this before building, so that a connection
+    // to a nonexistent node is reported instead of panicking during
+    // evaluation.
+    pub fn check_node_indices(&self) -> Result<(), Error> {
+        let num_nodes = self.nodes.len();
+        self.connections.iter().enumerate().try_for_each(
+            |(connection, conn)| {
+                [conn.origin, conn.dest].iter().try_for_each(|index| {
+                    if (*index as usize) < num_nodes {
+                        Ok(())
+                    } else {
+                        Err(Error::InvalidNodeIndex {
+                            connection,
+                            index: *index,
+                        })
+                    }
+                })
+            },
+        )
+    }
+
+    // Every backend builds from the result of this, which drops
+    // disabled connections, then applies the duplicate policy and the
+    // weight bounds.  Errors refer to the connections of `self`.
+    pub(crate) fn resolve_connections(&self) -> Result<Resolved<'_>, Error> {
+        let mut resolved = Resolved {
+            builder: Cow::Borrowed(self),
+            slots: ConnectionSlots(
+                self.connections
+                    .iter()
+                    .scan(0, |next, conn| {
+                        let slot = conn.enabled.then_some(*next);
+                        *next += conn.enabled as usize;
+                        Some(slot)
+                    })
+                    .collect(),
+            ),
+            origins: (0..self.connections.len())
+                .filter(|i| self.connections[*i].enabled)
+                .collect(),
+        };
+        if resolved.origins.len() < self.connections.len() {
+            let builder = resolved.builder.to_mut();
+            builder.connections.retain(|conn| conn.enabled);
+        }
+        let merged = resolved
+            .builder
+            .merge_duplicates()
+            .map_err(|error| resolved.original_error(error))?;
+        if let Some((merged, merged_index)) = merged {
+            resolved.slots.0.iter_mut().flatten().for_each(|slot| {
+                *slot = merged_index[*slot];
+            });
+            let mut origins = vec![0; merged.len()];
+            (0..merged_index.len()).rev().for_each(|i| {
+                origins[merged_index[i]] = resolved.origins[i];
+            });
+            resolved.origins = origins;
+            resolved.builder.to_mut().connections = merged;
+        }
+        if let Some(bounds) = self.weight_bounds {
+            if !bounds.is_valid() {
+                return Err(Error::InvalidWeightBounds {
+                    min: bounds.min,
+                    max: bounds.max,
+                });
+            }
+            let in_bounds =
+                |conn: &ConnectionTemplate| bounds.contains(conn.weight);
+            if !resolved.connections.iter().all(in_bounds) {
+                let origins = &resolved.origins;
+                resolved
+                    .builder
+                    .to_mut()
+                    .connections
+                    .iter_mut()
+                    .enumerate()
+                    .try_for_each(|(slot, conn)| {
+                        conn.weight = bounds.apply(conn.weight).ok_or(
+                            Error::WeightOutOfBounds {
+                                connection: origins[slot],
+                                weight: conn.weight,
+                            },
+                        )?;
+                        Ok(())
+                    })?;
+            }
+        }
+        Ok(resolved)
+    }
+
+    // Connections with the duplicates merged, along with the index in
+    // the merged connections of each connection, or `None` if there
+    // are none to merge.
+    #[allow(clippy::type_complexity)]
+    fn merge_duplicates(
+        &self,
+    ) -> Result<Option<(Vec<ConnectionTemplate>, Vec<usize>)>, Error> {
+        if self.duplicate_policy == DuplicatePolicy::Allow {
+            return Ok(None);
+        }
+        // Index of the first copy of each connection, both here and in
+        // the merged connections.
+        let mut first_of = BTreeMap::new();
+        let mut merged = Vec::<ConnectionTemplate>::new();
+        let mut merged_index = Vec::with_capacity(self.connections.len());
+        self.connections
+            .iter()
+            .enumerate()
+            .try_for_each(|(i, conn)| {
+                let key = (conn.origin, conn.dest, conn.connection_type);
+                match first_of.entry(key) {
+                    Entry::Vacant(entry) => {
+                        entry.insert((i, merged.len()));
+                        merged_index.push(merged.len());
+                        merged.push(*conn);
+                    }
+                    Entry::Occupied(entry) => {
+                        let (first, index) = *entry.get();
+                        if self.duplicate_policy == DuplicatePolicy::Reject {
+                            return Err(Error::DuplicateConnection {
+                                connection: i,
+                                first,
+                            });
+                        }
+                        merged_index.push(index);
+                        merged[index].weight += conn.weight;
+                    }
+                }
+                Ok(())
+            })?;
+        match merged.len() == self.connections.len() {
+            true => Ok(None),
+            false => Ok(Some((merged, merged_index))),
+        }
+    }
+
+    // Backends that can't carry values between evaluations call this
+    // before building, which rules out plastic weights and memory
+    // cells too.
+    pub(crate) fn check_feed_forward(&self) -> Result<(), Error> {
+        let is_memory = |node: &NodeTemplate| {
+            node.memory.is_some()
+                && !matches!(node.node_type, NodeType::Input | NodeType::Bias)
+        };
+        if let Some(node) = self.nodes.iter().position(is_memory) {
+            return Err(Error::UnsupportedMemoryCell { node });
+        }
+        self.connections.iter().enumerate().try_for_each(
+            |(connection, conn)| {
+                if conn.connection_type == ConnectionType::Recurrent {
+                    Err(Error::UnsupportedRecurrentConnection { connection })
+                } else if conn.plasticity.is_some() {
+                    Err(Error::UnsupportedPlasticConnection { connection })
+                } else {
+                    Ok(())
+                }
+            },
+        )
+    }
+
+    // Backends that can only sum the incoming values of a node call
+    // this before building.
+    pub(crate) fn check_sum_aggregation(&self) -> Result<(), Error> {
+        match self
+            .nodes
+            .iter()
+            .find(|node| node.aggregation != AggregationFunction::Sum)
+        {
+            Some(node) => Err(Error::UnsupportedAggregationFunction {
+                aggregation: node.aggregation,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    pub fn build<N>(&self) -> Result<N, Error>
     where
         N: NeuralNet,
     {
         N::build_from(self)
     }
+
+    // Removes disabled connections, and hidden nodes with no path to
+    // an output along with their connections, as `Genome::prune`
+    // does.  The remaining nodes keep their order, and the built
+    // network computes the same outputs.
+    pub fn prune(&mut self) -> &mut Self {
+        let mut useful = self
+            .nodes
+            .iter()
+            .map(|n| n.node_type != NodeType::Hidden)
+            .collect::<Vec<_>>();
+        let mut reaches_output = self
+            .nodes
+            .iter()
+            .map(|n| n.node_type == NodeType::Output)
+            .collect::<Vec<_>>();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            self.connections
+                .iter()
+                .filter(|c| c.enabled)
+                .for_each(|conn| {
+                    let (origin, dest) =
+                        (conn.origin as usize, conn.dest as usize);
+                    if reaches_output.get(dest) == Some(&true)
+                        && reaches_output.get(origin) == Some(&false)
+                    {
+                        reaches_output[origin] = true;
+                        changed = true;
+                    }
+                });
+        }
+        useful
+            .iter_mut()
+            .zip(reaches_output.iter())
+            .for_each(|(u, r)| *u |= r);
+
+        let mut new_index = Vec::with_capacity(self.nodes.len());
+        let mut num_kept = 0;
+        useful.iter().for_each(|keep| {
+            new_index.push(num_kept);
+            num_kept += *keep as u32;
+        });
+        let mut i = 0;
+        self.nodes.retain(|_| {
+            i += 1;
+            useful[i - 1]
+        });
+        self.connections.retain(|conn| {
+            conn.enabled
+                && reaches_output.get(conn.dest as usize) == Some(&true)
+                && useful.get(conn.origin as usize) == Some(&true)
+        });
+        self.connections.iter_mut().for_each(|conn| {
+            conn.origin = new_index[conn.origin as usize];
+            conn.dest = new_index[conn.dest as usize];
+        });
+        self
+    }
+
+    // All nodes with a path of normal connections into `node`.
+    // Recurrent connections are excluded, as they only carry values
+    // from the previous evaluation.
+    pub fn upstream_nodes(&self, node: u32) -> BTreeSet<u32> {
+        self.reachable_from(node, |conn| conn.dest, |conn| conn.origin)
+    }
+
+    // Breadth-first search along normal connections, starting at
+    // `start`.  `from` and `to` select which end of a connection
+    // must match the current node, and which end is visited next,
+    // so the same search can walk the graph in either direction.
+    fn reachable_from(
+        &self,
+        start: u32,
+        from: impl Fn(&ConnectionTemplate) -> u32,
+        to: impl Fn(&ConnectionTemplate) -> u32,
+    ) -> BTreeSet<u32> {
+        let mut visited = BTreeSet::new();
+        let mut queue = VecDeque::new();
+        queue.push_back(start);
+
+        while let Some(current) = queue.pop_front() {
+            self.connections
+                .iter()
+                .filter(|conn| {
+                    conn.enabled
+                        && conn.connection_type == ConnectionType::Normal
+                })
+                .filter(|conn| from(conn) == current)
+                .for_each(|conn| {
+                    let next = to(conn);
+                    if visited.insert(next) {
+                        queue.push_back(next);
+                    }
+                });
+        }
+
+        visited
+    }
 }
 
-#[derive(Debug)]
+// Consuming versions of the methods above, so that a builder can be
+// made in a single expression and returned from a function.  Nodes
+// added this way have no ids to hand back, so connections take raw
+// indices.
+impl NeuralNetBuilder {
+    pub fn with_bias_value(mut self, value: f32) -> Self {
+        self.set_bias_value(value);
+        self
+    }
+
+    pub fn with_output_transform(mut self, transform: OutputTransform) -> Self {
+        self.set_output_transform(transform);
+        self
+    }
+
+    pub fn with_input_scaling(mut self, scaling: Vec<InputScaling>) -> Self {
+        self.set_input_scaling(scaling);
+        self
+    }
+
+    pub fn with_output_mapping(mut self, mapping: Vec<OutputMapping>) -> Self {
+        self.set_output_mapping(mapping);
+        self
+    }
+
+    pub fn with_propagation(mut self, propagation: Propagation) -> Self {
+        self.set_propagation(propagation);
+        self
+    }
+
+    pub fn with_duplicate_policy(mut self, policy: DuplicatePolicy) -> Self {
+        self.set_duplicate_policy(policy);
+        self
+    }
+
+    pub fn with_weight_bounds(mut self, bounds: Option<WeightBounds>) -> Self {
+        self.set_weight_bounds(bounds);
+        self
+    }
+
+    pub fn with_default_activation(mut self, func: ActivationFunction) -> Self {
+        self.set_default_activation(func);
+        self
+    }
+
+    pub fn with_default_aggregation(
+        mut self,
+        aggregation: AggregationFunction,
+    ) -> Self {
+        self.set_default_aggregation(aggregation);
+        self
+    }
+
+    pub fn with_inputs(mut self, n: u32) -> Self {
+        self.add_inputs(n);
+        self
+    }
+
+    pub fn with_bias(mut self) -> Self {
+        self.add_bias();
+        self
+    }
+
+    pub fn with_node(
+        mut self,
+        node_type: NodeType,
+        func: ActivationFunction,
+    ) -> Self {
+        self.add_node(node_type, func);
+        self
+    }
+
+    pub fn with_nodes(mut self, node_type: NodeType, n: u32) -> Self {
+        self.add_nodes(node_type, n);
+        self
+    }
+
+    pub fn with_connection_raw(
+        mut self,
+        origin: u32,
+        dest: u32,
+        weight: f32,
+        connection_type: ConnectionType,
+    ) -> Self {
+        self.add_connection_raw(origin, dest, weight, connection_type);
+        self
+    }
+
+    pub fn with_normal_connection_raw(
+        mut self,
+        origin: u32,
+        dest: u32,
+        weight: f32,
+    ) -> Self {
+        self.add_normal_connection_raw(origin, dest, weight);
+        self
+    }
+
+    pub fn with_recurrent_connection_raw(
+        mut self,
+        origin: u32,
+        dest: u32,
+        weight: f32,
+    ) -> Self {
+        self.add_recurrent_connection_raw(origin, dest, weight);
+        self
+    }
+
+    pub fn with_memory(
+        mut self,
+        node: NodeId,
+        memory: Option<MemoryCell>,
+    ) -> Self {
+        self.set_memory(node, memory);
+        self
+    }
+
+    pub fn with_plasticity(
+        mut self,
+        connection: usize,
+        plasticity: Option<HebbianRule>,
+    ) -> Self {
+        self.set_plasticity(connection, plasticity);
+        self
+    }
+
+    pub fn with_shared_weight(mut self, weight: f32) -> Self {
+        self.set_shared_weight(weight);
+        self
+    }
+
+    pub fn with_enabled(mut self, connection: usize, enabled: bool) -> Self {
+        self.set_enabled(connection, enabled);
+        self
+    }
+
+    pub fn pruned(mut self) -> Self {
+        self.prune();
+        self
+    }
+}
+
+// A builder as backends build from it, from
+// `NeuralNetBuilder::resolve_connections`, which dereferences to the
+// resolved builder.
+pub(crate) struct Resolved<'a> {
+    pub(crate) builder: Cow<'a, NeuralNetBuilder>,
+    pub(crate) slots: ConnectionSlots,
+    // Index in the original builder of each resolved connection, or
+    // of the first copy of merged duplicates.
+    origins: Vec<usize>,
+}
+
+impl core::ops::Deref for Resolved<'_> {
+    type Target = NeuralNetBuilder;
+
+    fn deref(&self) -> &NeuralNetBuilder {
+        &self.builder
+    }
+}
+
+impl Resolved<'_> {
+    // `error`, raised while building from the resolved builder, with
+    // its connection indices changed to those of the original.
+    pub(crate) fn original_error(&self, error: Error) -> Error {
+        let original = |connection: usize| {
+            self.origins.get(connection).copied().unwrap_or(connection)
+        };
+        use Error::*;
+        match error {
+            ConnectionLoop { cycle } => ConnectionLoop {
+                cycle: cycle.into_iter().map(original).collect(),
+            },
+            DuplicateConnection { connection, first } => DuplicateConnection {
+                connection: original(connection),
+                first: original(first),
+            },
+            WeightOutOfBounds { connection, weight } => WeightOutOfBounds {
+                connection: original(connection),
+                weight,
+            },
+            UnsupportedRecurrentConnection { connection } => {
+                UnsupportedRecurrentConnection {
+                    connection: original(connection),
+                }
+            }
+            UnsupportedPlasticConnection { connection } => {
+                UnsupportedPlasticConnection {
+                    connection: original(connection),
+                }
+            }
+            InvalidNodeIndex { connection, index } => InvalidNodeIndex {
+                connection: original(connection),
+                index,
+            },
+            error => error,
+        }
+    }
+}
+
+// Position among the resolved connections of each connection of the
+// builder a network was built from, so that backends can take
+// connections by their builder index.  Disabled connections have
+// none, and merged duplicates share the position of their sum.
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub(crate) struct ConnectionSlots(pub(crate) Vec<Option<usize>>);
+
+impl ConnectionSlots {
+    pub(crate) fn get(&self, connection: usize) -> Result<usize, Error> {
+        self.0
+            .get(connection)
+            .copied()
+            .flatten()
+            .ok_or(Error::InvalidConnectionIndex { index: connection })
+    }
+
+    // Where each connection of the builder went, given the position
+    // of each resolved connection in a backend's own storage.
+    pub(crate) fn through(&self, positions: &[usize]) -> Self {
+        ConnectionSlots(
+            self.0
+                .iter()
+                .map(|slot| slot.map(|slot| positions[slot]))
+                .collect(),
+        )
+    }
+
+    // Slots of a network built from connections that are all enabled
+    // and distinct.
+    pub(crate) fn identity(num_connections: usize) -> Self {
+        ConnectionSlots((0..num_connections).map(Some).collect())
+    }
+}
+
+// Connections are identified by their index in
+// `NeuralNetBuilder::connections`.
+#[derive(Debug, PartialEq, Clone)]
 pub enum Error {
-    ConnectionLoop,
-    InvalidConnectionIndex,
+    // Normal connections that form a loop, each ending at the origin
+    // of the next, with the last ending at the origin of the first.
+    ConnectionLoop { cycle: Vec<usize> },
+    InvalidConnectionIndex { index: usize },
+    // Same origin, destination and type as the `first` connection,
+    // with `DuplicatePolicy::Reject`.
+    DuplicateConnection { connection: usize, first: usize },
+    // Weight outside of the builder's weight bounds, with
+    // `BoundsPolicy::Reject`.
+    WeightOutOfBounds { connection: usize, weight: f32 },
+    // Weight bounds with `min` above `max`, or either NaN.
+    InvalidWeightBounds { min: f32, max: f32 },
+    UnsupportedRecurrentConnection { connection: usize },
+    UnsupportedPlasticConnection { connection: usize },
+    UnsupportedMemoryCell { node: usize },
+    UnsupportedOutputTransform { transform: OutputTransform },
+    // Row of a weight matrix with the wrong number of columns, or the
+    // number of rows if there are too few for the inputs and outputs.
+    InvalidWeightMatrix { row: usize },
+    // Dense layer whose weights or biases don't match the size of the
+    // layer before it or its own number of nodes.
+    InvalidDenseLayer { layer: usize },
+    UnsupportedActivationFunction { func: ActivationFunction },
+    UnsupportedAggregationFunction { aggregation: AggregationFunction },
+    // Line of a NEAT genome file that couldn't be read.
+    InvalidNeatFormat { line: usize },
+    // Line of a NEAT-Python config file that couldn't be read.
+    InvalidNeatPythonConfig { line: usize },
+    // JSON genome or network that couldn't be read.
+    InvalidJson { message: String },
+    // JSON written by a later version of the format.
+    UnsupportedJsonVersion { version: u32 },
+    InvalidNodeIndex { connection: usize, index: u32 },
+    InputSizeMismatch { expected: usize, got: usize },
+    UnknownInputName { name: String },
+    // Input, by its position among the inputs, that wasn't given a
+    // value.
+    MissingInput { index: usize },
+    // No GPU adapter or device could be had.  Only returned with the
+    // `gpu` feature, but always declared so that matching on errors
+    // doesn't depend on the features.
+    GpuUnavailable,
+}
+
+impl core::fmt::Display for Error {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        use Error::*;
+        match self {
+            ConnectionLoop { cycle } => {
+                write!(f, "Normal connections form a loop: {:?}", cycle)
+            }
+            InvalidConnectionIndex { index } => {
+                write!(f, "No connection with index {}", index)
+            }
+            DuplicateConnection { connection, first } => write!(
+                f,
+                "Connection {} duplicates connection {}",
+                connection, first
+            ),
+            WeightOutOfBounds { connection, weight } => write!(
+                f,
+                "Connection {} has weight {}, outside of the weight bounds",
+                connection, weight
+            ),
+            InvalidWeightBounds { min, max } => {
+                write!(f, "Invalid weight bounds from {} to {}", min, max)
+            }
+            UnsupportedRecurrentConnection { connection } => write!(
+                f,
+                "Connection {} is recurrent, which this network type \
+                 doesn't support",
+                connection
+            ),
+            UnsupportedPlasticConnection { connection } => write!(
+                f,
+                "Connection {} is plastic, which this network type \
+                 doesn't support",
+                connection
+            ),
+            UnsupportedMemoryCell { node } => write!(
+                f,
+                "Node {} is a memory cell, which this network type \
+                 doesn't support",
+                node
+            ),
+            UnsupportedOutputTransform { transform } => write!(
+                f,
+                "Output transform {:?} isn't supported by this network \
+                 type",
+                transform
+            ),
+            UnsupportedActivationFunction { func } => write!(
+                f,
+                "Activation function {:?} isn't supported by this \
+                 network type",
+                func
+            ),
+            UnsupportedAggregationFunction { aggregation } => write!(
+                f,
+                "Aggregation function {:?} isn't supported by this \
+                 network type",
+                aggregation
+            ),
+            InvalidNeatFormat { line } => {
+                write!(f, "Invalid NEAT genome on line {}", line)
+            }
+            InvalidWeightMatrix { row } => {
+                write!(f, "Weight matrix isn't square at row {}", row)
+            }
+            InvalidDenseLayer { layer } => {
+                write!(f, "Dense layer {} has the wrong shape", layer)
+            }
+            InvalidNeatPythonConfig { line } => {
+                write!(f, "Invalid NEAT-Python config on line {}", line)
+            }
+            InvalidJson { message } => write!(f, "Invalid JSON: {}", message),
+            UnsupportedJsonVersion { version } => {
+                write!(f, "Unsupported JSON format version {}", version)
+            }
+            InvalidNodeIndex { connection, index } => write!(
+                f,
+                "Connection {} refers to node {}, which doesn't exist",
+                connection, index
+            ),
+            InputSizeMismatch { expected, got } => {
+                write!(f, "Expected {} inputs, got {}", expected, got)
+            }
+            UnknownInputName { name } => {
+                write!(f, "No input is named {}", name)
+            }
+            MissingInput { index } => {
+                write!(f, "No value was given for input {}", index)
+            }
+            GpuUnavailable => write!(f, "No GPU adapter is available"),
+        }
+    }
+}
+
+// `core::error::Error` would need Rust 1.81, so without `std` the
+// error only implements `Display`.
+#[cfg(feature = "std")]
+impl std::error::Error for Error {}
+
+// A loop of normal connections, if there is one.  Any loop found is
+// shortened to the shortest loop through its first node, since a
+// depth-first search may wander through much of a large network
+// before closing a loop.
+pub(crate) fn find_connection_loop(
+    connections: &[ConnectionTemplate],
+) -> Option<Vec<usize>> {
+    let outgoing = normal_outgoing(connections);
+    let found = find_any_loop(connections, &outgoing)?;
+    let start = connections[found[0]].origin;
+    Some(shortest_loop_through(connections, &outgoing, start).unwrap_or(found))
+}
+
+fn normal_outgoing(
+    connections: &[ConnectionTemplate],
+) -> BTreeMap<u32, Vec<usize>> {
+    let mut outgoing = BTreeMap::<u32, Vec<usize>>::new();
+    connections
+        .iter()
+        .enumerate()
+        .filter(|(_i, conn)| conn.connection_type == ConnectionType::Normal)
+        .for_each(|(i, conn)| outgoing.entry(conn.origin).or_default().push(i));
+    outgoing
+}
+
+// Breadth-first search from `start`, following connections until one
+// leads back to `start`.
+fn shortest_loop_through(
+    connections: &[ConnectionTemplate],
+    outgoing: &BTreeMap<u32, Vec<usize>>,
+    start: u32,
+) -> Option<Vec<usize>> {
+    // Connection through which each node was first reached.
+    let mut reached_by = BTreeMap::<u32, usize>::new();
+    let mut queue = VecDeque::new();
+    queue.push_back(start);
+
+    while let Some(node) = queue.pop_front() {
+        for conn in outgoing.get(&node).into_iter().flatten() {
+            let dest = connections[*conn].dest;
+            if dest == start {
+                let mut cycle = vec![*conn];
+                let mut current = node;
+                while current != start {
+                    let prev = reached_by[&current];
+                    cycle.push(prev);
+                    current = connections[prev].origin;
+                }
+                cycle.reverse();
+                return Some(cycle);
+            }
+            if let Entry::Vacant(entry) = reached_by.entry(dest) {
+                entry.insert(*conn);
+                queue.push_back(dest);
+            }
+        }
+    }
+
+    None
+}
+
+// Depth-first search, which reaches a loop when it meets a node that
+// is still on its stack.
+fn find_any_loop(
+    connections: &[ConnectionTemplate],
+    outgoing: &BTreeMap<u32, Vec<usize>>,
+) -> Option<Vec<usize>> {
+    let mut starts = outgoing.keys().cloned().collect::<Vec<_>>();
+    starts.sort_unstable();
+
+    // Whether each visited node has been fully explored, as opposed
+    // to still being on the stack.
+    let mut finished = BTreeMap::new();
+    for start in starts {
+        if finished.contains_key(&start) {
+            continue;
+        }
+
+        // Each entry holds a node and the number of its outgoing
+        // connections followed so far.  `path[i]` is the connection
+        // that led from `stack[i]` to `stack[i + 1]`.
+        let mut stack = vec![(start, 0)];
+        let mut path = Vec::new();
+        finished.insert(start, false);
+
+        while let Some((node, followed)) = stack.last_mut() {
+            let edges = outgoing.get(node).map_or(&[][..], |e| &e[..]);
+            match edges.get(*followed) {
+                Some(conn) => {
+                    *followed += 1;
+                    let dest = connections[*conn].dest;
+                    match finished.get(&dest) {
+                        Some(false) => {
+                            let pos = stack
+                                .iter()
+                                .position(|(n, _)| *n == dest)
+                                .unwrap_or(0);
+                            let mut cycle = path[pos..].to_vec();
+                            cycle.push(*conn);
+                            return Some(cycle);
+                        }
+                        Some(true) => {}
+                        None => {
+                            finished.insert(dest, false);
+                            stack.push((dest, 0));
+                            path.push(*conn);
+                        }
+                    }
+                }
+                None => {
+                    finished.insert(*node, true);
+                    stack.pop();
+                    path.pop();
+                }
+            }
+        }
+    }
+
+    None
 }
 
 pub trait NeuralNet: Sized {
     fn build_from(builder: &NeuralNetBuilder) -> Result<Self, Error>;
+    fn num_inputs(&self) -> usize;
+    fn num_outputs(&self) -> usize;
+
+    // Input nodes without a value are treated as zero, and extra
+    // values are ignored.  Inputs are then scaled as set by
+    // `NeuralNetBuilder::set_input_scaling`, and the outputs mapped
+    // as set by `NeuralNetBuilder::set_output_mapping`.
     fn evaluate(&mut self, inputs: &[f32]) -> Vec<f32>;
+
+    // Same as `evaluate`, but requires exactly one value per input
+    // node.
+    fn try_evaluate(&mut self, inputs: &[f32]) -> Result<Vec<f32>, Error> {
+        let expected = self.num_inputs();
+        if inputs.len() == expected {
+            Ok(self.evaluate(inputs))
+        } else {
+            Err(Error::InputSizeMismatch {
+                expected,
+                got: inputs.len(),
+            })
+        }
+    }
+
+    // Same as `evaluate`, but writes the outputs into a buffer
+    // provided by the caller.  If the buffer is shorter than the
+    // number of outputs, the extra outputs are dropped.
+    fn evaluate_into(&mut self, inputs: &[f32], outputs: &mut [f32]) {
+        outputs
+            .iter_mut()
+            .zip(self.evaluate(inputs))
+            .for_each(|(out, x)| *out = x);
+    }
+
+    // Evaluates each input vector in order, as if by repeated calls
+    // to `evaluate`.  Backends may override this to reuse work
+    // across the batch.
+    fn evaluate_batch(&mut self, inputs: &[Vec<f32>]) -> Vec<Vec<f32>> {
+        inputs.iter().map(|x| self.evaluate(x)).collect()
+    }
+
+    // Forgets the activations carried by recurrent connections, as
+    // at the start of an episode.  Feed-forward backends keep nothing
+    // from one evaluation to the next.
+    fn reset_state(&mut self) {}
+
+    // Evaluates a time series as one episode, starting from a reset
+    // state, and returns the outputs of each timestep.
+    fn evaluate_sequence(&mut self, inputs: &[Vec<f32>]) -> Vec<Vec<f32>> {
+        self.evaluate_sequence_with_passes(inputs, 1)
+    }
+
+    // Same as `evaluate_sequence`, but evaluates each timestep
+    // `passes` times with the same inputs, keeping the outputs of the
+    // last pass, so that values can travel along several recurrent
+    // connections within one timestep.  Zero passes count as one.
+    fn evaluate_sequence_with_passes(
+        &mut self,
+        inputs: &[Vec<f32>],
+        passes: usize,
+    ) -> Vec<Vec<f32>> {
+        self.reset_state();
+        if passes <= 1 {
+            return self.evaluate_batch(inputs);
+        }
+        inputs
+            .iter()
+            .map(|x| {
+                (1..passes).for_each(|_| {
+                    self.evaluate(x);
+                });
+                self.evaluate(x)
+            })
+            .collect()
+    }
+
+    // Changes the weight of a connection without rebuilding the
+    // network.  Connections are identified by their index in the
+    // builder the network was built from.  Disabled connections
+    // aren't part of the network, so are reported as invalid, and
+    // either of two merged duplicates sets the weight of both
+    // together.
+    fn set_weight(
+        &mut self,
+        connection: usize,
+        weight: f32,
+    ) -> Result<(), Error>;
 }
 
 #[cfg(test)]
@@ -182,5 +2615,647 @@ mod test {
         let func = ActivationFunction::Sigmoid;
         assert!((func.apply(-1.0) - 0.26894).abs() < 1e-4);
         assert!((func.apply(1.0) - 0.73105).abs() < 1e-4);
+
+        use ActivationFunction::*;
+        assert_eq!(LeakyRelu(0.1).apply(-2.0), -0.2);
+        assert_eq!(LeakyRelu(0.1).apply(3.0), 3.0);
+        assert!((Elu(2.0).apply(-1.0) + 1.26424).abs() < 1e-4);
+        assert_eq!(SteepenedSigmoid(2.0).apply(0.5), Sigmoid.apply(1.0));
+        assert_eq!(Custom(|x| x + 1.0).apply(1.0), 2.0);
+
+        assert_eq!(Step.apply(0.0), 0.0);
+        assert_eq!(Step.apply(0.1), 1.0);
+        assert!((Softplus.apply(0.0) - 2f32.ln()).abs() < 1e-6);
+        assert_eq!(Softplus.apply(100.0), 100.0);
+        assert_eq!(Sinc.apply(0.0), 1.0);
+        assert_eq!(Clamped.apply(-3.0), -1.0);
+        assert_eq!(Clamped.apply(0.5), 0.5);
+        assert_eq!(Inverse.apply(4.0), 0.25);
+        assert_eq!(Inverse.apply(0.0), 0.0);
+        assert_eq!(Log.apply(1.0), 0.0);
+        assert!(Log.apply(-1.0).is_finite());
+    }
+
+    #[test]
+    fn test_activation_derivative() {
+        use ActivationFunction::*;
+        let funcs = [
+            Sigmoid,
+            Identity,
+            Tanh,
+            Relu,
+            Gaussian,
+            Sin,
+            Cos,
+            Abs,
+            Square,
+            LeakyRelu(0.1),
+            Elu(2.0),
+            SteepenedSigmoid(4.924273),
+            LeakyRelu(-0.5),
+            Step,
+            Softplus,
+            Sinc,
+            Clamped,
+            Inverse,
+        ];
+        // Away from zero, where some slopes jump.
+        let xs = [-2.5, -0.7, 0.3, 1.9];
+        funcs.iter().for_each(|func| {
+            xs.iter().for_each(|x| {
+                let exact = func.derivative(*x).unwrap();
+                let numerical = func.numerical_derivative(*x);
+                assert!((exact - numerical).abs() < 1e-2, "{:?}", func);
+                if let Some(slope) = func.derivative_from_output(func.apply(*x))
+                {
+                    assert!((exact - slope).abs() < 1e-5, "{:?}", func);
+                }
+            })
+        });
+        assert_eq!(Gaussian.derivative_from_output(0.5), None);
+        assert_eq!(LeakyRelu(-0.5).derivative_from_output(1.0), None);
+
+        let cube = Custom(|x| x * x * x);
+        assert_eq!(cube.derivative(2.0), None);
+        assert!((cube.numerical_derivative(2.0) - 12.0).abs() < 1e-2);
+    }
+
+    #[test]
+    fn test_apply_slice() {
+        use ActivationFunction::*;
+        let funcs = [
+            Sigmoid,
+            Identity,
+            Tanh,
+            Relu,
+            Gaussian,
+            Sin,
+            Abs,
+            LeakyRelu(0.1),
+            Elu(2.0),
+            SteepenedSigmoid(4.924273),
+            Step,
+            Softplus,
+            Sinc,
+            Clamped,
+            Inverse,
+            Log,
+            Custom(|x| x + 1.0),
+        ];
+        // Long enough for a partial chunk of 8 at the end, with values
+        // far enough out to saturate exponentials.
+        let xs = (0..21)
+            .map(|i| i as f32 * 0.4 - 4.0)
+            .chain([-200.0, -100.0, -30.0, 30.0, 100.0, 200.0])
+            .collect::<Vec<_>>();
+        funcs.iter().for_each(|func| {
+            let mut ys = xs.clone();
+            func.apply_slice(&mut ys);
+            xs.iter().zip(&ys).for_each(|(x, y)| {
+                let expected = func.apply(*x);
+                let tolerance = 1e-5 * (1.0 + expected.abs());
+                assert!((expected - y).abs() < tolerance, "{:?} {}", func, x);
+            });
+        });
+
+        (0..200).map(|i| i as f32 * 0.5 - 50.0).for_each(|x| {
+            let relative = (fast_exp(x) - x.exp()).abs() / x.exp();
+            assert!(relative < 1e-5, "{}", x);
+            assert!((fast_tanh(x) - x.tanh()).abs() < 1e-5, "{}", x);
+        });
+        assert_eq!(fast_exp(0.0), 1.0);
+        assert!(fast_exp(f32::NAN).is_nan());
+    }
+
+    #[test]
+    fn test_aggregation_function() {
+        use AggregationFunction::*;
+        let values = [2.0, -3.0, 0.5];
+        let results = [Sum, Product, Min, Max, Mean, AbsSum]
+            .iter()
+            .map(|a| a.aggregate(values.iter().cloned()))
+            .collect::<Vec<_>>();
+        assert_eq!(results, vec![-0.5, -3.0, -3.0, 2.0, -0.5 / 3.0, 5.5]);
+        assert_eq!(Product.aggregate(std::iter::empty()), 0.0);
+    }
+
+    #[test]
+    fn test_output_transform() -> Result<(), Error> {
+        let mut outputs = [1.0, 3.0, 3.0, -2.0];
+        OutputTransform::ArgMax.apply(&mut outputs);
+        assert_eq!(outputs, [0.0, 1.0, 0.0, 0.0]);
+
+        let mut net = NeuralNetBuilder::new()
+            .set_default_activation(ActivationFunction::Identity)
+            .set_output_transform(OutputTransform::Softmax)
+            .add_nodes(NodeType::Input, 1)
+            .add_nodes(NodeType::Output, 2)
+            .add_normal_connection_raw(0, 1, 1.0)
+            .build::<crate::ConsecutiveNeuralNet>()?;
+        let outputs = net.evaluate(&[2f32.ln()]);
+        assert!((outputs[0] - 2.0 / 3.0).abs() < 1e-6);
+        assert!((outputs[1] - 1.0 / 3.0).abs() < 1e-6);
+
+        // Dropped outputs still count towards the normalization.
+        let mut first = [0.0];
+        net.evaluate_into(&[2f32.ln()], &mut first);
+        assert_eq!(first[0], outputs[0]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_input_scaling() -> Result<(), Error> {
+        let samples = vec![vec![0.0, 4.0], vec![2.0, 6.0], vec![4.0]];
+        let min_max = InputScaling::fit_min_max(&samples);
+        assert_eq!(
+            min_max,
+            vec![
+                InputScaling::MinMax { min: 0.0, max: 4.0 },
+                InputScaling::MinMax { min: 4.0, max: 6.0 },
+            ]
+        );
+        assert_eq!(
+            InputScaling::fit_z_score(&samples)[1],
+            InputScaling::ZScore {
+                mean: 5.0,
+                std_dev: 1.0
+            }
+        );
+        assert_eq!(min_max[0].apply(3.0), 0.75);
+        assert_eq!(InputScaling::MinMax { min: 1.0, max: 1.0 }.apply(5.0), 0.0);
+
+        // Every backend sees the scaled inputs, including those that
+        // weren't given.
+        let builder = xor_builder(1.0).with_input_scaling(vec![
+            InputScaling::ZScore {
+                mean: 1.0,
+                std_dev: 2.0,
+            },
+            InputScaling::MinMax {
+                min: -1.0,
+                max: 1.0,
+            },
+        ]);
+        let expected = (5.0 - 1.0) / 2.0 - (0.0 + 1.0) / 2.0;
+        let mut consecutive = builder.build::<crate::ConsecutiveNeuralNet>()?;
+        let mut layered = builder.build::<crate::LayeredNeuralNet>()?;
+        let mut sparse = builder.build::<crate::SparseNeuralNet>()?;
+        let mut quantized = builder.build::<crate::QuantizedNeuralNet>()?;
+        assert_eq!(consecutive.evaluate(&[5.0]), [expected]);
+        assert_eq!(layered.evaluate(&[5.0]), [expected]);
+        assert_eq!(sparse.evaluate(&[5.0]), [expected]);
+        let got = quantized.evaluate(&[5.0])[0];
+        assert!((got - expected).abs() < 1e-2);
+        assert_eq!(
+            consecutive.to_builder().input_scaling(),
+            builder.input_scaling()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_output_mapping() -> Result<(), Error> {
+        let mapping = vec![
+            OutputMapping::Affine {
+                scale: 2.0,
+                offset: -1.0,
+            },
+            OutputMapping::Discrete(3),
+        ];
+        let outputs = [0.5, 0.1, 0.7, 0.7, 4.0];
+        assert_eq!(OutputMapping::map(&mapping, &outputs), [0.0, 1.0, 4.0]);
+        assert_eq!(OutputMapping::num_mapped(&mapping, 5), 3);
+        // Groups are cut short by the last output.
+        assert_eq!(OutputMapping::map(&mapping, &outputs[..3]), [0.0, 1.0]);
+        assert_eq!(OutputMapping::num_mapped(&mapping, 3), 2);
+        assert_eq!(OutputMapping::num_mapped(&mapping, 0), 0);
+
+        let builder = NeuralNetBuilder::new()
+            .with_default_activation(ActivationFunction::Identity)
+            .with_output_mapping(mapping)
+            .with_inputs(2)
+            .with_nodes(NodeType::Output, 4)
+            .with_normal_connection_raw(0, 2, 0.25)
+            .with_normal_connection_raw(0, 4, 1.0)
+            .with_normal_connection_raw(1, 5, 1.0);
+        assert_eq!(builder.num_outputs(), 2);
+        let mut consecutive = builder.build::<crate::ConsecutiveNeuralNet>()?;
+        let mut layered = builder.build::<crate::LayeredNeuralNet>()?;
+        let mut sparse = builder.build::<crate::SparseNeuralNet>()?;
+        let mut quantized = builder.build::<crate::QuantizedNeuralNet>()?;
+        assert_eq!(consecutive.num_outputs(), 2);
+        assert_eq!(consecutive.evaluate(&[2.0, 1.0]), [0.0, 1.0]);
+        assert_eq!(layered.evaluate(&[2.0, 3.0]), [0.0, 2.0]);
+        assert_eq!(sparse.evaluate(&[-2.0, -1.0]), [-2.0, 0.0]);
+        assert_eq!(quantized.evaluate(&[2.0, 1.0]), [0.0, 1.0]);
+
+        let mut short = [0.0];
+        layered.evaluate_into(&[2.0, 3.0], &mut short);
+        assert_eq!(short, [0.0]);
+        Ok(())
+    }
+
+    fn xor_builder(weight: f32) -> NeuralNetBuilder {
+        NeuralNetBuilder::new()
+            .with_default_activation(ActivationFunction::Identity)
+            .with_inputs(2)
+            .with_nodes(NodeType::Output, 1)
+            .with_normal_connection_raw(0, 2, weight)
+            .with_normal_connection_raw(1, 2, -weight)
+    }
+
+    #[test]
+    fn test_consuming_builder() -> Result<(), Error> {
+        let mut net = xor_builder(2.0)
+            .with_bias()
+            .with_normal_connection_raw(3, 2, 0.5)
+            .build::<crate::ConsecutiveNeuralNet>()?;
+        assert_eq!(net.evaluate(&[1.0, 0.25]), vec![2.0]);
+
+        let mut builder = NeuralNetBuilder::new();
+        builder
+            .set_default_activation(ActivationFunction::Identity)
+            .add_inputs(2)
+            .add_nodes(NodeType::Output, 1)
+            .add_normal_connection_raw(0, 2, 2.0)
+            .add_normal_connection_raw(1, 2, -2.0);
+        assert_eq!(xor_builder(2.0), builder);
+        Ok(())
+    }
+
+    #[test]
+    fn test_duplicate_policy() -> Result<(), Error> {
+        let mut builder = NeuralNetBuilder::new();
+        builder
+            .set_default_activation(ActivationFunction::Identity)
+            .set_default_aggregation(AggregationFunction::Max)
+            .add_inputs(1)
+            .add_nodes(NodeType::Output, 1)
+            .add_normal_connection_raw(0, 1, 1.0)
+            .add_recurrent_connection_raw(0, 1, 1.0)
+            .add_normal_connection_raw(0, 1, 2.0);
+        let mut net = builder.build::<crate::ConsecutiveNeuralNet>()?;
+        assert_eq!(net.evaluate(&[1.0]), vec![2.0]);
+
+        builder.set_duplicate_policy(DuplicatePolicy::Merge);
+        let mut net = builder.build::<crate::ConsecutiveNeuralNet>()?;
+        assert_eq!(net.evaluate(&[1.0]), vec![3.0]);
+
+        builder.set_duplicate_policy(DuplicatePolicy::Reject);
+        let res = builder.build::<crate::ConsecutiveNeuralNet>();
+        assert_eq!(
+            res.err(),
+            Some(Error::DuplicateConnection {
+                connection: 2,
+                first: 0,
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_weight_bounds() -> Result<(), Error> {
+        let bounds = |policy| WeightBounds {
+            min: -1.0,
+            max: 3.0,
+            policy,
+        };
+        let clamp = bounds(BoundsPolicy::Clamp);
+        assert_eq!(clamp.apply(0.5), Some(0.5));
+        assert_eq!(clamp.apply(5.0), Some(3.0));
+        assert_eq!(clamp.apply(-2.0), Some(-1.0));
+        let wrap = bounds(BoundsPolicy::Wrap);
+        assert_eq!(wrap.apply(5.0), Some(1.0));
+        assert_eq!(wrap.apply(-2.0), Some(2.0));
+        assert_eq!(bounds(BoundsPolicy::Reject).apply(5.0), None);
+
+        let mut builder = NeuralNetBuilder::new()
+            .with_default_activation(ActivationFunction::Identity)
+            .with_inputs(1)
+            .with_nodes(NodeType::Output, 1)
+            .with_normal_connection_raw(0, 1, 0.5)
+            .with_normal_connection_raw(0, 1, 5.0);
+        let mut net = builder.build::<crate::ConsecutiveNeuralNet>()?;
+        assert_eq!(net.evaluate(&[1.0]), vec![5.5]);
+
+        builder.set_weight_bounds(Some(clamp));
+        let mut net = builder.build::<crate::ConsecutiveNeuralNet>()?;
+        assert_eq!(net.evaluate(&[1.0]), vec![3.5]);
+
+        builder.set_weight_bounds(Some(bounds(BoundsPolicy::Reject)));
+        let res = builder.build::<crate::ConsecutiveNeuralNet>();
+        assert_eq!(
+            res.err(),
+            Some(Error::WeightOutOfBounds {
+                connection: 1,
+                weight: 5.0,
+            })
+        );
+
+        let backwards = WeightBounds {
+            min: 1.0,
+            max: -1.0,
+            policy: BoundsPolicy::Clamp,
+        };
+        builder.set_weight_bounds(Some(backwards));
+        let res = builder.build::<crate::ConsecutiveNeuralNet>();
+        assert_eq!(
+            res.err(),
+            Some(Error::InvalidWeightBounds {
+                min: 1.0,
+                max: -1.0
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_disabled_connections() -> Result<(), Error> {
+        let enabled = NeuralNetBuilder::new()
+            .with_default_activation(ActivationFunction::Identity)
+            .with_inputs(1)
+            .with_nodes(NodeType::Output, 1)
+            .with_nodes(NodeType::Hidden, 1)
+            .with_normal_connection_raw(0, 2, 2.0)
+            .with_normal_connection_raw(2, 1, 3.0);
+        // Disabled, the connection back into the hidden node closes no
+        // loop, and the direct connection adds nothing.
+        let builder = enabled
+            .clone()
+            .with_normal_connection_raw(1, 2, 1.0)
+            .with_normal_connection_raw(0, 1, 5.0)
+            .with_enabled(2, false)
+            .with_enabled(3, false);
+        let mut net = builder.build::<crate::ConsecutiveNeuralNet>()?;
+        assert_eq!(net.evaluate(&[1.0]), vec![6.0]);
+        assert_eq!(builder.connections.len(), 4);
+        assert_eq!(builder.topology_hash(true), enabled.topology_hash(true));
+        assert_eq!(builder.to_weight_matrix(), enabled.to_weight_matrix());
+        assert!(builder.validate().is_empty());
+
+        let res = builder
+            .with_enabled(2, true)
+            .build::<crate::ConsecutiveNeuralNet>();
+        assert!(matches!(res, Err(Error::ConnectionLoop { .. })));
+        Ok(())
+    }
+
+    #[test]
+    fn test_connection_indices_after_resolution() -> Result<(), Error> {
+        // A disabled connection, and a duplicate that's merged into
+        // an earlier one.
+        let builder = NeuralNetBuilder::new()
+            .with_default_activation(ActivationFunction::Identity)
+            .with_duplicate_policy(DuplicatePolicy::Merge)
+            .with_inputs(2)
+            .with_nodes(NodeType::Output, 1)
+            .with_nodes(NodeType::Hidden, 1)
+            .with_normal_connection_raw(0, 2, 1.0)
+            .with_normal_connection_raw(1, 2, 1.0)
+            .with_normal_connection_raw(0, 3, 1.0)
+            .with_normal_connection_raw(3, 2, 1.0)
+            .with_normal_connection_raw(1, 2, 1.0)
+            .with_enabled(0, false);
+
+        fn check<N: NeuralNet>(
+            builder: &NeuralNetBuilder,
+        ) -> Result<(), Error> {
+            let mut net = builder.build::<N>()?;
+            assert_eq!(
+                net.set_weight(0, 5.0),
+                Err(Error::InvalidConnectionIndex { index: 0 })
+            );
+            net.set_weight(3, 2.0)?;
+            assert!((net.evaluate(&[1.0, 1.0])[0] - 4.0).abs() < 0.05);
+            net.set_weight(4, 0.5)?;
+            assert!((net.evaluate(&[1.0, 1.0])[0] - 2.5).abs() < 0.05);
+            assert!(net.set_weight(5, 1.0).is_err());
+            Ok(())
+        }
+        check::<crate::ConsecutiveNeuralNet>(&builder)?;
+        check::<crate::LayeredNeuralNet>(&builder)?;
+        check::<crate::SparseNeuralNet>(&builder)?;
+        check::<crate::QuantizedNeuralNet>(&builder)?;
+
+        // Errors found after resolution refer to the builder too.
+        let res = builder
+            .with_normal_connection_raw(2, 3, 1.0)
+            .build::<crate::LayeredNeuralNet>();
+        let mut cycle = match res {
+            Err(Error::ConnectionLoop { cycle }) => cycle,
+            other => panic!("expected a loop, got {:?}", other),
+        };
+        cycle.sort_unstable();
+        assert_eq!(cycle, vec![3, 5]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_topology_hash() {
+        let mut a = NeuralNetBuilder::new();
+        a.add_inputs(2)
+            .add_nodes(NodeType::Output, 1)
+            .add_normal_connection_raw(0, 2, 1.0)
+            .add_normal_connection_raw(1, 2, -1.0);
+        let mut b = NeuralNetBuilder::new();
+        b.add_inputs(2)
+            .add_nodes(NodeType::Output, 1)
+            .add_normal_connection_raw(1, 2, 0.5)
+            .add_normal_connection_raw(0, 2, 1.0);
+
+        assert_eq!(a.topology_hash(false), b.topology_hash(false));
+        assert_ne!(a.topology_hash(true), b.topology_hash(true));
+        b.connections[0].weight = -1.0;
+        assert_eq!(a.topology_hash(true), b.topology_hash(true));
+        b.connections[0].connection_type = ConnectionType::Recurrent;
+        assert_ne!(a.topology_hash(false), b.topology_hash(false));
+        // Stable across releases and platforms.
+        assert_eq!(
+            NeuralNetBuilder::new().topology_hash(true),
+            0x947d9a6815a08e2d
+        );
+
+        // Parameters of activation functions are numbers, so are only
+        // covered with the weights.
+        let mut c = a.clone();
+        a.nodes[2].func = ActivationFunction::LeakyRelu(0.1);
+        c.nodes[2].func = ActivationFunction::LeakyRelu(0.2);
+        assert_eq!(a.topology_hash(false), c.topology_hash(false));
+        assert_ne!(a.topology_hash(true), c.topology_hash(true));
+        c.nodes[2].func = ActivationFunction::Elu(0.1);
+        assert_ne!(a.topology_hash(false), c.topology_hash(false));
+
+        // As are the settings that change what the network computes.
+        let mut c = a.clone();
+        c.set_propagation(Propagation::Passes(2));
+        assert_ne!(a.topology_hash(false), c.topology_hash(false));
+        let mut c = a.clone();
+        c.set_duplicate_policy(DuplicatePolicy::Merge);
+        assert_ne!(a.topology_hash(false), c.topology_hash(false));
+        let mut c = a.clone();
+        c.set_weight_bounds(Some(WeightBounds::default()));
+        let hash = c.topology_hash(true);
+        assert_ne!(a.topology_hash(false), c.topology_hash(false));
+        c.set_weight_bounds(Some(WeightBounds {
+            max: 10.0,
+            ..WeightBounds::default()
+        }));
+        assert_ne!(a.topology_hash(false), c.topology_hash(false));
+        assert_ne!(c.topology_hash(true), hash);
+
+        // Custom functions aren't hashed by address, which changes
+        // between runs.
+        let mut c = a.clone();
+        let mut d = a.clone();
+        c.nodes[2].func = ActivationFunction::Custom(|x| x);
+        d.nodes[2].func = ActivationFunction::Custom(|x| 2.0 * x);
+        assert_eq!(c.topology_hash(true), d.topology_hash(true));
+    }
+
+    #[test]
+    fn test_weight_matrix() -> Result<(), Error> {
+        let matrix = vec![
+            vec![0.0, 0.0, 0.5, 1.0],
+            vec![0.0, 0.0, 0.0, -1.0],
+            vec![0.0, 0.0, 0.0, 0.0],
+            vec![0.0, 0.0, 2.0, 0.0],
+        ];
+        let builder = NeuralNetBuilder::from_matrices(2, 1, &matrix)?;
+        assert_eq!(builder.num_inputs(), 2);
+        assert_eq!(builder.num_outputs(), 1);
+        assert_eq!(builder.num_hidden(), 1);
+        assert_eq!(builder.connections.len(), 4);
+        assert_eq!(builder.to_weight_matrix(), matrix);
+
+        let res = NeuralNetBuilder::from_matrices(2, 1, &matrix[..2]);
+        assert_eq!(res, Err(Error::InvalidWeightMatrix { row: 2 }));
+        let res = NeuralNetBuilder::from_matrices(u32::MAX, 2, &matrix);
+        assert_eq!(res, Err(Error::InvalidWeightMatrix { row: 4 }));
+        Ok(())
+    }
+
+    #[test]
+    fn test_upstream_nodes() {
+        let mut builder = NeuralNetBuilder::new();
+        builder
+            .add_nodes(NodeType::Input, 2)
+            .add_nodes(NodeType::Hidden, 2)
+            .add_nodes(NodeType::Output, 2)
+            // Hidden node 2 feeds both outputs
+            .add_normal_connection_raw(0, 2, 1.0)
+            .add_normal_connection_raw(2, 4, 1.0)
+            .add_normal_connection_raw(2, 5, 1.0)
+            // Hidden node 3 only feeds the second output
+            .add_normal_connection_raw(1, 3, 1.0)
+            .add_normal_connection_raw(3, 5, 1.0)
+            // Recurrent connections don't count as upstream
+            .add_recurrent_connection_raw(5, 4, 1.0);
+
+        let upstream_a = builder.upstream_nodes(4);
+        let upstream_b = builder.upstream_nodes(5);
+
+        assert_eq!(upstream_a, [0, 2].iter().cloned().collect());
+        assert_eq!(upstream_b, [0, 1, 2, 3].iter().cloned().collect());
+        assert!(upstream_a.contains(&2) && upstream_b.contains(&2));
+    }
+
+    #[test]
+    fn test_try_evaluate() -> Result<(), Error> {
+        let mut builder = NeuralNetBuilder::new();
+        builder.add_nodes(NodeType::Input, 2).add_bias();
+        let output =
+            builder.add_node(NodeType::Output, ActivationFunction::Sigmoid);
+        assert_eq!(output, NodeId(3));
+        builder.add_normal_connection(NodeId(0), output, 1.0);
+        assert_eq!(builder.num_inputs(), 2);
+        assert_eq!(builder.num_outputs(), 1);
+
+        let mut net = builder.build::<crate::ConsecutiveNeuralNet>()?;
+        assert_eq!(net.num_inputs(), 2);
+        assert_eq!(net.try_evaluate(&[0.0, 1.0])?.len(), 1);
+        assert!(matches!(
+            net.try_evaluate(&[0.0]),
+            Err(Error::InputSizeMismatch {
+                expected: 2,
+                got: 1
+            })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_node_index() {
+        let mut builder = NeuralNetBuilder::new();
+        builder
+            .add_nodes(NodeType::Input, 1)
+            .add_nodes(NodeType::Output, 1)
+            .add_normal_connection_raw(0, 1, 1.0)
+            .add_normal_connection_raw(5, 1, 1.0);
+
+        let is_invalid_index = |res: Result<(), Error>| {
+            matches!(
+                res,
+                Err(Error::InvalidNodeIndex {
+                    connection: 1,
+                    index: 5
+                })
+            )
+        };
+        assert!(is_invalid_index(
+            builder.build::<crate::ConsecutiveNeuralNet>().map(|_| ())
+        ));
+        assert!(is_invalid_index(
+            builder.build::<crate::LayeredNeuralNet>().map(|_| ())
+        ));
+        assert!(is_invalid_index(
+            builder.build::<crate::SparseNeuralNet>().map(|_| ())
+        ));
+    }
+
+    #[test]
+    fn test_connection_loop() -> Result<(), Box<dyn std::error::Error>> {
+        let mut builder = NeuralNetBuilder::new();
+        builder
+            .add_nodes(NodeType::Input, 1)
+            .add_nodes(NodeType::Hidden, 3)
+            .add_nodes(NodeType::Output, 1)
+            .add_normal_connection_raw(0, 1, 1.0)
+            .add_normal_connection_raw(3, 1, 1.0)
+            .add_normal_connection_raw(1, 2, 1.0)
+            .add_normal_connection_raw(2, 3, 1.0)
+            .add_normal_connection_raw(3, 4, 1.0);
+
+        let err = builder.build::<crate::ConsecutiveNeuralNet>().unwrap_err();
+        assert_eq!(
+            err,
+            Error::ConnectionLoop {
+                cycle: vec![2, 3, 1]
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            "Normal connections form a loop: [2, 3, 1]"
+        );
+
+        // The shortest loop is reported, even when a longer one
+        // through the same node is found first.
+        let mut shortcut = NeuralNetBuilder::new();
+        shortcut
+            .add_nodes(NodeType::Input, 1)
+            .add_nodes(NodeType::Hidden, 3)
+            .add_normal_connection_raw(0, 1, 1.0)
+            .add_normal_connection_raw(1, 2, 1.0)
+            .add_normal_connection_raw(2, 3, 1.0)
+            .add_normal_connection_raw(3, 1, 1.0)
+            .add_normal_connection_raw(1, 1, 1.0);
+        assert_eq!(
+            shortcut.build::<crate::ConsecutiveNeuralNet>().err(),
+            Some(Error::ConnectionLoop { cycle: vec![4] })
+        );
+
+        // Usable with `?` in functions returning any error type.
+        builder.connections.remove(1);
+        builder.build::<crate::ConsecutiveNeuralNet>()?;
+        Ok(())
     }
 }
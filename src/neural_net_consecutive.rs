@@ -1,181 +1,968 @@
-use std::collections::HashMap;
+use alloc::collections::VecDeque;
+#[cfg(not(feature = "std"))]
+use alloc::{string::String, vec, vec::Vec};
 
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::float::*;
 use crate::neural_net::*;
 
-#[derive(Debug)]
-enum NodeValue {
-    Accumulator(f32),
-    Activated(f32),
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+enum NodeValue<F> {
+    // Aggregate of the values received so far, and their number.
+    Accumulator(F, u32),
+    Activated(F),
 }
 
-#[derive(Debug)]
-struct Node {
-    value: NodeValue,
-    node_type: NodeType,
-    func: ActivationFunction,
-}
+impl<F: Float> NodeValue<F> {
+    const EMPTY: Self = NodeValue::Accumulator(F::ZERO, 0);
 
-impl Node {
-    fn get_val(&mut self) -> f32 {
-        match self.value {
+    // `previous` is the node's activation in the previous evaluation.
+    fn get_val(&mut self, node: &Node, previous: F) -> F {
+        match *self {
             NodeValue::Activated(x) => x,
-            NodeValue::Accumulator(x) => {
-                let output = self.func.apply(x);
-                self.value = NodeValue::Activated(output);
+            NodeValue::Accumulator(x, count) => {
+                let aggregate = node.aggregation.finish(x, count);
+                let output = node.activate(aggregate, previous);
+                *self = NodeValue::Activated(output);
                 output
             }
         }
     }
 
-    fn add_to_val(&mut self, x: f32) {
-        self.value = match self.value {
-            NodeValue::Activated(_) => NodeValue::Accumulator(x),
-            NodeValue::Accumulator(y) => NodeValue::Accumulator(x + y),
+    fn add_to_val(&mut self, x: F, aggregation: AggregationFunction) {
+        *self = match *self {
+            NodeValue::Activated(_) => {
+                NodeValue::Accumulator(aggregation.combine(F::ZERO, 0, x), 1)
+            }
+            NodeValue::Accumulator(y, count) => NodeValue::Accumulator(
+                aggregation.combine(y, count, x),
+                count + 1,
+            ),
         };
     }
 }
 
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+struct Node {
+    node_type: NodeType,
+    func: ActivationFunction,
+    aggregation: AggregationFunction,
+    bias: f32,
+    response: f32,
+    memory: Option<MemoryCell>,
+}
+
+impl Node {
+    // The activation of a memory cell is its memory, so it is
+    // updated from the previous activation.
+    fn activate<F: Float>(&self, aggregate: F, previous: F) -> F {
+        let (response, bias) = (self.response, self.bias);
+        let x = F::from_f32(response) * (aggregate + F::from_f32(bias));
+        match &self.memory {
+            Some(cell) => cell.update(self.func, x, previous),
+            None => self.func.apply_float(x),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 struct Connection {
     origin: u32,
     dest: u32,
-    weight: f32,
-    connection_type: ConnectionType,
+    // Index of the connection in the builder, and of its weight in
+    // `ConsecutiveNeuralNet::weights`.
+    index: usize,
+}
+
+// The node values of a `ConsecutiveNeuralNet`, kept separate from the
+// network itself so that one network can be evaluated from several
+// threads at once, each with its own state.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct EvalState<F = f32> {
+    values: Vec<NodeValue<F>>,
+    // Activation of every node in the most recent evaluation, read by
+    // recurrent connections in the next one.  All zero before the
+    // first evaluation.
+    activations: Vec<F>,
+    // Current weight of every connection, changed by the rules of
+    // plastic connections.  Empty if the network has none.
+    plastic_weights: Vec<F>,
 }
 
-#[derive(Debug)]
-pub struct ConsecutiveNeuralNet {
+// Each evaluation computes every node from the current inputs and
+// the previous evaluation's activations.  Normal connections carry
+// values within an evaluation, and are ordered so that each node has
+// received all of its normal inputs before it is read.  Recurrent
+// connections carry the activation of their origin from the previous
+// evaluation, so they impose no order, and may form loops.
+//
+// Networks are evaluated in the scalar `F`.  Only networks of `f32`
+// implement `NeuralNet`, so others are built with `from_builder` and
+// evaluated with `evaluate_with_state`.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ConsecutiveNeuralNet<F = f32> {
     nodes: Vec<Node>,
     connections: Vec<Connection>,
+    recurrent_connections: Vec<Connection>,
+    // One per resolved connection, so that weights can be changed
+    // without rebuilding.  Plastic connections start each episode
+    // from these.
+    weights: Vec<F>,
+    // Index in `weights` of each connection of the builder.
+    connection_slots: ConnectionSlots,
+    plastic_connections: Vec<(Connection, HebbianRule)>,
+    input_indices: Vec<usize>,
+    output_indices: Vec<usize>,
+    bias_indices: Vec<usize>,
+    bias_value: f32,
+    input_scaling: Vec<InputScaling>,
+    output_transform: OutputTransform,
+    output_mapping: Vec<OutputMapping>,
+    propagation: Propagation,
+    // Kept only so that `to_builder` gives back the builder's names
+    // and policies.
+    #[cfg_attr(feature = "serde", serde(default))]
+    names: Vec<Option<String>>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    weight_bounds: Option<WeightBounds>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    duplicate_policy: DuplicatePolicy,
+    state: EvalState<F>,
 }
 
-fn connection_order(
-    connections: &[ConnectionTemplate],
+// Order in which to apply the normal connections, as indices into
+// `builder.connections`.  Recurrent connections are left out.  Nodes
+// are visited in topological order using Kahn's algorithm, and each
+// node's outgoing connections are applied once all of its incoming
+// connections have been, taking O(nodes + connections) time.
+pub(crate) fn connection_order(
+    builder: &NeuralNetBuilder,
 ) -> Result<Vec<usize>, Error> {
-    let mut must_be_after = (0..connections.len())
-        .map(|j| {
-            let required_before = (0..connections.len())
-                .filter(|i| {
-                    let i = *i;
-                    let conn_i = &connections[i];
-                    let conn_j = &connections[j];
-
-                    // Avoid nonsensical dependencies
-                    let different_connection = i != j;
-
-                    // The origin of a normal connection has no unused
-                    // input connections.
-                    let after_input_conn = (conn_i.dest == conn_j.origin)
-                        && (conn_j.connection_type == ConnectionType::Normal);
-
-                    // The destination has no unused recurrent output
-                    // connections.
-                    let before_output_conn = (conn_i.origin == conn_j.dest)
-                        && (conn_i.connection_type
-                            == ConnectionType::Recurrent);
-
-                    different_connection
-                        && (after_input_conn || before_output_conn)
-                })
-                .collect::<Vec<_>>();
+    let connections = &builder.connections;
+    let num_nodes = builder.nodes.len();
 
-            (j, required_before)
-        })
-        .collect::<HashMap<usize, Vec<usize>>>();
+    let mut outgoing = vec![Vec::new(); num_nodes];
+    let mut num_incoming = vec![0; num_nodes];
+    connections
+        .iter()
+        .enumerate()
+        .filter(|(_i, conn)| conn.connection_type == ConnectionType::Normal)
+        .for_each(|(i, conn)| {
+            outgoing[conn.origin as usize].push(i);
+            num_incoming[conn.dest as usize] += 1;
+        });
+    let num_normal = num_incoming.iter().sum::<usize>();
 
-    let mut output = Vec::new();
+    let mut ready = (0..num_nodes)
+        .filter(|i| num_incoming[*i] == 0)
+        .collect::<VecDeque<_>>();
+    let mut output = Vec::with_capacity(num_normal);
 
-    while must_be_after.len() > 0 {
-        let next_connection = must_be_after
-            .iter()
-            .filter(|(_k, v)| {
-                v.iter().all(|before| !must_be_after.contains_key(before))
-            })
-            .map(|(k, _v)| *k)
-            .next()
-            // If no connections can occur next, the network contains
-            // a loop of normal connections or a loop of recurrent
-            // connections..  A loop of normal connections is
-            // ill-defined.  A loop of recurrent connections is
-            // semantically valid, but isn't possible to represent
-            // with this representation.
-            .ok_or(Error::ConnectionLoop)?;
+    while let Some(node) = ready.pop_front() {
+        outgoing[node].iter().for_each(|conn| {
+            output.push(*conn);
+            let dest = connections[*conn].dest as usize;
+            num_incoming[dest] -= 1;
+            if num_incoming[dest] == 0 {
+                ready.push_back(dest);
+            }
+        });
+    }
 
-        output.push(next_connection);
-        must_be_after.remove(&next_connection);
+    // Connections out of nodes that never became ready are part of,
+    // or downstream of, a loop of normal connections, which is
+    // ill-defined.
+    if output.len() == num_normal {
+        Ok(output)
+    } else {
+        Err(Error::ConnectionLoop {
+            cycle: find_connection_loop(connections).unwrap_or_default(),
+        })
     }
+}
 
-    Ok(output)
+impl<F: Float> Default for ConsecutiveNeuralNet<F> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
-impl ConsecutiveNeuralNet {
-    pub fn new() -> ConsecutiveNeuralNet {
+impl<F: Float> ConsecutiveNeuralNet<F> {
+    pub fn new() -> Self {
         Self {
             nodes: Vec::new(),
             connections: Vec::new(),
+            recurrent_connections: Vec::new(),
+            weights: Vec::new(),
+            connection_slots: ConnectionSlots::default(),
+            plastic_connections: Vec::new(),
+            input_indices: Vec::new(),
+            output_indices: Vec::new(),
+            bias_indices: Vec::new(),
+            bias_value: 1.0,
+            input_scaling: Vec::new(),
+            output_transform: OutputTransform::Identity,
+            output_mapping: Vec::new(),
+            propagation: Propagation::default(),
+            names: Vec::new(),
+            weight_bounds: None,
+            duplicate_policy: DuplicatePolicy::default(),
+            state: EvalState::default(),
         }
     }
 
-    fn load_input_values(&mut self, inputs: &[f32]) {
-        self.nodes
+    pub fn new_state(&self) -> EvalState<F> {
+        EvalState {
+            values: vec![NodeValue::EMPTY; self.nodes.len()],
+            activations: vec![F::ZERO; self.nodes.len()],
+            plastic_weights: self.initial_plastic_weights(),
+        }
+    }
+
+    fn initial_plastic_weights(&self) -> Vec<F> {
+        let mut weights = self.weights.clone();
+        weights.truncate(self.num_plastic_weights());
+        weights
+    }
+
+    fn num_plastic_weights(&self) -> usize {
+        if self.plastic_connections.is_empty() {
+            0
+        } else {
+            self.weights.len()
+        }
+    }
+
+    // Returns plastic connections to their initial weights, to start
+    // a new episode, keeping the rest of the state.  `reset_state`
+    // resets both.
+    pub fn reset_plastic_weights(&mut self) {
+        self.state.plastic_weights = self.initial_plastic_weights();
+    }
+
+    // Weights as changed by plasticity so far, indexed like
+    // `weights`.
+    pub fn plastic_weights(&self) -> &[F] {
+        if self.plastic_connections.is_empty() {
+            &self.weights
+        } else {
+            &self.state.plastic_weights
+        }
+    }
+
+    pub fn evaluate_with_state(
+        &self,
+        state: &mut EvalState<F>,
+        inputs: &[F],
+    ) -> Vec<F> {
+        let num_outputs = OutputMapping::num_mapped(
+            &self.output_mapping,
+            self.output_indices.len(),
+        );
+        let mut outputs = vec![F::ZERO; num_outputs];
+        self.evaluate_into_with_state(state, inputs, &mut outputs);
+        outputs
+    }
+
+    pub fn evaluate_into_with_state(
+        &self,
+        state: &mut EvalState<F>,
+        inputs: &[F],
+        outputs: &mut [F],
+    ) {
+        match self.propagation {
+            Propagation::Passes(passes) => {
+                (0..passes.max(1)).for_each(|_| self.propagate(state, inputs))
+            }
+            Propagation::UntilStable {
+                epsilon,
+                max_passes,
+            } => {
+                let epsilon = F::from_f32(epsilon);
+                let output_values = |state: &EvalState<F>| {
+                    self.output_indices
+                        .iter()
+                        .map(|i| state.activations[*i])
+                        .collect::<Vec<_>>()
+                };
+                self.propagate(state, inputs);
+                let mut previous = output_values(state);
+                for _ in 1..max_passes {
+                    self.propagate(state, inputs);
+                    let current = output_values(state);
+                    let stable = current
+                        .iter()
+                        .zip(&previous)
+                        .all(|(a, b)| (*a - *b).abs() <= epsilon);
+                    previous = current;
+                    if stable {
+                        break;
+                    }
+                }
+            }
+        }
+        let activations = &state.activations;
+        finish_outputs(
+            self.output_transform,
+            &self.output_mapping,
+            self.output_indices.iter().map(|i| activations[*i]),
+            outputs,
+        );
+    }
+
+    // Weight of each connection that remains once disabled
+    // connections are dropped and duplicates merged, as found by
+    // `connection_slot`.  These are the initial weights of plastic
+    // connections.
+    pub fn weights(&self) -> &[F] {
+        &self.weights
+    }
+
+    // Index in `weights` of a connection of the builder this network
+    // was built from, or `None` if it's disabled or doesn't exist.
+    pub fn connection_slot(&self, connection: usize) -> Option<usize> {
+        self.connection_slots.get(connection).ok()
+    }
+
+    pub fn weights_mut(&mut self) -> &mut [F] {
+        &mut self.weights
+    }
+
+    pub fn num_hidden(&self) -> usize {
+        self.node_types().filter(|t| *t == NodeType::Hidden).count()
+    }
+
+    // Type of each node, in builder order.
+    pub fn node_types(&self) -> impl Iterator<Item = NodeType> + '_ {
+        self.nodes.iter().map(|node| node.node_type)
+    }
+
+    pub fn activation_functions(
+        &self,
+    ) -> impl Iterator<Item = ActivationFunction> + '_ {
+        self.nodes.iter().map(|node| node.func)
+    }
+
+    // Every connection with its current weight, indexed like
+    // `weights`.
+    pub fn connections(&self) -> impl Iterator<Item = ConnectionTemplate> {
+        let mut connections = self
+            .connections
+            .iter()
+            .map(|conn| (conn, ConnectionType::Normal))
+            .chain(
+                self.recurrent_connections
+                    .iter()
+                    .map(|conn| (conn, ConnectionType::Recurrent)),
+            )
+            .map(|(conn, connection_type)| {
+                let template = ConnectionTemplate {
+                    origin: conn.origin,
+                    dest: conn.dest,
+                    weight: self.weights[conn.index].to_f32(),
+                    connection_type,
+                    plasticity: None,
+                    enabled: true,
+                };
+                (conn.index, template)
+            })
+            .collect::<Vec<_>>();
+        connections.sort_by_key(|(index, _)| *index);
+        self.plastic_connections.iter().for_each(|(conn, rule)| {
+            connections[conn.index].1.plasticity = Some(*rule);
+        });
+        connections.into_iter().map(|(_, template)| template)
+    }
+
+    // Number of normal connections on the longest path that ends at
+    // each node.  Recurrent connections don't count, since they
+    // carry values across evaluations.
+    pub fn node_depths(&self) -> Vec<usize> {
+        let mut depths = vec![0; self.nodes.len()];
+        self.connections.iter().for_each(|conn| {
+            let depth = depths[conn.origin as usize] + 1;
+            let dest = &mut depths[conn.dest as usize];
+            *dest = (*dest).max(depth);
+        });
+        depths
+    }
+
+    // Length of the longest path of normal connections.
+    pub fn depth(&self) -> usize {
+        self.node_depths().into_iter().max().unwrap_or(0)
+    }
+
+    // Builder that produces this network, with connections indexed
+    // like `weights`.
+    pub fn to_builder(&self) -> NeuralNetBuilder {
+        let mut builder = NeuralNetBuilder::new();
+        builder
+            .set_bias_value(self.bias_value)
+            .set_input_scaling(self.input_scaling.clone())
+            .set_output_transform(self.output_transform)
+            .set_output_mapping(self.output_mapping.clone())
+            .set_propagation(self.propagation)
+            .set_weight_bounds(self.weight_bounds)
+            .set_duplicate_policy(self.duplicate_policy);
+        self.nodes.iter().enumerate().for_each(|(i, node)| {
+            builder.nodes.push(NodeTemplate {
+                node_type: node.node_type,
+                func: node.func,
+                aggregation: node.aggregation,
+                bias: node.bias,
+                response: node.response,
+                memory: node.memory,
+                name: self.names.get(i).cloned().flatten(),
+            });
+        });
+        self.connections().for_each(|conn| {
+            builder.connections.push(conn);
+        });
+        builder
+    }
+
+    // Adds a node to the built network, without rebuilding it.
+    // Returns the index of the new node.
+    pub fn add_node_built(
+        &mut self,
+        node_type: NodeType,
+        func: ActivationFunction,
+    ) -> u32 {
+        let index = self.nodes.len();
+        self.nodes.push(Node {
+            node_type,
+            func,
+            aggregation: AggregationFunction::Sum,
+            bias: 0.0,
+            response: 1.0,
+            memory: None,
+        });
+        match node_type {
+            NodeType::Input => self.input_indices.push(index),
+            NodeType::Output => self.output_indices.push(index),
+            NodeType::Bias => self.bias_indices.push(index),
+            NodeType::Hidden => {}
+        }
+        self.names.push(None);
+        self.state.values.push(NodeValue::EMPTY);
+        self.state.activations.push(F::ZERO);
+        index as u32
+    }
+
+    // Adds a connection to the built network.  Where possible, the
+    // connection is inserted into the existing evaluation order,
+    // after every normal connection into its origin and before every
+    // normal connection out of its destination.  Otherwise the order
+    // is recomputed from scratch.  The connection is checked against
+    // the loops, weight bounds and duplicate policy of the builder, as
+    // when building, and on error the network is left unchanged.
+    pub fn add_connection_built(
+        &mut self,
+        origin: u32,
+        dest: u32,
+        weight: F,
+        connection_type: ConnectionType,
+    ) -> Result<(), Error> {
+        // Numbered after the connections of the builder.
+        let connection = self.connection_slots.0.len();
+        let slot = self.weights.len();
+        if let Some(index) = [origin, dest]
+            .iter()
+            .find(|i| **i as usize >= self.nodes.len())
+        {
+            return Err(Error::InvalidNodeIndex {
+                connection,
+                index: *index,
+            });
+        }
+
+        // Checked as building would check the new connection.
+        let weight = match self.weight_bounds {
+            Some(bounds) if !bounds.contains(weight.to_f32()) => {
+                let bounded = bounds.apply(weight.to_f32()).ok_or(
+                    Error::WeightOutOfBounds {
+                        connection,
+                        weight: weight.to_f32(),
+                    },
+                )?;
+                F::from_f32(bounded)
+            }
+            _ => weight,
+        };
+        if self.duplicate_policy != DuplicatePolicy::Allow {
+            let existing = match connection_type {
+                ConnectionType::Normal => &self.connections,
+                ConnectionType::Recurrent => &self.recurrent_connections,
+            };
+            if let Some(first) = existing
+                .iter()
+                .find(|c| c.origin == origin && c.dest == dest)
+            {
+                let index = first.index;
+                if self.duplicate_policy == DuplicatePolicy::Reject {
+                    return Err(Error::DuplicateConnection {
+                        connection,
+                        first: self.connection_of_slot(index),
+                    });
+                }
+                self.weights[index] += weight;
+                self.connection_slots.0.push(Some(index));
+                return Ok(());
+            }
+        }
+
+        let conn = Connection {
+            origin,
+            dest,
+            index: slot,
+        };
+        if connection_type == ConnectionType::Recurrent {
+            self.recurrent_connections.push(conn);
+            self.push_weight(weight);
+            return Ok(());
+        }
+        if let Some(cycle) = self.loop_through(origin, dest, connection) {
+            return Err(Error::ConnectionLoop { cycle });
+        }
+
+        let after = self
+            .connections
+            .iter()
+            .rposition(|c| c.dest == origin)
+            .map_or(0, |i| i + 1);
+        let before = self
+            .connections
+            .iter()
+            .position(|c| c.origin == dest)
+            .unwrap_or(self.connections.len());
+
+        if after <= before {
+            self.connections.insert(after, conn);
+        } else {
+            // Only the new connection was checked against the bounds.
+            // Existing weights may have been set outside of them since
+            // building, and are kept as they are.
+            let mut builder = self.to_builder();
+            builder.set_weight_bounds(None).add_normal_connection_raw(
+                origin,
+                dest,
+                weight.to_f32(),
+            );
+            let rebuilt = Self::from_builder(&builder)?;
+
+            // The nodes are unchanged, so recurrent memory and plastic
+            // weights carry over.  The weights are kept too, since the
+            // builder only holds them as `f32`s.
+            let state = core::mem::take(&mut self.state);
+            let weights = core::mem::take(&mut self.weights);
+            let connection_slots = core::mem::take(&mut self.connection_slots);
+            let weight_bounds = self.weight_bounds;
+            *self = rebuilt;
+            self.state = state;
+            self.weights = weights;
+            self.connection_slots = connection_slots;
+            self.weight_bounds = weight_bounds;
+        }
+        self.push_weight(weight);
+        Ok(())
+    }
+
+    // Connection of the builder that first maps to a slot of
+    // `weights`.
+    fn connection_of_slot(&self, slot: usize) -> usize {
+        self.connection_slots
+            .0
+            .iter()
+            .position(|s| *s == Some(slot))
+            .unwrap_or(slot)
+    }
+
+    // The loop that a new normal connection from `origin` to `dest`
+    // would close, as connections of the builder, the same one that
+    // building would report.  Only looked for when `dest` already
+    // reaches `origin`.
+    fn loop_through(
+        &self,
+        origin: u32,
+        dest: u32,
+        connection: usize,
+    ) -> Option<Vec<usize>> {
+        let mut outgoing = vec![Vec::new(); self.nodes.len()];
+        self.connections.iter().for_each(|c| {
+            outgoing[c.origin as usize].push(c.dest as usize);
+        });
+        let mut reached = vec![false; self.nodes.len()];
+        let mut stack = vec![dest as usize];
+        reached[dest as usize] = true;
+        while let Some(node) = stack.pop() {
+            outgoing[node].iter().for_each(|next| {
+                if !reached[*next] {
+                    reached[*next] = true;
+                    stack.push(*next);
+                }
+            });
+        }
+        if !reached[origin as usize] {
+            return None;
+        }
+
+        let mut connections = self.connections().collect::<Vec<_>>();
+        connections.push(ConnectionTemplate {
+            origin,
+            dest,
+            weight: 0.0,
+            connection_type: ConnectionType::Normal,
+            plasticity: None,
+            enabled: true,
+        });
+        let cycle = find_connection_loop(&connections).unwrap_or_default();
+        Some(
+            cycle
+                .into_iter()
+                .map(|slot| match slot == self.weights.len() {
+                    true => connection,
+                    false => self.connection_of_slot(slot),
+                })
+                .collect(),
+        )
+    }
+
+    // The new connection is static, so its current weight is its
+    // initial one.
+    fn push_weight(&mut self, weight: F) {
+        self.connection_slots.0.push(Some(self.weights.len()));
+        self.weights.push(weight);
+        if !self.plastic_connections.is_empty() {
+            self.state.plastic_weights.push(weight);
+        }
+    }
+
+    fn propagate(&self, state: &mut EvalState<F>, inputs: &[F]) {
+        // A state that was made for a different network can't be
+        // reused, so start over from a fresh one.
+        if state.values.len() != self.nodes.len()
+            || state.plastic_weights.len() != self.num_plastic_weights()
+        {
+            *state = self.new_state();
+        }
+
+        let nodes = &self.nodes;
+        let values = &mut state.values;
+        let activations = &mut state.activations;
+        let weights = if self.plastic_connections.is_empty() {
+            &self.weights
+        } else {
+            &state.plastic_weights
+        };
+
+        values.iter_mut().for_each(|val| *val = NodeValue::EMPTY);
+        self.input_indices.iter().enumerate().for_each(|(j, i)| {
+            let x = inputs.get(j).cloned().unwrap_or(F::ZERO);
+            let x = scale_input(&self.input_scaling, j, x);
+            values[*i] = NodeValue::Activated(x);
+        });
+        self.bias_indices.iter().for_each(|i| {
+            values[*i] = NodeValue::Activated(F::from_f32(self.bias_value));
+        });
+
+        self.recurrent_connections.iter().for_each(|conn| {
+            let val = activations[conn.origin as usize];
+            let dest = conn.dest as usize;
+            values[dest]
+                .add_to_val(val * weights[conn.index], nodes[dest].aggregation);
+        });
+
+        self.connections.iter().for_each(|conn| {
+            let origin = conn.origin as usize;
+            let val =
+                values[origin].get_val(&nodes[origin], activations[origin]);
+            let dest = conn.dest as usize;
+            values[dest]
+                .add_to_val(val * weights[conn.index], nodes[dest].aggregation);
+        });
+
+        // Every node is activated, even those that aren't read within
+        // this evaluation, so that recurrent connections see all of
+        // them next time.
+        activations
             .iter_mut()
-            .filter(|n| n.node_type == NodeType::Input)
-            .zip(inputs.iter())
-            .for_each(|(n, x)| {
-                n.value = NodeValue::Activated(*x);
+            .zip(values.iter_mut().zip(nodes.iter()))
+            .for_each(|(activation, (val, node))| {
+                *activation = val.get_val(node, *activation);
             });
+
+        let plastic_weights = &mut state.plastic_weights;
+        self.plastic_connections.iter().for_each(|(conn, rule)| {
+            let pre = activations[conn.origin as usize];
+            let post = activations[conn.dest as usize];
+            plastic_weights[conn.index] += rule.weight_change(pre, post);
+        });
     }
-}
 
-impl NeuralNet for ConsecutiveNeuralNet {
-    //fn build_from(&mut self, builder: NeuralNetBuilder) -> Result<(), Error> {
-    fn build_from(builder: &NeuralNetBuilder) -> Result<Self, Error> {
+    // Same as `NeuralNetBuilder::build`, for any scalar.
+    pub fn from_builder(builder: &NeuralNetBuilder) -> Result<Self, Error> {
+        let resolved = builder.resolve_connections()?;
+        let mut net = Self::from_resolved(&resolved)
+            .map_err(|error| resolved.original_error(error))?;
+        net.connection_slots = resolved.slots;
+        Ok(net)
+    }
+
+    fn from_resolved(builder: &NeuralNetBuilder) -> Result<Self, Error> {
+        builder.check_node_indices()?;
+
         let nodes = builder
             .nodes
             .iter()
             .map(|t| Node {
-                value: NodeValue::Accumulator(0.0),
                 node_type: t.node_type,
                 func: t.func,
+                aggregation: t.aggregation,
+                bias: t.bias,
+                response: t.response,
+                memory: t.memory,
             })
             .collect::<Vec<_>>();
 
-        let connections = connection_order(&builder.connections)?
-            .iter()
-            .map(|i| {
-                let template = builder
-                    .connections
-                    .get(*i)
-                    .ok_or(Error::InvalidConnectionIndex)?;
-                Ok(Connection {
+        let to_connection = |index: usize| {
+            builder
+                .connections
+                .get(index)
+                .map(|template| Connection {
                     origin: template.origin,
                     dest: template.dest,
-                    weight: template.weight,
-                    connection_type: template.connection_type,
+                    index,
                 })
+                .ok_or(Error::InvalidConnectionIndex { index })
+        };
+
+        let connections = connection_order(builder)?
+            .iter()
+            .map(|i| to_connection(*i))
+            .collect::<Result<Vec<_>, _>>()?;
+        let recurrent_connections = (0..builder.connections.len())
+            .filter(|i| {
+                builder.connections[*i].connection_type
+                    == ConnectionType::Recurrent
+            })
+            .map(to_connection)
+            .collect::<Result<Vec<_>, _>>()?;
+        let weights = builder
+            .connections
+            .iter()
+            .map(|conn| F::from_f32(conn.weight))
+            .collect();
+        let plastic_connections = builder
+            .connections
+            .iter()
+            .enumerate()
+            .filter_map(|(i, template)| {
+                template
+                    .plasticity
+                    .map(|rule| Ok((to_connection(i)?, rule)))
             })
             .collect::<Result<Vec<_>, _>>()?;
 
-        Ok(Self { nodes, connections })
+        let input_indices = indices_of_type(&nodes, NodeType::Input);
+        let output_indices = indices_of_type(&nodes, NodeType::Output);
+        let bias_indices = indices_of_type(&nodes, NodeType::Bias);
+
+        let mut net = Self {
+            nodes,
+            connections,
+            recurrent_connections,
+            weights,
+            connection_slots: ConnectionSlots::identity(
+                builder.connections.len(),
+            ),
+            plastic_connections,
+            input_indices,
+            output_indices,
+            bias_indices,
+            bias_value: builder.bias_value(),
+            input_scaling: builder.input_scaling().to_vec(),
+            output_transform: builder.output_transform(),
+            output_mapping: builder.output_mapping().to_vec(),
+            propagation: builder.propagation(),
+            names: builder.nodes.iter().map(|t| t.name.clone()).collect(),
+            weight_bounds: builder.weight_bounds(),
+            duplicate_policy: builder.duplicate_policy(),
+            state: EvalState::default(),
+        };
+        net.state = net.new_state();
+        Ok(net)
+    }
+}
+
+fn indices_of_type(nodes: &[Node], node_type: NodeType) -> Vec<usize> {
+    nodes
+        .iter()
+        .enumerate()
+        .filter(|(_i, n)| n.node_type == node_type)
+        .map(|(i, _n)| i)
+        .collect()
+}
+
+impl NeuralNet for ConsecutiveNeuralNet {
+    //fn build_from(&mut self, builder: NeuralNetBuilder) -> Result<(), Error> {
+    fn build_from(builder: &NeuralNetBuilder) -> Result<Self, Error> {
+        Self::from_builder(builder)
+    }
+
+    fn num_inputs(&self) -> usize {
+        self.input_indices.len()
+    }
+
+    fn num_outputs(&self) -> usize {
+        OutputMapping::num_mapped(
+            &self.output_mapping,
+            self.output_indices.len(),
+        )
     }
 
     fn evaluate(&mut self, inputs: &[f32]) -> Vec<f32> {
-        self.load_input_values(&inputs);
+        let mut outputs = vec![0.0; self.num_outputs()];
+        self.evaluate_into(inputs, &mut outputs);
+        outputs
+    }
 
+    fn evaluate_into(&mut self, inputs: &[f32], outputs: &mut [f32]) {
+        let mut state = core::mem::take(&mut self.state);
+        self.evaluate_into_with_state(&mut state, inputs, outputs);
+        self.state = state;
+    }
+
+    fn evaluate_batch(&mut self, inputs: &[Vec<f32>]) -> Vec<Vec<f32>> {
+        #[cfg(feature = "simd")]
         {
-            let connections = &mut self.connections;
-            let nodes = &mut self.nodes;
+            if self.can_evaluate_lanes() {
+                return inputs
+                    .chunks(LANES)
+                    .flat_map(|chunk| self.evaluate_lanes(chunk))
+                    .collect();
+            }
+        }
 
-            connections.iter().for_each(|conn| {
-                let val = nodes[conn.origin as usize].get_val();
-                nodes[conn.dest as usize].add_to_val(val * conn.weight);
-            });
+        // Otherwise the inputs are evaluated one at a time, the same
+        // as calling `evaluate` on each in turn.
+        let mut state = core::mem::take(&mut self.state);
+        let outputs = inputs
+            .iter()
+            .map(|x| self.evaluate_with_state(&mut state, x))
+            .collect();
+        self.state = state;
+        outputs
+    }
+
+    fn reset_state(&mut self) {
+        self.state = self.new_state();
+    }
+
+    fn set_weight(
+        &mut self,
+        connection: usize,
+        weight: f32,
+    ) -> Result<(), Error> {
+        let slot = self.connection_slots.get(connection)?;
+        self.weights[slot] = weight;
+        if let Some(w) = self.state.plastic_weights.get_mut(slot) {
+            *w = weight;
         }
+        Ok(())
+    }
+}
 
-        self.nodes
-            .iter_mut()
-            .filter(|n| n.node_type == NodeType::Output)
-            .map(|n| n.get_val())
+#[cfg(feature = "simd")]
+const LANES: usize = 8;
+
+#[cfg(feature = "simd")]
+#[derive(Debug, Clone, Copy)]
+enum LaneValue {
+    Accumulator(wide::f32x8),
+    Activated(wide::f32x8),
+}
+
+#[cfg(feature = "simd")]
+impl ConsecutiveNeuralNet {
+    // Evaluating several inputs side by side is only equivalent to
+    // consecutive calls to `evaluate` if no state is carried from one
+    // call to the next, so there may not be any recurrent or plastic
+    // connections, or memory cells.  Lanes also only support summing the incoming
+    // values.
+    fn can_evaluate_lanes(&self) -> bool {
+        self.recurrent_connections.is_empty()
+            && self.plastic_connections.is_empty()
+            && self.nodes.iter().all(|node| node.memory.is_none())
+            && self
+                .nodes
+                .iter()
+                .all(|node| node.aggregation == AggregationFunction::Sum)
+    }
+
+    // Same propagation as `evaluate`, with up to `LANES` input
+    // vectors packed into SIMD lanes.  Unused lanes are zero-filled
+    // and discarded.
+    fn evaluate_lanes(&self, inputs: &[Vec<f32>]) -> Vec<Vec<f32>> {
+        use wide::f32x8;
+
+        let mut values =
+            vec![LaneValue::Accumulator(f32x8::ZERO); self.nodes.len()];
+
+        self.input_indices.iter().enumerate().for_each(|(j, i)| {
+            let mut lanes = [0.0; LANES];
+            lanes.iter_mut().zip(inputs.iter()).for_each(|(lane, x)| {
+                let x = x.get(j).cloned().unwrap_or(0.0);
+                *lane = scale_input(&self.input_scaling, j, x);
+            });
+            values[*i] = LaneValue::Activated(lanes.into());
+        });
+        self.bias_indices.iter().for_each(|i| {
+            values[*i] = LaneValue::Activated(f32x8::splat(self.bias_value));
+        });
+
+        let nodes = &self.nodes;
+        let get_val = |values: &mut [LaneValue], i: usize| match values[i] {
+            LaneValue::Activated(x) => x,
+            LaneValue::Accumulator(x) => {
+                let node = &nodes[i];
+                let x =
+                    f32x8::splat(node.response) * (x + f32x8::splat(node.bias));
+                let output = node.func.apply_x8(x);
+                values[i] = LaneValue::Activated(output);
+                output
+            }
+        };
+
+        self.connections.iter().for_each(|conn| {
+            let val = get_val(&mut values, conn.origin as usize)
+                * f32x8::splat(self.weights[conn.index]);
+            let dest = &mut values[conn.dest as usize];
+            *dest = match *dest {
+                LaneValue::Activated(_) => LaneValue::Accumulator(val),
+                LaneValue::Accumulator(y) => LaneValue::Accumulator(val + y),
+            };
+        });
+
+        let output_lanes = self
+            .output_indices
+            .iter()
+            .map(|i| get_val(&mut values, *i).to_array())
+            .collect::<Vec<_>>();
+
+        (0..inputs.len())
+            .map(|lane| {
+                let mut outputs =
+                    output_lanes.iter().map(|x| x[lane]).collect::<Vec<_>>();
+                self.output_transform.apply(&mut outputs);
+                OutputMapping::map(&self.output_mapping, &outputs)
+            })
             .collect()
     }
 }
@@ -190,8 +977,8 @@ mod test {
             .set_default_activation(ActivationFunction::Identity)
             .add_nodes(NodeType::Input, 2)
             .add_nodes(NodeType::Output, 1)
-            .add_normal_connection(0, 2, 1.0)
-            .add_normal_connection(1, 2, -1.0)
+            .add_normal_connection_raw(0, 2, 1.0)
+            .add_normal_connection_raw(1, 2, -1.0)
             .build::<ConsecutiveNeuralNet>()?;
 
         let res = net.evaluate(&[0.5, 1.5]);
@@ -200,6 +987,142 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_plastic_connection() -> Result<(), Error> {
+        let rule = HebbianRule {
+            learning_rate: 0.5,
+            ..HebbianRule::default()
+        };
+        let mut builder = NeuralNetBuilder::new();
+        builder
+            .set_default_activation(ActivationFunction::Identity)
+            .add_nodes(NodeType::Input, 1)
+            .add_nodes(NodeType::Output, 1)
+            .add_normal_connection_raw(0, 1, 0.5)
+            .set_plasticity(0, Some(rule));
+        let mut net = builder.build::<ConsecutiveNeuralNet>()?;
+        assert!(net.to_builder().approx_eq(&builder, 0.0));
+
+        // Each evaluation adds 0.5 * pre * post to the weight.
+        assert_eq!(net.evaluate(&[1.0]), vec![0.5]);
+        assert_eq!(net.evaluate(&[1.0]), vec![0.75]);
+        assert_eq!(net.plastic_weights(), &[1.125]);
+        assert_eq!(net.weights(), &[0.5]);
+        net.reset_plastic_weights();
+        assert_eq!(net.evaluate(&[1.0]), vec![0.5]);
+
+        let res = builder.build::<crate::LayeredNeuralNet>();
+        assert_eq!(
+            res.err(),
+            Some(Error::UnsupportedPlasticConnection { connection: 0 })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_memory_cell() -> Result<(), Error> {
+        // Stores its input while the input is one, and holds it while
+        // the input is zero.
+        let cell = MemoryCell {
+            update_gate: Gate {
+                input_weight: 20.0,
+                memory_weight: 0.0,
+                bias: -10.0,
+            },
+            memory_weight: 0.0,
+            ..MemoryCell::default()
+        };
+        let mut builder = NeuralNetBuilder::new();
+        builder
+            .set_default_activation(ActivationFunction::Identity)
+            .add_nodes(NodeType::Input, 1)
+            .add_nodes(NodeType::Output, 1)
+            .add_normal_connection_raw(0, 1, 1.0)
+            .set_memory(NodeId(1), Some(cell));
+        let mut net = builder.build::<ConsecutiveNeuralNet>()?;
+
+        assert!(net.evaluate(&[1.0])[0] > 0.99);
+        (0..20).for_each(|_| {
+            net.evaluate(&[0.0]);
+        });
+        assert!(net.evaluate(&[0.0])[0] > 0.99);
+        net.reset_state();
+        assert!(net.evaluate(&[0.0])[0].abs() < 0.01);
+
+        let res = builder.build::<crate::LayeredNeuralNet>();
+        assert_eq!(res.err(), Some(Error::UnsupportedMemoryCell { node: 1 }));
+        Ok(())
+    }
+
+    #[test]
+    fn test_evaluate_sequence() -> Result<(), Error> {
+        // Outputs the running sum of its inputs.
+        let mut net = NeuralNetBuilder::new()
+            .set_default_activation(ActivationFunction::Identity)
+            .add_nodes(NodeType::Input, 1)
+            .add_nodes(NodeType::Output, 1)
+            .add_normal_connection_raw(0, 1, 1.0)
+            .add_recurrent_connection_raw(1, 1, 1.0)
+            .build::<ConsecutiveNeuralNet>()?;
+        let inputs = vec![vec![1.0], vec![2.0], vec![3.0]];
+
+        let expected = vec![vec![1.0], vec![3.0], vec![6.0]];
+        assert_eq!(net.evaluate_sequence(&inputs), expected);
+        assert_eq!(net.evaluate_sequence(&inputs), expected);
+        assert_eq!(
+            net.evaluate_sequence_with_passes(&inputs[..2], 2),
+            vec![vec![2.0], vec![6.0]]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_propagation() -> Result<(), Error> {
+        // Converges to twice its input when evaluated repeatedly.
+        let mut builder = NeuralNetBuilder::new();
+        builder
+            .set_default_activation(ActivationFunction::Identity)
+            .add_nodes(NodeType::Input, 1)
+            .add_nodes(NodeType::Output, 1)
+            .add_normal_connection_raw(0, 1, 1.0)
+            .add_recurrent_connection_raw(1, 1, 0.5)
+            .set_propagation(Propagation::Passes(2));
+        let mut net = builder.build::<ConsecutiveNeuralNet>()?;
+        assert_eq!(net.evaluate(&[1.0]), vec![1.5]);
+
+        builder.set_propagation(Propagation::UntilStable {
+            epsilon: 1e-4,
+            max_passes: 100,
+        });
+        let mut net = builder.build::<ConsecutiveNeuralNet>()?;
+        assert!((net.evaluate(&[1.0])[0] - 2.0).abs() < 1e-3);
+        assert_eq!(net.to_builder().propagation(), builder.propagation());
+        Ok(())
+    }
+
+    #[test]
+    fn test_f64_net() -> Result<(), Error> {
+        let mut builder = NeuralNetBuilder::new();
+        builder
+            .set_default_activation(ActivationFunction::Identity)
+            .add_nodes(NodeType::Input, 3)
+            .add_nodes(NodeType::Output, 1)
+            .add_normal_connection_raw(0, 3, 1.0)
+            .add_normal_connection_raw(1, 3, 1.0)
+            .add_normal_connection_raw(2, 3, -1.0);
+        let inputs = [1e8, 1.0, 1e8];
+
+        // The one is lost in the rounding of `f32`.
+        let mut net = builder.build::<ConsecutiveNeuralNet>()?;
+        let [a, b, c] = inputs.map(|x: f64| x as f32);
+        assert_eq!(net.evaluate(&[a, b, c]), vec![0.0]);
+
+        let net = ConsecutiveNeuralNet::<f64>::from_builder(&builder)?;
+        let mut state = net.new_state();
+        assert_eq!(net.evaluate_with_state(&mut state, &inputs), vec![1.0]);
+        Ok(())
+    }
+
     #[test]
     fn test_multilayered_net() -> Result<(), Error> {
         let func = ActivationFunction::Sigmoid;
@@ -210,8 +1133,8 @@ mod test {
             .add_nodes(NodeType::Output, 1)
             // First connection added first, correct order of
             // evaluation
-            .add_normal_connection(0, 1, 1.0)
-            .add_normal_connection(1, 2, 1.0)
+            .add_normal_connection_raw(0, 1, 1.0)
+            .add_normal_connection_raw(1, 2, 1.0)
             .build::<ConsecutiveNeuralNet>()?;
 
         let res = net.evaluate(&[0.0]);
@@ -224,8 +1147,8 @@ mod test {
             .add_nodes(NodeType::Hidden, 1)
             .add_nodes(NodeType::Output, 1)
             // Second connection added first, needs to be sorted.
-            .add_normal_connection(1, 2, 1.0)
-            .add_normal_connection(0, 1, 1.0)
+            .add_normal_connection_raw(1, 2, 1.0)
+            .add_normal_connection_raw(0, 1, 1.0)
             .build::<ConsecutiveNeuralNet>()?;
 
         let res = net.evaluate(&[0.0]);
@@ -234,4 +1157,374 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    fn test_evaluate_batch() -> Result<(), Error> {
+        let mut builder = NeuralNetBuilder::new();
+        builder
+            .set_default_activation(ActivationFunction::Sigmoid)
+            .add_nodes(NodeType::Input, 2)
+            .add_nodes(NodeType::Hidden, 1)
+            .add_nodes(NodeType::Output, 2)
+            .add_normal_connection_raw(0, 2, 0.5)
+            .add_normal_connection_raw(1, 2, -1.5)
+            .add_normal_connection_raw(2, 3, 2.0)
+            .add_normal_connection_raw(0, 4, 1.0);
+        let bias = builder.add_bias();
+        builder.add_normal_connection(bias, NodeId(4), -0.5);
+
+        // More than one full set of SIMD lanes
+        let inputs = (0..11)
+            .map(|i| vec![0.25 * i as f32, 3.0 - i as f32])
+            .collect::<Vec<_>>();
+
+        let mut net = builder.build::<ConsecutiveNeuralNet>()?;
+        let batched = net.evaluate_batch(&inputs);
+
+        let mut net = builder.build::<ConsecutiveNeuralNet>()?;
+        let individual =
+            inputs.iter().map(|x| net.evaluate(x)).collect::<Vec<_>>();
+
+        // Batches may be evaluated in SIMD lanes, which use slightly
+        // different approximations of the activation functions.
+        assert_eq!(batched.len(), individual.len());
+        batched.iter().zip(individual.iter()).for_each(|(a, b)| {
+            assert_eq!(a.len(), b.len());
+            a.iter()
+                .zip(b.iter())
+                .for_each(|(x, y)| assert!((x - y).abs() < 1e-6));
+        });
+        Ok(())
+    }
+
+    #[test]
+    fn test_shared_network() -> Result<(), Error> {
+        let net = NeuralNetBuilder::new()
+            .set_default_activation(ActivationFunction::Tanh)
+            .add_nodes(NodeType::Input, 1)
+            .add_nodes(NodeType::Hidden, 1)
+            .add_nodes(NodeType::Output, 1)
+            .add_normal_connection_raw(0, 1, 0.5)
+            .add_normal_connection_raw(1, 2, 2.0)
+            .build::<ConsecutiveNeuralNet>()?;
+        let net = &net;
+
+        let results = std::thread::scope(|scope| {
+            let handles = (0..4)
+                .map(|i| {
+                    scope.spawn(move || {
+                        let mut state = net.new_state();
+                        net.evaluate_with_state(&mut state, &[i as f32])
+                    })
+                })
+                .collect::<Vec<_>>();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().unwrap())
+                .collect::<Vec<_>>()
+        });
+
+        let func = ActivationFunction::Tanh;
+        results.iter().enumerate().for_each(|(i, res)| {
+            let expected = func.apply(2.0 * func.apply(0.5 * i as f32));
+            assert_eq!(res, &vec![expected]);
+        });
+        Ok(())
+    }
+
+    #[test]
+    fn test_evaluate_into() -> Result<(), Error> {
+        let mut builder = NeuralNetBuilder::new();
+        builder
+            .set_default_activation(ActivationFunction::Sigmoid)
+            .add_nodes(NodeType::Input, 2)
+            .add_nodes(NodeType::Output, 2)
+            .add_normal_connection_raw(0, 2, 1.0)
+            .add_normal_connection_raw(1, 2, -1.0)
+            .add_normal_connection_raw(1, 3, 0.5);
+
+        let mut net = builder.build::<ConsecutiveNeuralNet>()?;
+        let expected = net.evaluate(&[0.5, 2.0]);
+
+        let mut outputs = [0.0; 2];
+        net.evaluate_into(&[0.5, 2.0], &mut outputs);
+        assert_eq!(outputs.to_vec(), expected);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_bias() -> Result<(), Error> {
+        let mut builder = NeuralNetBuilder::new();
+        builder
+            .set_default_activation(ActivationFunction::Identity)
+            .add_nodes(NodeType::Input, 1);
+        let bias = builder.add_bias();
+        builder
+            .add_nodes(NodeType::Output, 1)
+            .add_normal_connection_raw(0, 2, 1.0)
+            .add_normal_connection(bias, NodeId(2), 0.5);
+
+        let mut net = builder.build::<ConsecutiveNeuralNet>()?;
+        assert_eq!(net.evaluate(&[2.0]), vec![2.5]);
+
+        let mut net = builder
+            .set_bias_value(-2.0)
+            .build::<ConsecutiveNeuralNet>()?;
+        assert_eq!(net.evaluate(&[2.0]), vec![1.0]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_node_bias_and_response() -> Result<(), Error> {
+        let mut builder = NeuralNetBuilder::new();
+        builder
+            .set_default_activation(ActivationFunction::Identity)
+            .add_nodes(NodeType::Input, 1)
+            .add_nodes(NodeType::Output, 2)
+            .add_normal_connection_raw(0, 1, 1.0);
+        builder.nodes[1].bias = 1.0;
+        builder.nodes[1].response = 2.0;
+        // Applies to nodes without incoming connections too
+        builder.nodes[2].bias = -0.5;
+
+        let mut net = builder.build::<ConsecutiveNeuralNet>()?;
+        assert_eq!(net.evaluate(&[1.5]), vec![5.0, -0.5]);
+        assert_eq!(net.evaluate_batch(&[vec![1.5]]), vec![vec![5.0, -0.5]]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_recurrent_state() -> Result<(), Error> {
+        let mut net = NeuralNetBuilder::new()
+            .set_default_activation(ActivationFunction::Identity)
+            .add_nodes(NodeType::Input, 1)
+            .add_nodes(NodeType::Hidden, 2)
+            .add_nodes(NodeType::Output, 1)
+            .add_normal_connection_raw(0, 1, 1.0)
+            .add_normal_connection_raw(1, 3, 1.0)
+            // Loop of recurrent connections, each step delayed by one
+            // evaluation.
+            .add_recurrent_connection_raw(1, 2, 1.0)
+            .add_recurrent_connection_raw(2, 1, 1.0)
+            // Output remembers its own previous value
+            .add_recurrent_connection_raw(3, 3, 0.5)
+            .build::<ConsecutiveNeuralNet>()?;
+
+        // Hidden node 1 receives the input, plus its own value from
+        // two evaluations ago through node 2.
+        assert_eq!(net.evaluate(&[1.0]), vec![1.0]);
+        assert_eq!(net.evaluate(&[1.0]), vec![1.0 + 0.5]);
+        assert_eq!(net.evaluate(&[1.0]), vec![2.0 + 0.75]);
+
+        net.reset_state();
+        assert_eq!(net.evaluate(&[1.0]), vec![1.0]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_long_chain() -> Result<(), Error> {
+        // Connections are added from the end of the chain, so each
+        // has to be moved past all of the others when ordering.
+        let length = 20000;
+        let mut builder = NeuralNetBuilder::new();
+        builder
+            .set_default_activation(ActivationFunction::Identity)
+            .add_nodes(NodeType::Input, 1)
+            .add_nodes(NodeType::Hidden, length)
+            .add_nodes(NodeType::Output, 1);
+        (0..=length).rev().for_each(|i| {
+            builder.add_normal_connection_raw(i, i + 1, 1.0);
+        });
+
+        let mut net = builder.build::<ConsecutiveNeuralNet>()?;
+        assert_eq!(net.evaluate(&[2.5]), vec![2.5]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_add_connection_built() -> Result<(), Error> {
+        let mut builder = NeuralNetBuilder::new();
+        builder
+            .set_default_activation(ActivationFunction::Tanh)
+            .add_nodes(NodeType::Input, 1)
+            .add_nodes(NodeType::Output, 1)
+            .add_nodes(NodeType::Hidden, 3)
+            .add_normal_connection_raw(2, 1, 1.0)
+            .add_normal_connection_raw(0, 3, -1.0)
+            .add_normal_connection_raw(3, 4, 0.5);
+        let mut net = builder.build::<ConsecutiveNeuralNet>()?;
+
+        let hidden =
+            net.add_node_built(NodeType::Hidden, ActivationFunction::Relu);
+        // Fits into the existing order
+        net.add_connection_built(0, hidden, 2.0, ConnectionType::Normal)?;
+        net.add_connection_built(hidden, 1, -0.5, ConnectionType::Normal)?;
+        // Must come before the connection out of node 2, which is
+        // ordered before the connections into node 4, so the order is
+        // recomputed.
+        net.add_connection_built(4, 2, 1.5, ConnectionType::Normal)?;
+        net.add_connection_built(1, hidden, 0.5, ConnectionType::Recurrent)?;
+
+        let added =
+            builder.add_node(NodeType::Hidden, ActivationFunction::Relu);
+        builder
+            .add_normal_connection(NodeId(0), added, 2.0)
+            .add_normal_connection(added, NodeId(1), -0.5)
+            .add_normal_connection_raw(4, 2, 1.5)
+            .add_recurrent_connection(NodeId(1), added, 0.5);
+        let mut expected = builder.build::<ConsecutiveNeuralNet>()?;
+        for inputs in &[[1.0], [-1.0], [0.0]] {
+            assert_eq!(net.evaluate(inputs), expected.evaluate(inputs));
+        }
+
+        // Loops are rejected without changing the network.
+        let res = net.add_connection_built(1, 0, 1.0, ConnectionType::Normal);
+        assert!(matches!(res, Err(Error::ConnectionLoop { .. })));
+        assert_eq!(net.to_builder().connections.len(), 7);
+        let lone =
+            net.add_node_built(NodeType::Hidden, ActivationFunction::Relu);
+        let res =
+            net.add_connection_built(lone, lone, 1.0, ConnectionType::Normal);
+        assert_eq!(res, Err(Error::ConnectionLoop { cycle: vec![7] }));
+
+        // Recomputing the order keeps the names and policies of the
+        // builder, which also apply to the new connections.
+        let mut builder = NeuralNetBuilder::new();
+        builder.add_named_input("x");
+        builder
+            .add_nodes(NodeType::Output, 1)
+            .add_nodes(NodeType::Hidden, 2)
+            .add_normal_connection_raw(2, 1, 1.0)
+            .add_normal_connection_raw(0, 3, 1.0)
+            .set_duplicate_policy(DuplicatePolicy::Reject)
+            .set_weight_bounds(Some(WeightBounds {
+                min: -1.0,
+                max: 1.0,
+                policy: BoundsPolicy::Clamp,
+            }));
+        let mut net = builder.build::<ConsecutiveNeuralNet>()?;
+        net.add_connection_built(3, 2, 5.0, ConnectionType::Normal)?;
+        let rebuilt = net.to_builder();
+        assert_eq!(rebuilt.nodes[0].name.as_deref(), Some("x"));
+        assert_eq!(rebuilt.duplicate_policy(), DuplicatePolicy::Reject);
+        assert_eq!(rebuilt.weight_bounds(), builder.weight_bounds());
+        assert_eq!(rebuilt.connections[2].weight, 1.0);
+        let res = net.add_connection_built(0, 3, 1.0, ConnectionType::Normal);
+        assert_eq!(
+            res,
+            Err(Error::DuplicateConnection {
+                connection: 3,
+                first: 1
+            })
+        );
+        let mut cycle =
+            match net.add_connection_built(1, 0, 1.0, ConnectionType::Normal) {
+                Err(Error::ConnectionLoop { cycle }) => cycle,
+                res => panic!("{:?}", res),
+            };
+        cycle.sort_unstable();
+        assert_eq!(cycle, vec![0, 1, 2, 3]);
+
+        // Recomputing the order doesn't check the bounds against
+        // weights set since building.
+        let mut builder = NeuralNetBuilder::new();
+        builder
+            .add_nodes(NodeType::Input, 1)
+            .add_nodes(NodeType::Output, 1)
+            .add_nodes(NodeType::Hidden, 3)
+            .add_normal_connection_raw(2, 1, 1.0)
+            .add_normal_connection_raw(0, 3, -1.0)
+            .add_normal_connection_raw(3, 4, 0.5)
+            .set_weight_bounds(Some(WeightBounds {
+                min: -1.0,
+                max: 1.0,
+                policy: BoundsPolicy::Reject,
+            }));
+        let mut net = builder.build::<ConsecutiveNeuralNet>()?;
+        net.set_weight(0, 3.0)?;
+        net.add_connection_built(4, 2, 0.5, ConnectionType::Normal)?;
+        let rebuilt = net.to_builder();
+        assert_eq!(rebuilt.connections[0].weight, 3.0);
+        assert_eq!(rebuilt.weight_bounds(), builder.weight_bounds());
+        let res = net.add_connection_built(0, 2, 2.0, ConnectionType::Normal);
+        assert_eq!(
+            res,
+            Err(Error::WeightOutOfBounds {
+                connection: 4,
+                weight: 2.0
+            })
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_introspection() -> Result<(), Error> {
+        let net = NeuralNetBuilder::new()
+            .add_inputs(2)
+            .add_nodes(NodeType::Output, 1)
+            .set_default_activation(ActivationFunction::Tanh)
+            .add_nodes(NodeType::Hidden, 2)
+            .add_normal_connection_raw(3, 2, 0.5)
+            .add_normal_connection_raw(0, 3, 1.0)
+            .add_normal_connection_raw(4, 3, 2.0)
+            .add_recurrent_connection_raw(2, 4, -1.0)
+            .add_normal_connection_raw(1, 4, 3.0)
+            .build::<ConsecutiveNeuralNet>()?;
+
+        assert_eq!((net.num_inputs(), net.num_outputs()), (2, 1));
+        assert_eq!(net.num_hidden(), 2);
+        use ActivationFunction::*;
+        assert_eq!(
+            net.activation_functions().collect::<Vec<_>>(),
+            vec![Identity, Identity, Sigmoid, Tanh, Tanh]
+        );
+
+        let connections = net.connections().collect::<Vec<_>>();
+        assert_eq!(connections.len(), 5);
+        assert_eq!((connections[1].origin, connections[1].dest), (0, 3));
+        assert_eq!(connections[3].weight, -1.0);
+        assert_eq!(connections[3].connection_type, ConnectionType::Recurrent);
+
+        // The longest path is 1 -> 4 -> 3 -> 2.
+        assert_eq!(net.node_depths(), vec![0, 0, 3, 2, 1]);
+        assert_eq!(net.depth(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_weight() -> Result<(), Error> {
+        let mut builder = NeuralNetBuilder::new();
+        builder
+            .set_default_activation(ActivationFunction::Identity)
+            .add_nodes(NodeType::Input, 1)
+            .add_nodes(NodeType::Output, 1)
+            .add_recurrent_connection_raw(1, 1, 0.5)
+            .add_normal_connection_raw(0, 1, 1.0);
+        let mut net = builder.build::<ConsecutiveNeuralNet>()?;
+
+        net.set_weight(1, 2.0)?;
+        net.weights_mut()[0] = 0.25;
+        assert_eq!(net.weights(), &[0.25, 2.0]);
+        assert_eq!(net.evaluate(&[1.0]), vec![2.0]);
+
+        // Clones carry their own weights and recurrent state.
+        let mut copy = net.clone();
+        copy.set_weight(1, 0.0)?;
+        assert_eq!(copy.evaluate(&[1.0]), vec![0.5]);
+        assert_eq!(net.evaluate(&[1.0]), vec![2.5]);
+
+        let builder = net.to_builder();
+        assert!(builder.approx_eq(&builder.clone(), 0.0));
+        assert_eq!(builder.connections[0].weight, 0.25);
+        assert_eq!(
+            builder.connections[0].connection_type,
+            ConnectionType::Recurrent
+        );
+        assert!(net.set_weight(2, 1.0).is_err());
+        Ok(())
+    }
 }
@@ -0,0 +1,520 @@
+use wgpu::util::DeviceExt;
+
+use crate::neural_net::*;
+use crate::neural_net_sparse::SparseNeuralNet;
+
+// Each invocation evaluates the entire network for one input vector,
+// walking the same layer-sorted CSR rows as `SparseNeuralNet`.  This
+// suits large batches of inputs through a single network, such as
+// querying a CPPN at every point of a substrate.
+const SHADER: &str = r#"
+@group(0) @binding(0) var<storage, read> params: array<u32>;
+@group(0) @binding(1) var<storage, read> row_starts: array<u32>;
+@group(0) @binding(2) var<storage, read> columns: array<u32>;
+@group(0) @binding(3) var<storage, read> weights: array<f32>;
+// Per row, the activation function code, the bits of its parameter,
+// the aggregation function code, and the bits of the node's bias and
+// response.
+@group(0) @binding(4) var<storage, read> funcs: array<u32>;
+@group(0) @binding(5) var<storage, read> output_positions: array<u32>;
+@group(0) @binding(6) var<storage, read_write> values: array<f32>;
+@group(0) @binding(7) var<storage, read_write> outputs: array<f32>;
+
+fn activate(func: u32, param: f32, x: f32) -> f32 {
+    switch func {
+        case 0u: { return 1.0 / (1.0 + exp(-x)); }
+        case 1u: { return x; }
+        case 2u: { return tanh(x); }
+        case 3u: { return max(x, 0.0); }
+        case 4u: { return exp(-x * x / 2.0); }
+        case 5u: { return sin(x); }
+        case 6u: { return cos(x); }
+        case 7u: { return abs(x); }
+        case 8u: { return x * x; }
+        case 9u: { return select(param * x, x, x > 0.0); }
+        case 10u: { return select(param * (exp(x) - 1.0), x, x > 0.0); }
+        case 12u: { return select(0.0, 1.0, x > 0.0); }
+        case 13u: { return max(x, 0.0) + log(1.0 + exp(-abs(x))); }
+        case 14u: { return select(sin(x) / x, 1.0, x == 0.0); }
+        case 15u: { return clamp(x, -1.0, 1.0); }
+        case 16u: { return select(1.0 / x, 0.0, x == 0.0); }
+        case 17u: { return log(max(x, 1e-7)); }
+        default: { return 1.0 / (1.0 + exp(-param * x)); }
+    }
+}
+
+// Same as `AggregationFunction::combine`.
+fn combine(aggregation: u32, acc: f32, count: u32, x: f32) -> f32 {
+    if (aggregation == 5u) {
+        return acc + abs(x);
+    }
+    if (count == 0u) {
+        return x;
+    }
+    switch aggregation {
+        case 1u: { return acc * x; }
+        case 2u: { return min(acc, x); }
+        case 3u: { return max(acc, x); }
+        default: { return acc + x; }
+    }
+}
+
+@compute @workgroup_size(64)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let num_samples = params[0];
+    let num_nodes = params[1];
+    let first_row = params[2];
+    let num_rows = params[3];
+    let num_outputs = params[4];
+
+    let sample = id.x;
+    if (sample >= num_samples) {
+        return;
+    }
+
+    let base = sample * num_nodes;
+    for (var row = 0u; row < num_rows; row++) {
+        let node = 5u * row;
+        let aggregation = funcs[node + 2u];
+        let start = row_starts[row];
+        let end = row_starts[row + 1u];
+        var acc = 0.0;
+        for (var i = start; i < end; i++) {
+            let x = weights[i] * values[base + columns[i]];
+            acc = combine(aggregation, acc, i - start, x);
+        }
+        if (aggregation == 4u && end > start) {
+            acc /= f32(end - start);
+        }
+        let bias = bitcast<f32>(funcs[node + 3u]);
+        let response = bitcast<f32>(funcs[node + 4u]);
+        let x = response * (acc + bias);
+        let param = bitcast<f32>(funcs[node + 1u]);
+        values[base + first_row + row] = activate(funcs[node], param, x);
+    }
+
+    for (var k = 0u; k < num_outputs; k++) {
+        outputs[sample * num_outputs + k] =
+            values[base + output_positions[k]];
+    }
+}
+"#;
+
+const WORKGROUP_SIZE: usize = 64;
+
+// Largest number of samples evaluated by a single dispatch, which is
+// limited both by the number of workgroups per dispatch and by the
+// size of the buffers holding every sample's node values and outputs.
+// Larger batches are split into several dispatches.
+fn samples_per_dispatch(
+    limits: &wgpu::Limits,
+    num_nodes: usize,
+    num_outputs: usize,
+) -> usize {
+    let by_workgroups =
+        limits.max_compute_workgroups_per_dimension as usize * WORKGROUP_SIZE;
+    let bytes_per_sample =
+        num_nodes.max(num_outputs).max(1) * std::mem::size_of::<f32>();
+    let max_binding = limits.max_storage_buffer_binding_size as usize;
+    by_workgroups.min(max_binding / bytes_per_sample).max(1)
+}
+
+// Function code and parameter, as read by `activate` in the shader.
+fn gpu_code(func: ActivationFunction) -> Option<(u32, f32)> {
+    use ActivationFunction::*;
+    match func {
+        Sigmoid => Some((0, 0.0)),
+        Identity => Some((1, 0.0)),
+        Tanh => Some((2, 0.0)),
+        Relu => Some((3, 0.0)),
+        Gaussian => Some((4, 0.0)),
+        Sin => Some((5, 0.0)),
+        Cos => Some((6, 0.0)),
+        Abs => Some((7, 0.0)),
+        Square => Some((8, 0.0)),
+        LeakyRelu(alpha) => Some((9, alpha)),
+        Elu(alpha) => Some((10, alpha)),
+        SteepenedSigmoid(slope) => Some((11, slope)),
+        Step => Some((12, 0.0)),
+        Softplus => Some((13, 0.0)),
+        Sinc => Some((14, 0.0)),
+        Clamped => Some((15, 0.0)),
+        Inverse => Some((16, 0.0)),
+        Log => Some((17, 0.0)),
+        Custom(_) => None,
+    }
+}
+
+fn aggregation_code(aggregation: AggregationFunction) -> u32 {
+    use AggregationFunction::*;
+    match aggregation {
+        Sum => 0,
+        Product => 1,
+        Min => 2,
+        Max => 3,
+        Mean => 4,
+        AbsSum => 5,
+    }
+}
+
+// Storage buffers may not be empty, so pad every uploaded array to
+// at least one element.
+fn padded<T: Copy + Default>(values: impl Iterator<Item = T>) -> Vec<T> {
+    let mut values = values.collect::<Vec<_>>();
+    if values.is_empty() {
+        values.push(T::default());
+    }
+    values
+}
+
+#[derive(Debug)]
+pub struct GpuNeuralNet {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    pipeline: wgpu::ComputePipeline,
+    row_starts: wgpu::Buffer,
+    columns: wgpu::Buffer,
+    weights: wgpu::Buffer,
+    funcs: wgpu::Buffer,
+    output_positions: wgpu::Buffer,
+    // Position in `weights` of each builder connection.
+    weight_slots: ConnectionSlots,
+    // Node values before evaluation, with every bias node set.
+    initial_values: Vec<f32>,
+    first_row: usize,
+    num_rows: usize,
+    input_indices: Vec<usize>,
+    input_scaling: Vec<InputScaling>,
+    num_outputs: usize,
+    output_transform: OutputTransform,
+    output_mapping: Vec<OutputMapping>,
+    samples_per_dispatch: usize,
+}
+
+impl GpuNeuralNet {
+    fn storage_buffer<T: bytemuck::Pod>(
+        device: &wgpu::Device,
+        contents: &[T],
+        usage: wgpu::BufferUsages,
+    ) -> wgpu::Buffer {
+        device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(contents),
+            usage: wgpu::BufferUsages::STORAGE | usage,
+        })
+    }
+
+    fn evaluate_dispatch(&self, inputs: &[Vec<f32>]) -> Vec<Vec<f32>> {
+        let num_samples = inputs.len();
+
+        // Inputs that aren't provided are treated as zero, before
+        // scaling.
+        let num_nodes = self.initial_values.len();
+        let mut values = self.initial_values.repeat(num_samples);
+        inputs.iter().enumerate().for_each(|(sample, x)| {
+            let base = sample * num_nodes;
+            self.input_indices.iter().enumerate().for_each(|(j, i)| {
+                let x = x.get(j).cloned().unwrap_or(0.0);
+                values[base + i] = scale_input(&self.input_scaling, j, x);
+            });
+        });
+
+        let params = [
+            num_samples,
+            num_nodes,
+            self.first_row,
+            self.num_rows,
+            self.num_outputs,
+        ]
+        .iter()
+        .map(|x| *x as u32)
+        .collect::<Vec<_>>();
+
+        let device = &self.device;
+        let params =
+            Self::storage_buffer(device, &params, wgpu::BufferUsages::empty());
+        let values = Self::storage_buffer(
+            device,
+            &padded(values.into_iter()),
+            wgpu::BufferUsages::empty(),
+        );
+        let output_size = (num_samples * self.num_outputs).max(1)
+            * std::mem::size_of::<f32>();
+        let outputs = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: output_size as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let readback = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: output_size as u64,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &self.pipeline.get_bind_group_layout(0),
+            entries: &[
+                &params,
+                &self.row_starts,
+                &self.columns,
+                &self.weights,
+                &self.funcs,
+                &self.output_positions,
+                &values,
+                &outputs,
+            ]
+            .iter()
+            .enumerate()
+            .map(|(i, buffer)| wgpu::BindGroupEntry {
+                binding: i as u32,
+                resource: buffer.as_entire_binding(),
+            })
+            .collect::<Vec<_>>(),
+        });
+
+        let mut encoder = device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+        {
+            let mut pass = encoder
+                .begin_compute_pass(&wgpu::ComputePassDescriptor::default());
+            pass.set_pipeline(&self.pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            let num_workgroups = num_samples.div_ceil(WORKGROUP_SIZE);
+            pass.dispatch_workgroups(num_workgroups as u32, 1, 1);
+        }
+        encoder.copy_buffer_to_buffer(
+            &outputs,
+            0,
+            &readback,
+            0,
+            output_size as u64,
+        );
+        self.queue.submit(Some(encoder.finish()));
+
+        readback.map_async(wgpu::MapMode::Read, .., |_| {});
+        device
+            .poll(wgpu::PollType::wait_indefinitely())
+            .expect("GPU device lost during evaluation");
+
+        let flat = {
+            let view = readback
+                .get_mapped_range(..)
+                .expect("GPU output buffer could not be mapped");
+            bytemuck::cast_slice::<u8, f32>(&view).to_vec()
+        };
+        readback.unmap();
+
+        flat.chunks(self.num_outputs.max(1))
+            .take(num_samples)
+            .map(|x| {
+                let mut outputs = x[..self.num_outputs].to_vec();
+                self.output_transform.apply(&mut outputs);
+                OutputMapping::map(&self.output_mapping, &outputs)
+            })
+            .collect()
+    }
+}
+
+impl NeuralNet for GpuNeuralNet {
+    fn build_from(builder: &NeuralNetBuilder) -> Result<Self, Error> {
+        let sparse = SparseNeuralNet::build_from(builder)?;
+
+        let funcs = (0..sparse.funcs.len())
+            .map(|row| {
+                let func = sparse.funcs[row];
+                gpu_code(func)
+                    .map(|(code, param)| {
+                        [
+                            code,
+                            param.to_bits(),
+                            aggregation_code(sparse.aggregations[row]),
+                            sparse.biases[row].to_bits(),
+                            sparse.responses[row].to_bits(),
+                        ]
+                    })
+                    .ok_or(Error::UnsupportedActivationFunction { func })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let instance = wgpu::Instance::new(
+            wgpu::InstanceDescriptor::new_without_display_handle(),
+        );
+        let adapter = pollster::block_on(
+            instance.request_adapter(&wgpu::RequestAdapterOptions::default()),
+        )
+        .map_err(|_| Error::GpuUnavailable)?;
+        let (device, queue) = pollster::block_on(
+            adapter.request_device(&wgpu::DeviceDescriptor::default()),
+        )
+        .map_err(|_| Error::GpuUnavailable)?;
+
+        let module =
+            device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: None,
+                source: wgpu::ShaderSource::Wgsl(SHADER.into()),
+            });
+        let pipeline =
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: None,
+                layout: None,
+                module: &module,
+                entry_point: Some("main"),
+                compilation_options: Default::default(),
+                cache: None,
+            });
+
+        let as_u32 = |x: &[usize]| padded(x.iter().map(|x| *x as u32));
+        let usage = wgpu::BufferUsages::empty();
+
+        Ok(Self {
+            row_starts: Self::storage_buffer(
+                &device,
+                &as_u32(&sparse.row_starts),
+                usage,
+            ),
+            columns: Self::storage_buffer(
+                &device,
+                &as_u32(&sparse.columns),
+                usage,
+            ),
+            weights: Self::storage_buffer(
+                &device,
+                &padded(sparse.weights.iter().cloned()),
+                wgpu::BufferUsages::COPY_DST,
+            ),
+            funcs: Self::storage_buffer(
+                &device,
+                &padded(funcs.into_iter().flatten()),
+                usage,
+            ),
+            output_positions: Self::storage_buffer(
+                &device,
+                &as_u32(&sparse.output_indices),
+                usage,
+            ),
+            weight_slots: sparse.slots.clone(),
+            initial_values: sparse.values.clone(),
+            first_row: sparse.first_row,
+            num_rows: sparse.funcs.len(),
+            input_indices: sparse.input_indices.clone(),
+            input_scaling: sparse.input_scaling.clone(),
+            num_outputs: sparse.output_indices.len(),
+            output_transform: sparse.output_transform,
+            output_mapping: sparse.output_mapping.clone(),
+            samples_per_dispatch: samples_per_dispatch(
+                &device.limits(),
+                sparse.values.len(),
+                sparse.output_indices.len(),
+            ),
+            device,
+            queue,
+            pipeline,
+        })
+    }
+
+    fn num_inputs(&self) -> usize {
+        self.input_indices.len()
+    }
+
+    fn num_outputs(&self) -> usize {
+        OutputMapping::num_mapped(&self.output_mapping, self.num_outputs)
+    }
+
+    fn evaluate(&mut self, inputs: &[f32]) -> Vec<f32> {
+        self.evaluate_dispatch(&[inputs.to_vec()])
+            .pop()
+            .unwrap_or_default()
+    }
+
+    fn evaluate_batch(&mut self, inputs: &[Vec<f32>]) -> Vec<Vec<f32>> {
+        inputs
+            .chunks(self.samples_per_dispatch)
+            .flat_map(|chunk| self.evaluate_dispatch(chunk))
+            .collect()
+    }
+
+    fn set_weight(
+        &mut self,
+        connection: usize,
+        weight: f32,
+    ) -> Result<(), Error> {
+        let slot = self.weight_slots.get(connection)?;
+        let offset = (slot * std::mem::size_of::<f32>()) as u64;
+        self.queue.write_buffer(
+            &self.weights,
+            offset,
+            bytemuck::bytes_of(&weight),
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ConsecutiveNeuralNet;
+
+    #[test]
+    fn test_matches_consecutive() -> Result<(), Error> {
+        let mut builder = NeuralNetBuilder::new();
+        builder
+            .set_default_activation(ActivationFunction::Sigmoid)
+            .add_nodes(NodeType::Input, 2)
+            .add_node(NodeType::Hidden, ActivationFunction::LeakyRelu(0.1));
+        builder.add_nodes(NodeType::Output, 2);
+        builder.add_node(NodeType::Hidden, ActivationFunction::Gaussian);
+        builder.add_bias();
+        builder
+            .set_output_transform(OutputTransform::Softmax)
+            .add_normal_connection_raw(6, 5, 0.5)
+            .add_normal_connection_raw(0, 2, 1.5)
+            .add_normal_connection_raw(1, 2, -0.5)
+            .add_normal_connection_raw(2, 5, 2.0)
+            .add_normal_connection_raw(5, 3, 1.0)
+            .add_normal_connection_raw(1, 4, -1.0);
+        builder.nodes[2].aggregation = AggregationFunction::Min;
+        builder.nodes[5].aggregation = AggregationFunction::Mean;
+        builder.nodes[2].bias = 0.25;
+        builder.nodes[3].response = 2.0;
+
+        // Not every test machine has a GPU adapter available.
+        let mut gpu = match builder.build::<GpuNeuralNet>() {
+            Err(Error::GpuUnavailable) => return Ok(()),
+            res => res?,
+        };
+        let mut consecutive = builder.build::<ConsecutiveNeuralNet>()?;
+        gpu.set_weight(3, -1.0)?;
+        consecutive.set_weight(3, -1.0)?;
+
+        let inputs = (0..100)
+            .map(|i| vec![0.1 * i as f32, 1.0 - 0.05 * i as f32])
+            .collect::<Vec<_>>();
+        let res = gpu.evaluate_batch(&inputs);
+        assert_eq!(res.len(), inputs.len());
+
+        res.iter().zip(inputs.iter()).for_each(|(res, x)| {
+            let expected = consecutive.evaluate(x);
+            assert_eq!(res.len(), expected.len());
+            res.iter()
+                .zip(expected.iter())
+                .for_each(|(a, b)| assert!((a - b).abs() < 1e-5));
+        });
+
+        Ok(())
+    }
+    #[test]
+    fn test_samples_per_dispatch() {
+        let limits = wgpu::Limits {
+            max_compute_workgroups_per_dimension: 100,
+            max_storage_buffer_binding_size: 1 << 20,
+            ..wgpu::Limits::default()
+        };
+        assert_eq!(samples_per_dispatch(&limits, 10, 2), 100 * WORKGROUP_SIZE);
+        // A megabyte holds the node values of 2048 samples of 128 nodes.
+        assert_eq!(samples_per_dispatch(&limits, 128, 4), 2048);
+        assert_eq!(samples_per_dispatch(&limits, 1 << 20, 4), 1);
+    }
+}
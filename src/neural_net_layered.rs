@@ -0,0 +1,385 @@
+use alloc::collections::VecDeque;
+#[cfg(not(feature = "std"))]
+use alloc::{vec, vec::Vec};
+
+use crate::neural_net::*;
+
+#[derive(Debug, Clone)]
+struct Layer {
+    // Position of the first node in this layer within `values`.
+    // Every node before it belongs to an earlier layer, and may be
+    // the origin of a connection into this layer.
+    start: usize,
+    funcs: Vec<ActivationFunction>,
+    // Ranges of consecutive rows with the same function, which is
+    // applied to the whole range at once.
+    runs: Vec<(usize, usize)>,
+    biases: Vec<f32>,
+    responses: Vec<f32>,
+    // Row-major, `funcs.len()` rows by `start` columns.
+    weights: Vec<f32>,
+}
+
+impl Layer {
+    fn size(&self) -> usize {
+        self.funcs.len()
+    }
+}
+
+// Evaluates a feed-forward network as a sequence of dense
+// matrix-vector products, one per layer.  Nodes are stored sorted by
+// layer, so the inputs to a layer are always a prefix of the node
+// values.
+#[derive(Debug, Clone)]
+pub struct LayeredNeuralNet {
+    values: Vec<f32>,
+    layers: Vec<Layer>,
+    // Weight of each resolved connection, and the layer and position
+    // in that layer's matrix it was added to.  Duplicate connections
+    // share a matrix entry holding the sum of their weights.
+    weights: Vec<f32>,
+    slots: Vec<(usize, usize)>,
+    // Index in `weights` of each connection of the builder.
+    connection_slots: ConnectionSlots,
+    input_indices: Vec<usize>,
+    input_scaling: Vec<InputScaling>,
+    output_indices: Vec<usize>,
+    output_transform: OutputTransform,
+    output_mapping: Vec<OutputMapping>,
+}
+
+// Depth of each node, where input and bias nodes are at depth zero
+// and every other node is one deeper than the deepest node feeding
+// into it.
+pub(crate) fn node_depths(
+    builder: &NeuralNetBuilder,
+) -> Result<Vec<usize>, Error> {
+    let num_nodes = builder.nodes.len();
+
+    let mut outgoing = vec![Vec::new(); num_nodes];
+    let mut num_incoming = vec![0; num_nodes];
+    builder.connections.iter().for_each(|conn| {
+        outgoing[conn.origin as usize].push(conn.dest as usize);
+        num_incoming[conn.dest as usize] += 1;
+    });
+
+    let mut depths = builder
+        .nodes
+        .iter()
+        .map(|n| match n.node_type {
+            NodeType::Input | NodeType::Bias => 0,
+            _ => 1,
+        })
+        .collect::<Vec<_>>();
+
+    let mut ready = (0..num_nodes)
+        .filter(|i| num_incoming[*i] == 0)
+        .collect::<VecDeque<_>>();
+    let mut num_visited = 0;
+
+    while let Some(node) = ready.pop_front() {
+        num_visited += 1;
+        outgoing[node].iter().for_each(|dest| {
+            depths[*dest] = depths[*dest].max(depths[node] + 1);
+            num_incoming[*dest] -= 1;
+            if num_incoming[*dest] == 0 {
+                ready.push_back(*dest);
+            }
+        });
+    }
+
+    if num_visited == num_nodes {
+        Ok(depths)
+    } else {
+        Err(Error::ConnectionLoop {
+            cycle: find_connection_loop(&builder.connections)
+                .unwrap_or_default(),
+        })
+    }
+}
+
+// Node indices sorted by depth, along with the inverse mapping from
+// node index to sorted position.  The sort is stable, so nodes within
+// a layer keep the order they were added to the builder.
+pub(crate) fn layer_order(depths: &[usize]) -> (Vec<usize>, Vec<usize>) {
+    let mut order = (0..depths.len()).collect::<Vec<_>>();
+    order.sort_by_key(|i| depths[*i]);
+
+    let mut position = vec![0; order.len()];
+    order
+        .iter()
+        .enumerate()
+        .for_each(|(pos, node)| position[*node] = pos);
+
+    (order, position)
+}
+
+impl LayeredNeuralNet {
+    fn from_resolved(builder: &NeuralNetBuilder) -> Result<Self, Error> {
+        builder.check_node_indices()?;
+        builder.check_feed_forward()?;
+        builder.check_sum_aggregation()?;
+
+        let depths = node_depths(builder)?;
+        let (order, position) = layer_order(&depths);
+
+        let num_layers = depths.iter().max().map_or(0, |d| d + 1);
+        let mut layers = (1..num_layers)
+            .map(|depth| {
+                let start = order
+                    .iter()
+                    .position(|i| depths[*i] >= depth)
+                    .unwrap_or(order.len());
+                let nodes = order
+                    .iter()
+                    .filter(|i| depths[**i] == depth)
+                    .map(|i| &builder.nodes[*i])
+                    .collect::<Vec<_>>();
+                let funcs = nodes.iter().map(|n| n.func).collect::<Vec<_>>();
+                let mut runs = Vec::new();
+                funcs.chunk_by(|a, b| a == b).fold(0, |row, run| {
+                    runs.push((row, row + run.len()));
+                    row + run.len()
+                });
+                Layer {
+                    start,
+                    funcs,
+                    runs,
+                    biases: nodes.iter().map(|n| n.bias).collect(),
+                    responses: nodes.iter().map(|n| n.response).collect(),
+                    weights: vec![0.0; nodes.len() * start],
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let slots = builder
+            .connections
+            .iter()
+            .map(|conn| {
+                let index = depths[conn.dest as usize] - 1;
+                let layer = &mut layers[index];
+                let row = position[conn.dest as usize] - layer.start;
+                let col = position[conn.origin as usize];
+                layer.weights[row * layer.start + col] += conn.weight;
+                (index, row * layer.start + col)
+            })
+            .collect();
+
+        let indices_of_type = |node_type: NodeType| {
+            builder
+                .nodes
+                .iter()
+                .enumerate()
+                .filter(|(_i, n)| n.node_type == node_type)
+                .map(|(i, _n)| position[i])
+                .collect::<Vec<_>>()
+        };
+
+        // Bias values are never overwritten, so are only set once.
+        let mut values = vec![0.0; order.len()];
+        indices_of_type(NodeType::Bias)
+            .iter()
+            .for_each(|i| values[*i] = builder.bias_value());
+
+        Ok(Self {
+            values,
+            layers,
+            weights: builder.connections.iter().map(|c| c.weight).collect(),
+            slots,
+            connection_slots: ConnectionSlots::default(),
+            input_indices: indices_of_type(NodeType::Input),
+            input_scaling: builder.input_scaling().to_vec(),
+            output_indices: indices_of_type(NodeType::Output),
+            output_transform: builder.output_transform(),
+            output_mapping: builder.output_mapping().to_vec(),
+        })
+    }
+}
+
+impl NeuralNet for LayeredNeuralNet {
+    fn build_from(builder: &NeuralNetBuilder) -> Result<Self, Error> {
+        let resolved = builder.resolve_connections()?;
+        let mut net = Self::from_resolved(&resolved)
+            .map_err(|error| resolved.original_error(error))?;
+        net.connection_slots = resolved.slots;
+        Ok(net)
+    }
+
+    fn num_inputs(&self) -> usize {
+        self.input_indices.len()
+    }
+
+    fn num_outputs(&self) -> usize {
+        OutputMapping::num_mapped(
+            &self.output_mapping,
+            self.output_indices.len(),
+        )
+    }
+
+    fn evaluate(&mut self, inputs: &[f32]) -> Vec<f32> {
+        let mut outputs = vec![0.0; self.num_outputs()];
+        self.evaluate_into(inputs, &mut outputs);
+        outputs
+    }
+
+    fn evaluate_into(&mut self, inputs: &[f32], outputs: &mut [f32]) {
+        let values = &mut self.values;
+        let scaling = &self.input_scaling;
+
+        self.input_indices.iter().enumerate().for_each(|(j, i)| {
+            let x = inputs.get(j).cloned().unwrap_or(0.0);
+            values[*i] = scale_input(scaling, j, x);
+        });
+
+        self.layers.iter().for_each(|layer| {
+            let (sources, rest) = values.split_at_mut(layer.start);
+            (0..layer.size()).for_each(|row| {
+                let weights =
+                    &layer.weights[row * layer.start..(row + 1) * layer.start];
+                let sum = weights
+                    .iter()
+                    .zip(sources.iter())
+                    .map(|(w, x)| w * x)
+                    .sum::<f32>();
+                rest[row] = layer.responses[row] * (sum + layer.biases[row]);
+            });
+            layer.runs.iter().for_each(|(first, end)| {
+                layer.funcs[*first].apply_slice(&mut rest[*first..*end]);
+            });
+        });
+
+        finish_outputs(
+            self.output_transform,
+            &self.output_mapping,
+            self.output_indices.iter().map(|i| values[*i]),
+            outputs,
+        );
+    }
+
+    fn set_weight(
+        &mut self,
+        connection: usize,
+        weight: f32,
+    ) -> Result<(), Error> {
+        let index = self.connection_slots.get(connection)?;
+        let (layer, slot) = self.slots[index];
+        self.layers[layer].weights[slot] += weight - self.weights[index];
+        self.weights[index] = weight;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ConsecutiveNeuralNet;
+
+    #[test]
+    fn test_matches_consecutive() -> Result<(), Error> {
+        let mut builder = NeuralNetBuilder::new();
+        builder
+            .set_default_activation(ActivationFunction::Tanh)
+            .add_nodes(NodeType::Input, 3)
+            .add_nodes(NodeType::Output, 2)
+            .add_nodes(NodeType::Hidden, 3)
+            .set_bias_value(0.5)
+            .set_output_transform(OutputTransform::Softmax)
+            .add_bias();
+        builder
+            .add_normal_connection_raw(8, 6, 1.0)
+            .add_normal_connection_raw(0, 5, 0.5)
+            .add_normal_connection_raw(1, 5, -1.0)
+            .add_normal_connection_raw(1, 6, 0.75)
+            .add_normal_connection_raw(5, 6, 2.0)
+            .add_normal_connection_raw(6, 7, -0.5)
+            .add_normal_connection_raw(2, 7, 1.5)
+            .add_normal_connection_raw(7, 3, 1.0)
+            .add_normal_connection_raw(5, 3, -2.0)
+            // Skips over several layers
+            .add_normal_connection_raw(0, 4, 0.25)
+            .add_normal_connection_raw(7, 4, 1.0);
+        builder.nodes[5].bias = 0.5;
+        builder.nodes[6].response = -1.5;
+
+        let mut consecutive = builder.build::<ConsecutiveNeuralNet>()?;
+        let mut layered = builder.build::<LayeredNeuralNet>()?;
+
+        for inputs in &[[0.0, 0.0, 0.0], [1.0, -0.5, 2.0], [-3.0, 0.1, 0.2]] {
+            let expected = consecutive.evaluate(inputs);
+            let res = layered.evaluate(inputs);
+            assert_eq!(res.len(), expected.len());
+            res.iter()
+                .zip(expected.iter())
+                .for_each(|(a, b)| assert!((a - b).abs() < 1e-6));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rejects_loops() {
+        let res = NeuralNetBuilder::new()
+            .add_nodes(NodeType::Input, 1)
+            .add_nodes(NodeType::Hidden, 2)
+            .add_nodes(NodeType::Output, 1)
+            .add_normal_connection_raw(0, 1, 1.0)
+            .add_normal_connection_raw(1, 2, 1.0)
+            .add_normal_connection_raw(2, 1, 1.0)
+            .add_normal_connection_raw(2, 3, 1.0)
+            .build::<LayeredNeuralNet>();
+        assert_eq!(
+            res.err(),
+            Some(Error::ConnectionLoop { cycle: vec![1, 2] })
+        );
+
+        let res = NeuralNetBuilder::new()
+            .add_nodes(NodeType::Input, 1)
+            .add_nodes(NodeType::Output, 1)
+            .add_normal_connection_raw(0, 1, 1.0)
+            .add_recurrent_connection_raw(1, 1, 1.0)
+            .build::<LayeredNeuralNet>();
+        assert!(matches!(
+            res,
+            Err(Error::UnsupportedRecurrentConnection { .. })
+        ));
+
+        let res = NeuralNetBuilder::new()
+            .set_default_aggregation(AggregationFunction::Max)
+            .add_nodes(NodeType::Input, 1)
+            .add_nodes(NodeType::Output, 1)
+            .add_normal_connection_raw(0, 1, 1.0)
+            .build::<LayeredNeuralNet>();
+        assert_eq!(
+            res.err(),
+            Some(Error::UnsupportedAggregationFunction {
+                aggregation: AggregationFunction::Max
+            })
+        );
+    }
+
+    #[test]
+    fn test_set_weight() -> Result<(), Error> {
+        let mut builder = NeuralNetBuilder::new();
+        builder
+            .set_default_activation(ActivationFunction::Identity)
+            .add_nodes(NodeType::Input, 2)
+            .add_nodes(NodeType::Output, 1)
+            .add_normal_connection_raw(0, 2, 1.0)
+            .add_normal_connection_raw(1, 2, 2.0)
+            // Shares a matrix entry with the first connection
+            .add_normal_connection_raw(0, 2, 4.0);
+        let mut net = builder.build::<LayeredNeuralNet>()?;
+
+        net.set_weight(2, -0.5)?;
+        builder.connections[2].weight = -0.5;
+        let mut rebuilt = builder.build::<LayeredNeuralNet>()?;
+        assert_eq!(net.evaluate(&[1.0, 1.0]), rebuilt.evaluate(&[1.0, 1.0]));
+        assert_eq!(net.evaluate(&[1.0, 1.0]), vec![2.5]);
+
+        assert_eq!(
+            net.set_weight(3, 1.0),
+            Err(Error::InvalidConnectionIndex { index: 3 })
+        );
+        Ok(())
+    }
+}
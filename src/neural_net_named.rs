@@ -0,0 +1,133 @@
+use alloc::collections::BTreeMap;
+#[cfg(not(feature = "std"))]
+use alloc::{
+    borrow::ToOwned,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+
+use crate::neural_net::*;
+
+// A network that remembers the names its builder gave to the inputs
+// and outputs, so that it can be evaluated with values looked up by
+// name rather than by position.
+#[derive(Debug, Clone)]
+pub struct NamedNeuralNet<N> {
+    pub net: N,
+    input_names: Vec<Option<String>>,
+    output_names: Vec<Option<String>>,
+}
+
+fn owned(names: Vec<Option<&str>>) -> Vec<Option<String>> {
+    names
+        .into_iter()
+        .map(|name| name.map(|name| name.to_owned()))
+        .collect()
+}
+
+impl<N: NeuralNet> NamedNeuralNet<N> {
+    pub fn build_from(builder: &NeuralNetBuilder) -> Result<Self, Error> {
+        Ok(Self {
+            net: N::build_from(builder)?,
+            input_names: owned(builder.input_names()),
+            output_names: owned(builder.output_names()),
+        })
+    }
+
+    // Position of the named input in the slice passed to `evaluate`.
+    pub fn input_index(&self, name: &str) -> Option<usize> {
+        self.input_names
+            .iter()
+            .position(|n| n.as_deref() == Some(name))
+    }
+
+    // Position of the named output in the result of `evaluate`.
+    pub fn output_index(&self, name: &str) -> Option<usize> {
+        self.output_names
+            .iter()
+            .position(|n| n.as_deref() == Some(name))
+    }
+
+    // Looks up a named output in the result of `evaluate`.
+    pub fn output(&self, outputs: &[f32], name: &str) -> Option<f32> {
+        self.output_index(name)
+            .and_then(|i| outputs.get(i).copied())
+    }
+
+    // Evaluates with a value for every input, such as from a
+    // `HashMap` or `BTreeMap` of names to values, and returns the
+    // named outputs.  Unknown names and inputs without a value are
+    // errors rather than being ignored or left at zero, so that
+    // misspelled names can't go unnoticed.
+    pub fn evaluate_named<'a, K: AsRef<str>>(
+        &mut self,
+        inputs: impl IntoIterator<Item = (K, &'a f32)>,
+    ) -> Result<BTreeMap<String, f32>, Error> {
+        let mut values = vec![None; self.input_names.len()];
+        inputs.into_iter().try_for_each(|(name, value)| {
+            let name = name.as_ref();
+            let index = self.input_index(name).ok_or_else(|| {
+                Error::UnknownInputName {
+                    name: name.to_string(),
+                }
+            })?;
+            values[index] = Some(*value);
+            Ok(())
+        })?;
+        let values = values
+            .iter()
+            .enumerate()
+            .map(|(index, value)| value.ok_or(Error::MissingInput { index }))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let outputs = self.net.try_evaluate(&values)?;
+        Ok(self
+            .output_names
+            .iter()
+            .zip(outputs)
+            .filter_map(|(name, value)| Some((name.clone()?, value)))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::*;
+
+    #[test]
+    fn test_named_evaluation() -> Result<(), Error> {
+        let mut builder = NeuralNetBuilder::new();
+        builder.set_default_activation(ActivationFunction::Identity);
+        let pos_x = builder.add_named_input("pos_x");
+        let pos_y = builder.add_named_input("pos_y");
+        let speed = builder.add_named_output("speed");
+        builder
+            .add_normal_connection(pos_x, speed, 2.0)
+            .add_normal_connection(pos_y, speed, -1.0);
+        assert_eq!(builder.find_node("pos_y"), Some(pos_y));
+        assert_eq!(builder.input_names(), vec![Some("pos_x"), Some("pos_y")]);
+
+        let mut net =
+            NamedNeuralNet::<ConsecutiveNeuralNet>::build_from(&builder)?;
+        let mut inputs = BTreeMap::new();
+        inputs.insert("pos_y", 1.0);
+        assert_eq!(
+            net.evaluate_named(&inputs),
+            Err(Error::MissingInput { index: 0 })
+        );
+        inputs.insert("pos_x", 3.0);
+        let outputs = net.evaluate_named(&inputs)?;
+        assert_eq!(outputs.get("speed"), Some(&5.0));
+
+        let outputs = net.net.evaluate(&[3.0, 1.0]);
+        assert_eq!(net.output(&outputs, "speed"), Some(5.0));
+        inputs.insert("pos_z", 0.0);
+        assert!(matches!(
+            net.evaluate_named(&inputs),
+            Err(Error::UnknownInputName { .. })
+        ));
+        Ok(())
+    }
+}
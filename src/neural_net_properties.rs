@@ -0,0 +1,453 @@
+// Property tests that every CPU backend computes the same outputs for
+// the same builder.  Failing builders are shrunk by proptest, and
+// reported along with the inputs they fail on.
+//
+// The quantized backend rounds weights to 8 bits, so is only checked
+// against the consecutive backend by its own tests.
+//
+// Arbitrary builders, valid or not, are also checked to build and
+// evaluate without panicking, the same as the fuzz target in `fuzz/`
+// but with a fixed budget of cases.
+
+use proptest::collection::vec;
+use proptest::prelude::*;
+use proptest::sample::select;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+
+use crate::*;
+
+const NUM_CASES: u32 = 300;
+
+// Functions that are continuous, or only jump at zero, and stay
+// finite, so that rounding differences between backends stay small.
+const FUNCS: [ActivationFunction; 12] = [
+    ActivationFunction::Sigmoid,
+    ActivationFunction::Identity,
+    ActivationFunction::Tanh,
+    ActivationFunction::Relu,
+    ActivationFunction::Gaussian,
+    ActivationFunction::Sin,
+    ActivationFunction::Abs,
+    ActivationFunction::LeakyRelu(0.1),
+    ActivationFunction::Elu(1.0),
+    ActivationFunction::SteepenedSigmoid(4.924273),
+    ActivationFunction::Softplus,
+    ActivationFunction::Clamped,
+];
+
+const AGGREGATIONS: [AggregationFunction; 6] = [
+    AggregationFunction::Sum,
+    AggregationFunction::Product,
+    AggregationFunction::Min,
+    AggregationFunction::Max,
+    AggregationFunction::Mean,
+    AggregationFunction::AbsSum,
+];
+
+prop_compose! {
+    // Random hidden or output node, as its activation, bias, response
+    // and aggregation.
+    fn random_node(sum_only: bool)(
+        func in select(FUNCS.to_vec()),
+        bias in -1.0..1.0f32,
+        response in 0.5..2.0f32,
+        aggregation in select(AGGREGATIONS.to_vec()),
+    ) -> (ActivationFunction, f32, f32, AggregationFunction) {
+        let aggregation = match sum_only {
+            true => AggregationFunction::Sum,
+            false => aggregation,
+        };
+        (func, bias, response, aggregation)
+    }
+}
+
+prop_compose! {
+    // Random valid network.  Normal connections only go from earlier
+    // to later nodes in a random order of the nodes, so never form a
+    // loop, but the order is unrelated to the order the nodes were
+    // added in.  Some connections are duplicated or disabled.  Without
+    // `recurrent` the network is feed-forward, and without `sum_only`
+    // nodes may use any aggregation.
+    fn random_builder(recurrent: bool, sum_only: bool)(
+        num_inputs in 1..4u32,
+        bias in any::<bool>(),
+        num_hidden in 0..8usize,
+        num_outputs in 1..4usize,
+    )(
+        types in Just(
+            [NodeType::Hidden]
+                .repeat(num_hidden)
+                .into_iter()
+                .chain([NodeType::Output].repeat(num_outputs))
+                .collect::<Vec<_>>(),
+        )
+        .prop_shuffle(),
+        nodes in vec(random_node(sum_only), num_hidden + num_outputs),
+        rank in Just((0..num_hidden + num_outputs).collect::<Vec<_>>())
+            .prop_shuffle(),
+        connections in vec(
+            (
+                0..num_inputs as usize + bias as usize + num_hidden
+                    + num_outputs,
+                0..num_hidden + num_outputs,
+                -2.0..2.0f32,
+                prop::bool::weighted(0.25),
+            ),
+            0..3 * (num_inputs as usize + bias as usize + num_hidden
+                + num_outputs),
+        ),
+        softmax in prop::bool::weighted(0.3),
+        duplicate in prop::bool::weighted(0.3),
+        disabled in vec(prop::bool::weighted(0.1), 100),
+        num_inputs in Just(num_inputs),
+        bias in Just(bias),
+    ) -> NeuralNetBuilder {
+        let mut builder = NeuralNetBuilder::new();
+        builder.add_nodes(NodeType::Input, num_inputs);
+        if bias {
+            builder.add_bias();
+        }
+        let num_sensors = builder.nodes.len();
+        types.iter().zip(nodes).for_each(
+            |(node_type, (func, bias, response, aggregation))| {
+                let id = builder.add_node(*node_type, func);
+                let node = &mut builder.nodes[id.0 as usize];
+                node.bias = bias;
+                node.response = response;
+                node.aggregation = aggregation;
+            },
+        );
+        if softmax {
+            builder.set_output_transform(OutputTransform::Softmax);
+        }
+
+        // Sensors come first in the order, as they have no incoming
+        // connections.
+        let rank = |node: usize| match node.checked_sub(num_sensors) {
+            Some(i) => num_sensors + rank[i],
+            None => node,
+        };
+        connections.iter().for_each(|(a, b, weight, is_recurrent)| {
+            let (a, b) = (*a, num_sensors + b);
+            let connection_type = match recurrent && *is_recurrent {
+                true => ConnectionType::Recurrent,
+                false if rank(a) < rank(b) => ConnectionType::Normal,
+                false => return,
+            };
+            builder.add_connection_raw(
+                a as u32,
+                b as u32,
+                *weight,
+                connection_type,
+            );
+        });
+        if let Some(conn) = builder.connections.first().cloned() {
+            if duplicate {
+                builder.connections.push(conn);
+            }
+        }
+        disabled.iter().enumerate().for_each(|(i, disabled)| {
+            if *disabled && i < builder.connections.len() {
+                builder.set_enabled(i, false);
+            }
+        });
+        builder
+    }
+}
+
+// Four inputs for a network, with any inputs past its own ignored.
+fn random_inputs() -> impl Strategy<Value = Vec<Vec<f32>>> {
+    vec(vec(-2.0..2.0f32, 3), 4)
+}
+
+fn assert_close(expected: &[f32], got: &[f32], backend: &str) {
+    let close = expected.len() == got.len()
+        && expected
+            .iter()
+            .zip(got)
+            .all(|(a, b)| (a - b).abs() <= 1e-4 * (1.0 + a.abs().max(b.abs())));
+    assert!(
+        close,
+        "Differs for {}: expected {:?}, got {:?}",
+        backend, expected, got
+    );
+}
+
+// Outputs of `builder` on each of `inputs` in turn, with backend `N`.
+fn outputs<N: NeuralNet>(
+    builder: &NeuralNetBuilder,
+    inputs: &[Vec<f32>],
+) -> Result<Vec<Vec<f32>>, Error> {
+    let mut net = builder.build::<N>()?;
+    Ok(inputs
+        .iter()
+        .map(|x| net.evaluate(&x[..builder.num_inputs()]))
+        .collect())
+}
+
+// Same as `outputs`, evaluated in `f64`.
+fn outputs_f64(
+    builder: &NeuralNetBuilder,
+    inputs: &[Vec<f32>],
+) -> Result<Vec<Vec<f32>>, Error> {
+    let net = ConsecutiveNeuralNet::<f64>::from_builder(builder)?;
+    let mut state = net.new_state();
+    Ok(inputs
+        .iter()
+        .map(|x| {
+            let x = x[..builder.num_inputs()]
+                .iter()
+                .map(|x| *x as f64)
+                .collect::<Vec<_>>();
+            let y = net.evaluate_with_state(&mut state, &x);
+            y.iter().map(|y| *y as f32).collect()
+        })
+        .collect())
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(NUM_CASES))]
+
+    #[test]
+    fn test_feed_forward_backends_agree(
+        (builder, sum_only) in any::<bool>().prop_flat_map(|sum_only| {
+            (random_builder(false, sum_only), Just(sum_only))
+        }),
+        inputs in random_inputs(),
+    ) {
+        let check = |backend: &str, got: &[Vec<f32>], expected: &[Vec<f32>]| {
+            got.iter().zip(expected).for_each(|(got, expected)| {
+                assert_close(expected, got, backend)
+            })
+        };
+
+        let expected = outputs::<ConsecutiveNeuralNet>(&builder, &inputs)?;
+        check(
+            "sparse",
+            &outputs::<SparseNeuralNet>(&builder, &inputs)?,
+            &expected,
+        );
+        check("f64", &outputs_f64(&builder, &inputs)?, &expected);
+        if sum_only {
+            let got = outputs::<LayeredNeuralNet>(&builder, &inputs)?;
+            check("layered", &got, &expected);
+        }
+
+        // Batches match one input at a time.
+        let inputs = inputs
+            .iter()
+            .map(|x| x[..builder.num_inputs()].to_vec())
+            .collect::<Vec<_>>();
+        let mut net = builder.build::<LayeredNeuralNet>().ok();
+        if let Some(net) = net.as_mut() {
+            check("layered batch", &net.evaluate_batch(&inputs), &expected);
+        }
+        let mut net = builder.build::<ConsecutiveNeuralNet>()?;
+        check("consecutive batch", &net.evaluate_batch(&inputs), &expected);
+    }
+
+    #[test]
+    fn test_recurrent_backends_agree(
+        builder in random_builder(true, false),
+        inputs in random_inputs(),
+    ) {
+        // Both keep the values of the previous evaluation between
+        // inputs, for the recurrent connections.  Loops through
+        // recurrent connections can amplify rounding errors with every
+        // evaluation, so only the first two are compared, which covers
+        // one step of feedback.
+        let inputs = &inputs[..2];
+        let expected = outputs::<ConsecutiveNeuralNet>(&builder, inputs)?;
+        let got = outputs_f64(&builder, inputs)?;
+        got.iter().zip(&expected).for_each(|(got, expected)| {
+            assert_close(expected, got, "f64")
+        });
+
+        // Networks with recurrent connections are rejected, rather
+        // than evaluated differently.
+        let has_recurrent = builder.connections.iter().any(|conn| {
+            conn.enabled && conn.connection_type == ConnectionType::Recurrent
+        });
+        if has_recurrent {
+            prop_assert!(builder.build::<SparseNeuralNet>().is_err());
+            prop_assert!(builder.build::<LayeredNeuralNet>().is_err());
+        }
+    }
+}
+
+// Any value at all, including ones no sensible caller would use.
+fn arbitrary_f32(rng: &mut ChaCha8Rng) -> f32 {
+    match rng.gen_range(0..8) {
+        0 => f32::NAN,
+        1 => f32::INFINITY,
+        2 => f32::NEG_INFINITY,
+        3 => 0.0,
+        4 => f32::MAX,
+        5 => rng.gen_range(-1e6..1e6),
+        _ => rng.gen_range(-3.0..3.0),
+    }
+}
+
+// Random sequence of builder calls, without regard for whether the
+// result is a valid network.
+fn arbitrary_builder(rng: &mut ChaCha8Rng) -> NeuralNetBuilder {
+    use ActivationFunction::*;
+    let funcs = [
+        Sigmoid, Identity, Tanh, Relu, Gaussian, Sin, Cos, Abs, Square, Step,
+        Softplus, Sinc, Clamped, Inverse, Log,
+    ];
+    let types = [
+        NodeType::Bias,
+        NodeType::Input,
+        NodeType::Input,
+        NodeType::Output,
+        NodeType::Output,
+        NodeType::Hidden,
+        NodeType::Hidden,
+    ];
+    // Indices are occasionally out of range, in some builders.
+    let sloppy = rng.gen_bool(0.3);
+    let index = |rng: &mut ChaCha8Rng, len: usize| {
+        if (sloppy && rng.gen_bool(0.05)) || len == 0 {
+            len + rng.gen_range(0..3)
+        } else {
+            rng.gen_range(0..len)
+        }
+    };
+    let mut builder = NeuralNetBuilder::new();
+    (0..rng.gen_range(0..40)).for_each(|_| {
+        let num_nodes = builder.nodes.len();
+        let num_connections = builder.connections.len();
+        match rng.gen_range(0..16) {
+            0..=2 => {
+                let node_type = *types.choose(rng).unwrap();
+                let func = match rng.gen_range(0..4) {
+                    0 => LeakyRelu(arbitrary_f32(rng)),
+                    1 => Elu(arbitrary_f32(rng)),
+                    2 => SteepenedSigmoid(arbitrary_f32(rng)),
+                    _ => *funcs.choose(rng).unwrap(),
+                };
+                builder.add_node(node_type, func);
+            }
+            3..=9 if num_nodes == 0 && !sloppy => {}
+            3..=9 => {
+                let connection_type = if rng.gen_bool(0.7) {
+                    ConnectionType::Normal
+                } else {
+                    ConnectionType::Recurrent
+                };
+                builder.add_connection_raw(
+                    index(rng, num_nodes) as u32,
+                    index(rng, num_nodes) as u32,
+                    arbitrary_f32(rng),
+                    connection_type,
+                );
+            }
+            10 => {
+                let node = index(rng, num_nodes);
+                if let Some(node) = builder.nodes.get_mut(node) {
+                    node.bias = arbitrary_f32(rng);
+                    node.response = arbitrary_f32(rng);
+                    node.aggregation = *AGGREGATIONS.choose(rng).unwrap();
+                }
+            }
+            11 => {
+                let node = index(rng, num_nodes);
+                let memory = MemoryCell {
+                    memory_weight: arbitrary_f32(rng),
+                    ..MemoryCell::default()
+                };
+                builder.set_memory(NodeId(node as u32), Some(memory));
+            }
+            12 => {
+                let connection = index(rng, num_connections);
+                let rule = HebbianRule {
+                    learning_rate: arbitrary_f32(rng),
+                    ..HebbianRule::default()
+                };
+                builder.set_plasticity(connection, Some(rule));
+                let enabled = rng.gen_bool(0.5);
+                builder.set_enabled(index(rng, num_connections), enabled);
+            }
+            13 => {
+                builder.set_output_transform(match rng.gen_range(0..3) {
+                    0 => OutputTransform::Identity,
+                    1 => OutputTransform::Softmax,
+                    _ => OutputTransform::ArgMax,
+                });
+                builder.set_propagation(match rng.gen_range(0..3) {
+                    0 => Propagation::Passes(rng.gen_range(0..4)),
+                    1 => Propagation::UntilStable {
+                        epsilon: arbitrary_f32(rng),
+                        max_passes: rng.gen_range(0..4),
+                    },
+                    _ => Propagation::default(),
+                });
+                builder.set_bias_value(arbitrary_f32(rng));
+            }
+            _ => {
+                builder.set_duplicate_policy(match rng.gen_range(0..8) {
+                    0 => DuplicatePolicy::Reject,
+                    1 => DuplicatePolicy::Merge,
+                    _ => DuplicatePolicy::Allow,
+                });
+                let bounds = WeightBounds {
+                    min: arbitrary_f32(rng),
+                    max: arbitrary_f32(rng),
+                    policy: match rng.gen_range(0..3) {
+                        0 => BoundsPolicy::Clamp,
+                        1 => BoundsPolicy::Reject,
+                        _ => BoundsPolicy::Wrap,
+                    },
+                };
+                builder.set_weight_bounds(rng.gen_bool(0.5).then_some(bounds));
+            }
+        }
+    });
+    builder
+}
+
+// Builds and evaluates with backend `N` if the builder is valid for
+// it, and otherwise only expects an error.
+fn exercise<N: NeuralNet>(builder: &NeuralNetBuilder, rng: &mut ChaCha8Rng) {
+    let mut net = match builder.build::<N>() {
+        Ok(net) => net,
+        Err(_) => return,
+    };
+    (0..3).for_each(|_| {
+        let num_inputs = (net.num_inputs() + rng.gen_range(0..3)).max(1) - 1;
+        let inputs = (0..num_inputs)
+            .map(|_| arbitrary_f32(rng))
+            .collect::<Vec<_>>();
+        let _ = net.evaluate(&inputs);
+        let _ = net.try_evaluate(&inputs);
+        let _ = net.evaluate_batch(&[inputs.clone(), inputs]);
+        let connection = rng.gen_range(0..builder.connections.len() + 2);
+        let _ = net.set_weight(connection, arbitrary_f32(rng));
+    });
+    net.reset_state();
+}
+
+#[test]
+fn test_arbitrary_builders_never_panic() {
+    (0..2000).for_each(|seed| {
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        let builder = arbitrary_builder(&mut rng);
+        let result = std::panic::catch_unwind(|| {
+            let mut rng = rng.clone();
+            exercise::<ConsecutiveNeuralNet>(&builder, &mut rng);
+            if let Ok(net) = ConsecutiveNeuralNet::<f64>::from_builder(&builder)
+            {
+                let mut state = net.new_state();
+                let _ = net.evaluate_with_state(&mut state, &[1.0; 4]);
+            }
+            exercise::<LayeredNeuralNet>(&builder, &mut rng);
+            exercise::<SparseNeuralNet>(&builder, &mut rng);
+            exercise::<QuantizedNeuralNet>(&builder, &mut rng);
+        });
+        assert!(result.is_ok(), "Case {} panicked: {:?}", seed, builder);
+    });
+}
@@ -0,0 +1,626 @@
+#[cfg(not(feature = "std"))]
+use alloc::{vec, vec::Vec};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::neural_net::*;
+use crate::neural_net_consecutive::connection_order;
+
+// Fixed-point values have this many fractional bits, so one is
+// `1 << FIXED_FRAC_BITS`.
+pub const FIXED_FRAC_BITS: u32 = 16;
+const FIXED_ONE: i64 = 1 << FIXED_FRAC_BITS;
+
+// Functions other than the piecewise linear ones are looked up in a
+// table of `TABLE_SIZE` points, evenly spaced over
+// `-TABLE_RANGE..=TABLE_RANGE`, and interpolated linearly.  Inputs
+// outside the range are clamped to it, so only functions that have
+// settled by then, like sigmoid and tanh, are tabulated.  Periodic and
+// unbounded ones are rejected.
+const TABLE_RANGE: i64 = 8;
+const TABLE_STEP_BITS: u32 = 12;
+const TABLE_SIZE: usize =
+    (2 * TABLE_RANGE as usize) << (FIXED_FRAC_BITS - TABLE_STEP_BITS) | 1;
+
+fn round(x: f32) -> i64 {
+    if x >= 0.0 {
+        (x + 0.5) as i64
+    } else {
+        (x - 0.5) as i64
+    }
+}
+
+// Nearest fixed-point value, saturating at the limits of `i32`.
+pub fn to_fixed(x: f32) -> i32 {
+    saturate(round(x * FIXED_ONE as f32))
+}
+
+pub fn from_fixed(x: i32) -> f32 {
+    x as f32 / FIXED_ONE as f32
+}
+
+fn saturate(x: i64) -> i32 {
+    x.clamp(i32::MIN as i64, i32::MAX as i64) as i32
+}
+
+fn mul_fixed(a: i64, b: i64) -> i64 {
+    a.saturating_mul(b) >> FIXED_FRAC_BITS
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+enum QuantizedFunction {
+    Identity,
+    Relu,
+    Abs,
+    Square,
+    LeakyRelu(i32),
+    Step,
+    Clamped,
+    // Identity for positive inputs, and looked up otherwise.
+    Elu(usize),
+    // Index of the function's table.
+    Table(usize),
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+struct QuantizedNode {
+    func: QuantizedFunction,
+    aggregation: AggregationFunction,
+    bias: i32,
+    response: i32,
+}
+
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+struct QuantizedConnection {
+    origin: u32,
+    dest: u32,
+    index: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+enum Value {
+    Accumulator(i64, u32),
+    Activated(i32),
+}
+
+// Integer-only version of a `ConsecutiveNeuralNet`, for hardware
+// without an FPU.  Building a network is its quantization pass, which
+// still uses floats, so it is usually done on a host before the
+// network is sent to the device.  Weights become `i8`s, which are
+// scaled by `2^-weight_shift`, with the shift chosen so that the
+// largest weight uses the whole range.  Every other value, including
+// the inputs and outputs of `evaluate_fixed`, is fixed-point with
+// `FIXED_FRAC_BITS` fractional bits, and saturates rather than
+// overflowing.
+//
+// Recurrent connections are supported, but plastic connections,
+// memory cells, the softmax output transform and activation functions
+// that can't be tabulated aren't.  `evaluate`
+// converts to and from floats, which is mostly useful for comparing
+// against the unquantized network.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct QuantizedNeuralNet {
+    nodes: Vec<QuantizedNode>,
+    connections: Vec<QuantizedConnection>,
+    recurrent_connections: Vec<QuantizedConnection>,
+    // One per resolved connection.
+    weights: Vec<i8>,
+    // Index in `weights` of each connection of the builder.
+    connection_slots: ConnectionSlots,
+    weight_shift: i32,
+    tables: Vec<Vec<i32>>,
+    input_indices: Vec<usize>,
+    input_scaling: Vec<InputScaling>,
+    output_indices: Vec<usize>,
+    bias_indices: Vec<usize>,
+    bias_value: i32,
+    output_transform: OutputTransform,
+    output_mapping: Vec<OutputMapping>,
+    passes: u32,
+    // Stops passing early once no output changes by more than this.
+    epsilon: Option<i32>,
+    values: Vec<Value>,
+    activations: Vec<i32>,
+}
+
+// Largest shift that keeps every weight within the range of `i8`.
+fn weight_shift(connections: &[ConnectionTemplate]) -> i32 {
+    let max = connections
+        .iter()
+        .map(|conn| conn.weight.abs())
+        .fold(0.0, f32::max);
+    if max == 0.0 {
+        return 0;
+    }
+    let (mut shift, mut scaled) = (0, max);
+    while shift < 24 && scaled * 2.0 <= i8::MAX as f32 {
+        shift += 1;
+        scaled *= 2.0;
+    }
+    while shift > -24 && scaled > i8::MAX as f32 {
+        shift -= 1;
+        scaled /= 2.0;
+    }
+    shift
+}
+
+fn table(func: ActivationFunction) -> Vec<i32> {
+    let step = (1 << TABLE_STEP_BITS) as f32 / FIXED_ONE as f32;
+    (0..TABLE_SIZE)
+        .map(|i| to_fixed(func.apply(i as f32 * step - TABLE_RANGE as f32)))
+        .collect()
+}
+
+fn look_up(table: &[i32], x: i64) -> i64 {
+    let offset =
+        (TABLE_RANGE * FIXED_ONE + x).clamp(0, 2 * TABLE_RANGE * FIXED_ONE);
+    let i = (offset >> TABLE_STEP_BITS) as usize;
+    let frac = offset & ((1 << TABLE_STEP_BITS) - 1);
+    let (a, b) = (
+        table[i] as i64,
+        *table.get(i + 1).unwrap_or(&table[i]) as i64,
+    );
+    a + (((b - a) * frac) >> TABLE_STEP_BITS)
+}
+
+impl QuantizedNeuralNet {
+    // Scale of the weights, which are worth `weight * 2^-weight_shift`.
+    pub fn weight_shift(&self) -> i32 {
+        self.weight_shift
+    }
+
+    // Quantized weight of each connection that remains once disabled
+    // connections are dropped and duplicates merged, as found by
+    // `connection_slot`.
+    pub fn weights(&self) -> &[i8] {
+        &self.weights
+    }
+
+    // Index in `weights` of a connection of the builder this network
+    // was built from, or `None` if it's disabled or doesn't exist.
+    pub fn connection_slot(&self, connection: usize) -> Option<usize> {
+        self.connection_slots.get(connection).ok()
+    }
+
+    fn from_resolved(builder: &NeuralNetBuilder) -> Result<Self, Error> {
+        builder.check_node_indices()?;
+
+        if let Some(node) = builder.nodes.iter().position(|node| {
+            node.memory.is_some()
+                && !matches!(node.node_type, NodeType::Input | NodeType::Bias)
+        }) {
+            return Err(Error::UnsupportedMemoryCell { node });
+        }
+        if let Some(connection) = builder
+            .connections
+            .iter()
+            .position(|conn| conn.plasticity.is_some())
+        {
+            return Err(Error::UnsupportedPlasticConnection { connection });
+        }
+        let output_transform = builder.output_transform();
+        if output_transform == OutputTransform::Softmax {
+            return Err(Error::UnsupportedOutputTransform {
+                transform: output_transform,
+            });
+        }
+
+        let mut table_funcs = Vec::new();
+        let mut tables = Vec::new();
+        let mut table_of = |func: ActivationFunction| match table_funcs
+            .iter()
+            .position(|f| *f == func)
+        {
+            Some(i) => i,
+            None => {
+                table_funcs.push(func);
+                tables.push(table(func));
+                tables.len() - 1
+            }
+        };
+        let nodes = builder
+            .nodes
+            .iter()
+            .map(|t| {
+                use ActivationFunction::*;
+                let func = match t.func {
+                    Identity => QuantizedFunction::Identity,
+                    Relu => QuantizedFunction::Relu,
+                    Abs => QuantizedFunction::Abs,
+                    Square => QuantizedFunction::Square,
+                    Step => QuantizedFunction::Step,
+                    Clamped => QuantizedFunction::Clamped,
+                    LeakyRelu(alpha) => {
+                        QuantizedFunction::LeakyRelu(to_fixed(alpha))
+                    }
+                    Elu(_) => QuantizedFunction::Elu(table_of(t.func)),
+                    Sigmoid | Tanh | Gaussian => {
+                        QuantizedFunction::Table(table_of(t.func))
+                    }
+                    // Shallower than sigmoid, it is still rising at the
+                    // end of the table.
+                    SteepenedSigmoid(slope) if slope.abs() >= 1.0 => {
+                        QuantizedFunction::Table(table_of(t.func))
+                    }
+                    func => {
+                        return Err(Error::UnsupportedActivationFunction {
+                            func,
+                        })
+                    }
+                };
+                Ok(QuantizedNode {
+                    func,
+                    aggregation: t.aggregation,
+                    bias: to_fixed(t.bias),
+                    response: to_fixed(t.response),
+                })
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        let to_connection = |index: usize| {
+            let template = &builder.connections[index];
+            QuantizedConnection {
+                origin: template.origin,
+                dest: template.dest,
+                index,
+            }
+        };
+        let connections = connection_order(builder)?
+            .into_iter()
+            .map(to_connection)
+            .collect();
+        let recurrent_connections = (0..builder.connections.len())
+            .filter(|i| {
+                builder.connections[*i].connection_type
+                    == ConnectionType::Recurrent
+            })
+            .map(to_connection)
+            .collect();
+
+        let indices_of_type = |node_type: NodeType| {
+            (0..builder.nodes.len())
+                .filter(|i| builder.nodes[*i].node_type == node_type)
+                .collect::<Vec<_>>()
+        };
+        let (passes, epsilon) = match builder.propagation() {
+            Propagation::Passes(passes) => (passes, None),
+            Propagation::UntilStable {
+                epsilon,
+                max_passes,
+            } => (max_passes, Some(to_fixed(epsilon))),
+        };
+
+        let mut net = Self {
+            nodes,
+            connections,
+            recurrent_connections,
+            weights: Vec::new(),
+            connection_slots: ConnectionSlots::default(),
+            weight_shift: weight_shift(&builder.connections),
+            tables,
+            input_indices: indices_of_type(NodeType::Input),
+            input_scaling: builder.input_scaling().to_vec(),
+            output_indices: indices_of_type(NodeType::Output),
+            bias_indices: indices_of_type(NodeType::Bias),
+            bias_value: to_fixed(builder.bias_value()),
+            output_transform,
+            output_mapping: builder.output_mapping().to_vec(),
+            passes,
+            epsilon,
+            values: vec![Value::Accumulator(0, 0); builder.nodes.len()],
+            activations: vec![0; builder.nodes.len()],
+        };
+        net.weights = builder
+            .connections
+            .iter()
+            .map(|conn| net.quantize_weight(conn.weight))
+            .collect();
+        Ok(net)
+    }
+
+    fn quantize_weight(&self, weight: f32) -> i8 {
+        let mut scaled = weight;
+        (0..self.weight_shift.abs()).for_each(|_| {
+            if self.weight_shift > 0 {
+                scaled *= 2.0;
+            } else {
+                scaled /= 2.0;
+            }
+        });
+        round(scaled).clamp(i8::MIN as i64, i8::MAX as i64) as i8
+    }
+
+    fn weighted(&self, x: i32, connection: usize) -> i64 {
+        let product = x as i64 * self.weights[connection] as i64;
+        if self.weight_shift >= 0 {
+            product >> self.weight_shift
+        } else {
+            product.saturating_mul(1 << -self.weight_shift)
+        }
+    }
+
+    fn activate(&self, node: &QuantizedNode, aggregate: i64) -> i32 {
+        use QuantizedFunction::*;
+        let x = mul_fixed(node.response as i64, aggregate + node.bias as i64);
+        saturate(match node.func {
+            Identity => x,
+            Relu => x.max(0),
+            Abs => x.abs(),
+            Square => mul_fixed(x, x),
+            LeakyRelu(alpha) if x < 0 => mul_fixed(alpha as i64, x),
+            LeakyRelu(_) => x,
+            Step => (x > 0) as i64 * FIXED_ONE,
+            Clamped => x.clamp(-FIXED_ONE, FIXED_ONE),
+            Elu(table) if x < 0 => look_up(&self.tables[table], x),
+            Elu(_) => x,
+            Table(table) => look_up(&self.tables[table], x),
+        })
+    }
+
+    fn get_val(&self, values: &mut [Value], node: usize) -> i32 {
+        match values[node] {
+            Value::Activated(x) => x,
+            Value::Accumulator(acc, count) => {
+                let n = &self.nodes[node];
+                let aggregate = match n.aggregation {
+                    AggregationFunction::Mean if count > 0 => {
+                        acc / count as i64
+                    }
+                    _ => acc,
+                };
+                let output = self.activate(n, aggregate);
+                values[node] = Value::Activated(output);
+                output
+            }
+        }
+    }
+
+    fn add_to_val(&self, values: &mut [Value], node: usize, x: i64) {
+        use AggregationFunction::*;
+        let aggregation = self.nodes[node].aggregation;
+        values[node] = match values[node] {
+            Value::Activated(_) | Value::Accumulator(_, 0) => {
+                let x = if aggregation == AbsSum { x.abs() } else { x };
+                Value::Accumulator(x, 1)
+            }
+            Value::Accumulator(acc, count) => {
+                let acc = match aggregation {
+                    Sum | Mean => acc.saturating_add(x),
+                    AbsSum => acc.saturating_add(x.abs()),
+                    Product => mul_fixed(acc, x),
+                    Min => acc.min(x),
+                    Max => acc.max(x),
+                };
+                Value::Accumulator(acc, count + 1)
+            }
+        };
+    }
+
+    fn propagate(&mut self, inputs: &[i32]) {
+        let mut values = core::mem::take(&mut self.values);
+        values
+            .iter_mut()
+            .for_each(|val| *val = Value::Accumulator(0, 0));
+        self.input_indices.iter().enumerate().for_each(|(j, i)| {
+            values[*i] = Value::Activated(inputs.get(j).cloned().unwrap_or(0));
+        });
+        self.bias_indices.iter().for_each(|i| {
+            values[*i] = Value::Activated(self.bias_value);
+        });
+
+        self.recurrent_connections.iter().for_each(|conn| {
+            let x = self
+                .weighted(self.activations[conn.origin as usize], conn.index);
+            self.add_to_val(&mut values, conn.dest as usize, x);
+        });
+        self.connections.iter().for_each(|conn| {
+            let val = self.get_val(&mut values, conn.origin as usize);
+            let x = self.weighted(val, conn.index);
+            self.add_to_val(&mut values, conn.dest as usize, x);
+        });
+
+        let activations = (0..values.len())
+            .map(|i| self.get_val(&mut values, i))
+            .collect();
+        self.activations = activations;
+        self.values = values;
+    }
+
+    // Evaluates the network on fixed-point inputs, without using
+    // floats.  Inputs and outputs are treated as in
+    // `NeuralNet::evaluate`, except that inputs are taken to be scaled
+    // already and outputs aren't mapped.
+    pub fn evaluate_fixed(&mut self, inputs: &[i32]) -> Vec<i32> {
+        let mut outputs = vec![0; self.output_indices.len()];
+        self.evaluate_fixed_into(inputs, &mut outputs);
+        outputs
+    }
+
+    pub fn evaluate_fixed_into(&mut self, inputs: &[i32], outputs: &mut [i32]) {
+        let output_values = |net: &Self| {
+            net.output_indices
+                .iter()
+                .map(|i| net.activations[*i])
+                .collect::<Vec<_>>()
+        };
+        self.propagate(inputs);
+        let mut previous = match self.epsilon {
+            Some(_) => output_values(self),
+            None => Vec::new(),
+        };
+        for _ in 1..self.passes {
+            self.propagate(inputs);
+            if let Some(epsilon) = self.epsilon {
+                let current = output_values(self);
+                let stable = current.iter().zip(&previous).all(|(a, b)| {
+                    (*a as i64 - *b as i64).abs() <= epsilon as i64
+                });
+                previous = current;
+                if stable {
+                    break;
+                }
+            }
+        }
+        let activations = &self.activations;
+        outputs
+            .iter_mut()
+            .zip(self.output_indices.iter())
+            .for_each(|(out, i)| *out = activations[*i]);
+
+        if self.output_transform == OutputTransform::ArgMax {
+            let n = outputs.len().min(self.output_indices.len());
+            let best = (0..n).rev().max_by_key(|i| outputs[*i]);
+            outputs.iter_mut().enumerate().for_each(|(i, x)| {
+                *x = if Some(i) == best { FIXED_ONE as i32 } else { 0 };
+            });
+        }
+    }
+}
+
+impl NeuralNet for QuantizedNeuralNet {
+    fn build_from(builder: &NeuralNetBuilder) -> Result<Self, Error> {
+        let resolved = builder.resolve_connections()?;
+        let mut net = Self::from_resolved(&resolved)
+            .map_err(|error| resolved.original_error(error))?;
+        net.connection_slots = resolved.slots;
+        Ok(net)
+    }
+
+    fn num_inputs(&self) -> usize {
+        self.input_indices.len()
+    }
+
+    fn num_outputs(&self) -> usize {
+        OutputMapping::num_mapped(
+            &self.output_mapping,
+            self.output_indices.len(),
+        )
+    }
+
+    fn evaluate(&mut self, inputs: &[f32]) -> Vec<f32> {
+        let inputs = (0..self.input_indices.len())
+            .map(|j| {
+                let x = inputs.get(j).cloned().unwrap_or(0.0);
+                to_fixed(scale_input(&self.input_scaling, j, x))
+            })
+            .collect::<Vec<_>>();
+        let outputs = self
+            .evaluate_fixed(&inputs)
+            .into_iter()
+            .map(from_fixed)
+            .collect::<Vec<_>>();
+        if self.output_mapping.is_empty() {
+            outputs
+        } else {
+            OutputMapping::map(&self.output_mapping, &outputs)
+        }
+    }
+
+    fn reset_state(&mut self) {
+        self.activations.iter_mut().for_each(|x| *x = 0);
+    }
+
+    // The weight is quantized at the existing scale, saturating if it
+    // doesn't fit.
+    fn set_weight(
+        &mut self,
+        connection: usize,
+        weight: f32,
+    ) -> Result<(), Error> {
+        let slot = self.connection_slots.get(connection)?;
+        self.weights[slot] = self.quantize_weight(weight);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ConsecutiveNeuralNet;
+
+    #[test]
+    fn test_matches_consecutive() -> Result<(), Error> {
+        let mut builder = NeuralNetBuilder::new();
+        builder
+            .set_default_activation(ActivationFunction::Sigmoid)
+            .add_nodes(NodeType::Input, 2)
+            .add_bias();
+        builder.add_node(NodeType::Hidden, ActivationFunction::Tanh);
+        builder.add_node(NodeType::Hidden, ActivationFunction::Relu);
+        builder
+            .add_nodes(NodeType::Output, 1)
+            .add_normal_connection_raw(0, 3, 1.5)
+            .add_normal_connection_raw(1, 3, -2.0)
+            .add_normal_connection_raw(2, 4, 0.25)
+            .add_normal_connection_raw(0, 4, 0.75)
+            .add_normal_connection_raw(3, 5, 3.0)
+            .add_normal_connection_raw(4, 5, -1.0)
+            .add_recurrent_connection_raw(5, 3, 0.5);
+        let mut net = builder.build::<ConsecutiveNeuralNet>()?;
+        let mut quantized = builder.build::<QuantizedNeuralNet>()?;
+        assert_eq!(quantized.weight_shift(), 5);
+        assert_eq!(quantized.weights()[1], -64);
+
+        [[0.0, 0.0], [1.0, 0.0], [0.5, -1.0], [-2.0, 3.0]]
+            .iter()
+            .for_each(|inputs| {
+                let expected = net.evaluate(inputs)[0];
+                let got = quantized.evaluate(inputs)[0];
+                assert!((expected - got).abs() < 0.01, "{} {}", expected, got);
+            });
+
+        let fixed = quantized.evaluate_fixed(&[to_fixed(1.0), 0]);
+        assert_eq!(fixed.len(), 1);
+
+        builder.set_output_transform(OutputTransform::Softmax);
+        let res = builder.build::<QuantizedNeuralNet>();
+        assert_eq!(
+            res.err(),
+            Some(Error::UnsupportedOutputTransform {
+                transform: OutputTransform::Softmax
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_unsupported_functions() {
+        let build = |func| {
+            let mut builder = NeuralNetBuilder::new();
+            builder.add_inputs(1);
+            builder.add_node(NodeType::Output, func);
+            builder.add_normal_connection_raw(0, 1, 1.0);
+            builder.build::<QuantizedNeuralNet>()
+        };
+        use ActivationFunction::*;
+        [Sigmoid, Tanh, Gaussian, SteepenedSigmoid(4.9), Elu(1.0)]
+            .iter()
+            .for_each(|func| assert!(build(*func).is_ok(), "{:?}", func));
+
+        // Clamping the input to the table would change these far from
+        // zero.
+        [
+            Sin,
+            Cos,
+            Softplus,
+            Sinc,
+            Inverse,
+            Log,
+            SteepenedSigmoid(0.5),
+            Custom(|x| 2.0 * x),
+        ]
+        .iter()
+        .for_each(|func| {
+            assert_eq!(
+                build(*func).err(),
+                Some(Error::UnsupportedActivationFunction { func: *func })
+            );
+        });
+    }
+}
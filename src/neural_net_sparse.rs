@@ -0,0 +1,240 @@
+#[cfg(not(feature = "std"))]
+use alloc::{vec, vec::Vec};
+
+use crate::neural_net::*;
+use crate::neural_net_layered::{layer_order, node_depths};
+
+// Evaluates a feed-forward network as a sparse matrix-vector product
+// in compressed sparse row (CSR) format.  Each row holds the incoming
+// connections of one non-input node.  Nodes are stored sorted by
+// layer, so every row only reads values from earlier rows, and the
+// whole network is evaluated in a single pass over the rows.
+#[derive(Debug, Clone)]
+pub struct SparseNeuralNet {
+    pub(crate) values: Vec<f32>,
+    // Position in `values` of the node computed by the first row.
+    pub(crate) first_row: usize,
+    pub(crate) funcs: Vec<ActivationFunction>,
+    pub(crate) aggregations: Vec<AggregationFunction>,
+    pub(crate) biases: Vec<f32>,
+    pub(crate) responses: Vec<f32>,
+    // Connections of row `i` are stored in
+    // `row_starts[i]..row_starts[i+1]`.
+    pub(crate) row_starts: Vec<usize>,
+    pub(crate) columns: Vec<usize>,
+    pub(crate) weights: Vec<f32>,
+    // Position in `weights` of each builder connection.
+    pub(crate) slots: ConnectionSlots,
+    pub(crate) input_indices: Vec<usize>,
+    pub(crate) input_scaling: Vec<InputScaling>,
+    pub(crate) output_indices: Vec<usize>,
+    pub(crate) output_transform: OutputTransform,
+    pub(crate) output_mapping: Vec<OutputMapping>,
+}
+
+impl SparseNeuralNet {
+    // Network from a resolved builder, along with the position in
+    // `weights` of each of its connections.
+    fn from_resolved(
+        builder: &NeuralNetBuilder,
+    ) -> Result<(Self, Vec<usize>), Error> {
+        builder.check_node_indices()?;
+        builder.check_feed_forward()?;
+
+        let depths = node_depths(builder)?;
+        let (order, position) = layer_order(&depths);
+
+        let first_row = depths.iter().filter(|d| **d == 0).count();
+        let num_rows = order.len() - first_row;
+
+        let mut incoming = vec![Vec::new(); num_rows];
+        builder
+            .connections
+            .iter()
+            .enumerate()
+            .for_each(|(i, conn)| {
+                let row = position[conn.dest as usize] - first_row;
+                incoming[row].push((position[conn.origin as usize], i));
+            });
+
+        let mut row_starts = Vec::with_capacity(num_rows + 1);
+        let mut columns = Vec::with_capacity(builder.connections.len());
+        let mut weights = Vec::with_capacity(builder.connections.len());
+        let mut slots = vec![0; builder.connections.len()];
+        row_starts.push(0);
+        incoming.iter_mut().for_each(|row| {
+            row.sort_by_key(|(col, _conn)| *col);
+            row.iter().for_each(|(col, conn)| {
+                slots[*conn] = columns.len();
+                columns.push(*col);
+                weights.push(builder.connections[*conn].weight);
+            });
+            row_starts.push(columns.len());
+        });
+
+        let rows = order[first_row..]
+            .iter()
+            .map(|i| &builder.nodes[*i])
+            .collect::<Vec<_>>();
+        let funcs = rows.iter().map(|n| n.func).collect();
+        let aggregations = rows.iter().map(|n| n.aggregation).collect();
+        let biases = rows.iter().map(|n| n.bias).collect();
+        let responses = rows.iter().map(|n| n.response).collect();
+
+        let indices_of_type = |node_type: NodeType| {
+            builder
+                .nodes
+                .iter()
+                .enumerate()
+                .filter(|(_i, n)| n.node_type == node_type)
+                .map(|(i, _n)| position[i])
+                .collect::<Vec<_>>()
+        };
+
+        // Bias values are never overwritten, so are only set once.
+        let mut values = vec![0.0; order.len()];
+        indices_of_type(NodeType::Bias)
+            .iter()
+            .for_each(|i| values[*i] = builder.bias_value());
+
+        let net = Self {
+            values,
+            first_row,
+            funcs,
+            aggregations,
+            biases,
+            responses,
+            row_starts,
+            columns,
+            weights,
+            slots: ConnectionSlots::default(),
+            input_indices: indices_of_type(NodeType::Input),
+            input_scaling: builder.input_scaling().to_vec(),
+            output_indices: indices_of_type(NodeType::Output),
+            output_transform: builder.output_transform(),
+            output_mapping: builder.output_mapping().to_vec(),
+        };
+        Ok((net, slots))
+    }
+}
+
+impl NeuralNet for SparseNeuralNet {
+    fn build_from(builder: &NeuralNetBuilder) -> Result<Self, Error> {
+        let resolved = builder.resolve_connections()?;
+        let (mut net, positions) = Self::from_resolved(&resolved)
+            .map_err(|error| resolved.original_error(error))?;
+        net.slots = resolved.slots.through(&positions);
+        Ok(net)
+    }
+
+    fn num_inputs(&self) -> usize {
+        self.input_indices.len()
+    }
+
+    fn num_outputs(&self) -> usize {
+        OutputMapping::num_mapped(
+            &self.output_mapping,
+            self.output_indices.len(),
+        )
+    }
+
+    fn evaluate(&mut self, inputs: &[f32]) -> Vec<f32> {
+        let mut outputs = vec![0.0; self.num_outputs()];
+        self.evaluate_into(inputs, &mut outputs);
+        outputs
+    }
+
+    fn evaluate_into(&mut self, inputs: &[f32], outputs: &mut [f32]) {
+        let values = &mut self.values;
+        let scaling = &self.input_scaling;
+
+        self.input_indices.iter().enumerate().for_each(|(j, i)| {
+            let x = inputs.get(j).cloned().unwrap_or(0.0);
+            values[*i] = scale_input(scaling, j, x);
+        });
+
+        let row_starts = &self.row_starts;
+        let columns = &self.columns;
+        let weights = &self.weights;
+        let aggregations = &self.aggregations;
+        let biases = &self.biases;
+        let responses = &self.responses;
+        let first_row = self.first_row;
+
+        self.funcs.iter().enumerate().for_each(|(row, func)| {
+            let range = row_starts[row]..row_starts[row + 1];
+            let x = aggregations[row].aggregate(
+                columns[range.clone()]
+                    .iter()
+                    .zip(weights[range].iter())
+                    .map(|(col, w)| w * values[*col]),
+            );
+            let x = responses[row] * (x + biases[row]);
+            values[first_row + row] = func.apply(x);
+        });
+
+        finish_outputs(
+            self.output_transform,
+            &self.output_mapping,
+            self.output_indices.iter().map(|i| values[*i]),
+            outputs,
+        );
+    }
+
+    fn set_weight(
+        &mut self,
+        connection: usize,
+        weight: f32,
+    ) -> Result<(), Error> {
+        let slot = self.slots.get(connection)?;
+        self.weights[slot] = weight;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ConsecutiveNeuralNet;
+
+    #[test]
+    fn test_matches_consecutive() -> Result<(), Error> {
+        let mut builder = NeuralNetBuilder::new();
+        builder
+            .set_default_activation(ActivationFunction::Sigmoid)
+            .add_nodes(NodeType::Input, 2)
+            .add_nodes(NodeType::Hidden, 2)
+            .add_nodes(NodeType::Output, 2)
+            .set_output_transform(OutputTransform::ArgMax)
+            .add_bias();
+        builder
+            .add_normal_connection_raw(6, 2, -0.75)
+            .add_normal_connection_raw(3, 5, -1.0)
+            .add_normal_connection_raw(0, 2, 1.5)
+            .add_normal_connection_raw(2, 3, 0.5)
+            .add_normal_connection_raw(1, 3, -2.0)
+            .add_normal_connection_raw(2, 4, 1.0)
+            .add_normal_connection_raw(1, 5, 0.25);
+        builder.nodes[2].aggregation = AggregationFunction::Product;
+        builder.nodes[3].aggregation = AggregationFunction::Max;
+        builder.nodes[5].aggregation = AggregationFunction::Mean;
+        builder.nodes[3].bias = -0.5;
+        builder.nodes[4].response = 3.0;
+
+        let mut consecutive = builder.build::<ConsecutiveNeuralNet>()?;
+        let mut sparse = builder.build::<SparseNeuralNet>()?;
+        consecutive.set_weight(4, 3.0)?;
+        sparse.set_weight(4, 3.0)?;
+
+        for inputs in &[[0.0, 0.0], [1.0, -0.5], [-3.0, 2.0]] {
+            let expected = consecutive.evaluate(inputs);
+            let res = sparse.evaluate(inputs);
+            assert_eq!(res.len(), expected.len());
+            res.iter()
+                .zip(expected.iter())
+                .for_each(|(a, b)| assert!((a - b).abs() < 1e-6));
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,155 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct NoveltyConfig {
+    // Novelty is the mean distance to this many nearest neighbors,
+    // among the rest of the population and the archive.
+    pub k: usize,
+    // Behaviors whose novelty exceeds this are added to the archive.
+    pub archive_threshold: f32,
+    // Once the archive is full, the oldest behaviors are dropped.
+    pub max_archive_size: usize,
+    // Weight of novelty when combined with fitness, from zero for
+    // pure fitness to one for pure novelty.
+    pub novelty_weight: f32,
+}
+
+impl Default for NoveltyConfig {
+    fn default() -> Self {
+        Self {
+            k: 15,
+            archive_threshold: 1.0,
+            max_archive_size: 1000,
+            novelty_weight: 1.0,
+        }
+    }
+}
+
+// Euclidean distance between two behavior descriptors.  Missing
+// trailing values of the shorter descriptor count as zero.
+pub fn behavior_distance(a: &[f32], b: &[f32]) -> f32 {
+    let (a, b) = if a.len() < b.len() { (b, a) } else { (a, b) };
+    a.iter()
+        .enumerate()
+        .map(|(i, x)| x - b.get(i).cloned().unwrap_or(0.0))
+        .map(|d| d * d)
+        .sum::<f32>()
+        .sqrt()
+}
+
+// Rewards genomes for behaving differently from the rest of the
+// population and from the novel behaviors seen in past generations.
+#[derive(Debug, PartialEq, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct NoveltySearch {
+    pub config: NoveltyConfig,
+    archive: Vec<Vec<f32>>,
+}
+
+impl NoveltySearch {
+    pub fn new(config: NoveltyConfig) -> Self {
+        Self {
+            config,
+            archive: Vec::new(),
+        }
+    }
+
+    pub fn archive(&self) -> &[Vec<f32>] {
+        &self.archive
+    }
+
+    // Novelty of each behavior of the current population, without
+    // changing the archive.
+    pub fn novelty(&self, behaviors: &[Vec<f32>]) -> Vec<f32> {
+        behaviors
+            .iter()
+            .enumerate()
+            .map(|(i, behavior)| {
+                let mut distances = behaviors
+                    .iter()
+                    .enumerate()
+                    .filter(|(j, _)| *j != i)
+                    .map(|(_, other)| other)
+                    .chain(self.archive.iter())
+                    .map(|other| behavior_distance(behavior, other))
+                    .collect::<Vec<_>>();
+                distances.sort_by(|a, b| a.total_cmp(b));
+                let nearest = &distances[..self.config.k.min(distances.len())];
+                match nearest.len() {
+                    0 => 0.0,
+                    n => nearest.iter().sum::<f32>() / n as f32,
+                }
+            })
+            .collect()
+    }
+
+    // Novelty of each behavior of the current population, after which
+    // the most novel behaviors are archived.
+    pub fn evaluate(&mut self, behaviors: &[Vec<f32>]) -> Vec<f32> {
+        let novelty = self.novelty(behaviors);
+        behaviors
+            .iter()
+            .zip(novelty.iter())
+            .for_each(|(behavior, n)| {
+                if *n > self.config.archive_threshold {
+                    self.archive.push(behavior.clone());
+                }
+            });
+        let excess = self
+            .archive
+            .len()
+            .saturating_sub(self.config.max_archive_size);
+        self.archive.drain(..excess);
+        novelty
+    }
+
+    // Blends novelty with objective fitness according to
+    // `novelty_weight`, giving a score to advance the population by.
+    pub fn combine(&self, novelty: &[f32], fitnesses: &[f32]) -> Vec<f32> {
+        assert_eq!(
+            novelty.len(),
+            fitnesses.len(),
+            "Expected one fitness value per novelty score"
+        );
+        let w = self.config.novelty_weight;
+        novelty
+            .iter()
+            .zip(fitnesses.iter())
+            .map(|(n, f)| w * n + (1.0 - w) * f)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_novelty_search() {
+        assert_eq!(behavior_distance(&[0.0, 3.0], &[4.0, 0.0]), 5.0);
+        assert_eq!(behavior_distance(&[3.0], &[0.0, 4.0]), 5.0);
+
+        let mut search = NoveltySearch::new(NoveltyConfig {
+            k: 2,
+            archive_threshold: 2.0,
+            max_archive_size: 2,
+            novelty_weight: 0.25,
+        });
+        let behaviors = vec![vec![0.0], vec![1.0], vec![2.0], vec![6.0]];
+        let novelty = search.evaluate(&behaviors);
+        assert_eq!(novelty, vec![1.5, 1.0, 1.5, 4.5]);
+        assert_eq!(search.archive(), &[vec![6.0]]);
+
+        // The archived behavior is no longer novel.
+        let novelty = search.evaluate(&[vec![6.0], vec![10.0]]);
+        assert_eq!(novelty, vec![2.0, 4.0]);
+        assert_eq!(search.archive(), &[vec![6.0], vec![10.0]]);
+        search.evaluate(&[vec![20.0]]);
+        assert_eq!(search.archive(), &[vec![10.0], vec![20.0]]);
+
+        let scores = search.combine(&[4.0, 0.0], &[0.0, 4.0]);
+        assert_eq!(scores, vec![1.0, 3.0]);
+    }
+}
@@ -0,0 +1,657 @@
+use crate::neural_net::*;
+use crate::neural_net_layered::{layer_order, node_depths};
+
+// Just enough of the protobuf wire format to write an ONNX model.
+// Field numbers are those of onnx.proto.
+mod proto {
+    const VARINT: u32 = 0;
+    const LENGTH_DELIMITED: u32 = 2;
+    const FIXED32: u32 = 5;
+
+    fn varint(buf: &mut Vec<u8>, mut x: u64) {
+        while x >= 0x80 {
+            buf.push((x as u8 & 0x7f) | 0x80);
+            x >>= 7;
+        }
+        buf.push(x as u8);
+    }
+
+    fn key(buf: &mut Vec<u8>, field: u32, wire_type: u32) {
+        varint(buf, ((field << 3) | wire_type) as u64);
+    }
+
+    pub fn int(buf: &mut Vec<u8>, field: u32, x: i64) {
+        key(buf, field, VARINT);
+        varint(buf, x as u64);
+    }
+
+    pub fn float(buf: &mut Vec<u8>, field: u32, x: f32) {
+        key(buf, field, FIXED32);
+        buf.extend_from_slice(&x.to_le_bytes());
+    }
+
+    pub fn bytes(buf: &mut Vec<u8>, field: u32, x: &[u8]) {
+        key(buf, field, LENGTH_DELIMITED);
+        varint(buf, x.len() as u64);
+        buf.extend_from_slice(x);
+    }
+
+    pub fn string(buf: &mut Vec<u8>, field: u32, x: &str) {
+        bytes(buf, field, x.as_bytes());
+    }
+}
+
+const IR_VERSION: i64 = 8;
+const OPSET_VERSION: i64 = 13;
+
+// TensorProto.DataType
+const FLOAT: i64 = 1;
+const INT64: i64 = 7;
+
+// AttributeProto.AttributeType
+const ATTRIBUTE_FLOAT: i64 = 1;
+const ATTRIBUTE_INT: i64 = 2;
+
+fn int_attribute(name: &str, x: i64) -> Vec<u8> {
+    let mut attr = Vec::new();
+    proto::string(&mut attr, 1, name);
+    proto::int(&mut attr, 3, x);
+    proto::int(&mut attr, 20, ATTRIBUTE_INT);
+    attr
+}
+
+fn float_attribute(name: &str, x: f32) -> Vec<u8> {
+    let mut attr = Vec::new();
+    proto::string(&mut attr, 1, name);
+    proto::float(&mut attr, 2, x);
+    proto::int(&mut attr, 20, ATTRIBUTE_FLOAT);
+    attr
+}
+
+#[derive(Default)]
+struct Graph {
+    nodes: Vec<Vec<u8>>,
+    initializers: Vec<Vec<u8>>,
+    next_name: usize,
+}
+
+impl Graph {
+    fn new_name(&mut self) -> String {
+        let name = format!("t{}", self.next_name);
+        self.next_name += 1;
+        name
+    }
+
+    fn op_into(
+        &mut self,
+        op_type: &str,
+        inputs: &[&str],
+        attribute: Option<Vec<u8>>,
+        output: &str,
+    ) {
+        let mut node = Vec::new();
+        inputs.iter().for_each(|x| proto::string(&mut node, 1, x));
+        proto::string(&mut node, 2, output);
+        proto::string(&mut node, 3, output);
+        proto::string(&mut node, 4, op_type);
+        if let Some(attr) = attribute {
+            proto::bytes(&mut node, 5, &attr);
+        }
+        self.nodes.push(node);
+    }
+
+    fn op(&mut self, op_type: &str, inputs: &[&str]) -> String {
+        self.op_with(op_type, inputs, None)
+    }
+
+    fn op_with(
+        &mut self,
+        op_type: &str,
+        inputs: &[&str],
+        attribute: Option<Vec<u8>>,
+    ) -> String {
+        let output = self.new_name();
+        self.op_into(op_type, inputs, attribute, &output);
+        output
+    }
+
+    fn tensor(
+        &mut self,
+        data_type: i64,
+        dims: &[usize],
+        data: &[u8],
+    ) -> String {
+        let name = self.new_name();
+        let mut tensor = Vec::new();
+        dims.iter()
+            .for_each(|d| proto::int(&mut tensor, 1, *d as i64));
+        proto::int(&mut tensor, 2, data_type);
+        proto::string(&mut tensor, 8, &name);
+        proto::bytes(&mut tensor, 9, data);
+        self.initializers.push(tensor);
+        name
+    }
+
+    fn floats(&mut self, dims: &[usize], data: &[f32]) -> String {
+        let raw = data
+            .iter()
+            .flat_map(|x| x.to_le_bytes().to_vec())
+            .collect::<Vec<_>>();
+        self.tensor(FLOAT, dims, &raw)
+    }
+
+    fn int64s(&mut self, dims: &[usize], data: &[i64]) -> String {
+        let raw = data
+            .iter()
+            .flat_map(|x| x.to_le_bytes().to_vec())
+            .collect::<Vec<_>>();
+        self.tensor(INT64, dims, &raw)
+    }
+
+    fn activation(
+        &mut self,
+        func: ActivationFunction,
+        x: &str,
+    ) -> Result<String, Error> {
+        use ActivationFunction::*;
+        Ok(match func {
+            Sigmoid => self.op("Sigmoid", &[x]),
+            Identity => x.to_string(),
+            Tanh => self.op("Tanh", &[x]),
+            Relu => self.op("Relu", &[x]),
+            Gaussian => {
+                let square = self.op("Mul", &[x, x]);
+                let neg_half = self.floats(&[], &[-0.5]);
+                let exponent = self.op("Mul", &[&square, &neg_half]);
+                self.op("Exp", &[&exponent])
+            }
+            Sin => self.op("Sin", &[x]),
+            Cos => self.op("Cos", &[x]),
+            Abs => self.op("Abs", &[x]),
+            Square => self.op("Mul", &[x, x]),
+            LeakyRelu(alpha) => self.op_with(
+                "LeakyRelu",
+                &[x],
+                Some(float_attribute("alpha", alpha)),
+            ),
+            Elu(alpha) => {
+                self.op_with("Elu", &[x], Some(float_attribute("alpha", alpha)))
+            }
+            SteepenedSigmoid(slope) => {
+                let slope = self.floats(&[], &[slope]);
+                let x = self.op("Mul", &[x, &slope]);
+                self.op("Sigmoid", &[&x])
+            }
+            Step => {
+                let zero = self.floats(&[], &[0.0]);
+                let positive = self.op("Greater", &[x, &zero]);
+                self.op_with(
+                    "Cast",
+                    &[&positive],
+                    Some(int_attribute("to", FLOAT)),
+                )
+            }
+            Softplus => self.op("Softplus", &[x]),
+            Sinc => {
+                let (zero, one) =
+                    (self.floats(&[], &[0.0]), self.floats(&[], &[1.0]));
+                let is_zero = self.op("Equal", &[x, &zero]);
+                let sin = self.op("Sin", &[x]);
+                let sinc = self.op("Div", &[&sin, x]);
+                self.op("Where", &[&is_zero, &one, &sinc])
+            }
+            Clamped => {
+                let (min, max) =
+                    (self.floats(&[], &[-1.0]), self.floats(&[], &[1.0]));
+                self.op("Clip", &[x, &min, &max])
+            }
+            Inverse => {
+                let zero = self.floats(&[], &[0.0]);
+                let is_zero = self.op("Equal", &[x, &zero]);
+                let inverse = self.op("Reciprocal", &[x]);
+                self.op("Where", &[&is_zero, &zero, &inverse])
+            }
+            Log => {
+                let min = self.floats(&[], &[1e-7]);
+                let x = self.op("Max", &[x, &min]);
+                self.op("Log", &[&x])
+            }
+            Custom(_) => {
+                return Err(Error::UnsupportedActivationFunction { func })
+            }
+        })
+    }
+}
+
+// Float tensor of shape [N, size], with a variable batch size N.
+fn value_info(name: &str, size: usize) -> Vec<u8> {
+    let mut batch = Vec::new();
+    proto::string(&mut batch, 2, "N");
+    let mut features = Vec::new();
+    proto::int(&mut features, 1, size as i64);
+
+    let mut shape = Vec::new();
+    proto::bytes(&mut shape, 1, &batch);
+    proto::bytes(&mut shape, 1, &features);
+
+    let mut tensor_type = Vec::new();
+    proto::int(&mut tensor_type, 1, FLOAT);
+    proto::bytes(&mut tensor_type, 2, &shape);
+
+    let mut type_proto = Vec::new();
+    proto::bytes(&mut type_proto, 1, &tensor_type);
+
+    let mut info = Vec::new();
+    proto::string(&mut info, 1, name);
+    proto::bytes(&mut info, 2, &type_proto);
+    info
+}
+
+impl NeuralNetBuilder {
+    // Serialized ONNX model of a feed-forward network, mapping an
+    // `input` of shape [N, inputs] to an `output` of shape
+    // [N, outputs].  Nodes are evaluated layer by layer as in
+    // `LayeredNeuralNet`, with each layer appending its values to
+    // those of the earlier layers.
+    pub fn to_onnx(&self) -> Result<Vec<u8>, Error> {
+        let resolved = self.resolve_connections()?;
+        let builder = &*resolved;
+        let depths = builder
+            .check_node_indices()
+            .and_then(|_| builder.check_feed_forward())
+            .and_then(|_| builder.check_sum_aggregation())
+            .and_then(|_| node_depths(builder))
+            .map_err(|error| resolved.original_error(error))?;
+        let is_bias = |i: usize| builder.nodes[i].node_type == NodeType::Bias;
+
+        // Bias nodes follow the inputs, so that the first layer of
+        // values is the `input` with the bias values appended.
+        let (mut order, _) = layer_order(&depths);
+        order.sort_by_key(|i| (depths[*i], is_bias(*i)));
+        let mut position = vec![0; order.len()];
+        order
+            .iter()
+            .enumerate()
+            .for_each(|(pos, node)| position[*node] = pos);
+
+        let num_sensors = depths.iter().filter(|d| **d == 0).count();
+        let num_biases = (0..depths.len())
+            .filter(|i| depths[*i] == 0 && is_bias(*i))
+            .count();
+        let num_inputs = num_sensors - num_biases;
+        let num_layers = depths.iter().max().map_or(0, |d| d + 1);
+
+        let mut graph = Graph::default();
+        let mut values = "input".to_string();
+        let scaling = (0..num_inputs)
+            .map(|j| match builder.input_scaling().get(j) {
+                Some(scaling) => scaling.affine(),
+                None => (1.0, 0.0),
+            })
+            .collect::<Vec<_>>();
+        if scaling.iter().any(|(scale, _)| *scale != 1.0) {
+            let scales = scaling.iter().map(|x| x.0).collect::<Vec<_>>();
+            let scales = graph.floats(&[num_inputs], &scales);
+            values = graph.op("Mul", &[&values, &scales]);
+        }
+        if scaling.iter().any(|(_, offset)| *offset != 0.0) {
+            let offsets = scaling.iter().map(|x| x.1).collect::<Vec<_>>();
+            let offsets = graph.floats(&[num_inputs], &offsets);
+            values = graph.op("Add", &[&values, &offsets]);
+        }
+        if num_biases > 0 {
+            // A product with zeros gives a column per bias node with
+            // the batch size of the input, to which the bias value is
+            // added.
+            let zeros = graph.floats(
+                &[num_inputs, num_biases],
+                &vec![0.0; num_inputs * num_biases],
+            );
+            let zeros = graph.op("MatMul", &[&values, &zeros]);
+            let bias_values = graph
+                .floats(&[num_biases], &vec![builder.bias_value(); num_biases]);
+            let biases = graph.op("Add", &[&zeros, &bias_values]);
+
+            let next = graph.new_name();
+            graph.op_into(
+                "Concat",
+                &[&values, &biases],
+                Some(int_attribute("axis", 1)),
+                &next,
+            );
+            values = next;
+        }
+        let mut start = num_sensors;
+
+        (1..num_layers).try_for_each(|depth| {
+            let layer = order[start..]
+                .iter()
+                .take_while(|i| depths[**i] == depth)
+                .cloned()
+                .collect::<Vec<_>>();
+            let size = layer.len();
+
+            // Transposed, so that the layer is a product of the
+            // values on the left.
+            let mut weights = vec![0.0; start * size];
+            builder
+                .connections
+                .iter()
+                .filter(|conn| depths[conn.dest as usize] == depth)
+                .for_each(|conn| {
+                    let row = position[conn.dest as usize] - start;
+                    let col = position[conn.origin as usize];
+                    weights[col * size + row] += conn.weight;
+                });
+            let weights = graph.floats(&[start, size], &weights);
+            let mut sums = graph.op("MatMul", &[&values, &weights]);
+
+            // Node biases and responses are only added to the graph
+            // where they have an effect.
+            let biases = layer
+                .iter()
+                .map(|i| builder.nodes[*i].bias)
+                .collect::<Vec<_>>();
+            if biases.iter().any(|b| *b != 0.0) {
+                let biases = graph.floats(&[size], &biases);
+                sums = graph.op("Add", &[&sums, &biases]);
+            }
+            let responses = layer
+                .iter()
+                .map(|i| builder.nodes[*i].response)
+                .collect::<Vec<_>>();
+            if responses.iter().any(|r| *r != 1.0) {
+                let responses = graph.floats(&[size], &responses);
+                sums = graph.op("Mul", &[&sums, &responses]);
+            }
+
+            // Every function used in the layer is applied to the whole
+            // layer, then masked to the nodes that use it.
+            let mut funcs = Vec::new();
+            layer.iter().for_each(|i| {
+                let func = builder.nodes[*i].func;
+                if !funcs.contains(&func) {
+                    funcs.push(func);
+                }
+            });
+            let activated = if funcs.len() == 1 {
+                graph.activation(funcs[0], &sums)?
+            } else {
+                let terms = funcs
+                    .iter()
+                    .map(|func| {
+                        let mask = layer
+                            .iter()
+                            .map(|i| {
+                                (builder.nodes[*i].func == *func) as u8 as f32
+                            })
+                            .collect::<Vec<_>>();
+                        let mask = graph.floats(&[size], &mask);
+                        let x = graph.activation(*func, &sums)?;
+                        Ok(graph.op("Mul", &[&x, &mask]))
+                    })
+                    .collect::<Result<Vec<_>, Error>>()?;
+                let terms =
+                    terms.iter().map(|x| x.as_str()).collect::<Vec<_>>();
+                graph.op("Sum", &terms)
+            };
+
+            let next = graph.new_name();
+            graph.op_into(
+                "Concat",
+                &[&values, &activated],
+                Some(int_attribute("axis", 1)),
+                &next,
+            );
+            values = next;
+            start += size;
+            Ok(())
+        })?;
+
+        let output_positions = builder
+            .nodes
+            .iter()
+            .enumerate()
+            .filter(|(_i, n)| n.node_type == NodeType::Output)
+            .map(|(i, _n)| position[i] as i64)
+            .collect::<Vec<_>>();
+        let num_outputs = output_positions.len();
+        let output_positions = graph.int64s(&[num_outputs], &output_positions);
+        // Hardmax gives the same one-hot vector as `ArgMax`, with ties
+        // going to the first output.
+        let transform = match builder.output_transform() {
+            OutputTransform::Identity => None,
+            OutputTransform::Softmax => Some("Softmax"),
+            OutputTransform::ArgMax => Some("Hardmax"),
+        };
+        let mapping = builder.output_mapping();
+        let transformed = if mapping.is_empty() {
+            "output".to_string()
+        } else {
+            graph.new_name()
+        };
+        let gathered = match transform {
+            Some(_) => graph.new_name(),
+            None => transformed.clone(),
+        };
+        graph.op_into(
+            "Gather",
+            &[&values, &output_positions],
+            Some(int_attribute("axis", 1)),
+            &gathered,
+        );
+        if let Some(op_type) = transform {
+            graph.op_into(
+                op_type,
+                &[&gathered],
+                Some(int_attribute("axis", 1)),
+                &transformed,
+            );
+        }
+        if !mapping.is_empty() {
+            // Each mapping gathers its group of outputs, and the
+            // results are concatenated along with the outputs left
+            // over.
+            let mut start = 0;
+            let mut pieces = Vec::new();
+            let gather = |graph: &mut Graph, start: usize, size: usize| {
+                let positions =
+                    (start..start + size).map(|i| i as i64).collect::<Vec<_>>();
+                let positions = graph.int64s(&[size], &positions);
+                graph.op_with(
+                    "Gather",
+                    &[&transformed, &positions],
+                    Some(int_attribute("axis", 1)),
+                )
+            };
+            for mapping in mapping {
+                if start == num_outputs {
+                    break;
+                }
+                let size = mapping.group_size(num_outputs - start);
+                let group = gather(&mut graph, start, size);
+                pieces.push(match *mapping {
+                    OutputMapping::Affine { scale, offset } => {
+                        let scale = graph.floats(&[1], &[scale]);
+                        let offset = graph.floats(&[1], &[offset]);
+                        let scaled = graph.op("Mul", &[&group, &scale]);
+                        graph.op("Add", &[&scaled, &offset])
+                    }
+                    OutputMapping::Discrete(_) => {
+                        let index = graph.op_with(
+                            "ArgMax",
+                            &[&group],
+                            Some(int_attribute("axis", 1)),
+                        );
+                        graph.op_with(
+                            "Cast",
+                            &[&index],
+                            Some(int_attribute("to", FLOAT)),
+                        )
+                    }
+                });
+                start += size;
+            }
+            if start < num_outputs {
+                pieces.push(gather(&mut graph, start, num_outputs - start));
+            }
+            let pieces = pieces.iter().map(|x| x.as_str()).collect::<Vec<_>>();
+            graph.op_into(
+                "Concat",
+                &pieces,
+                Some(int_attribute("axis", 1)),
+                "output",
+            );
+        }
+        let num_outputs = OutputMapping::num_mapped(mapping, num_outputs);
+
+        let mut graph_proto = Vec::new();
+        graph.nodes.iter().for_each(|node| {
+            proto::bytes(&mut graph_proto, 1, node);
+        });
+        proto::string(&mut graph_proto, 2, "entendre");
+        graph.initializers.iter().for_each(|tensor| {
+            proto::bytes(&mut graph_proto, 5, tensor);
+        });
+        proto::bytes(&mut graph_proto, 11, &value_info("input", num_inputs));
+        proto::bytes(&mut graph_proto, 12, &value_info("output", num_outputs));
+
+        let mut opset = Vec::new();
+        proto::string(&mut opset, 1, "");
+        proto::int(&mut opset, 2, OPSET_VERSION);
+
+        let mut model = Vec::new();
+        proto::int(&mut model, 1, IR_VERSION);
+        proto::string(&mut model, 2, "entendre-rust");
+        proto::string(&mut model, 3, env!("CARGO_PKG_VERSION"));
+        proto::bytes(&mut model, 7, &graph_proto);
+        proto::bytes(&mut model, 8, &opset);
+        Ok(model)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Length-delimited fields of a protobuf message with the given
+    // field number, skipping over all other fields.
+    fn fields(mut buf: &[u8], field: u32) -> Vec<&[u8]> {
+        let varint = |buf: &mut &[u8]| {
+            let mut x = 0u64;
+            let mut shift = 0;
+            loop {
+                let byte = buf[0];
+                *buf = &buf[1..];
+                x |= ((byte & 0x7f) as u64) << shift;
+                shift += 7;
+                if byte < 0x80 {
+                    return x;
+                }
+            }
+        };
+
+        let mut res = Vec::new();
+        while !buf.is_empty() {
+            let key = varint(&mut buf);
+            match key & 7 {
+                0 => {
+                    varint(&mut buf);
+                }
+                2 => {
+                    let len = varint(&mut buf) as usize;
+                    if key >> 3 == field as u64 {
+                        res.push(&buf[..len]);
+                    }
+                    buf = &buf[len..];
+                }
+                _ => panic!("Unexpected wire type"),
+            }
+        }
+        res
+    }
+
+    #[test]
+    fn test_to_onnx() -> Result<(), Error> {
+        let mut builder = NeuralNetBuilder::new();
+        builder
+            .set_output_transform(OutputTransform::Softmax)
+            .add_nodes(NodeType::Input, 2)
+            .add_nodes(NodeType::Output, 1);
+        builder.add_node(NodeType::Hidden, ActivationFunction::Relu);
+        builder.add_node(NodeType::Hidden, ActivationFunction::Tanh);
+        let model = builder
+            .add_normal_connection_raw(0, 3, 1.0)
+            .add_normal_connection_raw(1, 4, 1.0)
+            .add_normal_connection_raw(3, 2, 1.0)
+            .add_normal_connection_raw(4, 2, 1.0)
+            .to_onnx()?;
+
+        let graph = fields(&model, 7)[0];
+        let op_types = fields(graph, 1)
+            .iter()
+            .map(|node| std::str::from_utf8(fields(node, 4)[0]).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            op_types,
+            vec![
+                // Hidden layer, with two activation functions
+                "MatMul", "Relu", "Mul", "Tanh", "Mul", "Sum", "Concat",
+                // Output layer
+                "MatMul", "Sigmoid", "Concat", "Gather", "Softmax",
+            ]
+        );
+
+        // Input scaling and output mapping.
+        let model = NeuralNetBuilder::new()
+            .with_input_scaling(vec![InputScaling::ZScore {
+                mean: 1.0,
+                std_dev: 2.0,
+            }])
+            .with_output_mapping(vec![
+                OutputMapping::Affine {
+                    scale: 2.0,
+                    offset: 1.0,
+                },
+                OutputMapping::Discrete(2),
+            ])
+            .with_inputs(1)
+            .with_nodes(NodeType::Output, 3)
+            .with_normal_connection_raw(0, 1, 1.0)
+            .to_onnx()?;
+        let graph = fields(&model, 7)[0];
+        let op_types = fields(graph, 1)
+            .iter()
+            .map(|node| std::str::from_utf8(fields(node, 4)[0]).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            op_types,
+            vec![
+                "Mul", "Add", "MatMul", "Sigmoid", "Concat", "Gather",
+                "Gather", "Mul", "Add", "Gather", "ArgMax", "Cast", "Concat",
+            ]
+        );
+
+        let res = NeuralNetBuilder::new()
+            .add_nodes(NodeType::Input, 1)
+            .add_nodes(NodeType::Output, 1)
+            .add_recurrent_connection_raw(1, 1, 1.0)
+            .to_onnx();
+        assert!(matches!(
+            res,
+            Err(Error::UnsupportedRecurrentConnection { .. })
+        ));
+
+        let mut builder = NeuralNetBuilder::new();
+        let input = builder.add_input();
+        let output = builder
+            .add_node(NodeType::Output, ActivationFunction::Custom(f32::sqrt));
+        let res = builder.add_normal_connection(input, output, 1.0).to_onnx();
+        assert!(matches!(
+            res,
+            Err(Error::UnsupportedActivationFunction { .. })
+        ));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,136 @@
+use crate::mutation::*;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum SearchPhase {
+    // Genomes grow through the usual mutations.
+    Complexifying,
+    // Genomes shrink through the mutations of
+    // `PhasedSearchConfig::simplify_mutation`.
+    Simplifying,
+}
+
+// Alternates between complexifying and simplifying, as in SharpNEAT,
+// so that genomes don't keep growing over long runs.  Complexity is
+// the mean number of connection genes per genome.
+#[derive(Debug, PartialEq, Clone)]
+pub struct PhasedSearchConfig {
+    // Simplifying starts once complexity exceeds its value at the
+    // start of the last complexifying phase by this much.
+    pub complexity_threshold: f32,
+    // Simplifying ends once complexity hasn't reached a new low for
+    // this many generations.
+    pub min_simplify_generations: usize,
+    pub simplify_mutation: MutationConfig,
+}
+
+impl Default for PhasedSearchConfig {
+    fn default() -> Self {
+        Self {
+            complexity_threshold: 30.0,
+            min_simplify_generations: 10,
+            simplify_mutation: MutationConfig {
+                add_connection_prob: 0.0,
+                add_node_prob: 0.0,
+                delete_connection_prob: 0.2,
+                delete_node_prob: 0.05,
+                ..MutationConfig::default()
+            },
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) struct PhaseState {
+    pub(crate) phase: SearchPhase,
+    // Complexity at which to start simplifying, set at the start of
+    // each complexifying phase.
+    pub(crate) ceiling: Option<f32>,
+    // Lowest complexity of the current simplifying phase, and the
+    // number of generations since it was reached.
+    pub(crate) lowest: f32,
+    pub(crate) stalled: usize,
+}
+
+impl Default for PhaseState {
+    fn default() -> Self {
+        Self {
+            phase: SearchPhase::Complexifying,
+            ceiling: None,
+            lowest: f32::INFINITY,
+            stalled: 0,
+        }
+    }
+}
+
+impl PhaseState {
+    // Moves to the phase for the generation with the given
+    // complexity.
+    pub(crate) fn update(
+        &mut self,
+        config: &PhasedSearchConfig,
+        complexity: f32,
+    ) -> SearchPhase {
+        use SearchPhase::*;
+        match self.phase {
+            Complexifying => {
+                let ceiling = *self
+                    .ceiling
+                    .get_or_insert(complexity + config.complexity_threshold);
+                if complexity > ceiling {
+                    self.phase = Simplifying;
+                    self.lowest = complexity;
+                    self.stalled = 0;
+                }
+            }
+            Simplifying => {
+                if complexity < self.lowest {
+                    self.lowest = complexity;
+                    self.stalled = 0;
+                } else {
+                    self.stalled += 1;
+                }
+                if self.stalled >= config.min_simplify_generations {
+                    self.phase = Complexifying;
+                    self.ceiling =
+                        Some(complexity + config.complexity_threshold);
+                }
+            }
+        }
+        self.phase
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_phase_changes() {
+        let config = PhasedSearchConfig {
+            complexity_threshold: 5.0,
+            min_simplify_generations: 2,
+            ..PhasedSearchConfig::default()
+        };
+        let mut state = PhaseState::default();
+        use SearchPhase::*;
+        let phases = [2.0, 6.0, 8.0, 6.0, 4.0, 4.0, 5.0, 9.0, 11.0]
+            .iter()
+            .map(|complexity| state.update(&config, *complexity))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            phases,
+            vec![
+                Complexifying,
+                Complexifying,
+                Simplifying,
+                Simplifying,
+                Simplifying,
+                Simplifying,
+                Complexifying,
+                Complexifying,
+                Simplifying,
+            ]
+        );
+        assert_eq!(state.lowest, 11.0);
+    }
+}
@@ -0,0 +1,108 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::genome::*;
+use crate::neural_net::*;
+
+// Networks built from genomes, keyed by `Genome::topology_hash` with
+// weights, so that genomes carried over unchanged between
+// generations, such as elites, are cloned instead of rebuilt.  The
+// networks are stored before they are ever evaluated, so clones start
+// from a reset state.  Genomes whose hashes collide share a network,
+// which is unlikely enough with 64 bits to be ignored.
+#[derive(Debug, Clone)]
+pub struct PhenotypeCache<N> {
+    nets: HashMap<u64, N>,
+    // Keys built since the last `evict_unused`.
+    used: HashSet<u64>,
+    hits: usize,
+    misses: usize,
+}
+
+impl<N> Default for PhenotypeCache<N> {
+    fn default() -> Self {
+        Self {
+            nets: HashMap::new(),
+            used: HashSet::new(),
+            hits: 0,
+            misses: 0,
+        }
+    }
+}
+
+impl<N: NeuralNet + Clone> PhenotypeCache<N> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn build(&mut self, genome: &Genome) -> Result<N, Error> {
+        let key = genome.topology_hash(true);
+        self.used.insert(key);
+        if let Some(net) = self.nets.get(&key) {
+            self.hits += 1;
+            return Ok(net.clone());
+        }
+        self.misses += 1;
+        let net = genome.to_builder().build::<N>()?;
+        self.nets.insert(key, net.clone());
+        Ok(net)
+    }
+
+    // Drops the networks that haven't been built since the last call,
+    // which once per generation keeps only the current generation.
+    pub fn evict_unused(&mut self) {
+        let used = std::mem::take(&mut self.used);
+        self.nets.retain(|key, _| used.contains(key));
+    }
+
+    pub fn clear(&mut self) {
+        self.nets.clear();
+        self.used.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.nets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nets.is_empty()
+    }
+
+    // Number of builds answered from the cache, and of those that
+    // weren't.
+    pub fn hits(&self) -> usize {
+        self.hits
+    }
+
+    pub fn misses(&self) -> usize {
+        self.misses
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::*;
+
+    #[test]
+    fn test_phenotype_cache() -> Result<(), Error> {
+        let mut tracker = InnovationTracker::new();
+        let func = ActivationFunction::Identity;
+        let mut a = Genome::with_io(2, 1, func, &mut tracker);
+        a.add_connection(0, 2, 1.0, ConnectionType::Normal, &mut tracker);
+        let mut b = a.clone();
+        b.connections[0].weight = -1.0;
+
+        let mut cache = PhenotypeCache::<ConsecutiveNeuralNet>::new();
+        let mut net = cache.build(&a)?;
+        assert_eq!(net.evaluate(&[1.0, 0.0]), vec![1.0]);
+        cache.build(&a)?;
+        cache.build(&b)?;
+        assert_eq!((cache.hits(), cache.misses(), cache.len()), (1, 2, 2));
+
+        cache.evict_unused();
+        cache.build(&b)?;
+        cache.evict_unused();
+        assert_eq!((cache.hits(), cache.len()), (2, 1));
+        Ok(())
+    }
+}
@@ -0,0 +1,1262 @@
+use rand::seq::IteratorRandom;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use crate::crossover::*;
+use crate::environment::*;
+use crate::genome::*;
+use crate::hall_of_fame::*;
+use crate::lineage::*;
+use crate::multi_objective::*;
+use crate::mutation::*;
+use crate::neural_net::*;
+use crate::phased_search::*;
+use crate::phenotype_cache::*;
+use crate::reporting::*;
+use crate::selection::*;
+use crate::species::*;
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct PopulationConfig {
+    pub population_size: usize,
+    pub num_inputs: u32,
+    pub num_outputs: u32,
+    pub output_activation: ActivationFunction,
+    pub mutation: MutationConfig,
+    pub crossover: CrossoverConfig,
+    pub speciation: SpeciationConfig,
+    pub selection: SelectionStrategy,
+    // Number of fittest members of each species that are copied
+    // unchanged into the next generation.
+    pub elitism: usize,
+    // Probability that a child is produced by crossover of two
+    // parents, rather than by mutating a copy of a single parent.
+    pub crossover_prob: f32,
+    // Seed of the random number generator behind every random choice
+    // of the run, so that it can be reproduced exactly.  Without a
+    // seed, one is taken from the operating system.
+    pub seed: Option<u64>,
+    // If set, evolution alternates between complexifying with
+    // `mutation` and simplifying.
+    pub phased_search: Option<PhasedSearchConfig>,
+    // Number of entries kept in the hall of fame, with none archived
+    // at zero.
+    pub hall_of_fame_capacity: usize,
+    // Whether to record the parents and mutations of every genome, in
+    // `lineage`.
+    pub track_lineage: bool,
+    // If set, fitness is reduced in proportion to the size of each
+    // genome before selection, to keep networks from bloating.
+    pub complexity_penalty: Option<ComplexityPenalty>,
+    // Connections between the inputs and outputs of the genomes that
+    // `new` starts from.
+    pub initial_topology: InitialTopology,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum InitialTopology {
+    // Every input connected to every output.
+    #[default]
+    FullyConnected,
+    // Each input connected to each output with the given probability.
+    Partial {
+        connection_prob: f32,
+    },
+    // No connections, leaving evolution to add them all.
+    Unconnected,
+    // A single connection, from a random input to a random output, as
+    // in FS-NEAT, so that evolution selects which inputs to use.
+    FsNeat,
+}
+
+// Amount subtracted from the fitness of a genome for each hidden node
+// and each enabled connection.  Only selection sees the penalized
+// fitness: statistics, stagnation, the champion and the hall of fame
+// use the fitness as given.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct ComplexityPenalty {
+    pub per_node: f32,
+    pub per_connection: f32,
+}
+
+impl Default for PopulationConfig {
+    fn default() -> Self {
+        Self {
+            population_size: 150,
+            num_inputs: 1,
+            num_outputs: 1,
+            output_activation: ActivationFunction::Sigmoid,
+            mutation: MutationConfig::default(),
+            crossover: CrossoverConfig::default(),
+            speciation: SpeciationConfig::default(),
+            selection: SelectionStrategy::default(),
+            elitism: 1,
+            crossover_prob: 0.75,
+            seed: None,
+            phased_search: None,
+            hall_of_fame_capacity: 0,
+            track_lineage: false,
+            complexity_penalty: None,
+            initial_topology: InitialTopology::default(),
+        }
+    }
+}
+
+// A genome of the current generation that needs a fitness, for
+// callers that evaluate genomes outside of `evaluate`, such as by
+// asking a person or a remote worker.  Requests are `Send`, so they
+// can be passed over channels.
+#[derive(Debug, PartialEq, Clone)]
+pub struct EvaluationRequest {
+    pub generation: usize,
+    // Position of the genome in `genomes()`.
+    pub index: usize,
+    pub genome: Genome,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum TellOutcome {
+    // Number of genomes of the generation still without a fitness.
+    Pending(usize),
+    // That was the last fitness, so the population advanced to the
+    // next generation.
+    Advanced,
+    // The request was for an earlier generation, or for no genome,
+    // and the fitness was ignored.
+    Stale,
+}
+
+#[derive(Debug)]
+pub struct Population {
+    pub config: PopulationConfig,
+    pub(crate) genomes: Vec<Genome>,
+    pub(crate) tracker: InnovationTracker,
+    pub(crate) speciator: Speciator,
+    pub(crate) generation: usize,
+    pub(crate) champion: Option<(Genome, f32)>,
+    // Seekable, so that its state can be saved in checkpoints.
+    pub(crate) rng: ChaCha8Rng,
+    pub(crate) reporters: Vec<Box<dyn Reporter>>,
+    pub(crate) phase: PhaseState,
+    pub(crate) hall_of_fame: HallOfFame,
+    pub(crate) lineage: Lineage,
+    // Id in `lineage` of each genome, if tracking lineage.
+    pub(crate) genome_ids: Vec<u64>,
+    // Fitnesses given to `tell` this generation, or empty if none.
+    pub(crate) told: Vec<Option<f32>>,
+}
+
+impl Population {
+    // Starts from genomes with only input and output nodes, connected
+    // as set by `initial_topology` through randomly weighted
+    // connections.
+    pub fn new(config: PopulationConfig) -> Self {
+        let mut rng = seeded_rng(config.seed);
+        let mut tracker = InnovationTracker::new();
+
+        let genomes = (0..config.population_size)
+            .map(|_| {
+                let genome = initial_genome(
+                    config.initial_topology,
+                    config.num_inputs,
+                    config.num_outputs,
+                    config.output_activation,
+                    &config.mutation,
+                    &mut tracker,
+                    &mut rng,
+                );
+                (genome, Vec::new())
+            })
+            .collect();
+        Self::from_initial_genomes(config, genomes, tracker, rng)
+    }
+
+    // Starts from copies of `genome`, such as the champion of an
+    // earlier run, each mutated once with `config.mutation`, except
+    // for the first, which is kept as it is.  The numbers of inputs
+    // and outputs are taken from the genome rather than from the
+    // config.
+    pub fn from_seed_genome(genome: &Genome, config: PopulationConfig) -> Self {
+        let mut config = config;
+        let count = |node_type| {
+            genome
+                .nodes
+                .iter()
+                .filter(|node| node.node_type == node_type)
+                .count() as u32
+        };
+        config.num_inputs = count(NodeType::Input);
+        config.num_outputs = count(NodeType::Output);
+
+        let mut rng = seeded_rng(config.seed);
+        let mut tracker = InnovationTracker::new();
+        tracker.observe(genome);
+        let genomes = (0..config.population_size)
+            .map(|i| {
+                let mut copy = genome.clone();
+                let mutations = match i {
+                    0 => Vec::new(),
+                    _ => copy.mutate(&config.mutation, &mut tracker, &mut rng),
+                };
+                (copy, mutations)
+            })
+            .collect();
+        Self::from_initial_genomes(config, genomes, tracker, rng)
+    }
+
+    // First generation, given with the mutations that made each of
+    // its genomes, for the lineage.
+    fn from_initial_genomes(
+        config: PopulationConfig,
+        genomes: Vec<(Genome, Vec<MutationKind>)>,
+        tracker: InnovationTracker,
+        mut rng: ChaCha8Rng,
+    ) -> Self {
+        let (genomes, mutations): (Vec<_>, Vec<_>) =
+            genomes.into_iter().unzip();
+        let mut speciator = Speciator::new(config.speciation.clone());
+        speciator.speciate(&genomes, &mut rng);
+
+        let hall_of_fame = HallOfFame::new(config.hall_of_fame_capacity);
+        let mut lineage = Lineage::new();
+        let genome_ids = match config.track_lineage {
+            true => mutations
+                .into_iter()
+                .map(|mutations| lineage.record(0, Vec::new(), mutations))
+                .collect(),
+            false => Vec::new(),
+        };
+        Self {
+            config,
+            genomes,
+            tracker,
+            speciator,
+            generation: 0,
+            champion: None,
+            rng,
+            reporters: Vec::new(),
+            phase: PhaseState::default(),
+            hall_of_fame,
+            lineage,
+            genome_ids,
+            told: Vec::new(),
+        }
+    }
+
+    pub fn genomes(&self) -> &[Genome] {
+        &self.genomes
+    }
+
+    pub fn species(&self) -> &[Species] {
+        &self.speciator.species
+    }
+
+    pub fn generation(&self) -> usize {
+        self.generation
+    }
+
+    // Phase the current generation was produced in.
+    pub fn phase(&self) -> SearchPhase {
+        self.phase.phase
+    }
+
+    // Best genome seen so far, along with its fitness.
+    pub fn champion(&self) -> Option<(&Genome, f32)> {
+        self.champion
+            .as_ref()
+            .map(|(genome, fitness)| (genome, *fitness))
+    }
+
+    // Best genomes of past generations and of their species, if
+    // `hall_of_fame_capacity` is set.
+    pub fn hall_of_fame(&self) -> &HallOfFame {
+        &self.hall_of_fame
+    }
+
+    // Ancestry of every genome so far, if `track_lineage` is set.
+    pub fn lineage(&self) -> &Lineage {
+        &self.lineage
+    }
+
+    // Id in `lineage` of each genome, in the same order as
+    // `genomes()`, or none without `track_lineage`.
+    pub fn genome_ids(&self) -> &[u64] {
+        &self.genome_ids
+    }
+
+    // Adds a reporter, which receives the statistics of each
+    // generation as it is replaced by the next.
+    pub fn add_reporter(&mut self, reporter: Box<dyn Reporter>) {
+        self.reporters.push(reporter);
+    }
+
+    // The error of the first reporter that has failed, if any, since
+    // reporters keep failures to themselves rather than stop the run.
+    pub fn reporter_error(&self) -> Option<&std::io::Error> {
+        self.reporters.iter().find_map(|r| r.error())
+    }
+
+    // Statistics of the current generation, given the fitness of
+    // each genome.
+    pub fn stats(&self, fitnesses: &[f32]) -> GenerationStats {
+        let mean = |total: f32| total / fitnesses.len().max(1) as f32;
+        let num_genes = |genes: fn(&Genome) -> usize| {
+            self.genomes.iter().map(|g| genes(g) as f32).sum::<f32>()
+                / self.genomes.len().max(1) as f32
+        };
+        GenerationStats {
+            generation: self.generation,
+            best_fitness: fitnesses
+                .iter()
+                .cloned()
+                .fold(f32::NEG_INFINITY, f32::max),
+            mean_fitness: mean(fitnesses.iter().sum()),
+            species_sizes: self
+                .species()
+                .iter()
+                .map(|s| s.members.len())
+                .collect(),
+            mean_nodes: num_genes(|g| g.nodes.len()),
+            mean_connections: num_genes(|g| g.connections.len()),
+        }
+    }
+
+    // Produces the next generation, given the fitness of each genome
+    // of the current generation, in the same order as `genomes()`.
+    pub fn advance_generation(&mut self, fitnesses: &[f32]) {
+        assert_eq!(
+            fitnesses.len(),
+            self.genomes.len(),
+            "Expected one fitness value per genome"
+        );
+
+        #[cfg(feature = "tracing")]
+        let _span = tracing::info_span!(
+            "advance_generation",
+            generation = self.generation
+        )
+        .entered();
+
+        if !self.reporters.is_empty() {
+            let stats = self.stats(fitnesses);
+            self.reporters.iter_mut().for_each(|r| r.report(&stats));
+        }
+
+        self.update_champion(fitnesses);
+        if self.hall_of_fame.capacity > 0 {
+            self.hall_of_fame.record(
+                self.generation,
+                &self.genomes,
+                fitnesses,
+                &self.speciator.species,
+            );
+        }
+        let lineage = &mut self.lineage;
+        self.genome_ids
+            .iter()
+            .zip(fitnesses)
+            .for_each(|(id, fitness)| lineage.set_fitness(*id, *fitness));
+        if let Some(phased_search) = &self.config.phased_search {
+            let complexity = self.stats(fitnesses).mean_connections;
+            self.phase.update(phased_search, complexity);
+        }
+        self.speciator.cull_stagnant(fitnesses);
+        let penalized = self.penalized_fitnesses(fitnesses);
+        let fitnesses = &penalized[..];
+
+        let offspring_counts = self.offspring_counts(fitnesses);
+        let species = std::mem::take(&mut self.speciator.species);
+
+        let mut next_generation = Vec::with_capacity(self.genomes.len());
+        let mut next_ids = Vec::new();
+        species.iter().zip(offspring_counts.iter()).for_each(
+            |(species, num_offspring)| {
+                #[cfg(feature = "tracing")]
+                let _span = tracing::debug_span!(
+                    "reproduce",
+                    species = species.id,
+                    members = species.members.len(),
+                    offspring = *num_offspring
+                )
+                .entered();
+                self.reproduce(
+                    species,
+                    fitnesses,
+                    *num_offspring,
+                    &mut next_generation,
+                    &mut next_ids,
+                );
+            },
+        );
+
+        self.speciator.species = species;
+        self.genomes = next_generation;
+        self.genome_ids = next_ids;
+        self.told.clear();
+        self.speciator.speciate(&self.genomes, &mut self.rng);
+        self.generation += 1;
+
+        #[cfg(feature = "tracing")]
+        tracing::info!(
+            num_species = self.speciator.species.len(),
+            champion_fitness = self.champion.as_ref().map(|(_, f)| *f),
+            "Advanced generation"
+        );
+    }
+
+    // Fitness of each genome, in the same order as `genomes()`, from
+    // networks built with backend `N`.
+    pub fn evaluate<N, F>(&self, fitness: &F) -> Result<Vec<f32>, Error>
+    where
+        N: NeuralNet + Send,
+        F: FitnessFunction<N> + Sync,
+    {
+        self.evaluate_with(fitness, |genome| genome.to_builder().build::<N>())
+    }
+
+    // Same as `evaluate`, but takes the networks of genomes seen in
+    // the previous generation from `cache`, which then only holds
+    // those of this generation.
+    pub fn evaluate_cached<N, F>(
+        &self,
+        fitness: &F,
+        cache: &mut PhenotypeCache<N>,
+    ) -> Result<Vec<f32>, Error>
+    where
+        N: NeuralNet + Clone + Send,
+        F: FitnessFunction<N> + Sync,
+    {
+        let fitnesses =
+            self.evaluate_with(fitness, |genome| cache.build(genome));
+        cache.evict_unused();
+        fitnesses
+    }
+
+    fn evaluate_with<N, F, B>(
+        &self,
+        fitness: &F,
+        build: B,
+    ) -> Result<Vec<f32>, Error>
+    where
+        N: NeuralNet + Send,
+        F: FitnessFunction<N> + Sync,
+        B: FnMut(&Genome) -> Result<N, Error>,
+    {
+        #[cfg(feature = "tracing")]
+        let _span =
+            tracing::info_span!("evaluate", generation = self.generation)
+                .entered();
+
+        let mut nets = {
+            #[cfg(feature = "tracing")]
+            let _span = tracing::debug_span!("build").entered();
+            self.genomes
+                .iter()
+                .map(build)
+                .collect::<Result<Vec<_>, _>>()?
+        };
+        let fitnesses =
+            evaluate_parallel(&mut nets, |net| fitness.evaluate(net));
+
+        #[cfg(feature = "tracing")]
+        {
+            let stats = self.stats(&fitnesses);
+            tracing::info!(
+                best_fitness = stats.best_fitness,
+                mean_fitness = stats.mean_fitness,
+                "Evaluated generation"
+            );
+        }
+        Ok(fitnesses)
+    }
+
+    // Evaluates the current generation and advances to the next,
+    // returning the fitnesses of the evaluated generation.
+    pub fn run_generation<N, F>(
+        &mut self,
+        fitness: &F,
+    ) -> Result<Vec<f32>, Error>
+    where
+        N: NeuralNet + Send,
+        F: FitnessFunction<N> + Sync,
+    {
+        let fitnesses = self.evaluate(fitness)?;
+        self.advance_generation(&fitnesses);
+        Ok(fitnesses)
+    }
+
+    // Genomes of the current generation that haven't been given a
+    // fitness by `tell`.  Each generation can be asked about any
+    // number of times, such as to hand out requests in batches.
+    pub fn ask(&self) -> Vec<EvaluationRequest> {
+        self.genomes
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| !matches!(self.told.get(*i), Some(Some(_))))
+            .map(|(index, genome)| EvaluationRequest {
+                generation: self.generation,
+                index,
+                genome: genome.clone(),
+            })
+            .collect()
+    }
+
+    // Gives the fitness of a requested genome, in any order, and
+    // advances the population once every genome of the generation has
+    // one.  Telling a genome again replaces its fitness.
+    pub fn tell(
+        &mut self,
+        request: &EvaluationRequest,
+        fitness: f32,
+    ) -> TellOutcome {
+        if request.generation != self.generation
+            || request.index >= self.genomes.len()
+        {
+            return TellOutcome::Stale;
+        }
+        self.told.resize(self.genomes.len(), None);
+        self.told[request.index] = Some(fitness);
+
+        let remaining = self.told.iter().filter(|f| f.is_none()).count();
+        if remaining > 0 {
+            return TellOutcome::Pending(remaining);
+        }
+        let fitnesses = self.told.iter().flatten().cloned().collect::<Vec<_>>();
+        self.advance_generation(&fitnesses);
+        TellOutcome::Advanced
+    }
+
+    // Same as `advance_generation`, but with several objectives per
+    // genome, all maximized, which are ranked by NSGA-II.  The
+    // champion is then the best genome by that ranking in the
+    // generation it first appeared.
+    pub fn advance_generation_multi(&mut self, objectives: &[Vec<f32>]) {
+        self.advance_generation(&nsga2_fitness(objectives));
+    }
+
+    pub(crate) fn update_champion(&mut self, fitnesses: &[f32]) {
+        let best = fitnesses
+            .iter()
+            .enumerate()
+            .max_by(|(_, a), (_, b)| a.total_cmp(b));
+        if let Some((i, fitness)) = best {
+            let improved = match &self.champion {
+                Some((_, best)) => fitness > best,
+                None => true,
+            };
+            if improved {
+                self.champion = Some((self.genomes[i].clone(), *fitness));
+            }
+        }
+    }
+
+    // The fitness of each genome minus its complexity penalty, if
+    // any.
+    pub fn penalized_fitnesses(&self, fitnesses: &[f32]) -> Vec<f32> {
+        let penalty = match self.config.complexity_penalty {
+            Some(penalty) => penalty,
+            None => return fitnesses.to_vec(),
+        };
+        self.genomes
+            .iter()
+            .zip(fitnesses)
+            .map(|(genome, fitness)| {
+                let metrics = genome.metrics();
+                fitness
+                    - penalty.per_node * metrics.num_hidden as f32
+                    - penalty.per_connection * metrics.num_enabled as f32
+            })
+            .collect()
+    }
+
+    // Number of children of each species in the next generation, in
+    // proportion to the species' total shared fitness.  With
+    // explicit fitness sharing, the shared fitness of each genome is
+    // its fitness divided by the size of its species.
+    fn offspring_counts(&self, fitnesses: &[f32]) -> Vec<usize> {
+        // Shared fitness must be non-negative to be used as a
+        // proportion.
+        let min_fitness = fitnesses.iter().cloned().fold(0.0, f32::min);
+
+        let shares = self
+            .species()
+            .iter()
+            .map(|s| {
+                s.members
+                    .iter()
+                    .map(|i| fitnesses[*i] - min_fitness)
+                    .sum::<f32>()
+                    / s.members.len() as f32
+            })
+            .collect::<Vec<_>>();
+        let total = shares.iter().sum::<f32>();
+
+        let num_species = shares.len();
+        let exact = shares
+            .iter()
+            .map(|share| {
+                let fraction = if total > 0.0 {
+                    share / total
+                } else {
+                    1.0 / num_species as f32
+                };
+                fraction * self.config.population_size as f32
+            })
+            .collect::<Vec<_>>();
+
+        // Round down, then hand out the remaining children to the
+        // species with the largest remainders.
+        let mut counts = exact.iter().map(|x| *x as usize).collect::<Vec<_>>();
+        let assigned = counts.iter().sum::<usize>();
+        let mut by_remainder = (0..num_species).collect::<Vec<_>>();
+        by_remainder.sort_by(|a, b| {
+            let rem_a = exact[*a] - counts[*a] as f32;
+            let rem_b = exact[*b] - counts[*b] as f32;
+            rem_b.total_cmp(&rem_a)
+        });
+        by_remainder
+            .iter()
+            .cycle()
+            .take(self.config.population_size.saturating_sub(assigned))
+            .for_each(|i| counts[*i] += 1);
+
+        counts
+    }
+
+    // Adds `num_offspring` children of the species to
+    // `next_generation`.  The elite of the species survives
+    // unchanged, and the rest are bred from parents chosen by the
+    // selection strategy.
+    fn reproduce(
+        &mut self,
+        species: &Species,
+        fitnesses: &[f32],
+        num_offspring: usize,
+        next_generation: &mut Vec<Genome>,
+        next_ids: &mut Vec<u64>,
+    ) {
+        if num_offspring == 0 {
+            return;
+        }
+
+        let mut ranked = species.members.clone();
+        ranked.sort_by(|a, b| fitnesses[*b].total_cmp(&fitnesses[*a]));
+
+        let num_elites = self.config.elitism.min(num_offspring);
+        ranked
+            .iter()
+            .take(num_elites)
+            .for_each(|i| next_generation.push(self.genomes[*i].clone()));
+        if self.config.track_lineage {
+            next_ids.extend(
+                ranked.iter().take(num_elites).map(|i| self.genome_ids[*i]),
+            );
+        }
+
+        (num_elites..num_offspring).for_each(|_| {
+            let (child, parents, mutations) = self.breed(&ranked, fitnesses);
+            next_generation.push(child);
+            if self.config.track_lineage {
+                let parents =
+                    parents.iter().map(|i| self.genome_ids[*i]).collect();
+                let generation = self.generation + 1;
+                next_ids
+                    .push(self.lineage.record(generation, parents, mutations));
+            }
+        });
+    }
+
+    // A child of parents chosen from `ranked` by the selection
+    // strategy, along with the positions of its parents and the
+    // mutations it went through.
+    pub(crate) fn breed(
+        &mut self,
+        ranked: &[usize],
+        fitnesses: &[f32],
+    ) -> (Genome, Vec<usize>, Vec<MutationKind>) {
+        let selection = self.config.selection;
+        let simplifying = self.phase.phase == SearchPhase::Simplifying;
+        let rng = &mut self.rng;
+        let a = selection.select(ranked, fitnesses, rng);
+        let (mut child, parents) =
+            if rng.gen::<f32>() < self.config.crossover_prob {
+                let b = selection.select(ranked, fitnesses, rng);
+                let child = self.genomes[a].crossover(
+                    fitnesses[a],
+                    &self.genomes[b],
+                    fitnesses[b],
+                    &self.config.crossover,
+                    rng,
+                );
+                (child, vec![a, b])
+            } else {
+                (self.genomes[a].clone(), vec![a])
+            };
+        let mutation = match &self.config.phased_search {
+            Some(phased_search) if simplifying => {
+                &phased_search.simplify_mutation
+            }
+            _ => &self.config.mutation,
+        };
+        let mutations = child.mutate(mutation, &mut self.tracker, rng);
+        (child, parents, mutations)
+    }
+}
+
+fn seeded_rng(seed: Option<u64>) -> ChaCha8Rng {
+    match seed {
+        Some(seed) => ChaCha8Rng::seed_from_u64(seed),
+        None => ChaCha8Rng::from_entropy(),
+    }
+}
+
+// A genome with only input and output nodes, connected as given by
+// `topology` through weights drawn as new weights of `mutation` are.
+pub(crate) fn initial_genome<R: Rng + ?Sized>(
+    topology: InitialTopology,
+    num_inputs: u32,
+    num_outputs: u32,
+    output_activation: ActivationFunction,
+    mutation: &MutationConfig,
+    tracker: &mut InnovationTracker,
+    rng: &mut R,
+) -> Genome {
+    let mut genome =
+        Genome::with_io(num_inputs, num_outputs, output_activation, tracker);
+    let pairs = (0..num_inputs).flat_map(|origin| {
+        (num_inputs..num_inputs + num_outputs).map(move |dest| (origin, dest))
+    });
+    let pairs = match topology {
+        InitialTopology::FullyConnected => pairs.collect(),
+        InitialTopology::Partial { connection_prob } => pairs
+            .filter(|_| rng.gen::<f32>() < connection_prob)
+            .collect(),
+        InitialTopology::Unconnected => Vec::new(),
+        InitialTopology::FsNeat => pairs.choose(rng).into_iter().collect(),
+    };
+    pairs.into_iter().for_each(|(origin, dest)| {
+        genome.add_connection(
+            origin,
+            dest,
+            mutation.random_weight(rng),
+            ConnectionType::Normal,
+            tracker,
+        );
+    });
+    genome
+}
+
+// Computes the fitness of every network, spread across the rayon
+// thread pool.  Without the `parallel` feature, the networks are
+// evaluated one after another on the current thread.
+pub fn evaluate_parallel<N, F>(nets: &mut [N], fitness: F) -> Vec<f32>
+where
+    N: NeuralNet + Send,
+    F: Fn(&mut N) -> f32 + Sync,
+{
+    #[cfg(feature = "parallel")]
+    {
+        nets.par_iter_mut().map(&fitness).collect()
+    }
+
+    #[cfg(not(feature = "parallel"))]
+    {
+        nets.iter_mut().map(fitness).collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::*;
+    use std::sync::{Arc, Mutex};
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn test_backends_are_send_sync() {
+        assert_send_sync::<ConsecutiveNeuralNet>();
+        assert_send_sync::<LayeredNeuralNet>();
+        assert_send_sync::<SparseNeuralNet>();
+        #[cfg(feature = "gpu")]
+        assert_send_sync::<GpuNeuralNet>();
+    }
+
+    #[test]
+    fn test_evaluate_parallel() -> Result<(), Error> {
+        let mut nets = (0..16)
+            .map(|i| {
+                NeuralNetBuilder::new()
+                    .set_default_activation(ActivationFunction::Identity)
+                    .add_nodes(NodeType::Input, 1)
+                    .add_nodes(NodeType::Output, 1)
+                    .add_normal_connection_raw(0, 1, i as f32)
+                    .build::<ConsecutiveNeuralNet>()
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let fitnesses =
+            evaluate_parallel(&mut nets, |net| net.evaluate(&[2.0])[0]);
+
+        let expected = (0..16).map(|i| 2.0 * i as f32).collect::<Vec<_>>();
+        assert_eq!(fitnesses, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_advance_generation() -> Result<(), Error> {
+        let config = PopulationConfig {
+            population_size: 50,
+            num_inputs: 2,
+            num_outputs: 1,
+            ..PopulationConfig::default()
+        };
+        let mut population = Population::new(config);
+        assert_eq!(population.genomes().len(), 50);
+        assert!(!population.species().is_empty());
+
+        struct Recorder(Arc<Mutex<Vec<GenerationStats>>>);
+        impl Reporter for Recorder {
+            fn report(&mut self, stats: &GenerationStats) {
+                self.0.lock().unwrap().push(stats.clone());
+            }
+        }
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        population.add_reporter(Box::new(Recorder(reports.clone())));
+        struct Full;
+        impl std::io::Write for Full {
+            fn write(&mut self, _buf: &[u8]) -> std::io::Result<usize> {
+                Err(std::io::ErrorKind::WriteZero.into())
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+        population.add_reporter(Box::new(CsvReporter::new(Full)));
+        assert!(population.reporter_error().is_none());
+
+        let fitness = |net: &mut ConsecutiveNeuralNet| {
+            1.0 - (net.evaluate(&[1.0, 0.0])[0] - 0.9).abs()
+        };
+        for generation in 0..10 {
+            let fitnesses = population.run_generation(&fitness)?;
+            let best = fitnesses.iter().cloned().fold(f32::MIN, f32::max);
+
+            assert_eq!(population.generation(), generation + 1);
+            assert_eq!(population.genomes().len(), 50);
+
+            // The champion is never lost, since the default elitism
+            // keeps the best genome of each species.
+            let (champion, fitness) = population.champion().unwrap();
+            assert!(fitness >= best);
+            assert!(population.genomes().contains(champion));
+
+            let reports = reports.lock().unwrap();
+            let stats = reports.last().unwrap();
+            assert_eq!(reports.len(), generation + 1);
+            assert_eq!(stats.generation, generation);
+            assert_eq!(stats.best_fitness, best);
+            assert_eq!(stats.species_sizes.iter().sum::<usize>(), 50);
+        }
+        let error = population.reporter_error().map(|err| err.kind());
+        assert_eq!(error, Some(std::io::ErrorKind::WriteZero));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_seeded_runs_are_reproducible() {
+        let config = PopulationConfig {
+            population_size: 30,
+            num_inputs: 2,
+            num_outputs: 1,
+            seed: Some(42),
+            ..PopulationConfig::default()
+        };
+        let run = |config: &PopulationConfig| {
+            let mut population = Population::new(config.clone());
+            (0..5).for_each(|_| {
+                let fitnesses = population
+                    .genomes()
+                    .iter()
+                    .map(|g| g.connections.iter().map(|c| c.weight).sum())
+                    .collect::<Vec<_>>();
+                population.advance_generation(&fitnesses);
+            });
+            population.genomes().to_vec()
+        };
+
+        assert_eq!(run(&config), run(&config));
+        let tracked = PopulationConfig {
+            track_lineage: true,
+            ..config.clone()
+        };
+        assert_eq!(run(&config), run(&tracked));
+        let other = PopulationConfig {
+            seed: Some(43),
+            ..config.clone()
+        };
+        assert_ne!(run(&config), run(&other));
+    }
+
+    #[test]
+    fn test_ask_tell() {
+        let config = PopulationConfig {
+            population_size: 10,
+            num_inputs: 2,
+            num_outputs: 1,
+            seed: Some(7),
+            ..PopulationConfig::default()
+        };
+        let fitness = |genome: &Genome| {
+            genome.connections.iter().map(|c| c.weight).sum::<f32>()
+        };
+        let mut expected = Population::new(config.clone());
+        let fitnesses =
+            expected.genomes().iter().map(fitness).collect::<Vec<_>>();
+        expected.advance_generation(&fitnesses);
+
+        // Answers in reverse order, half at a time.
+        let mut population = Population::new(config);
+        let mut requests = population.ask();
+        requests.reverse();
+        let (first, second) = requests.split_at(5);
+        first.iter().for_each(|request| {
+            population.tell(request, fitness(&request.genome));
+        });
+        assert_eq!(population.ask().len(), 5);
+        assert_eq!(population.ask()[0].index, 0);
+        let outcomes = second
+            .iter()
+            .map(|request| population.tell(request, fitness(&request.genome)))
+            .collect::<Vec<_>>();
+        assert_eq!(outcomes[0], TellOutcome::Pending(4));
+        assert_eq!(outcomes[4], TellOutcome::Advanced);
+
+        assert_eq!(population.generation(), 1);
+        assert_eq!(population.genomes(), expected.genomes());
+        assert_eq!(population.tell(&first[0], 0.0), TellOutcome::Stale);
+    }
+
+    #[test]
+    fn test_lineage() {
+        let config = PopulationConfig {
+            population_size: 20,
+            num_inputs: 2,
+            num_outputs: 1,
+            seed: Some(1),
+            track_lineage: true,
+            ..PopulationConfig::default()
+        };
+        let mut population = Population::new(config);
+        (0..3).for_each(|_| {
+            let fitnesses = population
+                .genomes()
+                .iter()
+                .map(|g| g.connections.iter().map(|c| c.weight).sum())
+                .collect::<Vec<_>>();
+            population.advance_generation(&fitnesses);
+        });
+
+        let ids = population.genome_ids();
+        assert_eq!(ids.len(), population.genomes().len());
+        assert!(ids.iter().all(|id| {
+            let ancestry = population.lineage().ancestry(*id);
+            ancestry[0].generation == 0 && ancestry[0].parents.is_empty()
+        }));
+        let children = ids
+            .iter()
+            .filter_map(|id| population.lineage().get(*id))
+            .filter(|record| record.generation == 3)
+            .collect::<Vec<_>>();
+        assert!(!children.is_empty());
+        assert!(children.iter().all(|record| !record.parents.is_empty()));
+    }
+
+    #[test]
+    fn test_complexity_penalty() {
+        let config = |complexity_penalty| PopulationConfig {
+            population_size: 40,
+            num_inputs: 2,
+            num_outputs: 1,
+            seed: Some(5),
+            mutation: MutationConfig {
+                add_connection_prob: 0.5,
+                add_node_prob: 0.3,
+                ..MutationConfig::default()
+            },
+            complexity_penalty,
+            ..PopulationConfig::default()
+        };
+        let penalty = ComplexityPenalty {
+            per_node: 0.5,
+            per_connection: 0.1,
+        };
+        let mut plain = Population::new(config(None));
+        let mut penalized = Population::new(config(Some(penalty)));
+
+        let genome = &penalized.genomes()[0];
+        let expected = 1.0 - 0.1 * genome.connections.len() as f32;
+        let fitnesses = vec![1.0; penalized.genomes().len()];
+        assert_eq!(penalized.penalized_fitnesses(&fitnesses)[0], expected);
+        assert_eq!(plain.penalized_fitnesses(&fitnesses), fitnesses);
+
+        // With every genome equally fit, only the penalty keeps
+        // networks from growing.
+        for _ in 0..20 {
+            plain.advance_generation(&fitnesses);
+            penalized.advance_generation(&fitnesses);
+        }
+        let plain_size = plain.stats(&fitnesses).mean_connections;
+        let penalized_size = penalized.stats(&fitnesses).mean_connections;
+        assert!(penalized_size < plain_size);
+
+        // Stagnation is tracked on the fitness as given, so the best
+        // fitness of every species that has been evaluated is 1.
+        let bests = penalized
+            .species()
+            .iter()
+            .map(|s| s.best_fitness)
+            .filter(|best| *best > f32::NEG_INFINITY)
+            .collect::<Vec<_>>();
+        assert!(!bests.is_empty());
+        assert!(bests.iter().all(|best| *best == 1.0));
+    }
+
+    #[test]
+    fn test_initial_topology() {
+        let config = |initial_topology| PopulationConfig {
+            population_size: 50,
+            num_inputs: 4,
+            num_outputs: 2,
+            seed: Some(9),
+            initial_topology,
+            ..PopulationConfig::default()
+        };
+        let num_connections = |topology| {
+            Population::new(config(topology))
+                .genomes()
+                .iter()
+                .map(|genome| genome.connections.len())
+                .collect::<Vec<_>>()
+        };
+        assert!(num_connections(InitialTopology::FullyConnected)
+            .iter()
+            .all(|n| *n == 8));
+        assert!(num_connections(InitialTopology::Unconnected)
+            .iter()
+            .all(|n| *n == 0));
+        assert!(num_connections(InitialTopology::FsNeat)
+            .iter()
+            .all(|n| *n == 1));
+
+        let partial = num_connections(InitialTopology::Partial {
+            connection_prob: 0.5,
+        });
+        let total = partial.iter().sum::<usize>();
+        assert!(partial.iter().all(|n| *n <= 8));
+        assert!(total > 100 && total < 300);
+
+        // Whichever connections are chosen, the same pair gets the
+        // same innovation.
+        let population = Population::new(config(InitialTopology::FsNeat));
+        let origins = population
+            .genomes()
+            .iter()
+            .map(|genome| genome.connections[0].origin)
+            .collect::<std::collections::HashSet<_>>();
+        assert!(origins.len() > 1);
+        population.genomes().iter().for_each(|genome| {
+            let conn = &genome.connections[0];
+            let same = population.genomes().iter().flat_map(|g| &g.connections);
+            same.filter(|c| (c.origin, c.dest) == (conn.origin, conn.dest))
+                .for_each(|c| assert_eq!(c.innovation, conn.innovation));
+        });
+    }
+
+    #[test]
+    fn test_from_seed_genome() {
+        let mut tracker = InnovationTracker::new();
+        let mut seed =
+            Genome::with_io(2, 1, ActivationFunction::Sigmoid, &mut tracker);
+        seed.add_connection(0, 2, 1.0, ConnectionType::Normal, &mut tracker);
+        seed.add_connection(1, 2, -1.0, ConnectionType::Normal, &mut tracker);
+        let hidden = seed.add_node(
+            NodeType::Hidden,
+            ActivationFunction::Tanh,
+            &mut tracker,
+        );
+        seed.add_connection(
+            0,
+            hidden,
+            0.5,
+            ConnectionType::Normal,
+            &mut tracker,
+        );
+        seed.add_connection(
+            hidden,
+            2,
+            2.0,
+            ConnectionType::Normal,
+            &mut tracker,
+        );
+
+        let config = PopulationConfig {
+            population_size: 20,
+            seed: Some(3),
+            mutation: MutationConfig {
+                add_connection_prob: 0.5,
+                add_node_prob: 0.5,
+                ..MutationConfig::default()
+            },
+            ..PopulationConfig::default()
+        };
+        let mut population = Population::from_seed_genome(&seed, config);
+        assert_eq!(population.config.num_inputs, 2);
+        assert_eq!(population.genomes().len(), 20);
+        assert_eq!(population.genomes()[0], seed);
+
+        // New genes are numbered after the seed's, and the seed's own
+        // connections keep their innovations.
+        let max_node = seed.nodes.iter().map(|n| n.id).max().unwrap();
+        let max_innovation = seed.connections.len() as u32 - 1;
+        population.genomes().iter().for_each(|genome| {
+            assert!(seed.nodes.iter().all(|n| genome.nodes.contains(n)));
+            genome
+                .nodes
+                .iter()
+                .filter(|n| !seed.nodes.contains(n))
+                .for_each(|n| assert!(n.id > max_node));
+            genome.connections.iter().for_each(|conn| {
+                match seed.connections.iter().find(|c| {
+                    (c.origin, c.dest, c.connection_type)
+                        == (conn.origin, conn.dest, conn.connection_type)
+                }) {
+                    Some(c) => assert_eq!(c.innovation, conn.innovation),
+                    None => assert!(conn.innovation > max_innovation),
+                }
+            });
+        });
+        assert!(population.genomes()[1..].iter().any(|g| *g != seed));
+
+        population.advance_generation(&[1.0; 20]);
+        assert_eq!(population.generation(), 1);
+    }
+
+    #[test]
+    fn test_seed_from_dense_layers() -> Result<(), Error> {
+        let hidden = DenseLayer {
+            weights: vec![vec![1.0, -1.0], vec![0.5, 0.0]],
+            biases: vec![0.5, -1.0],
+            activation: ActivationFunction::Relu,
+        };
+        let output = DenseLayer {
+            weights: vec![vec![1.0, 2.0]],
+            biases: vec![0.25],
+            activation: ActivationFunction::Identity,
+        };
+        let builder =
+            NeuralNetBuilder::from_dense_layers(2, &[hidden, output])?;
+        let mut tracker = InnovationTracker::new();
+        let seed = Genome::from_builder(&builder, &mut tracker);
+        assert_eq!(seed.nodes[2].bias, 0.5);
+
+        let config = PopulationConfig {
+            population_size: 10,
+            seed: Some(3),
+            ..PopulationConfig::default()
+        };
+        let population = Population::from_seed_genome(&seed, config);
+        assert_eq!(population.config.num_inputs, 2);
+        assert_eq!(population.config.num_outputs, 1);
+
+        // The unmutated seed computes the same function as the layers,
+        // biases included: relu(4 - 1 + 0.5, 2 - 1) = (3.5, 1).
+        let mut imported: ConsecutiveNeuralNet = builder.build()?;
+        let mut seeded: ConsecutiveNeuralNet =
+            population.genomes()[0].to_builder().build()?;
+        let expected = vec![3.5 + 2.0 + 0.25];
+        assert_eq!(imported.evaluate(&[4.0, 1.0]), expected);
+        assert_eq!(seeded.evaluate(&[4.0, 1.0]), expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_phased_search() -> Result<(), Error> {
+        let config = PopulationConfig {
+            population_size: 30,
+            num_inputs: 2,
+            num_outputs: 1,
+            seed: Some(3),
+            mutation: MutationConfig {
+                add_connection_prob: 0.5,
+                add_node_prob: 0.5,
+                ..MutationConfig::default()
+            },
+            phased_search: Some(PhasedSearchConfig {
+                complexity_threshold: 3.0,
+                min_simplify_generations: 3,
+                ..PhasedSearchConfig::default()
+            }),
+            ..PopulationConfig::default()
+        };
+        let mut population = Population::new(config);
+        let fitness =
+            |net: &mut ConsecutiveNeuralNet| net.evaluate(&[1.0, 1.0])[0];
+
+        // Complexity rises until it crosses the ceiling, then falls
+        // while simplifying.
+        let mut complexities = Vec::new();
+        let mut phases = Vec::new();
+        for _ in 0..40 {
+            let fitnesses = population.evaluate(&fitness)?;
+            complexities.push(population.stats(&fitnesses).mean_connections);
+            population.advance_generation(&fitnesses);
+            phases.push(population.phase());
+        }
+        let start = phases
+            .iter()
+            .position(|p| *p == SearchPhase::Simplifying)
+            .unwrap();
+        let end = start
+            + phases[start..]
+                .iter()
+                .position(|p| *p == SearchPhase::Complexifying)
+                .unwrap();
+        assert!(complexities[start] > complexities[0] + 3.0);
+        assert!(complexities[end] < complexities[start]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_advance_generation_multi() -> Result<(), Error> {
+        let config = PopulationConfig {
+            population_size: 30,
+            num_inputs: 2,
+            num_outputs: 1,
+            ..PopulationConfig::default()
+        };
+        let mut population = Population::new(config);
+
+        // Trades off the output against the number of connections.
+        for _ in 0..5 {
+            let objectives = population
+                .genomes()
+                .iter()
+                .map(|genome| {
+                    let mut net =
+                        genome.to_builder().build::<ConsecutiveNeuralNet>()?;
+                    let size = genome.connections.len() as f32;
+                    Ok(vec![net.evaluate(&[1.0, 0.0])[0], -size])
+                })
+                .collect::<Result<Vec<_>, Error>>()?;
+            population.advance_generation_multi(&objectives);
+            assert_eq!(population.genomes().len(), 30);
+        }
+        assert!(population.champion().is_some());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,158 @@
+use std::io::Write;
+
+// Summary of an evaluated generation, before it is replaced by the
+// next.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct GenerationStats {
+    pub generation: usize,
+    pub best_fitness: f32,
+    pub mean_fitness: f32,
+    // Number of members of each species.
+    pub species_sizes: Vec<usize>,
+    // Mean number of nodes and of connections, enabled or not, per
+    // genome.
+    pub mean_nodes: f32,
+    pub mean_connections: f32,
+}
+
+impl GenerationStats {
+    pub fn num_species(&self) -> usize {
+        self.species_sizes.len()
+    }
+}
+
+// Receives the statistics of each generation as the population
+// advances.
+pub trait Reporter: Send {
+    fn report(&mut self, stats: &GenerationStats);
+
+    // The error that stopped the reporter, for reporters that can
+    // fail, such as by writing to a full disk.
+    fn error(&self) -> Option<&std::io::Error> {
+        None
+    }
+}
+
+impl std::fmt::Debug for dyn Reporter {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Reporter")
+    }
+}
+
+// Prints a line per generation.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StdoutReporter;
+
+impl Reporter for StdoutReporter {
+    fn report(&mut self, stats: &GenerationStats) {
+        println!(
+            "Generation {}: best fitness {:.4}, mean fitness {:.4}, \
+             {} species, {:.1} nodes and {:.1} connections on average",
+            stats.generation,
+            stats.best_fitness,
+            stats.mean_fitness,
+            stats.num_species(),
+            stats.mean_nodes,
+            stats.mean_connections,
+        );
+    }
+}
+
+// Writes a row per generation, after a header row.  Species sizes
+// are separated by semicolons within their column.  Once writing
+// fails, no more rows are written, and the error is kept for
+// `Reporter::error`, so that a full disk doesn't stop the run.
+#[derive(Debug)]
+pub struct CsvReporter<W> {
+    writer: W,
+    header_written: bool,
+    error: Option<std::io::Error>,
+}
+
+impl<W: Write> CsvReporter<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            header_written: false,
+            error: None,
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    fn write_row(&mut self, stats: &GenerationStats) -> std::io::Result<()> {
+        if !self.header_written {
+            writeln!(
+                self.writer,
+                "generation,best_fitness,mean_fitness,num_species,\
+                 species_sizes,mean_nodes,mean_connections"
+            )?;
+            self.header_written = true;
+        }
+        let sizes = stats
+            .species_sizes
+            .iter()
+            .map(|n| n.to_string())
+            .collect::<Vec<_>>()
+            .join(";");
+        writeln!(
+            self.writer,
+            "{},{},{},{},{},{},{}",
+            stats.generation,
+            stats.best_fitness,
+            stats.mean_fitness,
+            stats.num_species(),
+            sizes,
+            stats.mean_nodes,
+            stats.mean_connections,
+        )?;
+        self.writer.flush()
+    }
+}
+
+impl<W: Write + Send> Reporter for CsvReporter<W> {
+    fn report(&mut self, stats: &GenerationStats) {
+        if self.error.is_none() {
+            self.error = self.write_row(stats).err();
+        }
+    }
+
+    fn error(&self) -> Option<&std::io::Error> {
+        self.error.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_csv_reporter() {
+        let mut reporter = CsvReporter::new(Vec::new());
+        let stats = GenerationStats {
+            generation: 3,
+            best_fitness: 2.5,
+            mean_fitness: 1.0,
+            species_sizes: vec![4, 6],
+            mean_nodes: 5.0,
+            mean_connections: 7.5,
+        };
+        reporter.report(&stats);
+        reporter.report(&stats);
+
+        let csv = String::from_utf8(reporter.into_inner()).unwrap();
+        let lines = csv.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("generation,best_fitness"));
+        assert_eq!(lines[1], "3,2.5,1,2,4;6,5,7.5");
+
+        // Rows stop at the first error, which is kept.
+        let mut buffer = [0; 100];
+        let mut reporter = CsvReporter::new(&mut buffer[..]);
+        (0..3).for_each(|_| reporter.report(&stats));
+        let error = reporter.error().map(|err| err.kind());
+        assert_eq!(error, Some(std::io::ErrorKind::WriteZero));
+    }
+}
@@ -0,0 +1,144 @@
+use rand::Rng;
+
+// How parents are chosen from the members of a species.  Members are
+// always passed ranked from fittest to least fit.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum SelectionStrategy {
+    // Uniformly among the given fraction of fittest members.
+    Truncation { survival_threshold: f32 },
+    // The fittest of `size` members drawn uniformly, with replacement.
+    Tournament { size: usize },
+    // With probability proportional to fitness, shifted so that the
+    // least fit member has zero fitness.
+    Roulette,
+}
+
+impl Default for SelectionStrategy {
+    fn default() -> Self {
+        SelectionStrategy::Truncation {
+            survival_threshold: 0.2,
+        }
+    }
+}
+
+impl SelectionStrategy {
+    // Picks a parent from `ranked`, which must not be empty, and
+    // returns its index into `fitnesses`.
+    pub fn select<R: Rng + ?Sized>(
+        &self,
+        ranked: &[usize],
+        fitnesses: &[f32],
+        rng: &mut R,
+    ) -> usize {
+        use SelectionStrategy::*;
+        match *self {
+            Truncation { survival_threshold } => {
+                let num_parents = ((ranked.len() as f32 * survival_threshold)
+                    .ceil() as usize)
+                    .clamp(1, ranked.len());
+                ranked[rng.gen_range(0..num_parents)]
+            }
+            Tournament { size } => {
+                // Members are ranked, so the winner is the one with
+                // the lowest rank.
+                let best = (0..size.max(1))
+                    .map(|_| rng.gen_range(0..ranked.len()))
+                    .min()
+                    .unwrap_or(0);
+                ranked[best]
+            }
+            Roulette => {
+                let min_fitness = ranked
+                    .iter()
+                    .map(|i| fitnesses[*i])
+                    .fold(f32::INFINITY, f32::min);
+                let total = ranked
+                    .iter()
+                    .map(|i| fitnesses[*i] - min_fitness)
+                    .sum::<f32>();
+                if total <= 0.0 || total.is_nan() {
+                    return ranked[rng.gen_range(0..ranked.len())];
+                }
+                if total.is_infinite() {
+                    // No finite share can be drawn, so an infinitely
+                    // fit member is picked if there is one, or any
+                    // member if the sum merely overflowed.
+                    let infinite = ranked
+                        .iter()
+                        .filter(|i| fitnesses[**i] == f32::INFINITY)
+                        .cloned()
+                        .collect::<Vec<_>>();
+                    return match infinite.is_empty() {
+                        true => ranked[rng.gen_range(0..ranked.len())],
+                        false => infinite[rng.gen_range(0..infinite.len())],
+                    };
+                }
+
+                let mut remaining = rng.gen_range(0.0..total);
+                ranked
+                    .iter()
+                    .find(|i| {
+                        remaining -= fitnesses[**i] - min_fitness;
+                        remaining < 0.0
+                    })
+                    .cloned()
+                    // Rounding may leave a small remainder after the
+                    // last member.
+                    .unwrap_or(ranked[0])
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_select() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let fitnesses = [1.0, 4.0, 0.0, 2.0, 3.0];
+        let ranked = [1, 4, 3, 0, 2];
+
+        let strategy = SelectionStrategy::Truncation {
+            survival_threshold: 0.4,
+        };
+        (0..100).for_each(|_| {
+            let i = strategy.select(&ranked, &fitnesses, &mut rng);
+            assert!(i == 1 || i == 4);
+        });
+
+        // A large enough tournament almost surely draws the fittest.
+        let strategy = SelectionStrategy::Tournament { size: 1000 };
+        (0..100).for_each(|_| {
+            assert_eq!(strategy.select(&ranked, &fitnesses, &mut rng), 1);
+        });
+
+        // The least fit member has no chance with roulette selection.
+        let counts = (0..1000).fold([0; 5], |mut counts, _| {
+            let i = SelectionStrategy::Roulette
+                .select(&ranked, &fitnesses, &mut rng);
+            counts[i] += 1;
+            counts
+        });
+        assert_eq!(counts[2], 0);
+        assert!(counts[1] > counts[4] && counts[4] > counts[0]);
+
+        // Infinite fitnesses don't break roulette selection.
+        let fitnesses = [1.0, f32::INFINITY, 0.0, f32::INFINITY, 3.0];
+        let ranked = [1, 3, 4, 0, 2];
+        (0..100).for_each(|_| {
+            let i = SelectionStrategy::Roulette
+                .select(&ranked, &fitnesses, &mut rng);
+            assert!(i == 1 || i == 3);
+        });
+        let fitnesses = [1.0, f32::MAX, f32::NEG_INFINITY, f32::MAX, 3.0];
+        (0..100).for_each(|_| {
+            let i = SelectionStrategy::Roulette
+                .select(&ranked, &fitnesses, &mut rng);
+            assert!(i < 5);
+        });
+    }
+}
@@ -0,0 +1,384 @@
+use std::collections::{HashMap, HashSet};
+
+use rand::Rng;
+
+use crate::genome::*;
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct SpeciationConfig {
+    pub excess_coefficient: f32,
+    pub disjoint_coefficient: f32,
+    pub weight_coefficient: f32,
+    // Initial maximum distance between a genome and the
+    // representative of its species.
+    pub compatibility_threshold: f32,
+    // Genomes with fewer genes than this aren't normalized by their
+    // size when computing the excess and disjoint terms, as in the
+    // original NEAT.
+    pub normalize_min_genes: usize,
+    // If set, the threshold is nudged by `threshold_step` after each
+    // speciation to move the number of species towards this target.
+    pub target_species: Option<usize>,
+    pub threshold_step: f32,
+    pub min_threshold: f32,
+    // Species whose best fitness hasn't improved for this many
+    // generations are removed, unless they hold the fittest genome.
+    pub max_stagnation: Option<usize>,
+}
+
+impl Default for SpeciationConfig {
+    fn default() -> Self {
+        Self {
+            excess_coefficient: 1.0,
+            disjoint_coefficient: 1.0,
+            weight_coefficient: 0.4,
+            compatibility_threshold: 3.0,
+            normalize_min_genes: 20,
+            target_species: None,
+            threshold_step: 0.1,
+            min_threshold: 0.1,
+            max_stagnation: Some(15),
+        }
+    }
+}
+
+impl Genome {
+    // NEAT compatibility distance,
+    //   c1*E/N + c2*D/N + c3*W
+    // where E and D are the number of excess and disjoint genes, W is
+    // the mean weight difference of matching genes, and N is the
+    // number of genes in the larger genome.
+    pub fn compatibility_distance(
+        &self,
+        other: &Genome,
+        config: &SpeciationConfig,
+    ) -> f32 {
+        let max_innovation = |genome: &Genome| {
+            genome.connections.iter().map(|c| c.innovation).max()
+        };
+        let self_max = max_innovation(self);
+        let other_max = max_innovation(other);
+
+        let other_weights = other
+            .connections
+            .iter()
+            .map(|c| (c.innovation, c.weight))
+            .collect::<HashMap<_, _>>();
+        let self_innovations = self
+            .connections
+            .iter()
+            .map(|c| c.innovation)
+            .collect::<HashSet<_>>();
+
+        let mut num_matching = 0;
+        let mut weight_difference = 0.0;
+        let mut num_excess = 0;
+        let mut num_disjoint = 0;
+
+        self.connections.iter().for_each(|conn| {
+            match other_weights.get(&conn.innovation) {
+                Some(weight) => {
+                    num_matching += 1;
+                    weight_difference += (conn.weight - weight).abs();
+                }
+                None if Some(conn.innovation) > other_max => num_excess += 1,
+                None => num_disjoint += 1,
+            }
+        });
+        other
+            .connections
+            .iter()
+            .filter(|conn| !self_innovations.contains(&conn.innovation))
+            .for_each(|conn| {
+                if Some(conn.innovation) > self_max {
+                    num_excess += 1;
+                } else {
+                    num_disjoint += 1;
+                }
+            });
+
+        let num_genes = self.connections.len().max(other.connections.len());
+        let normalization = if num_genes < config.normalize_min_genes {
+            1.0
+        } else {
+            num_genes as f32
+        };
+        let mean_weight_difference = if num_matching > 0 {
+            weight_difference / num_matching as f32
+        } else {
+            0.0
+        };
+
+        config.excess_coefficient * num_excess as f32 / normalization
+            + config.disjoint_coefficient * num_disjoint as f32 / normalization
+            + config.weight_coefficient * mean_weight_difference
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Species {
+    pub id: u32,
+    pub representative: Genome,
+    // Indices of the member genomes, in the slice given to the most
+    // recent `Speciator::speciate`.
+    pub members: Vec<usize>,
+    // Best fitness of any member so far, and the number of
+    // generations since it last improved.
+    pub best_fitness: f32,
+    pub stagnant_generations: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct Speciator {
+    pub config: SpeciationConfig,
+    pub species: Vec<Species>,
+    pub(crate) threshold: f32,
+    pub(crate) next_species_id: u32,
+}
+
+impl Speciator {
+    pub fn new(config: SpeciationConfig) -> Self {
+        Self {
+            threshold: config.compatibility_threshold,
+            config,
+            species: Vec::new(),
+            next_species_id: 0,
+        }
+    }
+
+    pub fn threshold(&self) -> f32 {
+        self.threshold
+    }
+
+    // Assigns every genome to the first species whose representative
+    // is within the compatibility threshold, creating new species as
+    // needed.  Species without members are dropped, and each
+    // remaining species picks a random member as its representative
+    // for the next round.
+    pub fn speciate<R: Rng + ?Sized>(
+        &mut self,
+        genomes: &[Genome],
+        rng: &mut R,
+    ) {
+        #[cfg(feature = "tracing")]
+        let _span =
+            tracing::debug_span!("speciate", genomes = genomes.len()).entered();
+
+        self.species.iter_mut().for_each(|s| s.members.clear());
+
+        genomes.iter().enumerate().for_each(|(i, genome)| {
+            let threshold = self.threshold;
+            let config = &self.config;
+            let existing = self.species.iter_mut().find(|s| {
+                genome.compatibility_distance(&s.representative, config)
+                    < threshold
+            });
+            match existing {
+                Some(species) => species.members.push(i),
+                None => {
+                    self.species.push(Species {
+                        id: self.next_species_id,
+                        representative: genome.clone(),
+                        members: vec![i],
+                        best_fitness: f32::NEG_INFINITY,
+                        stagnant_generations: 0,
+                    });
+                    self.next_species_id += 1;
+                }
+            }
+        });
+
+        self.species.retain(|s| !s.members.is_empty());
+        self.species.iter_mut().for_each(|s| {
+            let i = s.members[rng.gen_range(0..s.members.len())];
+            s.representative = genomes[i].clone();
+        });
+
+        if let Some(target) = self.config.target_species {
+            if self.species.len() < target {
+                self.threshold -= self.config.threshold_step;
+            } else if self.species.len() > target {
+                self.threshold += self.config.threshold_step;
+            }
+            self.threshold = self.threshold.max(self.config.min_threshold);
+        }
+
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            num_species = self.species.len(),
+            threshold = self.threshold,
+            "Speciated genomes"
+        );
+    }
+
+    // Moves genome `index` of `genomes`, which must have been passed
+    // to the last `speciate`, into the first species whose
+    // representative is within the compatibility threshold, or into a
+    // new species.  The rest of the species are left as they are,
+    // except that a species without members is dropped.
+    pub fn reassign(&mut self, genomes: &[Genome], index: usize) {
+        self.species
+            .iter_mut()
+            .for_each(|s| s.members.retain(|i| *i != index));
+        self.species.retain(|s| !s.members.is_empty());
+
+        let genome = &genomes[index];
+        let threshold = self.threshold;
+        let config = &self.config;
+        let existing = self.species.iter_mut().find(|s| {
+            genome.compatibility_distance(&s.representative, config) < threshold
+        });
+        match existing {
+            Some(species) => species.members.push(index),
+            None => {
+                self.species.push(Species {
+                    id: self.next_species_id,
+                    representative: genome.clone(),
+                    members: vec![index],
+                    best_fitness: f32::NEG_INFINITY,
+                    stagnant_generations: 0,
+                });
+                self.next_species_id += 1;
+            }
+        }
+    }
+
+    // Records the best fitness of each species, given the fitness of
+    // each genome passed to the last `speciate`, and removes species
+    // that have stagnated.  The species holding the fittest genome is
+    // always kept, so the population can't die out.
+    pub fn cull_stagnant(&mut self, fitnesses: &[f32]) {
+        self.species.iter_mut().for_each(|s| {
+            let best = s
+                .members
+                .iter()
+                .map(|i| fitnesses[*i])
+                .fold(f32::NEG_INFINITY, f32::max);
+            if best > s.best_fitness {
+                s.best_fitness = best;
+                s.stagnant_generations = 0;
+            } else {
+                s.stagnant_generations += 1;
+            }
+        });
+
+        let max_stagnation = match self.config.max_stagnation {
+            Some(max_stagnation) => max_stagnation,
+            None => return,
+        };
+        let champion = (0..fitnesses.len())
+            .max_by(|a, b| fitnesses[*a].total_cmp(&fitnesses[*b]));
+        self.species.retain(|s| {
+            let keep = s.stagnant_generations < max_stagnation
+                || champion.is_some_and(|i| s.members.contains(&i));
+            #[cfg(feature = "tracing")]
+            if !keep {
+                tracing::debug!(species = s.id, "Removed stagnant species");
+            }
+            keep
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::neural_net::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_compatibility_distance() {
+        let mut tracker = InnovationTracker::new();
+        let func = ActivationFunction::Sigmoid;
+        let normal = ConnectionType::Normal;
+
+        let mut a = Genome::with_io(3, 1, func, &mut tracker);
+        a.add_connection(0, 3, 1.0, normal, &mut tracker);
+        a.add_connection(1, 3, 1.0, normal, &mut tracker);
+        let mut b = a.clone();
+        b.connections[1].weight = 2.0;
+
+        // Disjoint, since `a` has genes with higher innovations
+        b.add_connection(2, 3, 1.0, normal, &mut tracker);
+        // Excess, since `b` has no genes with higher innovations
+        a.add_connection(3, 3, 1.0, ConnectionType::Recurrent, &mut tracker);
+        a.add_connection(0, 3, 1.0, ConnectionType::Recurrent, &mut tracker);
+        a.add_connection(1, 3, 1.0, ConnectionType::Recurrent, &mut tracker);
+
+        let config = SpeciationConfig {
+            excess_coefficient: 1.0,
+            disjoint_coefficient: 2.0,
+            weight_coefficient: 4.0,
+            ..SpeciationConfig::default()
+        };
+        let expected = 1.0 * 3.0 + 2.0 * 1.0 + 4.0 * 0.5;
+        assert_eq!(a.compatibility_distance(&b, &config), expected);
+        assert_eq!(b.compatibility_distance(&a, &config), expected);
+        assert_eq!(a.compatibility_distance(&a, &config), 0.0);
+    }
+
+    #[test]
+    fn test_speciate() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut tracker = InnovationTracker::new();
+        let func = ActivationFunction::Sigmoid;
+        let normal = ConnectionType::Normal;
+
+        let mut small = Genome::with_io(2, 1, func, &mut tracker);
+        small.add_connection(0, 2, 1.0, normal, &mut tracker);
+        let mut large = small.clone();
+        (0..5).for_each(|_| {
+            let node = large.add_node(NodeType::Hidden, func, &mut tracker);
+            large.add_connection(1, node, 1.0, normal, &mut tracker);
+        });
+
+        let genomes = vec![small.clone(), large.clone(), small, large];
+        let mut speciator = Speciator::new(SpeciationConfig {
+            target_species: Some(1),
+            ..SpeciationConfig::default()
+        });
+        speciator.speciate(&genomes, &mut rng);
+
+        let members = speciator
+            .species
+            .iter()
+            .map(|s| s.members.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(members, vec![vec![0, 2], vec![1, 3]]);
+
+        // Too many species for the target, so the threshold grows.
+        assert!(speciator.threshold() > 3.0);
+    }
+
+    #[test]
+    fn test_cull_stagnant() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut tracker = InnovationTracker::new();
+        let func = ActivationFunction::Sigmoid;
+        let normal = ConnectionType::Normal;
+
+        let mut a = Genome::with_io(2, 1, func, &mut tracker);
+        a.add_connection(0, 2, 1.0, normal, &mut tracker);
+        let mut b = Genome::with_io(2, 1, func, &mut tracker);
+        (0..5).for_each(|_| {
+            let node = b.add_node(NodeType::Hidden, func, &mut tracker);
+            b.add_connection(1, node, 1.0, normal, &mut tracker);
+        });
+
+        let genomes = vec![a, b];
+        let mut speciator = Speciator::new(SpeciationConfig {
+            max_stagnation: Some(2),
+            ..SpeciationConfig::default()
+        });
+        speciator.speciate(&genomes, &mut rng);
+        assert_eq!(speciator.species.len(), 2);
+
+        // Neither species improves after the first generation, but
+        // the first holds the fittest genome and is kept.
+        (0..3).for_each(|_| speciator.cull_stagnant(&[1.0, 0.5]));
+        assert_eq!(speciator.species.len(), 1);
+        assert_eq!(speciator.species[0].members, vec![0]);
+        assert_eq!(speciator.species[0].stagnant_generations, 2);
+    }
+}
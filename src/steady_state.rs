@@ -0,0 +1,212 @@
+use crate::population::*;
+use crate::selection::*;
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct SteadyStateConfig {
+    // Number of calls to `replace_worst` a genome must live through
+    // before it can be replaced itself, so that new genomes have time
+    // to be evaluated.
+    pub min_age: usize,
+}
+
+impl Default for SteadyStateConfig {
+    fn default() -> Self {
+        Self { min_age: 20 }
+    }
+}
+
+// Evolution one genome at a time, as in rtNEAT, for populations that
+// live in a running world and can't be replaced all at once.  Each
+// replacement removes the worst genome and breeds a single child in
+// its place, leaving the rest of the population untouched.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct SteadyState {
+    pub config: SteadyStateConfig,
+    // Number of calls to `replace_worst` each genome of the
+    // population has lived through.
+    ages: Vec<usize>,
+}
+
+impl SteadyState {
+    pub fn new(config: SteadyStateConfig) -> Self {
+        Self {
+            config,
+            ages: Vec::new(),
+        }
+    }
+
+    pub fn ages(&self) -> &[usize] {
+        &self.ages
+    }
+
+    // Replaces the genome of `population` with the lowest fitness
+    // shared within its species, among those at least `min_age` old,
+    // given the current fitness of each genome.  Its replacement is a
+    // child of a species chosen with probability proportional to the
+    // species' mean fitness.  Returns the position of the child, which
+    // has yet to be evaluated, or `None` if no genome is old enough.
+    pub fn replace_worst(
+        &mut self,
+        population: &mut Population,
+        fitnesses: &[f32],
+    ) -> Option<usize> {
+        assert_eq!(
+            fitnesses.len(),
+            population.genomes.len(),
+            "Expected one fitness value per genome"
+        );
+        self.ages.resize(population.genomes.len(), 0);
+        population.update_champion(fitnesses);
+        let replaced = self.replace(population, fitnesses);
+        self.ages.iter_mut().for_each(|age| *age += 1);
+        if let Some(i) = replaced {
+            self.ages[i] = 0;
+        }
+        replaced
+    }
+
+    fn replace(
+        &self,
+        population: &mut Population,
+        fitnesses: &[f32],
+    ) -> Option<usize> {
+        // Shared fitness must be non-negative to be compared across
+        // species of different sizes.
+        let min_fitness = fitnesses.iter().cloned().fold(0.0, f32::min);
+        let mut shared = vec![0.0; fitnesses.len()];
+        population.speciator.species.iter().for_each(|s| {
+            s.members.iter().for_each(|i| {
+                shared[*i] =
+                    (fitnesses[*i] - min_fitness) / s.members.len() as f32;
+            })
+        });
+        let min_age = self.config.min_age;
+        let worst = (0..fitnesses.len())
+            .filter(|i| self.ages[*i] >= min_age)
+            .min_by(|a, b| shared[*a].total_cmp(&shared[*b]))?;
+
+        // Members of each species other than the one being replaced,
+        // ranked from fittest to least fit.
+        let candidates = population
+            .speciator
+            .species
+            .iter()
+            .map(|s| {
+                let mut ranked = s
+                    .members
+                    .iter()
+                    .cloned()
+                    .filter(|i| *i != worst)
+                    .collect::<Vec<_>>();
+                ranked.sort_by(|a, b| fitnesses[*b].total_cmp(&fitnesses[*a]));
+                ranked
+            })
+            .filter(|ranked| !ranked.is_empty())
+            .collect::<Vec<_>>();
+        let means = candidates
+            .iter()
+            .map(|ranked| {
+                ranked.iter().map(|i| fitnesses[*i]).sum::<f32>()
+                    / ranked.len() as f32
+            })
+            .collect::<Vec<_>>();
+        let mut ranked_species = (0..candidates.len()).collect::<Vec<_>>();
+        if ranked_species.is_empty() {
+            return None;
+        }
+        ranked_species.sort_by(|a, b| means[*b].total_cmp(&means[*a]));
+        let species = SelectionStrategy::Roulette.select(
+            &ranked_species,
+            &means,
+            &mut population.rng,
+        );
+
+        let (child, parents, mutations) =
+            population.breed(&candidates[species], fitnesses);
+        if population.config.track_lineage {
+            let ids = &population.genome_ids;
+            let parents = parents.iter().map(|i| ids[*i]).collect();
+            population.lineage.set_fitness(ids[worst], fitnesses[worst]);
+            population.genome_ids[worst] = population.lineage.record(
+                population.generation,
+                parents,
+                mutations,
+            );
+        }
+        population.genomes[worst] = child;
+        if let Some(told) = population.told.get_mut(worst) {
+            *told = None;
+        }
+        population.speciator.reassign(&population.genomes, worst);
+        Some(worst)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::*;
+
+    #[test]
+    fn test_replace_worst() {
+        let config = PopulationConfig {
+            population_size: 20,
+            num_inputs: 2,
+            num_outputs: 1,
+            seed: Some(4),
+            track_lineage: true,
+            ..PopulationConfig::default()
+        };
+        let mut population = Population::new(config);
+        let mut steady_state =
+            SteadyState::new(SteadyStateConfig { min_age: 3 });
+        let fitness = |genome: &Genome| {
+            genome.connections.iter().map(|c| c.weight).sum::<f32>()
+        };
+
+        let mut replaced = Vec::new();
+        for _ in 0..60 {
+            let before = population.genomes().to_vec();
+            let fitnesses =
+                population.genomes().iter().map(fitness).collect::<Vec<_>>();
+            let ages = steady_state.ages();
+            let eligible = (0..before.len())
+                .filter(|i| ages.get(*i).is_some_and(|age| *age >= 3))
+                .collect::<Vec<_>>();
+            match steady_state.replace_worst(&mut population, &fitnesses) {
+                Some(i) => {
+                    assert!(eligible.contains(&i));
+                    replaced.push(i);
+                }
+                None => assert!(eligible.is_empty()),
+            }
+
+            // Only the replaced genome changes.
+            let after = population.genomes();
+            let changed = (0..before.len())
+                .filter(|i| before[*i] != after[*i])
+                .collect::<Vec<_>>();
+            assert!(changed.iter().all(|i| replaced.last() == Some(i)));
+            let members = population
+                .species()
+                .iter()
+                .map(|s| s.members.len())
+                .sum::<usize>();
+            assert_eq!(members, 20);
+        }
+
+        // The initial genomes have to age before any is replaced.
+        assert_eq!(replaced.len(), 57);
+        assert_eq!(population.generation(), 0);
+        assert!(population.champion().is_some());
+        let last = population.genome_ids()[*replaced.last().unwrap()];
+        assert!(!population.lineage().get(last).unwrap().parents.is_empty());
+
+        // A species with an infinite mean fitness is picked to breed.
+        let mut fitnesses = vec![0.0; 20];
+        fitnesses[0] = f32::INFINITY;
+        assert!(steady_state
+            .replace_worst(&mut population, &fitnesses)
+            .is_some());
+    }
+}
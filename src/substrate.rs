@@ -0,0 +1,165 @@
+use crate::cppn::*;
+use crate::neural_net::*;
+
+// Geometry of a HyperNEAT network.  Each layer is fully connected to
+// the next, from the inputs through the hidden layers to the outputs,
+// with the weight of each connection given by a CPPN.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Substrate {
+    pub inputs: Vec<Point>,
+    pub hidden: Vec<Vec<Point>>,
+    pub outputs: Vec<Point>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct SubstrateConfig {
+    // CPPN output read as the connection weight.
+    pub weight_output: usize,
+    // If set, a connection is only expressed if this CPPN output is
+    // positive, as in HyperNEAT-LEO.  Otherwise it is expressed if the
+    // magnitude of the weight output exceeds `expression_threshold`.
+    pub expression_output: Option<usize>,
+    pub expression_threshold: f32,
+    // Expressed weights are scaled from the range between the
+    // threshold and one to the range between zero and `max_weight`,
+    // keeping their sign.
+    pub max_weight: f32,
+    pub hidden_activation: ActivationFunction,
+    pub output_activation: ActivationFunction,
+}
+
+impl Default for SubstrateConfig {
+    fn default() -> Self {
+        Self {
+            weight_output: 0,
+            expression_output: None,
+            expression_threshold: 0.2,
+            max_weight: 3.0,
+            hidden_activation: ActivationFunction::Sigmoid,
+            output_activation: ActivationFunction::Sigmoid,
+        }
+    }
+}
+
+impl SubstrateConfig {
+    // Weight of a connection from the CPPN outputs of its query, if
+    // it is expressed.
+    pub(crate) fn connection_weight(&self, outputs: &[f32]) -> Option<f32> {
+        let w = outputs.get(self.weight_output).cloned().unwrap_or(0.0);
+        match self.expression_output {
+            Some(i) => outputs
+                .get(i)
+                .filter(|x| **x > 0.0)
+                .map(|_| w * self.max_weight),
+            None if w.abs() > self.expression_threshold => Some(
+                w.signum() * (w.abs() - self.expression_threshold)
+                    / (1.0 - self.expression_threshold)
+                    * self.max_weight,
+            ),
+            None => None,
+        }
+    }
+}
+
+impl Substrate {
+    // Network whose nodes are the inputs, then each hidden layer, then
+    // the outputs, in the order their points are given.
+    pub fn decode<N: NeuralNet>(
+        &self,
+        cppn: &mut Cppn<N>,
+        config: &SubstrateConfig,
+    ) -> NeuralNetBuilder {
+        let mut builder = NeuralNetBuilder::new();
+        builder
+            .add_inputs(self.inputs.len() as u32)
+            .set_default_activation(config.hidden_activation);
+        self.hidden.iter().for_each(|layer| {
+            builder.add_nodes(NodeType::Hidden, layer.len() as u32);
+        });
+        builder
+            .set_default_activation(config.output_activation)
+            .add_nodes(NodeType::Output, self.outputs.len() as u32);
+
+        let layers = std::iter::once(&self.inputs)
+            .chain(self.hidden.iter())
+            .chain(std::iter::once(&self.outputs))
+            .collect::<Vec<_>>();
+        let mut start = 0;
+        layers.windows(2).for_each(|pair| {
+            let (sources, targets) = (pair[0], pair[1]);
+            let next = start + sources.len();
+            let results = cppn.query_grid(sources, targets);
+            results.iter().enumerate().for_each(|(k, outputs)| {
+                let (i, j) = (k / targets.len(), k % targets.len());
+                if let Some(weight) = config.connection_weight(outputs) {
+                    builder.add_normal_connection_raw(
+                        (start + i) as u32,
+                        (next + j) as u32,
+                        weight,
+                    );
+                }
+            });
+            start = next;
+        });
+
+        builder
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ConsecutiveNeuralNet;
+
+    #[test]
+    fn test_decode() -> Result<(), Error> {
+        // A CPPN whose only output is x2 - x1.
+        let mut builder = NeuralNetBuilder::new();
+        builder
+            .set_default_activation(ActivationFunction::Identity)
+            .add_inputs(6)
+            .add_nodes(NodeType::Output, 1)
+            .add_normal_connection_raw(2, 6, 1.0)
+            .add_normal_connection_raw(0, 6, -1.0);
+        let mut cppn = Cppn::<ConsecutiveNeuralNet>::build_from(&builder)?;
+
+        let substrate = Substrate {
+            inputs: vec![(-1.0, -1.0), (0.0, -1.0), (1.0, -1.0)],
+            hidden: vec![vec![(0.1, 0.0)]],
+            outputs: vec![(0.0, 1.0)],
+        };
+        let config = SubstrateConfig::default();
+        let net = substrate.decode(&mut cppn, &config);
+
+        let node_types =
+            net.nodes.iter().map(|n| n.node_type).collect::<Vec<_>>();
+        use NodeType::*;
+        assert_eq!(node_types, vec![Input, Input, Input, Hidden, Output]);
+
+        // Weights of 0.1 in magnitude are below the threshold, so
+        // aren't expressed.
+        let scaled = |w: f32| w.signum() * (w.abs() - 0.2) / 0.8 * 3.0;
+        let expected = [(0, 3, scaled(1.1)), (2, 3, scaled(-0.9))];
+        assert_eq!(net.connections.len(), expected.len());
+        net.connections.iter().zip(expected.iter()).for_each(
+            |(conn, (origin, dest, weight))| {
+                assert_eq!((conn.origin, conn.dest), (*origin, *dest));
+                assert!((conn.weight - weight).abs() < 1e-5);
+            },
+        );
+
+        // With link expression, only positive outputs are expressed.
+        let config = SubstrateConfig {
+            expression_output: Some(0),
+            ..SubstrateConfig::default()
+        };
+        let net = substrate.decode(&mut cppn, &config);
+        let connections = net
+            .connections
+            .iter()
+            .map(|c| (c.origin, c.dest))
+            .collect::<Vec<_>>();
+        assert_eq!(connections, vec![(0, 3), (1, 3)]);
+        Ok(())
+    }
+}
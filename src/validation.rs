@@ -0,0 +1,173 @@
+use alloc::collections::{BTreeMap, BTreeSet, VecDeque};
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+
+use crate::neural_net::*;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct ValidationConfig {
+    // Whether connections may start at an output node, such as a
+    // recurrent connection feeding an output back into the network.
+    pub allow_output_connections: bool,
+}
+
+impl Default for ValidationConfig {
+    fn default() -> Self {
+        Self {
+            allow_output_connections: true,
+        }
+    }
+}
+
+// Connections are identified by their index in
+// `NeuralNetBuilder::connections`, and nodes by their index in
+// `NeuralNetBuilder::nodes`.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ValidationProblem {
+    InvalidNodeIndex { connection: usize, index: u32 },
+    // Input and bias nodes ignore incoming connections, since their
+    // value is always overwritten.
+    ConnectionIntoSensor { connection: usize },
+    ConnectionFromOutput { connection: usize },
+    // Same origin, destination and type as an earlier connection.
+    DuplicateConnection { connection: usize, first: usize },
+    // No path of connections from any input or bias node, so the
+    // output doesn't depend on the inputs.
+    UnreachableOutput { node: u32 },
+}
+
+impl NeuralNetBuilder {
+    pub fn validate(&self) -> Vec<ValidationProblem> {
+        self.validate_with(&ValidationConfig::default())
+    }
+
+    pub fn validate_with(
+        &self,
+        config: &ValidationConfig,
+    ) -> Vec<ValidationProblem> {
+        use ValidationProblem::*;
+
+        let num_nodes = self.nodes.len();
+        let node_type = |i: u32| self.nodes[i as usize].node_type;
+        let is_sensor =
+            |t: NodeType| t == NodeType::Input || t == NodeType::Bias;
+
+        let mut problems = Vec::new();
+        let mut first_of = BTreeMap::new();
+
+        // Disabled connections aren't part of the network, so can't
+        // cause problems.
+        let enabled = self
+            .connections
+            .iter()
+            .enumerate()
+            .filter(|(_, conn)| conn.enabled);
+        enabled.clone().for_each(|(i, conn)| {
+            let invalid = [conn.origin, conn.dest]
+                .iter()
+                .filter(|index| **index as usize >= num_nodes)
+                .map(|index| InvalidNodeIndex {
+                    connection: i,
+                    index: *index,
+                })
+                .collect::<Vec<_>>();
+            if !invalid.is_empty() {
+                problems.extend(invalid);
+                return;
+            }
+
+            if is_sensor(node_type(conn.dest)) {
+                problems.push(ConnectionIntoSensor { connection: i });
+            }
+            if !config.allow_output_connections
+                && node_type(conn.origin) == NodeType::Output
+            {
+                problems.push(ConnectionFromOutput { connection: i });
+            }
+
+            let key = (conn.origin, conn.dest, conn.connection_type);
+            match first_of.get(&key) {
+                Some(first) => problems.push(DuplicateConnection {
+                    connection: i,
+                    first: *first,
+                }),
+                None => {
+                    first_of.insert(key, i);
+                }
+            }
+        });
+
+        // Connections of either type carry information from the
+        // sensors, so both count towards reaching an output.
+        let mut reached = BTreeSet::new();
+        let mut queue = (0..num_nodes as u32)
+            .filter(|i| is_sensor(node_type(*i)))
+            .collect::<VecDeque<_>>();
+        while let Some(node) = queue.pop_front() {
+            enabled
+                .clone()
+                .map(|(_, conn)| conn)
+                .filter(|conn| {
+                    conn.origin == node && (conn.dest as usize) < num_nodes
+                })
+                .for_each(|conn| {
+                    if reached.insert(conn.dest) {
+                        queue.push_back(conn.dest);
+                    }
+                });
+        }
+        problems.extend(
+            (0..num_nodes as u32)
+                .filter(|i| {
+                    node_type(*i) == NodeType::Output && !reached.contains(i)
+                })
+                .map(|node| UnreachableOutput { node }),
+        );
+
+        problems
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        let mut builder = NeuralNetBuilder::new();
+        builder
+            .add_nodes(NodeType::Input, 1)
+            .add_nodes(NodeType::Hidden, 1)
+            .add_nodes(NodeType::Output, 2)
+            .add_normal_connection_raw(0, 1, 1.0)
+            .add_normal_connection_raw(1, 2, 1.0)
+            .add_normal_connection_raw(1, 2, -1.0)
+            .add_normal_connection_raw(1, 7, 1.0)
+            .add_recurrent_connection_raw(2, 0, 1.0);
+        assert!(NeuralNetBuilder::new().validate().is_empty());
+
+        use ValidationProblem::*;
+        assert_eq!(
+            builder.validate(),
+            vec![
+                DuplicateConnection {
+                    connection: 2,
+                    first: 1
+                },
+                InvalidNodeIndex {
+                    connection: 3,
+                    index: 7
+                },
+                ConnectionIntoSensor { connection: 4 },
+                UnreachableOutput { node: 3 },
+            ]
+        );
+
+        let config = ValidationConfig {
+            allow_output_connections: false,
+        };
+        assert!(builder
+            .validate_with(&config)
+            .contains(&ConnectionFromOutput { connection: 4 }));
+    }
+}
@@ -0,0 +1,253 @@
+use wasm_bindgen::prelude::*;
+
+use crate::neural_net::*;
+use crate::neural_net_consecutive::*;
+use crate::population::*;
+
+// JavaScript bindings, built with the `wasm` feature, for running
+// networks and evolution in the browser.  Networks use the
+// consecutive backend.  Errors are thrown as strings.  The library
+// isn't built as a cdylib by default, since that breaks builds
+// without std, so build the module with
+//
+//   cargo rustc --lib --release --crate-type cdylib \
+//       --target wasm32-unknown-unknown --features wasm
+//
+// and pass it to wasm-bindgen.
+
+fn js_error(err: Error) -> JsValue {
+    JsValue::from_str(&err.to_string())
+}
+
+fn activation(name: &str) -> Result<ActivationFunction, JsValue> {
+    ActivationFunction::from_name(name).ok_or_else(|| {
+        JsValue::from_str(&format!("Unknown activation function {}", name))
+    })
+}
+
+fn node_type(name: &str) -> Result<NodeType, JsValue> {
+    use NodeType::*;
+    match name {
+        "bias" => Ok(Bias),
+        "input" => Ok(Input),
+        "output" => Ok(Output),
+        "hidden" => Ok(Hidden),
+        _ => Err(JsValue::from_str(&format!("Unknown node type {}", name))),
+    }
+}
+
+#[wasm_bindgen(js_name = NeuralNet)]
+#[derive(Debug, Clone)]
+pub struct WasmNeuralNet {
+    net: ConsecutiveNeuralNet,
+}
+
+#[wasm_bindgen(js_class = NeuralNet)]
+impl WasmNeuralNet {
+    #[wasm_bindgen(js_name = numInputs)]
+    pub fn num_inputs(&self) -> usize {
+        self.net.num_inputs()
+    }
+
+    #[wasm_bindgen(js_name = numOutputs)]
+    pub fn num_outputs(&self) -> usize {
+        self.net.num_outputs()
+    }
+
+    pub fn evaluate(&mut self, inputs: &[f32]) -> Result<Vec<f32>, JsValue> {
+        self.net.try_evaluate(inputs).map_err(js_error)
+    }
+
+    #[wasm_bindgen(js_name = resetState)]
+    pub fn reset_state(&mut self) {
+        self.net.reset_state();
+    }
+}
+
+#[wasm_bindgen(js_name = NeuralNetBuilder)]
+#[derive(Debug, Clone, Default)]
+pub struct WasmNeuralNetBuilder {
+    builder: NeuralNetBuilder,
+}
+
+#[wasm_bindgen(js_class = NeuralNetBuilder)]
+impl WasmNeuralNetBuilder {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Applies to nodes added afterwards.
+    #[wasm_bindgen(js_name = setDefaultActivation)]
+    pub fn set_default_activation(
+        &mut self,
+        name: &str,
+    ) -> Result<(), JsValue> {
+        self.builder.set_default_activation(activation(name)?);
+        Ok(())
+    }
+
+    #[wasm_bindgen(js_name = addNodes)]
+    pub fn add_nodes(
+        &mut self,
+        node_type_name: &str,
+        n: u32,
+    ) -> Result<(), JsValue> {
+        self.builder.add_nodes(node_type(node_type_name)?, n);
+        Ok(())
+    }
+
+    #[wasm_bindgen(js_name = addConnection)]
+    pub fn add_connection(&mut self, origin: u32, dest: u32, weight: f32) {
+        self.builder.add_normal_connection_raw(origin, dest, weight);
+    }
+
+    #[wasm_bindgen(js_name = addRecurrentConnection)]
+    pub fn add_recurrent_connection(
+        &mut self,
+        origin: u32,
+        dest: u32,
+        weight: f32,
+    ) {
+        self.builder
+            .add_recurrent_connection_raw(origin, dest, weight);
+    }
+
+    pub fn build(&mut self) -> Result<WasmNeuralNet, JsValue> {
+        let net = self.builder.build().map_err(js_error)?;
+        Ok(WasmNeuralNet { net })
+    }
+}
+
+#[wasm_bindgen(js_name = Population)]
+#[derive(Debug)]
+pub struct WasmPopulation {
+    population: Population,
+}
+
+#[wasm_bindgen(js_class = Population)]
+impl WasmPopulation {
+    // Uses the default configuration apart from the given sizes, with
+    // a random seed unless one is given.
+    #[wasm_bindgen(constructor)]
+    pub fn new(
+        num_inputs: u32,
+        num_outputs: u32,
+        population_size: usize,
+        seed: Option<u64>,
+    ) -> Self {
+        let population = Population::new(PopulationConfig {
+            num_inputs,
+            num_outputs,
+            population_size,
+            seed,
+            ..PopulationConfig::default()
+        });
+        Self { population }
+    }
+
+    // Reads the configuration from a NEAT-Python config file.
+    #[wasm_bindgen(js_name = fromNeatPython)]
+    pub fn from_neat_python(
+        text: &str,
+        seed: Option<u64>,
+    ) -> Result<WasmPopulation, JsValue> {
+        let config = PopulationConfig {
+            seed,
+            ..PopulationConfig::from_neat_python(text).map_err(js_error)?
+        };
+        Ok(Self {
+            population: Population::new(config),
+        })
+    }
+
+    pub fn generation(&self) -> usize {
+        self.population.generation()
+    }
+
+    pub fn size(&self) -> usize {
+        self.population.genomes().len()
+    }
+
+    // Network of the genome at `index` of the current generation.
+    pub fn network(&self, index: usize) -> Result<WasmNeuralNet, JsValue> {
+        let genome =
+            self.population.genomes().get(index).ok_or_else(|| {
+                JsValue::from_str("Genome index out of range")
+            })?;
+        let net = genome.to_builder().build().map_err(js_error)?;
+        Ok(WasmNeuralNet { net })
+    }
+
+    // Best network so far, if any generation has been evaluated.
+    pub fn champion(&self) -> Result<Option<WasmNeuralNet>, JsValue> {
+        self.population
+            .champion()
+            .map(|(genome, _)| {
+                let net = genome.to_builder().build().map_err(js_error)?;
+                Ok(WasmNeuralNet { net })
+            })
+            .transpose()
+    }
+
+    #[wasm_bindgen(js_name = championFitness)]
+    pub fn champion_fitness(&self) -> Option<f32> {
+        self.population.champion().map(|(_, fitness)| fitness)
+    }
+
+    // Moves to the next generation given the fitness of each network
+    // of the current one, in the order of `network`.
+    #[wasm_bindgen(js_name = advanceGeneration)]
+    pub fn advance_generation(&mut self, fitnesses: &[f32]) {
+        self.population.advance_generation(fitnesses);
+    }
+
+    // Scores each network of the current generation with `fitness`, a
+    // JavaScript function taking a network and returning a number,
+    // then moves to the next generation.  Returns the fitnesses.
+    pub fn evolve(
+        &mut self,
+        fitness: &js_sys::Function,
+    ) -> Result<Vec<f32>, JsValue> {
+        let fitnesses = (0..self.size())
+            .map(|i| {
+                let net = self.network(i)?;
+                fitness
+                    .call1(&JsValue::NULL, &net.into())?
+                    .as_f64()
+                    .map(|f| f as f32)
+                    .ok_or_else(|| JsValue::from_str("Fitness is not a number"))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        self.population.advance_generation(&fitnesses);
+        Ok(fitnesses)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_bindings() -> Result<(), JsValue> {
+        let mut builder = WasmNeuralNetBuilder::new();
+        builder.set_default_activation("identity")?;
+        builder.add_nodes("input", 2)?;
+        builder.add_nodes("output", 1)?;
+        builder.add_connection(0, 2, 1.0);
+        builder.add_connection(1, 2, -2.0);
+        let mut net = builder.build()?;
+        assert_eq!((net.num_inputs(), net.num_outputs()), (2, 1));
+        assert_eq!(net.evaluate(&[3.0, 1.0])?, vec![1.0]);
+
+        let mut population = WasmPopulation::new(2, 1, 20, Some(0));
+        assert_eq!(population.champion_fitness(), None);
+        let fitnesses = (0..population.size())
+            .map(|i| Ok(population.network(i)?.evaluate(&[1.0, 0.0])?[0]))
+            .collect::<Result<Vec<_>, JsValue>>()?;
+        population.advance_generation(&fitnesses);
+        assert_eq!(population.generation(), 1);
+        assert!(population.champion()?.is_some());
+        Ok(())
+    }
+}
@@ -0,0 +1,158 @@
+use crate::environment::*;
+use crate::genome::*;
+use crate::neural_net::*;
+use crate::population::*;
+
+// How the fitnesses of a network at each shared weight are combined.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum WeightAggregation {
+    Mean,
+    // The worst weight, for topologies that work at every weight.
+    Min,
+    // The best weight, for topologies that work at some weight.
+    Max,
+}
+
+// Scores a topology rather than its weights, as in Weight Agnostic
+// Neural Networks, by giving every connection the same weight and
+// evaluating once per weight.  Evolved weights are ignored, so weight
+// mutations can be turned off in the `MutationConfig`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct WeightAgnosticConfig {
+    // Shared weights to evaluate at, of which there must be at least
+    // one.
+    pub weights: Vec<f32>,
+    pub aggregation: WeightAggregation,
+}
+
+impl Default for WeightAgnosticConfig {
+    fn default() -> Self {
+        Self {
+            weights: vec![-2.0, -1.0, -0.5, 0.5, 1.0, 2.0],
+            aggregation: WeightAggregation::Mean,
+        }
+    }
+}
+
+impl WeightAgnosticConfig {
+    pub fn aggregate(&self, fitnesses: &[f32]) -> f32 {
+        use WeightAggregation::*;
+        match self.aggregation {
+            Mean => {
+                fitnesses.iter().sum::<f32>() / fitnesses.len().max(1) as f32
+            }
+            Min => fitnesses.iter().cloned().fold(f32::INFINITY, f32::min),
+            Max => fitnesses.iter().cloned().fold(f32::NEG_INFINITY, f32::max),
+        }
+    }
+
+    // A network built from `builder` at each of the shared weights.
+    pub fn build_all<N: NeuralNet>(
+        &self,
+        builder: &NeuralNetBuilder,
+    ) -> Result<Vec<N>, Error> {
+        self.weights
+            .iter()
+            .map(|weight| builder.clone().with_shared_weight(*weight).build())
+            .collect()
+    }
+
+    // Aggregated fitness of the network of `builder`, built with
+    // backend `N`.
+    pub fn evaluate<N, F>(
+        &self,
+        builder: &NeuralNetBuilder,
+        fitness: &F,
+    ) -> Result<f32, Error>
+    where
+        N: NeuralNet,
+        F: FitnessFunction<N>,
+    {
+        let fitnesses = self
+            .build_all::<N>(builder)?
+            .iter_mut()
+            .map(|net| fitness.evaluate(net))
+            .collect::<Vec<_>>();
+        Ok(self.aggregate(&fitnesses))
+    }
+}
+
+impl Population {
+    // Same as `evaluate`, but with the aggregated fitness of each
+    // genome over the shared weights of `config`.
+    pub fn evaluate_weight_agnostic<N, F>(
+        &self,
+        fitness: &F,
+        config: &WeightAgnosticConfig,
+    ) -> Result<Vec<f32>, Error>
+    where
+        N: NeuralNet + Send,
+        F: FitnessFunction<N> + Sync,
+    {
+        let mut nets = self
+            .genomes
+            .iter()
+            .map(|genome| config.build_all::<N>(&genome.to_builder()))
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>();
+        let fitnesses =
+            evaluate_parallel(&mut nets, |net| fitness.evaluate(net));
+        Ok(fitnesses
+            .chunks(config.weights.len().max(1))
+            .map(|chunk| config.aggregate(chunk))
+            .collect())
+    }
+}
+
+impl Genome {
+    // Gives every connection gene the same weight, such as to keep the
+    // weight that a weight agnostic network performed best at.
+    pub fn set_shared_weight(&mut self, weight: f32) {
+        self.connections
+            .iter_mut()
+            .for_each(|conn| conn.weight = weight);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::*;
+
+    #[test]
+    fn test_weight_agnostic() -> Result<(), Error> {
+        let builder = NeuralNetBuilder::new()
+            .with_default_activation(ActivationFunction::Identity)
+            .with_inputs(1)
+            .with_nodes(NodeType::Output, 1)
+            .with_normal_connection_raw(0, 1, 5.0);
+        let fitness = |net: &mut ConsecutiveNeuralNet| net.evaluate(&[1.0])[0];
+
+        let mut config = WeightAgnosticConfig {
+            weights: vec![-1.0, 0.5, 2.0],
+            aggregation: WeightAggregation::Mean,
+        };
+        assert_eq!(config.evaluate(&builder, &fitness)?, 0.5);
+        config.aggregation = WeightAggregation::Min;
+        assert_eq!(config.evaluate(&builder, &fitness)?, -1.0);
+        config.aggregation = WeightAggregation::Max;
+        assert_eq!(config.evaluate(&builder, &fitness)?, 2.0);
+
+        let population = Population::new(PopulationConfig {
+            population_size: 8,
+            num_inputs: 2,
+            num_outputs: 1,
+            output_activation: ActivationFunction::Identity,
+            ..PopulationConfig::default()
+        });
+        // Every genome starts with both inputs connected to the output.
+        let fitnesses = population.evaluate_weight_agnostic(
+            &|net: &mut ConsecutiveNeuralNet| net.evaluate(&[1.0, 1.0])[0],
+            &config,
+        )?;
+        assert_eq!(fitnesses, vec![4.0; 8]);
+        Ok(())
+    }
+}